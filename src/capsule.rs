@@ -0,0 +1,454 @@
+//! The Capsule Protocol and HTTP Datagrams.
+//!
+//! The [Capsule Protocol] frames a sequence of type-length-value *capsules*
+//! in the body of an extended `CONNECT` stream. Over HTTP/2, capsules are
+//! carried in `DATA` frames, independently of frame boundaries. The
+//! `DATAGRAM` capsule carries [HTTP Datagrams], which protocols such as
+//! [CONNECT-UDP] use to exchange unreliable messages.
+//!
+//! [`CapsuleSender`] and [`CapsuleReceiver`] encode and decode capsules on
+//! top of a [`SendStream`] and a [`RecvStream`] respectively. For CONNECT-UDP,
+//! see [`SendRequest::send_connect_udp`] on the client and
+//! [`SendResponse::accept_connect_udp`] on the server, which set up the
+//! stream and return both halves.
+//!
+//! [Capsule Protocol]: https://www.rfc-editor.org/rfc/rfc9297#section-3
+//! [HTTP Datagrams]: https://www.rfc-editor.org/rfc/rfc9297#section-2
+//! [CONNECT-UDP]: https://www.rfc-editor.org/rfc/rfc9298
+//! [`CapsuleSender`]: struct.CapsuleSender.html
+//! [`CapsuleReceiver`]: struct.CapsuleReceiver.html
+//! [`SendStream`]: ../struct.SendStream.html
+//! [`RecvStream`]: ../struct.RecvStream.html
+//! [`SendRequest::send_connect_udp`]: ../client/struct.SendRequest.html#method.send_connect_udp
+//! [`SendResponse::accept_connect_udp`]: ../server/struct.SendResponse.html#method.accept_connect_udp
+
+use crate::{tracing, Reason, RecvStream, SendStream, StreamId};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::task::{Context, Poll};
+
+/// The capsule type of the `DATAGRAM` capsule.
+pub const DATAGRAM: u64 = 0x00;
+
+/// The `capsule-protocol` header field, signalling use of the Capsule
+/// Protocol.
+pub(crate) const CAPSULE_PROTOCOL: http::HeaderName =
+    http::HeaderName::from_static("capsule-protocol");
+
+/// The maximum value of a variable-length integer.
+const MAX_VARINT: u64 = (1 << 62) - 1;
+
+/// Default maximum size of a received capsule payload.
+///
+/// Large enough to hold any UDP payload along with its context ID.
+const DEFAULT_MAX_CAPSULE_SIZE: usize = 64 * 1024;
+
+/// A single capsule, as defined by [RFC 9297].
+///
+/// [RFC 9297]: https://www.rfc-editor.org/rfc/rfc9297#section-3.2
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Capsule {
+    /// A `DATAGRAM` capsule, holding an HTTP Datagram payload.
+    Datagram(Bytes),
+
+    /// A capsule of any other type.
+    ///
+    /// Endpoints must silently ignore capsule types they do not understand.
+    Unknown {
+        /// The capsule type.
+        capsule_type: u64,
+        /// The capsule value.
+        payload: Bytes,
+    },
+}
+
+/// Sends capsules on the body of a stream.
+///
+/// Capsules are queued on the underlying [`SendStream`] as `DATA` frames, and
+/// are subject to the same flow control. Use [`get_mut`] to access
+/// [`SendStream::reserve_capacity`] and [`SendStream::poll_capacity`].
+///
+/// [`SendStream`]: ../struct.SendStream.html
+/// [`get_mut`]: #method.get_mut
+/// [`SendStream::reserve_capacity`]: ../struct.SendStream.html#method.reserve_capacity
+/// [`SendStream::poll_capacity`]: ../struct.SendStream.html#method.poll_capacity
+#[derive(Debug)]
+pub struct CapsuleSender {
+    inner: SendStream<Bytes>,
+}
+
+/// Receives capsules from the body of a stream.
+///
+/// Received `DATA` frames are buffered until a complete capsule is
+/// available. Capacity is released back to the peer as data is received, and
+/// the size of a single capsule is bounded by [`set_max_capsule_size`].
+///
+/// [`set_max_capsule_size`]: #method.set_max_capsule_size
+#[derive(Debug)]
+pub struct CapsuleReceiver {
+    inner: RecvStream,
+    buf: BytesMut,
+    max_capsule_size: usize,
+}
+
+// ===== impl Capsule =====
+
+impl Capsule {
+    /// Creates a `DATAGRAM` capsule carrying a [CONNECT-UDP] payload.
+    ///
+    /// The UDP payload is prefixed with a context ID of zero.
+    ///
+    /// [CONNECT-UDP]: https://www.rfc-editor.org/rfc/rfc9298#section-5
+    pub fn udp(payload: &[u8]) -> Capsule {
+        let mut buf = BytesMut::with_capacity(payload.len() + 1);
+        put_varint(&mut buf, 0);
+        buf.put_slice(payload);
+        Capsule::Datagram(buf.freeze())
+    }
+
+    /// Returns the UDP payload of a [CONNECT-UDP] `DATAGRAM` capsule.
+    ///
+    /// Returns `None` if this is not a `DATAGRAM` capsule, or if its context
+    /// ID is not zero.
+    ///
+    /// [CONNECT-UDP]: https://www.rfc-editor.org/rfc/rfc9298#section-5
+    pub fn udp_payload(&self) -> Option<Bytes> {
+        match self {
+            Capsule::Datagram(payload) => {
+                let mut payload = payload.clone();
+                match get_varint(&mut payload) {
+                    Some(0) => Some(payload),
+                    _ => None,
+                }
+            }
+            Capsule::Unknown { .. } => None,
+        }
+    }
+
+    /// Returns the capsule type.
+    pub fn capsule_type(&self) -> u64 {
+        match self {
+            Capsule::Datagram(_) => DATAGRAM,
+            Capsule::Unknown { capsule_type, .. } => *capsule_type,
+        }
+    }
+
+    /// Returns the capsule value.
+    pub fn payload(&self) -> &Bytes {
+        match self {
+            Capsule::Datagram(payload) | Capsule::Unknown { payload, .. } => payload,
+        }
+    }
+
+    /// Encodes the capsule into `dst`.
+    ///
+    /// # Panics
+    ///
+    /// If the capsule type is too large to be encoded as a variable-length
+    /// integer.
+    pub fn encode(&self, dst: &mut BytesMut) {
+        let payload = self.payload();
+        dst.reserve(16 + payload.len());
+        put_varint(dst, self.capsule_type());
+        put_varint(dst, payload.len() as u64);
+        dst.put_slice(payload);
+    }
+
+    /// Decodes a capsule from the front of `src`.
+    ///
+    /// Returns `Ok(None)` if `src` does not yet hold a complete capsule, in
+    /// which case `src` is left untouched. Returns an error if the capsule
+    /// payload is larger than `max_size`.
+    fn decode(src: &mut BytesMut, max_size: usize) -> Result<Option<Capsule>, Reason> {
+        let mut peek = &src[..];
+
+        let capsule_type = match get_varint(&mut peek) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let len = match get_varint(&mut peek) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        if len > max_size as u64 {
+            tracing::debug!("capsule too large; type={}; len={}", capsule_type, len);
+            return Err(Reason::PROTOCOL_ERROR);
+        }
+
+        let len = len as usize;
+        if peek.len() < len {
+            return Ok(None);
+        }
+
+        let header_len = src.len() - peek.len();
+        src.advance(header_len);
+        let payload = src.split_to(len).freeze();
+
+        Ok(Some(match capsule_type {
+            DATAGRAM => Capsule::Datagram(payload),
+            capsule_type => Capsule::Unknown {
+                capsule_type,
+                payload,
+            },
+        }))
+    }
+}
+
+// ===== impl CapsuleSender =====
+
+impl CapsuleSender {
+    /// Creates a new `CapsuleSender` sending on `inner`.
+    pub fn new(inner: SendStream<Bytes>) -> Self {
+        CapsuleSender { inner }
+    }
+
+    /// Sends a capsule.
+    ///
+    /// # Panics
+    ///
+    /// If the capsule type is too large to be encoded as a variable-length
+    /// integer.
+    pub fn send_capsule(&mut self, capsule: &Capsule) -> Result<(), crate::Error> {
+        let mut buf = BytesMut::new();
+        capsule.encode(&mut buf);
+        self.inner.send_data(buf.freeze(), false)
+    }
+
+    /// Sends an HTTP Datagram in a `DATAGRAM` capsule.
+    pub fn send_datagram(&mut self, payload: Bytes) -> Result<(), crate::Error> {
+        self.send_capsule(&Capsule::Datagram(payload))
+    }
+
+    /// Ends the stream.
+    ///
+    /// No more capsules may be sent afterwards.
+    pub fn finish(&mut self) -> Result<(), crate::Error> {
+        self.inner.send_data(Bytes::new(), true)
+    }
+
+    /// Returns the stream ID of the stream.
+    pub fn stream_id(&self) -> StreamId {
+        self.inner.stream_id()
+    }
+
+    /// Returns a reference to the underlying `SendStream`.
+    pub fn get_ref(&self) -> &SendStream<Bytes> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying `SendStream`.
+    pub fn get_mut(&mut self) -> &mut SendStream<Bytes> {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the underlying `SendStream`.
+    pub fn into_inner(self) -> SendStream<Bytes> {
+        self.inner
+    }
+}
+
+// ===== impl CapsuleReceiver =====
+
+impl CapsuleReceiver {
+    /// Creates a new `CapsuleReceiver` receiving from `inner`.
+    pub fn new(inner: RecvStream) -> Self {
+        CapsuleReceiver {
+            inner,
+            buf: BytesMut::new(),
+            max_capsule_size: DEFAULT_MAX_CAPSULE_SIZE,
+        }
+    }
+
+    /// Sets the maximum size of a received capsule value.
+    ///
+    /// Receiving a larger capsule results in an error. The default is 64 KiB.
+    pub fn set_max_capsule_size(&mut self, max: usize) {
+        self.max_capsule_size = max;
+    }
+
+    /// Receives the next capsule.
+    ///
+    /// Returns `None` once the stream has ended.
+    pub async fn capsule(&mut self) -> Option<Result<Capsule, crate::Error>> {
+        crate::poll_fn(move |cx| self.poll_capsule(cx)).await
+    }
+
+    /// Polls for the next capsule.
+    ///
+    /// Returns `None` once the stream has ended. A stream ending in the
+    /// middle of a capsule, or a capsule larger than the configured maximum,
+    /// is malformed and results in a `PROTOCOL_ERROR`. The caller should then
+    /// reset the stream.
+    pub fn poll_capsule(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Capsule, crate::Error>>> {
+        loop {
+            match Capsule::decode(&mut self.buf, self.max_capsule_size) {
+                Ok(Some(capsule)) => return Poll::Ready(Some(Ok(capsule))),
+                Ok(None) => {}
+                Err(reason) => return Poll::Ready(Some(Err(reason.into()))),
+            }
+
+            match ready!(self.inner.poll_data(cx)) {
+                Some(Ok(data)) => {
+                    let _ = self.inner.flow_control().release_capacity(data.len());
+                    self.buf.extend_from_slice(&data);
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None if self.buf.is_empty() => return Poll::Ready(None),
+                None => {
+                    tracing::debug!("stream ended with a partial capsule");
+                    self.buf.clear();
+                    return Poll::Ready(Some(Err(Reason::PROTOCOL_ERROR.into())));
+                }
+            }
+        }
+    }
+
+    /// Receives the next HTTP Datagram.
+    ///
+    /// Capsules of other types are skipped. Returns `None` once the stream
+    /// has ended.
+    pub async fn datagram(&mut self) -> Option<Result<Bytes, crate::Error>> {
+        crate::poll_fn(move |cx| self.poll_datagram(cx)).await
+    }
+
+    /// Polls for the next HTTP Datagram.
+    ///
+    /// Capsules of other types are skipped. Returns `None` once the stream
+    /// has ended.
+    pub fn poll_datagram(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, crate::Error>>> {
+        loop {
+            match ready!(self.poll_capsule(cx)) {
+                Some(Ok(Capsule::Datagram(payload))) => return Poll::Ready(Some(Ok(payload))),
+                Some(Ok(_capsule)) => {
+                    tracing::trace!("skipping capsule; type={}", _capsule.capsule_type());
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+
+    /// Returns the stream ID of the stream.
+    pub fn stream_id(&self) -> StreamId {
+        self.inner.stream_id()
+    }
+
+    /// Returns a mutable reference to the underlying `RecvStream`.
+    ///
+    /// Data read directly from the stream bypasses capsule decoding.
+    pub fn get_mut(&mut self) -> &mut RecvStream {
+        &mut self.inner
+    }
+}
+
+// ===== Variable-length integers =====
+
+/// Encodes a variable-length integer, as defined by RFC 9000, section 16.
+fn put_varint(dst: &mut BytesMut, v: u64) {
+    if v < 1 << 6 {
+        dst.put_u8(v as u8);
+    } else if v < 1 << 14 {
+        dst.put_u16(0x4000 | v as u16);
+    } else if v < 1 << 30 {
+        dst.put_u32(0x8000_0000 | v as u32);
+    } else {
+        assert!(v <= MAX_VARINT, "varint out of range");
+        dst.put_u64(0xc000_0000_0000_0000 | v);
+    }
+}
+
+/// Decodes a variable-length integer, advancing `src` past it.
+///
+/// Returns `None`, leaving `src` untouched, if it is incomplete.
+fn get_varint<B: Buf>(src: &mut B) -> Option<u64> {
+    if !src.has_remaining() {
+        return None;
+    }
+
+    let len = 1 << (src.chunk()[0] >> 6);
+    if src.remaining() < len {
+        return None;
+    }
+
+    Some(match len {
+        1 => u64::from(src.get_u8() & 0x3f),
+        2 => u64::from(src.get_u16() & 0x3fff),
+        4 => u64::from(src.get_u32() & 0x3fff_ffff),
+        _ => src.get_u64() & MAX_VARINT,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint_round_trip() {
+        for &v in &[
+            0,
+            37,
+            63,
+            64,
+            15_293,
+            16_383,
+            16_384,
+            494_878_333,
+            MAX_VARINT,
+        ] {
+            let mut buf = BytesMut::new();
+            put_varint(&mut buf, v);
+            let mut src = buf.freeze();
+            assert_eq!(get_varint(&mut src), Some(v));
+            assert!(src.is_empty());
+        }
+    }
+
+    #[test]
+    fn varint_examples() {
+        // From RFC 9000, appendix A.1.
+        let mut src = &[0x9d, 0x7f, 0x3e, 0x7d][..];
+        assert_eq!(get_varint(&mut src), Some(494_878_333));
+        let mut src = &[0x7b, 0xbd][..];
+        assert_eq!(get_varint(&mut src), Some(15_293));
+        let mut src = &[0x7b][..];
+        assert_eq!(get_varint(&mut src), None);
+        assert_eq!(src.len(), 1);
+    }
+
+    #[test]
+    fn decode_partial_capsule() {
+        let mut encoded = BytesMut::new();
+        Capsule::Datagram(Bytes::from_static(b"hello")).encode(&mut encoded);
+
+        for i in 0..encoded.len() {
+            let mut src = BytesMut::from(&encoded[..i]);
+            assert_eq!(Capsule::decode(&mut src, 1024), Ok(None));
+            assert_eq!(src.len(), i);
+        }
+
+        let mut src = encoded.clone();
+        let capsule = Capsule::decode(&mut src, 1024).unwrap().unwrap();
+        assert_eq!(capsule, Capsule::Datagram(Bytes::from_static(b"hello")));
+        assert!(src.is_empty());
+
+        let mut src = encoded;
+        assert_eq!(Capsule::decode(&mut src, 4), Err(Reason::PROTOCOL_ERROR));
+    }
+
+    #[test]
+    fn udp_payload() {
+        let capsule = Capsule::udp(b"payload");
+        assert_eq!(capsule.payload()[0], 0);
+        assert_eq!(capsule.udp_payload(), Some(Bytes::from_static(b"payload")));
+
+        let capsule = Capsule::Datagram(Bytes::from_static(b"\x01payload"));
+        assert_eq!(capsule.udp_payload(), None);
+    }
+}
@@ -135,26 +135,35 @@
 //! [`Builder`]: struct.Builder.html
 //! [`Error`]: ../struct.Error.html
 
-use crate::codec::{Codec, SendError, UserError};
+use crate::capsule::{CapsuleReceiver, CapsuleSender, CAPSULE_PROTOCOL};
+#[cfg(feature = "futures-io")]
+use crate::codec::FuturesIo;
+use crate::codec::{Framed, Io, SendError, UserError};
 use crate::ext::Protocol;
 use crate::frame::{
     Headers, Priority, Pseudo, PseudoOrder, PseudoOrders, Reason, Settings, SettingsOrder,
     StreamDependency, StreamId,
 };
+#[cfg(feature = "unstable")]
+use crate::intercept::{FrameInterceptor, SharedInterceptor};
+use crate::observer::{FrameLog, FrameObserver, SharedObserver};
 use crate::proto::{self, Error};
-use crate::{tracing, FlowControl, PingPong, RecvStream, SendStream};
+use crate::qlog::MakeWriter;
+use crate::stats::{ConnectionStats, StreamMetrics};
+use crate::timer::{SharedTimer, Timer};
+use crate::{tracing, FloodLimit, FlowControl, PingPong, RecvStream, SendStream, Upgraded};
 
 #[cfg(feature = "tracing")]
 use ::tracing::Instrument;
 use bytes::{Buf, Bytes};
 use http::{uri, HeaderMap, Method, Request, Response, Version};
 use std::borrow::Cow;
-use std::fmt;
+use std::collections::VecDeque;
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use std::{fmt, io};
 
 /// Initializes new HTTP/2 streams on a connection by sending a request.
 ///
@@ -180,6 +189,13 @@ use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 pub struct SendRequest<B: Buf> {
     inner: proto::Streams<B, Peer>,
     pending: Option<proto::OpaqueStreamRef>,
+
+    /// Requests of this handle waiting in the request queue, when their
+    /// number is limited per handle.
+    queued: VecDeque<proto::OpaqueStreamRef>,
+
+    /// Maximum number of requests of this handle in the request queue.
+    max_queued: Option<usize>,
 }
 
 /// Returns a `SendRequest` instance once it is ready to send at least one
@@ -234,7 +250,8 @@ pub struct ReadySendRequest<B: Buf> {
 /// ```
 #[must_use = "futures do nothing unless polled"]
 pub struct Connection<T, B: Buf = Bytes> {
-    inner: proto::Connection<T, Peer, B>,
+    io: T,
+    inner: proto::Connection<Peer, B>,
 }
 
 /// A future of an HTTP response.
@@ -245,6 +262,36 @@ pub struct ResponseFuture {
     push_promise_consumed: bool,
 }
 
+/// A future of an [RFC 8441] WebSocket handshake.
+///
+/// Returned by [`SendRequest::send_websocket`], resolving to the response
+/// and the byte stream carrying the WebSocket connection once the server has
+/// accepted the request.
+///
+/// [RFC 8441]: https://datatracker.ietf.org/doc/html/rfc8441
+/// [`SendRequest::send_websocket`]: struct.SendRequest.html#method.send_websocket
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct WebSocketFuture {
+    response: ResponseFuture,
+    send: Option<SendStream<Bytes>>,
+}
+
+/// A future of an [RFC 9298] UDP proxying handshake.
+///
+/// Returned by [`SendRequest::send_connect_udp`], resolving to the response
+/// and the capsule halves of the stream once the proxy has accepted the
+/// request.
+///
+/// [RFC 9298]: https://www.rfc-editor.org/rfc/rfc9298
+/// [`SendRequest::send_connect_udp`]: struct.SendRequest.html#method.send_connect_udp
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct ConnectUdpFuture {
+    response: ResponseFuture,
+    send: Option<SendStream<Bytes>>,
+}
+
 /// A future of a pushed HTTP response.
 ///
 /// We have to differentiate between pushed and non pushed because of the spec
@@ -343,6 +390,9 @@ pub struct Builder {
     /// monotonically increasing stream IDs.
     stream_id: StreamId,
 
+    /// The highest stream ID to allocate before the connection is exhausted.
+    max_stream_id: StreamId,
+
     /// Maximum number of locally reset streams due to protocol error across
     /// the lifetime of the connection.
     ///
@@ -357,6 +407,53 @@ pub struct Builder {
 
     /// Priority stream list
     priority: Option<Cow<'static, [Priority]>>,
+
+    /// Hold back request bodies until the server answers
+    /// `Expect: 100-continue`.
+    expect_continue: bool,
+
+    /// Keep requests to return them when the server does not process them.
+    retain_unprocessed_requests: bool,
+
+    /// Maximum number of CONTINUATION frames in a received header block.
+    max_continuation_frames: Option<usize>,
+
+    /// Maximum number of undecoded bytes in a received header block.
+    max_header_block_size: Option<usize>,
+
+    /// Maximum ratio of decoded to encoded size of a received header block.
+    max_header_decompression_ratio: Option<usize>,
+
+    /// Limits on received control frames.
+    flood_limits: proto::FloodLimits,
+
+    /// Called with every frame read and written.
+    frame_observer: Option<SharedObserver>,
+
+    /// Logs every frame read and written, in addition to `frame_observer`.
+    frame_log: Option<SharedObserver>,
+
+    /// Creates the writer of the qlog trace of each connection.
+    qlog: Option<MakeWriter>,
+
+    /// Called with every frame before it is written.
+    #[cfg(feature = "unstable")]
+    frame_interceptor: Option<SharedInterceptor>,
+
+    /// Creates the sleeps used by time based settings.
+    pub(crate) timer: Option<SharedTimer>,
+
+    /// How long the connection may be idle before it is closed.
+    idle_timeout: Option<Duration>,
+
+    /// Maximum number of requests waiting for the server's concurrency limit.
+    max_queued_requests: Option<usize>,
+
+    /// Maximum number of requests of one handle in the request queue.
+    max_queued_requests_per_handle: Option<usize>,
+
+    /// How long a request may wait in the request queue.
+    request_queue_timeout: Option<Duration>,
 }
 
 #[derive(Debug)]
@@ -379,6 +476,13 @@ where
     ///
     /// [module]: index.html
     pub fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), crate::Error>> {
+        if let Some(max) = self.max_queued {
+            while self.queued.len() >= max {
+                ready!(self.inner.poll_pending_open(cx, self.queued.front()))?;
+                self.queued.pop_front();
+            }
+        }
+
         ready!(self.inner.poll_pending_open(cx, self.pending.as_ref()))?;
         self.pending = None;
         Poll::Ready(Ok(()))
@@ -450,6 +554,11 @@ where
     /// The caller should always set the request's version field to 2.0 unless
     /// specifically transmitting an HTTP 1.1 request over 2.0.
     ///
+    /// # Errors
+    ///
+    /// Returns an error if the request's header list is larger than the
+    /// server's `SETTINGS_MAX_HEADER_LIST_SIZE`. Nothing is sent in that case.
+    ///
     /// # Examples
     ///
     /// Sending a request with no body
@@ -528,6 +637,21 @@ where
         request: Request<()>,
         end_of_stream: bool,
     ) -> Result<(ResponseFuture, SendStream<B>), crate::Error> {
+        if let Some(max) = self.max_queued {
+            // Forget the requests that have left the queue.
+            while self
+                .queued
+                .front()
+                .map_or(false, |stream| !stream.is_pending_open())
+            {
+                self.queued.pop_front();
+            }
+
+            if self.queued.len() >= max {
+                return Err(UserError::Rejected.into());
+            }
+        }
+
         self.inner
             .send_request(request, end_of_stream, self.pending.as_ref())
             .map_err(Into::into)
@@ -538,6 +662,10 @@ where
                     self.pending = Some(stream.clone_to_opaque());
                 }
 
+                if self.max_queued.is_some() && stream.is_pending_open() {
+                    self.queued.push_back(stream.clone_to_opaque());
+                }
+
                 let response = ResponseFuture {
                     inner: stream.clone_to_opaque(),
                     push_promise_consumed: false,
@@ -571,6 +699,195 @@ where
     pub fn current_max_recv_streams(&self) -> usize {
         self.inner.current_max_recv_streams()
     }
+
+    /// Returns a snapshot of the connection's statistics.
+    ///
+    /// See [`Connection::stats`] for details.
+    ///
+    /// [`Connection::stats`]: struct.Connection.html#method.stats
+    pub fn stats(&self) -> ConnectionStats {
+        self.inner.stats()
+    }
+}
+
+impl SendRequest<Bytes> {
+    /// Opens a WebSocket over HTTP/2, as defined by [RFC 8441].
+    ///
+    /// `request` is sent as an extended `CONNECT` request with the
+    /// `:protocol` pseudo-header set to `websocket`. The method of `request`
+    /// is replaced with `CONNECT`, and the URI must contain a scheme, an
+    /// authority and a path, e.g. `https://example.com/chat`. A
+    /// `sec-websocket-version: 13` header is added if not already present.
+    /// Any other headers, such as `sec-websocket-protocol`, are sent as-is.
+    ///
+    /// The returned [`WebSocketFuture`] resolves once the server has
+    /// responded. A `2xx` response yields the response head together with an
+    /// [`Upgraded`] byte stream over which WebSocket frames are exchanged.
+    /// Any other status results in an error, after the stream has been reset
+    /// with `CANCEL`. Use [`send_request`] directly if the body of a rejected
+    /// handshake is needed.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the server has not enabled the
+    /// extended `CONNECT` protocol (see
+    /// [`is_extended_connect_protocol_enabled`]), which is only known after
+    /// the server's initial `SETTINGS` frame has been received. It also
+    /// returns an error if `request` contains headers of the HTTP/1.1
+    /// WebSocket handshake, which must not be used over HTTP/2:
+    /// `sec-websocket-key`, `connection` and `upgrade`. Otherwise, the same
+    /// errors as [`send_request`] apply.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use http2::client::*;
+    /// # use http::Request;
+    /// # use bytes::Bytes;
+    /// # use tokio::io::AsyncWriteExt;
+    /// # async fn doc(send_request: SendRequest<Bytes>) -> Result<(), http2::Error>
+    /// # {
+    /// let mut send_request = send_request.ready().await?;
+    ///
+    /// let request = Request::builder()
+    ///     .uri("https://example.com/chat")
+    ///     .header("sec-websocket-protocol", "chat")
+    ///     .body(())
+    ///     .unwrap();
+    ///
+    /// let (response, mut websocket) = send_request.send_websocket(request)?.await?;
+    /// println!("protocol: {:?}", response.headers().get("sec-websocket-protocol"));
+    ///
+    /// // Exchange WebSocket frames over `websocket`...
+    /// websocket.shutdown().await.unwrap();
+    /// # Ok(())
+    /// # }
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [RFC 8441]: https://datatracker.ietf.org/doc/html/rfc8441
+    /// [`WebSocketFuture`]: struct.WebSocketFuture.html
+    /// [`Upgraded`]: ../struct.Upgraded.html
+    /// [`send_request`]: #method.send_request
+    /// [`is_extended_connect_protocol_enabled`]: #method.is_extended_connect_protocol_enabled
+    pub fn send_websocket(
+        &mut self,
+        mut request: Request<()>,
+    ) -> Result<WebSocketFuture, crate::Error> {
+        if request
+            .headers()
+            .contains_key(http::header::SEC_WEBSOCKET_KEY)
+        {
+            tracing::debug!("sec-websocket-key is not used by websockets over HTTP/2");
+            return Err(UserError::MalformedHeaders.into());
+        }
+
+        request
+            .headers_mut()
+            .entry(http::header::SEC_WEBSOCKET_VERSION)
+            .or_insert(http::HeaderValue::from_static("13"));
+
+        let (response, send) = self.send_extended_connect(request, "websocket")?;
+
+        Ok(WebSocketFuture {
+            response,
+            send: Some(send),
+        })
+    }
+
+    /// Opens a UDP proxying tunnel, as defined by [RFC 9298].
+    ///
+    /// `request` is sent as an extended `CONNECT` request with the
+    /// `:protocol` pseudo-header set to `connect-udp`. The method of
+    /// `request` is replaced with `CONNECT`, and the URI must be the expanded
+    /// URI template of the proxy, e.g.
+    /// `https://proxy.example/.well-known/masque/udp/192.0.2.6/443/`. A
+    /// `capsule-protocol: ?1` header is added if not already present.
+    ///
+    /// The returned [`ConnectUdpFuture`] resolves once the proxy has
+    /// responded. A `2xx` response yields the response head together with a
+    /// [`CapsuleSender`] and a [`CapsuleReceiver`] used to exchange UDP
+    /// payloads in `DATAGRAM` capsules; see [`Capsule::udp`] and
+    /// [`Capsule::udp_payload`]. Any other status results in an error, after
+    /// the stream has been reset with `CANCEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the server has not enabled the
+    /// extended `CONNECT` protocol (see
+    /// [`is_extended_connect_protocol_enabled`]). Otherwise, the same errors
+    /// as [`send_request`] apply.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use http2::client::*;
+    /// # use http2::capsule::Capsule;
+    /// # use http::Request;
+    /// # use bytes::Bytes;
+    /// # async fn doc(mut send_request: SendRequest<Bytes>) -> Result<(), http2::Error>
+    /// # {
+    /// let request = Request::builder()
+    ///     .uri("https://proxy.example/.well-known/masque/udp/192.0.2.6/443/")
+    ///     .body(())
+    ///     .unwrap();
+    ///
+    /// let (_, mut send, mut recv) = send_request.send_connect_udp(request)?.await?;
+    ///
+    /// send.send_capsule(&Capsule::udp(b"hello"))?;
+    ///
+    /// while let Some(capsule) = recv.capsule().await {
+    ///     if let Some(payload) = capsule?.udp_payload() {
+    ///         println!("received UDP payload: {:?}", payload);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [RFC 9298]: https://www.rfc-editor.org/rfc/rfc9298
+    /// [`ConnectUdpFuture`]: struct.ConnectUdpFuture.html
+    /// [`CapsuleSender`]: ../capsule/struct.CapsuleSender.html
+    /// [`CapsuleReceiver`]: ../capsule/struct.CapsuleReceiver.html
+    /// [`Capsule::udp`]: ../capsule/enum.Capsule.html#method.udp
+    /// [`Capsule::udp_payload`]: ../capsule/enum.Capsule.html#method.udp_payload
+    /// [`send_request`]: #method.send_request
+    /// [`is_extended_connect_protocol_enabled`]: #method.is_extended_connect_protocol_enabled
+    pub fn send_connect_udp(
+        &mut self,
+        mut request: Request<()>,
+    ) -> Result<ConnectUdpFuture, crate::Error> {
+        request
+            .headers_mut()
+            .entry(CAPSULE_PROTOCOL)
+            .or_insert(http::HeaderValue::from_static("?1"));
+
+        let (response, send) = self.send_extended_connect(request, "connect-udp")?;
+
+        Ok(ConnectUdpFuture {
+            response,
+            send: Some(send),
+        })
+    }
+
+    /// Sends `request` as an extended CONNECT request for `protocol`.
+    fn send_extended_connect(
+        &mut self,
+        mut request: Request<()>,
+        protocol: &'static str,
+    ) -> Result<(ResponseFuture, SendStream<Bytes>), crate::Error> {
+        if !self.is_extended_connect_protocol_enabled() {
+            return Err(UserError::ExtendedConnectProtocolDisabled.into());
+        }
+
+        *request.method_mut() = Method::CONNECT;
+        request
+            .extensions_mut()
+            .insert(Protocol::from_static(protocol));
+
+        self.send_request(request, false)
+    }
 }
 
 impl<B> fmt::Debug for SendRequest<B>
@@ -590,6 +907,8 @@ where
         SendRequest {
             inner: self.inner.clone(),
             pending: None,
+            queued: VecDeque::new(),
+            max_queued: self.max_queued,
         }
     }
 }
@@ -676,10 +995,27 @@ impl Builder {
             initial_max_send_streams: usize::MAX,
             settings: Default::default(),
             stream_id: 1.into(),
+            max_stream_id: StreamId::MAX,
             local_max_error_reset_streams: Some(proto::DEFAULT_LOCAL_RESET_COUNT_MAX),
             headers_pseudo_order: None,
             headers_priority: None,
             priority: None,
+            expect_continue: false,
+            retain_unprocessed_requests: false,
+            max_continuation_frames: None,
+            max_header_block_size: None,
+            max_header_decompression_ratio: None,
+            frame_observer: None,
+            frame_log: None,
+            qlog: None,
+            #[cfg(feature = "unstable")]
+            frame_interceptor: None,
+            flood_limits: proto::FloodLimits::default(),
+            timer: None,
+            idle_timeout: None,
+            max_queued_requests: None,
+            max_queued_requests_per_handle: None,
+            request_queue_timeout: None,
         }
     }
 
@@ -851,29 +1187,16 @@ impl Builder {
         self
     }
 
-    /// Sets the maximum number of concurrent streams.
-    ///
-    /// The maximum concurrent streams setting only controls the maximum number
-    /// of streams that can be initiated by the remote peer. In other words,
-    /// when this setting is set to 100, this does not limit the number of
-    /// concurrent streams that can be created by the caller.
-    ///
-    /// It is recommended that this value be no smaller than 100, so as to not
-    /// unnecessarily limit parallelism. However, any value is legal, including
-    /// 0. If `max` is set to 0, then the remote will not be permitted to
-    /// initiate streams.
-    ///
-    /// Note that streams in the reserved state, i.e., push promises that have
-    /// been reserved but the stream has not started, do not count against this
-    /// setting.
-    ///
-    /// Also note that if the remote *does* exceed the value set here, it is not
-    /// a protocol level error. Instead, the `http2` library will immediately reset
-    /// the stream.
+    /// Sets the maximum number of CONTINUATION frames in a received header
+    /// block.
     ///
-    /// See [Section 5.1.2] in the HTTP/2 spec for more details.
+    /// A peer can split a header block across an unbounded number of
+    /// CONTINUATION frames. Once a header block goes over this limit, the
+    /// connection is closed with a GOAWAY frame using `ENHANCE_YOUR_CALM`,
+    /// without waiting for the rest of the block.
     ///
-    /// [Section 5.1.2]: https://http2.github.io/http2-spec/#rfc.section.5.1.2
+    /// By default, the limit is derived from the max header list size and the
+    /// max frame size.
     ///
     /// # Examples
     ///
@@ -888,38 +1211,27 @@ impl Builder {
     /// // `client_fut` is a future representing the completion of the HTTP/2
     /// // handshake.
     /// let client_fut = Builder::new()
-    ///     .max_concurrent_streams(1000)
+    ///     .max_continuation_frames(8)
     ///     .handshake(my_io);
     /// # client_fut.await
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_concurrent_streams(&mut self, max: u32) -> &mut Self {
-        self.settings.set_max_concurrent_streams(Some(max));
+    pub fn max_continuation_frames(&mut self, max: usize) -> &mut Self {
+        self.max_continuation_frames = Some(max);
         self
     }
 
-    /// Sets the initial maximum of locally initiated (send) streams.
-    ///
-    /// The initial settings will be overwritten by the remote peer when
-    /// the SETTINGS frame is received. The new value will be set to the
-    /// `max_concurrent_streams()` from the frame. If no value is advertised in
-    /// the initial SETTINGS frame from the remote peer as part of
-    /// [HTTP/2 Connection Preface], `usize::MAX` will be set.
-    ///
-    /// This setting prevents the caller from exceeding this number of
-    /// streams that are counted towards the concurrency limit.
+    /// Sets the maximum number of undecoded bytes in a received header block.
     ///
-    /// Sending streams past the limit returned by the peer will be treated
-    /// as a stream error of type PROTOCOL_ERROR or REFUSED_STREAM.
-    ///
-    /// See [Section 5.1.2] in the HTTP/2 spec for more details.
+    /// This counts the HPACK-encoded bytes of the HEADERS or PUSH_PROMISE frame
+    /// and all of its CONTINUATION frames. Once a header block goes over this
+    /// limit, the connection is closed with a GOAWAY frame using
+    /// `ENHANCE_YOUR_CALM`, without waiting for the rest of the block.
     ///
-    /// The default value is `usize::MAX`.
-    ///
-    /// [HTTP/2 Connection Preface]: https://httpwg.org/specs/rfc9113.html#preface
-    /// [Section 5.1.2]: https://httpwg.org/specs/rfc9113.html#rfc.section.5.1.2
+    /// By default, there is no limit other than the one implied by the max
+    /// header list size.
     ///
     /// # Examples
     ///
@@ -934,37 +1246,30 @@ impl Builder {
     /// // `client_fut` is a future representing the completion of the HTTP/2
     /// // handshake.
     /// let client_fut = Builder::new()
-    ///     .initial_max_send_streams(1000)
+    ///     .max_header_block_size(64 * 1024)
     ///     .handshake(my_io);
     /// # client_fut.await
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn initial_max_send_streams(&mut self, initial: usize) -> &mut Self {
-        self.initial_max_send_streams = initial;
+    pub fn max_header_block_size(&mut self, max: usize) -> &mut Self {
+        self.max_header_block_size = Some(max);
         self
     }
 
-    /// Sets the maximum number of concurrent locally reset streams.
-    ///
-    /// When a stream is explicitly reset, the HTTP/2 specification requires
-    /// that any further frames received for that stream must be ignored for
-    /// "some time".
+    /// Sets the maximum ratio of the decoded size of a received header list
+    /// to its HPACK-encoded size.
     ///
-    /// In order to satisfy the specification, internal state must be maintained
-    /// to implement the behavior. This state grows linearly with the number of
-    /// streams that are locally reset.
-    ///
-    /// The `max_concurrent_reset_streams` setting configures sets an upper
-    /// bound on the amount of state that is maintained. When this max value is
-    /// reached, the oldest reset stream is purged from memory.
-    ///
-    /// Once the stream has been fully purged from memory, any additional frames
-    /// received for that stream will result in a connection level protocol
-    /// error, forcing the connection to terminate.
+    /// Small indexed references to large dynamic table entries can expand a
+    /// tiny header block into a huge header list. Once a header list being
+    /// decoded goes over this ratio, the remaining headers are dropped as they
+    /// are decoded.
+    /// Responses and push promises over the limit are treated the same way as
+    /// ones over the max header list size: the stream is reset.
     ///
-    /// The default value is 10.
+    /// Header lists smaller than 16KB are not checked, since small header
+    /// blocks can be very well compressed. By default, there is no limit.
     ///
     /// # Examples
     ///
@@ -979,98 +1284,138 @@ impl Builder {
     /// // `client_fut` is a future representing the completion of the HTTP/2
     /// // handshake.
     /// let client_fut = Builder::new()
-    ///     .max_concurrent_reset_streams(1000)
+    ///     .max_header_decompression_ratio(64)
     ///     .handshake(my_io);
     /// # client_fut.await
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_concurrent_reset_streams(&mut self, max: usize) -> &mut Self {
-        self.reset_stream_max = max;
+    pub fn max_header_decompression_ratio(&mut self, ratio: usize) -> &mut Self {
+        self.max_header_decompression_ratio = Some(ratio);
         self
     }
 
-    /// Sets the duration to remember locally reset streams.
+    /// Sets an observer that is called with every frame read from and written
+    /// to the connection.
     ///
-    /// When a stream is explicitly reset, the HTTP/2 specification requires
-    /// that any further frames received for that stream must be ignored for
-    /// "some time".
-    ///
-    /// In order to satisfy the specification, internal state must be maintained
-    /// to implement the behavior. This state grows linearly with the number of
-    /// streams that are locally reset.
-    ///
-    /// The `reset_stream_duration` setting configures the max amount of time
-    /// this state will be maintained in memory. Once the duration elapses, the
-    /// stream state is purged from memory.
-    ///
-    /// Once the stream has been fully purged from memory, any additional frames
-    /// received for that stream will result in a connection level protocol
-    /// error, forcing the connection to terminate.
+    /// The observer sees each frame's type, flags, stream identifier and
+    /// length, the decoded header list of HEADERS and PUSH_PROMISE frames,
+    /// and optionally the raw bytes of the frame. See the [`observer`] module
+    /// for details.
     ///
-    /// The default value is 30 seconds.
+    /// [`observer`]: ../observer/index.html
     ///
     /// # Examples
     ///
     /// ```
     /// # use tokio::io::{AsyncRead, AsyncWrite};
     /// # use http2::client::*;
-    /// # use std::time::Duration;
+    /// # use http2::observer::{FrameObserver, ObservedFrame};
     /// # use bytes::Bytes;
     /// #
     /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
     /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
     /// # {
+    /// struct LogFrames;
+    ///
+    /// impl FrameObserver for LogFrames {
+    ///     fn on_frame(&self, frame: &ObservedFrame<'_>) {
+    ///         println!("{:?} {:?}", frame.direction(), frame.frame_type());
+    ///     }
+    /// }
+    ///
     /// // `client_fut` is a future representing the completion of the HTTP/2
     /// // handshake.
     /// let client_fut = Builder::new()
-    ///     .reset_stream_duration(Duration::from_secs(10))
+    ///     .frame_observer(LogFrames)
     ///     .handshake(my_io);
     /// # client_fut.await
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn reset_stream_duration(&mut self, dur: Duration) -> &mut Self {
-        self.reset_stream_duration = dur;
+    pub fn frame_observer<O>(&mut self, observer: O) -> &mut Self
+    where
+        O: FrameObserver + 'static,
+    {
+        self.frame_observer = Some(SharedObserver::new(observer));
         self
     }
 
-    /// Sets the maximum number of local resets due to protocol errors made by the remote end.
+    /// Writes a human-readable line for every frame read from and written to
+    /// the connection to `writer`, in the format of `nghttp -v`.
     ///
-    /// Invalid frames and many other protocol errors will lead to resets being generated for those streams.
-    /// Too many of these often indicate a malicious client, and there are attacks which can abuse this to DOS servers.
-    /// This limit protects against these DOS attacks by limiting the amount of resets we can be forced to generate.
+    /// The log is written in addition to calling the observer set with
+    /// [`frame_observer`], if any, in whichever order the two are set. See
+    /// [`FrameLog`] for details.
     ///
-    /// When the number of local resets exceeds this threshold, the client will close the connection.
+    /// [`frame_observer`]: #method.frame_observer
+    /// [`FrameLog`]: ../observer/struct.FrameLog.html
     ///
-    /// If you really want to disable this, supply [`Option::None`] here.
-    /// Disabling this is not recommended and may expose you to DOS attacks.
+    /// # Examples
     ///
-    /// The default value is currently 1024, but could change.
-    pub fn max_local_error_reset_streams(&mut self, max: Option<usize>) -> &mut Self {
-        self.local_max_error_reset_streams = max;
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .frame_log(std::io::stderr())
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn frame_log<W>(&mut self, writer: W) -> &mut Self
+    where
+        W: io::Write + Send + 'static,
+    {
+        self.frame_log = Some(SharedObserver::new(FrameLog::new(writer)));
         self
     }
 
-    /// Sets the maximum number of pending-accept remotely-reset streams.
+    /// Panics if a setting that requires a timer is used without one.
+    fn assert_timer(&self) {
+        if self.timer.is_some() {
+            return;
+        }
+
+        assert!(self.idle_timeout.is_none(), "idle_timeout requires a timer");
+        assert!(
+            self.request_queue_timeout.is_none(),
+            "request_queue_timeout requires a timer"
+        );
+    }
+
+    /// Writes a [qlog] trace of each connection, as JSON-SEQ, to a writer
+    /// returned by `make_writer`.
     ///
-    /// Streams that have been received by the peer, but not accepted by the
-    /// user, can also receive a RST_STREAM. This is a legitimate pattern: one
-    /// could send a request and then shortly after, realize it is not needed,
-    /// sending a CANCEL.
+    /// The trace records every frame read and written, changes to the
+    /// settings, GOAWAY frames, stream state changes and flow control window
+    /// updates, with times relative to the start of the handshake. It can be
+    /// loaded in tools such as [qvis]. Errors returned by the writer are
+    /// ignored.
     ///
-    /// However, since those streams are now "closed", they don't count towards
-    /// the max concurrent streams. So, they will sit in the accept queue,
-    /// using memory.
+    /// `make_writer` is called once per connection created from this
+    /// builder, when its handshake starts, so that the records of different
+    /// connections are not interleaved. Only the frame header of DATA frames
+    /// is recorded, and their payload is not copied.
     ///
-    /// When the number of remotely-reset streams sitting in the pending-accept
-    /// queue reaches this maximum value, a connection error with the code of
-    /// `ENHANCE_YOUR_CALM` will be sent to the peer, and returned by the
-    /// `Future`.
+    /// The writer is called synchronously from the task driving the
+    /// connection, sometimes while the state of the streams is locked. It is
+    /// only flushed once the connection is dropped, so writers that block,
+    /// such as files, should be wrapped in a [`BufWriter`].
     ///
-    /// The default value is currently 20, but could change.
+    /// [qlog]: https://datatracker.ietf.org/doc/draft-ietf-quic-qlog-main-schema/
+    /// [qvis]: https://qvis.quictools.info/
+    /// [`BufWriter`]: https://doc.rust-lang.org/std/io/struct.BufWriter.html
     ///
     /// # Examples
     ///
@@ -1085,21 +1430,707 @@ impl Builder {
     /// // `client_fut` is a future representing the completion of the HTTP/2
     /// // handshake.
     /// let client_fut = Builder::new()
-    ///     .max_pending_accept_reset_streams(100)
+    ///     .qlog(std::io::stderr)
     ///     .handshake(my_io);
     /// # client_fut.await
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_pending_accept_reset_streams(&mut self, max: usize) -> &mut Self {
-        self.pending_accept_reset_stream_max = max;
+    pub fn qlog<F, W>(&mut self, make_writer: F) -> &mut Self
+    where
+        F: Fn() -> W + Send + Sync + 'static,
+        W: io::Write + Send + 'static,
+    {
+        self.qlog = Some(MakeWriter::new(make_writer));
         self
     }
 
-    /// Sets the maximum send buffer size per stream.
+    /// Sets an interceptor that is called with every frame before it is
+    /// written to the connection.
     ///
-    /// Once a stream has buffered up to (or over) the maximum, the stream's
+    /// The interceptor can rewrite, drop, insert or delay frames, within
+    /// limits that keep the stream states consistent with what the peer sees.
+    /// See the [`intercept`] module for details.
+    ///
+    /// This is only available with the `unstable` feature.
+    ///
+    /// [`intercept`]: ../intercept/index.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use http2::frame::Frame;
+    /// # use http2::intercept::{FrameInterceptor, Intercept};
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// struct SplitData;
+    ///
+    /// impl FrameInterceptor for SplitData {
+    ///     fn intercept(&self, frame: Frame) -> Intercept {
+    ///         match frame {
+    ///             // Write DATA frames one byte at a time.
+    ///             Frame::Data(data) if data.payload().len() > 1 => {
+    ///                 let id = data.stream_id();
+    ///                 let eos = data.is_end_stream();
+    ///                 let payload = data.into_payload();
+    ///                 let frames = (0..payload.len())
+    ///                     .map(|i| {
+    ///                         let mut data = http2::frame::Data::new(id, payload.slice(i..i + 1));
+    ///                         data.set_end_stream(eos && i == payload.len() - 1);
+    ///                         data.into()
+    ///                     })
+    ///                     .collect();
+    ///                 Intercept::Replace(frames)
+    ///             }
+    ///             frame => Intercept::Send(frame),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .frame_interceptor(SplitData)
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    #[cfg(feature = "unstable")]
+    pub fn frame_interceptor<I>(&mut self, interceptor: I) -> &mut Self
+    where
+        I: FrameInterceptor + 'static,
+    {
+        self.frame_interceptor = Some(SharedInterceptor::new(interceptor));
+        self
+    }
+
+    /// Sets the maximum number of concurrent streams.
+    ///
+    /// The maximum concurrent streams setting only controls the maximum number
+    /// of streams that can be initiated by the remote peer. In other words,
+    /// when this setting is set to 100, this does not limit the number of
+    /// concurrent streams that can be created by the caller.
+    ///
+    /// It is recommended that this value be no smaller than 100, so as to not
+    /// unnecessarily limit parallelism. However, any value is legal, including
+    /// 0. If `max` is set to 0, then the remote will not be permitted to
+    /// initiate streams.
+    ///
+    /// Note that streams in the reserved state, i.e., push promises that have
+    /// been reserved but the stream has not started, do not count against this
+    /// setting.
+    ///
+    /// Also note that if the remote *does* exceed the value set here, it is not
+    /// a protocol level error. Instead, the `http2` library will immediately reset
+    /// the stream.
+    ///
+    /// See [Section 5.1.2] in the HTTP/2 spec for more details.
+    ///
+    /// [Section 5.1.2]: https://http2.github.io/http2-spec/#rfc.section.5.1.2
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_concurrent_streams(1000)
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_concurrent_streams(&mut self, max: u32) -> &mut Self {
+        self.settings.set_max_concurrent_streams(Some(max));
+        self
+    }
+
+    /// Sets the initial maximum of locally initiated (send) streams.
+    ///
+    /// The initial settings will be overwritten by the remote peer when
+    /// the SETTINGS frame is received. The new value will be set to the
+    /// `max_concurrent_streams()` from the frame. If no value is advertised in
+    /// the initial SETTINGS frame from the remote peer as part of
+    /// [HTTP/2 Connection Preface], `usize::MAX` will be set.
+    ///
+    /// This setting prevents the caller from exceeding this number of
+    /// streams that are counted towards the concurrency limit.
+    ///
+    /// Sending streams past the limit returned by the peer will be treated
+    /// as a stream error of type PROTOCOL_ERROR or REFUSED_STREAM.
+    ///
+    /// See [Section 5.1.2] in the HTTP/2 spec for more details.
+    ///
+    /// The default value is `usize::MAX`.
+    ///
+    /// [HTTP/2 Connection Preface]: https://httpwg.org/specs/rfc9113.html#preface
+    /// [Section 5.1.2]: https://httpwg.org/specs/rfc9113.html#rfc.section.5.1.2
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .initial_max_send_streams(1000)
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn initial_max_send_streams(&mut self, initial: usize) -> &mut Self {
+        self.initial_max_send_streams = initial;
+        self
+    }
+
+    /// Enables a request queue holding up to `max` requests beyond the
+    /// server's concurrency limit.
+    ///
+    /// By default, each [`SendRequest`] handle may have a single request
+    /// waiting for the server's `SETTINGS_MAX_CONCURRENT_STREAMS` limit, and
+    /// more requests can only be queued by cloning the handle. With a request
+    /// queue, any handle may send requests until `max` requests are waiting,
+    /// and `send_request` returns immediately. The streams are opened in the
+    /// order the requests were sent, as the server's limit allows.
+    ///
+    /// While the queue is full, [`SendRequest::poll_ready`] returns `Pending`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_queued_requests(10_000)
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`SendRequest`]: struct.SendRequest.html
+    /// [`SendRequest::poll_ready`]: struct.SendRequest.html#method.poll_ready
+    pub fn max_queued_requests(&mut self, max: usize) -> &mut Self {
+        self.max_queued_requests = Some(max);
+        self
+    }
+
+    /// Limits how many requests of a single [`SendRequest`] handle may wait
+    /// in the request queue.
+    ///
+    /// This keeps a handle sending many requests from taking the whole queue
+    /// from its clones. Once a handle has `max` requests in the queue, its
+    /// [`SendRequest::poll_ready`] returns `Pending` until the oldest one is
+    /// opened or fails.
+    ///
+    /// This only applies if a request queue is enabled with
+    /// [`max_queued_requests`]. By default, a handle may use the whole queue.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_queued_requests(10_000)
+    ///     .max_queued_requests_per_handle(1_000)
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`SendRequest`]: struct.SendRequest.html
+    /// [`SendRequest::poll_ready`]: struct.SendRequest.html#method.poll_ready
+    /// [`max_queued_requests`]: #method.max_queued_requests
+    pub fn max_queued_requests_per_handle(&mut self, max: usize) -> &mut Self {
+        self.max_queued_requests_per_handle = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of concurrent locally reset streams.
+    ///
+    /// When a stream is explicitly reset, the HTTP/2 specification requires
+    /// that any further frames received for that stream must be ignored for
+    /// "some time".
+    ///
+    /// In order to satisfy the specification, internal state must be maintained
+    /// to implement the behavior. This state grows linearly with the number of
+    /// streams that are locally reset.
+    ///
+    /// The `max_concurrent_reset_streams` setting configures sets an upper
+    /// bound on the amount of state that is maintained. When this max value is
+    /// reached, the oldest reset stream is purged from memory.
+    ///
+    /// Once the stream has been fully purged from memory, any additional frames
+    /// received for that stream will result in a connection level protocol
+    /// error, forcing the connection to terminate.
+    ///
+    /// The default value is 10.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_concurrent_reset_streams(1000)
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_concurrent_reset_streams(&mut self, max: usize) -> &mut Self {
+        self.reset_stream_max = max;
+        self
+    }
+
+    /// Sets the duration to remember locally reset streams.
+    ///
+    /// When a stream is explicitly reset, the HTTP/2 specification requires
+    /// that any further frames received for that stream must be ignored for
+    /// "some time".
+    ///
+    /// In order to satisfy the specification, internal state must be maintained
+    /// to implement the behavior. This state grows linearly with the number of
+    /// streams that are locally reset.
+    ///
+    /// The `reset_stream_duration` setting configures the max amount of time
+    /// this state will be maintained in memory. Once the duration elapses, the
+    /// stream state is purged from memory.
+    ///
+    /// Once the stream has been fully purged from memory, any additional frames
+    /// received for that stream will result in a connection level protocol
+    /// error, forcing the connection to terminate.
+    ///
+    /// The default value is 30 seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use std::time::Duration;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .reset_stream_duration(Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn reset_stream_duration(&mut self, dur: Duration) -> &mut Self {
+        self.reset_stream_duration = dur;
+        self
+    }
+
+    /// Enables or disables waiting for `100 Continue` before sending request
+    /// bodies.
+    ///
+    /// When enabled, the body of a request carrying an
+    /// `Expect: 100-continue` header is held back until the server responds
+    /// with either `100 Continue` or a final response. Data sent on the
+    /// [`SendStream`] in the meantime is buffered. Requests sent with
+    /// `end_of_stream` set are not affected.
+    ///
+    /// When disabled, request bodies are sent right away and the
+    /// `Expect` header is forwarded as is.
+    ///
+    /// Default value: `false`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .expect_continue(true)
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`SendStream`]: ../struct.SendStream.html
+    pub fn expect_continue(&mut self, enabled: bool) -> &mut Self {
+        self.expect_continue = enabled;
+        self
+    }
+
+    /// Enables or disables keeping requests the server did not process.
+    ///
+    /// When enabled, a copy of the method, URI, version, and headers of each
+    /// request is kept until the server starts processing it, so that
+    /// [`Error::take_request`] can return it when the stream fails in a way
+    /// that makes it [safe to retry]. The copy is dropped as soon as a
+    /// response starts.
+    ///
+    /// When disabled, [`Error::is_safe_to_retry`] still reports unprocessed
+    /// requests, but `take_request` always returns `None`.
+    ///
+    /// Default value: `false`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .retain_unprocessed_requests(true)
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`Error::take_request`]: ../struct.Error.html#method.take_request
+    /// [safe to retry]: ../struct.Error.html#method.is_safe_to_retry
+    /// [`Error::is_safe_to_retry`]: ../struct.Error.html#method.is_safe_to_retry
+    pub fn retain_unprocessed_requests(&mut self, enabled: bool) -> &mut Self {
+        self.retain_unprocessed_requests = enabled;
+        self
+    }
+
+    /// Sets the maximum number of local resets due to protocol errors made by the remote end.
+    ///
+    /// Invalid frames and many other protocol errors will lead to resets being generated for those streams.
+    /// Too many of these often indicate a malicious client, and there are attacks which can abuse this to DOS servers.
+    /// This limit protects against these DOS attacks by limiting the amount of resets we can be forced to generate.
+    ///
+    /// When the number of local resets exceeds this threshold, the client will close the connection.
+    ///
+    /// If you really want to disable this, supply [`Option::None`] here.
+    /// Disabling this is not recommended and may expose you to DOS attacks.
+    ///
+    /// The default value is currently 1024, but could change.
+    pub fn max_local_error_reset_streams(&mut self, max: Option<usize>) -> &mut Self {
+        self.local_max_error_reset_streams = max;
+        self
+    }
+
+    /// Limits how many frames of a kind the remote peer may send.
+    ///
+    /// Frames that cost little to send but must be processed or answered, such
+    /// as PING or SETTINGS, can be used to exhaust a peer's resources. This
+    /// allows at most `max` frames of the given kind within any sliding
+    /// `window` of time. Once the limit is exceeded, the connection is closed
+    /// with a GOAWAY frame using `ENHANCE_YOUR_CALM`, and the returned error
+    /// reports the limit with [`Error::flood_limit`].
+    ///
+    /// Memory used to track a limit is proportional to `max`.
+    ///
+    /// By default, none of these limits are enforced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use http2::FloodLimit;
+    /// # use std::time::Duration;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .flood_limit(FloodLimit::Ping, 100, Duration::from_secs(10))
+    ///     .flood_limit(FloodLimit::Settings, 100, Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`Error::flood_limit`]: ../struct.Error.html#method.flood_limit
+    pub fn flood_limit(&mut self, limit: FloodLimit, max: usize, window: Duration) -> &mut Self {
+        self.flood_limits.set(limit, max, window);
+        self
+    }
+
+    /// Sets the timer used by time based settings.
+    ///
+    /// A timer is required by [`idle_timeout`] and [`request_queue_timeout`].
+    /// See the [`timer`] module for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use http2::timer::{Sleep, Timer};
+    /// # use std::pin::Pin;
+    /// # use std::time::Duration;
+    /// # use bytes::Bytes;
+    /// #
+    /// #[derive(Debug)]
+    /// struct TokioTimer;
+    ///
+    /// impl Timer for TokioTimer {
+    ///     fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+    ///         Box::pin(tokio::time::sleep(duration))
+    ///     }
+    /// }
+    ///
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .timer(TokioTimer)
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`idle_timeout`]: #method.idle_timeout
+    /// [`request_queue_timeout`]: #method.request_queue_timeout
+    /// [`timer`]: ../timer/index.html
+    pub fn timer<M>(&mut self, timer: M) -> &mut Self
+    where
+        M: Timer + 'static,
+    {
+        self.timer = Some(SharedTimer::new(timer));
+        self
+    }
+
+    /// Sets how long the connection may be idle before it is closed.
+    ///
+    /// The connection is idle while no streams are open, no PING is waiting
+    /// to be acknowledged, and no frames are received. Once it has been idle
+    /// for `timeout`, the connection is closed, and [`Connection`] completes
+    /// with an error for which [`Error::is_idle_timeout`] returns `true`.
+    ///
+    /// By default, idle connections are kept open.
+    ///
+    /// # Panics
+    ///
+    /// [`handshake`] panics if no [`timer`] is configured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use http2::timer::{Sleep, Timer};
+    /// # use std::pin::Pin;
+    /// # use std::time::Duration;
+    /// #
+    /// # struct TokioTimer;
+    /// #
+    /// # impl Timer for TokioTimer {
+    /// #     fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+    /// #         Box::pin(tokio::time::sleep(duration))
+    /// #     }
+    /// # }
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .timer(TokioTimer)
+    ///     .idle_timeout(Duration::from_secs(90))
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`Connection`]: struct.Connection.html
+    /// [`Error::is_idle_timeout`]: ../struct.Error.html#method.is_idle_timeout
+    /// [`handshake`]: #method.handshake
+    /// [`timer`]: #method.timer
+    pub fn idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long a request may wait for the server's concurrency limit.
+    ///
+    /// Each request that cannot be sent right away because of the server's
+    /// `SETTINGS_MAX_CONCURRENT_STREAMS` limit fails once it has waited for
+    /// `timeout`. Its [`ResponseFuture`] then completes with a
+    /// `REFUSED_STREAM` error for which [`Error::is_library`] and
+    /// [`Error::is_safe_to_retry`] return `true`. Nothing is sent to the
+    /// server for such requests.
+    ///
+    /// By default, requests wait until they can be sent.
+    ///
+    /// # Panics
+    ///
+    /// [`handshake`] panics if no [`timer`] is configured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use http2::timer::{Sleep, Timer};
+    /// # use std::pin::Pin;
+    /// # use std::time::Duration;
+    /// #
+    /// # struct TokioTimer;
+    /// #
+    /// # impl Timer for TokioTimer {
+    /// #     fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+    /// #         Box::pin(tokio::time::sleep(duration))
+    /// #     }
+    /// # }
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .timer(TokioTimer)
+    ///     .max_queued_requests(10_000)
+    ///     .request_queue_timeout(Duration::from_secs(5))
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`ResponseFuture`]: struct.ResponseFuture.html
+    /// [`Error::is_library`]: ../struct.Error.html#method.is_library
+    /// [`Error::is_safe_to_retry`]: ../struct.Error.html#method.is_safe_to_retry
+    /// [`handshake`]: #method.handshake
+    /// [`timer`]: #method.timer
+    pub fn request_queue_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.request_queue_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum number of pending-accept remotely-reset streams.
+    ///
+    /// Streams that have been received by the peer, but not accepted by the
+    /// user, can also receive a RST_STREAM. This is a legitimate pattern: one
+    /// could send a request and then shortly after, realize it is not needed,
+    /// sending a CANCEL.
+    ///
+    /// However, since those streams are now "closed", they don't count towards
+    /// the max concurrent streams. So, they will sit in the accept queue,
+    /// using memory.
+    ///
+    /// When the number of remotely-reset streams sitting in the pending-accept
+    /// queue reaches this maximum value, a connection error with the code of
+    /// `ENHANCE_YOUR_CALM` will be sent to the peer, and returned by the
+    /// `Future`.
+    ///
+    /// The default value is currently 20, but could change.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_pending_accept_reset_streams(100)
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_pending_accept_reset_streams(&mut self, max: usize) -> &mut Self {
+        self.pending_accept_reset_stream_max = max;
+        self
+    }
+
+    /// Sets the maximum send buffer size per stream.
+    ///
+    /// Once a stream has buffered up to (or over) the maximum, the stream's
     /// flow control will not "poll" additional capacity. Once bytes for the
     /// stream have been written to the connection, the send buffer capacity
     /// will be freed up again.
@@ -1199,6 +2230,52 @@ impl Builder {
         self
     }
 
+    /// Sets the highest stream ID the client allocates.
+    ///
+    /// A connection can open at most 2^30 streams, as stream IDs cannot be
+    /// reused. Once the stream with ID `max` is opened, the connection is
+    /// exhausted: [`SendRequest::poll_ready`] and
+    /// [`SendRequest::send_request`] fail with an error for which
+    /// [`Error::is_connection_exhausted`] returns `true`, and the connection
+    /// sends a `GOAWAY` frame and closes once the streams in flight are done.
+    /// Requests must then be sent on a new connection.
+    ///
+    /// Setting this below the limit of 2^31-1 leaves room for the requests
+    /// in flight to finish before the IDs run out.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `max` is larger than 2^31-1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<((SendRequest<Bytes>, Connection<T, Bytes>)), http2::Error>
+    /// # {
+    /// // `client_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let client_fut = Builder::new()
+    ///     .max_stream_id(1 << 30)
+    ///     .handshake(my_io);
+    /// # client_fut.await
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`SendRequest::poll_ready`]: struct.SendRequest.html#method.poll_ready
+    /// [`SendRequest::send_request`]: struct.SendRequest.html#method.send_request
+    /// [`Error::is_connection_exhausted`]: ../struct.Error.html#method.is_connection_exhausted
+    pub fn max_stream_id(&mut self, max: u32) -> &mut Self {
+        self.max_stream_id = max.into();
+        self
+    }
+
     /// Sets the first stream ID to something other than 1.
     #[cfg(feature = "unstable")]
     pub fn initial_stream_id(&mut self, stream_id: u32) -> &mut Self {
@@ -1230,6 +2307,13 @@ impl Builder {
     /// [`SendRequest`]: struct.SendRequest.html
     /// [Outbound data type]: ../index.html#outbound-data-type.
     ///
+    /// # Panics
+    ///
+    /// This function panics if a setting that requires a [`timer`] is used
+    /// without one.
+    ///
+    /// [`timer`]: #method.timer
+    ///
     /// # Examples
     ///
     /// Basic usage:
@@ -1276,11 +2360,160 @@ impl Builder {
         io: T,
     ) -> impl Future<Output = Result<(SendRequest<B>, Connection<T, B>), crate::Error>>
     where
-        T: AsyncRead + AsyncWrite + Unpin,
+        T: Io,
         B: Buf,
     {
+        self.assert_timer();
         Connection::handshake2(io, self.clone())
     }
+
+    /// Creates a new configured HTTP/2 client backed by `io`, which
+    /// implements the [`futures-io`] traits instead of the tokio ones.
+    ///
+    /// This is the same as [`handshake`], except for the traits `io`
+    /// implements. Frames are read straight into the read buffer of the
+    /// connection, and written with vectored writes. This method is only
+    /// available with the `futures-io` feature.
+    ///
+    /// [`futures-io`]: https://docs.rs/futures-io
+    /// [`handshake`]: #method.handshake
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use futures_io::{AsyncRead, AsyncWrite};
+    /// # use http2::client::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Result<(), http2::Error>
+    /// # {
+    /// let (send_request, connection) = Builder::new()
+    ///     .handshake_futures_io::<_, Bytes>(my_io)
+    ///     .await?;
+    /// // The HTTP/2 handshake has completed, now start polling
+    /// // `connection` and use `send_request` to send requests to the
+    /// // server.
+    /// # Ok(())
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    #[cfg(feature = "futures-io")]
+    pub fn handshake_futures_io<T, B>(
+        &self,
+        io: T,
+    ) -> impl Future<Output = Result<(SendRequest<B>, Connection<FuturesIo<T>, B>), crate::Error>>
+    where
+        T: futures_io::AsyncRead + futures_io::AsyncWrite + Unpin,
+        B: Buf,
+    {
+        self.handshake(FuturesIo(io))
+    }
+
+    /// Creates the state of a connection whose preface was sent, with the
+    /// initial SETTINGS frame buffered.
+    pub(crate) fn bind<B: Buf>(
+        &self,
+        is_write_vectored: bool,
+    ) -> (SendRequest<B>, proto::Connection<Peer, B>) {
+        // Create the codec
+        let mut codec = Framed::new(is_write_vectored);
+
+        if let Some(max) = self.settings.max_frame_size() {
+            codec.set_max_recv_frame_size(max as usize);
+        }
+
+        if let Some(max) = self.settings.max_header_list_size() {
+            codec.set_max_recv_header_list_size(max as usize);
+        }
+
+        if let Some(max) = self.max_continuation_frames {
+            codec.set_max_recv_continuation_frames(max);
+        }
+
+        if let Some(max) = self.max_header_block_size {
+            codec.set_max_recv_header_block_size(max);
+        }
+
+        if let Some(ratio) = self.max_header_decompression_ratio {
+            codec.set_max_recv_header_decoded_ratio(ratio);
+        }
+
+        let mut observer = self.frame_observer.clone();
+
+        if let Some(ref log) = self.frame_log {
+            observer = Some(match observer {
+                Some(observer) => observer.join(log.clone()),
+                None => log.clone(),
+            });
+        }
+
+        let qlog = self.qlog.as_ref().map(|make| make.start(false));
+        if let Some(ref qlog) = qlog {
+            let frames = qlog.frame_observer();
+            observer = Some(match observer {
+                Some(observer) => observer.join(frames),
+                None => frames,
+            });
+        }
+
+        if let Some(observer) = observer {
+            codec.set_frame_observer(observer);
+        }
+
+        #[cfg(feature = "unstable")]
+        if let Some(interceptor) = self.frame_interceptor.clone() {
+            codec.set_frame_interceptor(interceptor);
+        }
+
+        // Send initial settings frame
+        codec
+            .buffer(self.settings.clone().into())
+            .expect("invalid SETTINGS frame");
+
+        let mut inner = proto::Connection::new(
+            codec,
+            proto::Config {
+                next_stream_id: self.stream_id,
+                max_stream_id: self.max_stream_id,
+                initial_max_send_streams: self.initial_max_send_streams,
+                max_send_buffer_size: self.max_send_buffer_size,
+                reset_stream_duration: self.reset_stream_duration,
+                reset_stream_max: self.reset_stream_max,
+                remote_reset_stream_max: self.pending_accept_reset_stream_max,
+                local_error_reset_streams_max: self.local_max_error_reset_streams,
+                settings: self.settings.clone(),
+                headers_pseudo_order: self.headers_pseudo_order,
+                headers_priority: self.headers_priority,
+                priority: self.priority.clone(),
+                expect_continue: self.expect_continue,
+                retain_unprocessed_requests: self.retain_unprocessed_requests,
+                oversized_response_431: false,
+                flood_limits: self.flood_limits.clone(),
+                qlog,
+                idle_timeout: self.idle_timeout,
+                timer: self.timer.clone(),
+                admission: None,
+                max_queued_requests: self.max_queued_requests,
+                request_queue_timeout: self.request_queue_timeout,
+            },
+        );
+        let send_request = SendRequest {
+            inner: inner.streams().clone(),
+            pending: None,
+            queued: VecDeque::new(),
+            max_queued: self
+                .max_queued_requests
+                .and(self.max_queued_requests_per_handle),
+        };
+
+        if let Some(sz) = self.initial_target_connection_window_size {
+            inner.set_target_window_size(sz);
+        }
+
+        (send_request, inner)
+    }
 }
 
 impl Default for Builder {
@@ -1326,7 +2559,7 @@ impl Default for Builder {
 /// ```
 pub async fn handshake<T>(io: T) -> Result<(SendRequest<Bytes>, Connection<T, Bytes>), crate::Error>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Io,
 {
     let builder = Builder::new();
 
@@ -1344,12 +2577,23 @@ where
 
 async fn bind_connection<T>(io: &mut T) -> Result<(), crate::Error>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Io,
 {
     tracing::debug!("binding client connection");
 
-    let msg: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
-    io.write_all(msg).await.map_err(crate::Error::from_io)?;
+    let mut preface = &proto::PREFACE[..];
+    while !preface.is_empty() {
+        let n = crate::poll_fn(|cx| io.poll_write(cx, preface))
+            .await
+            .map_err(crate::Error::from_io)?;
+        if n == 0 {
+            return Err(crate::Error::from_io(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "failed to write connection preface",
+            )));
+        }
+        preface = &preface[n..];
+    }
 
     tracing::debug!("client connection bound");
 
@@ -1358,7 +2602,7 @@ where
 
 impl<T, B> Connection<T, B>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Io,
     B: Buf,
 {
     async fn handshake2(
@@ -1366,50 +2610,8 @@ where
         builder: Builder,
     ) -> Result<(SendRequest<B>, Connection<T, B>), crate::Error> {
         bind_connection(&mut io).await?;
-
-        // Create the codec
-        let mut codec = Codec::new(io);
-
-        if let Some(max) = builder.settings.max_frame_size() {
-            codec.set_max_recv_frame_size(max as usize);
-        }
-
-        if let Some(max) = builder.settings.max_header_list_size() {
-            codec.set_max_recv_header_list_size(max as usize);
-        }
-
-        // Send initial settings frame
-        codec
-            .buffer((builder.settings.clone()).into())
-            .expect("invalid SETTINGS frame");
-
-        let inner = proto::Connection::new(
-            codec,
-            proto::Config {
-                next_stream_id: builder.stream_id,
-                initial_max_send_streams: builder.initial_max_send_streams,
-                max_send_buffer_size: builder.max_send_buffer_size,
-                reset_stream_duration: builder.reset_stream_duration,
-                reset_stream_max: builder.reset_stream_max,
-                remote_reset_stream_max: builder.pending_accept_reset_stream_max,
-                local_error_reset_streams_max: builder.local_max_error_reset_streams,
-                settings: builder.settings.clone(),
-                headers_pseudo_order: builder.headers_pseudo_order,
-                headers_priority: builder.headers_priority,
-                priority: builder.priority,
-            },
-        );
-        let send_request = SendRequest {
-            inner: inner.streams().clone(),
-            pending: None,
-        };
-
-        let mut connection = Connection { inner };
-        if let Some(sz) = builder.initial_target_connection_window_size {
-            connection.set_target_window_size(sz);
-        }
-
-        Ok((send_request, connection))
+        let (send_request, inner) = builder.bind(io.is_write_vectored());
+        Ok((send_request, Connection { io, inner }))
     }
 
     /// Sets the target window size for the whole connection.
@@ -1488,35 +2690,47 @@ where
     pub fn max_concurrent_recv_streams(&self) -> usize {
         self.inner.max_recv_streams()
     }
+
+    /// Returns a snapshot of the connection's statistics.
+    ///
+    /// The snapshot includes the frames and bytes sent and received by frame
+    /// type, the number of streams opened, closed and reset, the connection
+    /// level flow control windows, and the state of the HPACK encoder and
+    /// decoder.
+    pub fn stats(&self) -> ConnectionStats {
+        self.inner.stats()
+    }
 }
 
 impl<T, B> Future for Connection<T, B>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Io,
     B: Buf,
 {
     type Output = Result<(), crate::Error>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.inner.maybe_close_connection_if_no_streams();
-        let had_streams_or_refs = self.inner.has_streams_or_other_references();
-        let result = self.inner.poll(cx).map_err(Into::into);
-        // if we had streams/refs, and don't anymore, wake up one more time to
-        // ensure proper shutdown
-        if result.is_pending()
-            && had_streams_or_refs
-            && !self.inner.has_streams_or_other_references()
-        {
-            tracing::trace!("last stream closed during poll, wake again");
-            cx.waker().wake_by_ref();
+        let me = &mut *self;
+
+        // Read what the server sent first, so that the frames received are
+        // handled before new ones are written.
+        let _ = me.inner.poll_io(&mut me.io, cx);
+        loop {
+            let (result, again) = me.inner.poll_once(cx);
+            if result.is_ready() {
+                return result.map_err(Into::into);
+            }
+
+            if !again {
+                ready!(me.inner.poll_io(&mut me.io, cx));
+            }
         }
-        result
     }
 }
 
 impl<T, B> fmt::Debug for Connection<T, B>
 where
-    T: AsyncRead + AsyncWrite,
+    T: Io,
     T: fmt::Debug,
     B: fmt::Debug + Buf,
 {
@@ -1538,6 +2752,72 @@ impl Future for ResponseFuture {
     }
 }
 
+// ===== impl WebSocketFuture =====
+
+impl Future for WebSocketFuture {
+    type Output = Result<(Response<()>, Upgraded), crate::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (parts, body) = ready!(Pin::new(&mut self.response).poll(cx))?.into_parts();
+        let mut send = self.send.take().expect("polled after complete");
+
+        if !parts.status.is_success() {
+            tracing::debug!("websocket handshake rejected; status={}", parts.status);
+            send.send_reset(Reason::CANCEL);
+            return Poll::Ready(Err(UserError::ExtendedConnectRejected.into()));
+        }
+
+        let upgraded = Upgraded::new(send, body);
+
+        Poll::Ready(Ok((Response::from_parts(parts, ()), upgraded)))
+    }
+}
+
+impl WebSocketFuture {
+    /// Returns the stream ID of the WebSocket stream.
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the stream store has been poisoned.
+    pub fn stream_id(&self) -> crate::StreamId {
+        self.response.stream_id()
+    }
+}
+
+// ===== impl ConnectUdpFuture =====
+
+impl Future for ConnectUdpFuture {
+    type Output = Result<(Response<()>, CapsuleSender, CapsuleReceiver), crate::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (parts, body) = ready!(Pin::new(&mut self.response).poll(cx))?.into_parts();
+        let mut send = self.send.take().expect("polled after complete");
+
+        if !parts.status.is_success() {
+            tracing::debug!("connect-udp rejected; status={}", parts.status);
+            send.send_reset(Reason::CANCEL);
+            return Poll::Ready(Err(UserError::ExtendedConnectRejected.into()));
+        }
+
+        Poll::Ready(Ok((
+            Response::from_parts(parts, ()),
+            CapsuleSender::new(send),
+            CapsuleReceiver::new(body),
+        )))
+    }
+}
+
+impl ConnectUdpFuture {
+    /// Returns the stream ID of the tunnel stream.
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the stream store has been poisoned.
+    pub fn stream_id(&self) -> crate::StreamId {
+        self.response.stream_id()
+    }
+}
+
 impl ResponseFuture {
     /// Returns the stream ID of the response stream.
     ///
@@ -1547,6 +2827,42 @@ impl ResponseFuture {
     pub fn stream_id(&self) -> crate::StreamId {
         crate::StreamId::from_internal(self.inner.stream_id())
     }
+
+    /// Returns a snapshot of the timings and frame counts of this stream.
+    ///
+    /// See [`StreamMetrics`] for details.
+    ///
+    /// [`StreamMetrics`]: ../stats/struct.StreamMetrics.html
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the stream store has been poisoned.
+    pub fn metrics(&self) -> StreamMetrics {
+        self.inner.metrics()
+    }
+
+    /// Get the next informational (1xx) response, such as `100 Continue` or
+    /// `103 Early Hints`, received ahead of the final response.
+    ///
+    /// Returns `None` once the final response has been received. Informational
+    /// responses that are not retrieved before awaiting the final response
+    /// are discarded.
+    pub async fn informational(&mut self) -> Option<Result<Response<()>, crate::Error>> {
+        crate::poll_fn(move |cx| self.poll_informational(cx)).await
+    }
+
+    #[doc(hidden)]
+    pub fn poll_informational(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Response<()>, crate::Error>>> {
+        match ready!(self.inner.poll_informational(cx)) {
+            Some(Ok(response)) => Poll::Ready(Some(Ok(response))),
+            Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
+            None => Poll::Ready(None),
+        }
+    }
+
     /// Returns a stream of PushPromises
     ///
     /// # Panics
@@ -32,6 +32,11 @@ pub enum UserError {
     /// A new connection is needed.
     OverflowedStreamId,
 
+    /// The configured maximum stream ID was reached.
+    ///
+    /// A new connection is needed.
+    ConnectionExhausted,
+
     /// Illegal headers, such as connection-specific headers.
     MalformedHeaders,
 
@@ -49,6 +54,24 @@ pub enum UserError {
 
     /// Tries to send push promise to peer who has disabled server push
     PeerDisabledServerPush,
+
+    /// Tries to use the extended CONNECT protocol when the peer has not
+    /// enabled it.
+    ExtendedConnectProtocolDisabled,
+
+    /// An extended CONNECT request was answered with a non-2xx status.
+    ExtendedConnectRejected,
+
+    /// A request or response is not a valid extended CONNECT handshake for
+    /// the expected protocol.
+    InvalidExtendedConnect,
+
+    /// Tries to send an informational response with a status that is not 1xx,
+    /// or is `101 Switching Protocols`.
+    InvalidInformationalStatus,
+
+    /// The header list is larger than the peer's `SETTINGS_MAX_HEADER_LIST_SIZE`.
+    HeaderListTooLarge,
 }
 
 // ===== impl SendError =====
@@ -97,12 +120,18 @@ impl fmt::Display for UserError {
             Rejected => "rejected",
             ReleaseCapacityTooBig => "release capacity too big",
             OverflowedStreamId => "stream ID overflowed",
+            ConnectionExhausted => "connection exhausted its stream IDs",
             MalformedHeaders => "malformed headers",
             MissingUriSchemeAndAuthority => "request URI missing scheme and authority",
             PollResetAfterSendResponse => "poll_reset after send_response is illegal",
             SendPingWhilePending => "send_ping before received previous pong",
             SendSettingsWhilePending => "sending SETTINGS before received previous ACK",
             PeerDisabledServerPush => "sending PUSH_PROMISE to peer who disabled server push",
+            ExtendedConnectProtocolDisabled => "extended CONNECT protocol not enabled by peer",
+            ExtendedConnectRejected => "extended CONNECT rejected by peer",
+            InvalidExtendedConnect => "invalid extended CONNECT handshake",
+            InvalidInformationalStatus => "invalid informational status code",
+            HeaderListTooLarge => "header list larger than peer's max header list size",
         })
     }
 }
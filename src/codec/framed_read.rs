@@ -4,27 +4,54 @@ use crate::frame::{
 };
 use crate::proto::Error;
 
+use crate::codec::io::Io;
+use crate::codec::stats::CodecStats;
 use crate::hpack;
+use crate::observer::{self, Direction, SharedObserver};
 use crate::tracing;
 
 use futures_core::Stream;
 
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, Bytes, BytesMut};
 
 use std::io;
 
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
-use tokio::io::AsyncRead;
-use tokio_util::codec::FramedRead as InnerFramedRead;
-use tokio_util::codec::{LengthDelimitedCodec, LengthDelimitedCodecError};
+use tokio_util::codec::{Decoder, LengthDelimitedCodec, LengthDelimitedCodecError};
+
+/// Initialize the connection with this amount of read buffer.
+const INITIAL_BUFFER_CAPACITY: usize = 8 * 1_024;
 
 // 16 MB "sane default" taken from golang http2
 const DEFAULT_SETTINGS_MAX_HEADER_LIST_SIZE: usize = 16 << 20;
 
 #[derive(Debug)]
 pub struct FramedRead<T> {
-    inner: InnerFramedRead<T, LengthDelimitedCodec>,
+    inner: T,
+
+    /// Splits the bytes read into frames
+    delimiter: LengthDelimitedCodec,
+
+    /// Bytes read that were not split into frames yet
+    buf: BytesMut,
+
+    /// Whether `buf` may hold a frame that was not split off yet
+    is_readable: bool,
+
+    /// Whether the peer closed its side of the I/O
+    eof: bool,
+
+    /// Whether splitting the bytes read failed
+    has_errored: bool,
+
+    /// Whether all the bytes read were decoded, so more must be read
+    wants_read: bool,
+
+    /// Error reading from the I/O, returned once the bytes read before it
+    /// are decoded
+    error: Option<io::Error>,
 
     // hpack decoder state
     hpack: hpack::Decoder,
@@ -33,7 +60,20 @@ pub struct FramedRead<T> {
 
     max_continuation_frames: usize,
 
+    /// Configured CONTINUATION frame limit, used instead of the one derived
+    /// from the max header list size and max frame size.
+    max_continuation_frames_override: Option<usize>,
+
+    /// Maximum number of undecoded bytes in a single header block.
+    max_header_block_size: Option<usize>,
+
     partial: Option<Partial>,
+
+    /// Connection statistics
+    stats: Arc<CodecStats>,
+
+    /// Called with every frame read
+    observer: Option<SharedObserver>,
 }
 
 /// Partially loaded headers frame
@@ -46,6 +86,12 @@ struct Partial {
     buf: BytesMut,
 
     continuation_frames_count: usize,
+
+    /// Undecoded bytes received so far for this header block
+    header_block_size: usize,
+
+    /// Decoded header list size so far for this header block
+    header_list_limit: hpack::HeaderListLimit,
 }
 
 #[derive(Debug)]
@@ -55,31 +101,121 @@ enum Continuable {
 }
 
 impl<T> FramedRead<T> {
-    pub fn new(inner: InnerFramedRead<T, LengthDelimitedCodec>) -> FramedRead<T> {
+    pub fn new(inner: T, delimiter: LengthDelimitedCodec, stats: Arc<CodecStats>) -> FramedRead<T> {
         let max_header_list_size = DEFAULT_SETTINGS_MAX_HEADER_LIST_SIZE;
         let max_continuation_frames =
-            calc_max_continuation_frames(max_header_list_size, inner.decoder().max_frame_length());
+            calc_max_continuation_frames(max_header_list_size, delimiter.max_frame_length());
+        let hpack = hpack::Decoder::new(DEFAULT_SETTINGS_HEADER_TABLE_SIZE);
+        stats.update_decoder_table(&hpack);
         FramedRead {
             inner,
-            hpack: hpack::Decoder::new(DEFAULT_SETTINGS_HEADER_TABLE_SIZE),
+            delimiter,
+            buf: BytesMut::with_capacity(INITIAL_BUFFER_CAPACITY),
+            is_readable: false,
+            eof: false,
+            has_errored: false,
+            wants_read: true,
+            error: None,
+            hpack,
             max_header_list_size,
             max_continuation_frames,
+            max_continuation_frames_override: None,
+            max_header_block_size: None,
             partial: None,
+            stats,
+            observer: None,
         }
     }
 
     pub fn get_ref(&self) -> &T {
-        self.inner.get_ref()
+        &self.inner
     }
 
     pub fn get_mut(&mut self) -> &mut T {
-        self.inner.get_mut()
+        &mut self.inner
+    }
+
+    /// Passes bytes received from the peer to the codec.
+    pub fn recv(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+        self.is_readable = true;
+        self.wants_read = false;
+    }
+
+    /// Tells the codec that the peer closed its side of the I/O.
+    pub fn recv_eof(&mut self) {
+        self.eof = true;
+        self.is_readable = true;
+        self.wants_read = false;
+    }
+
+    /// Returns true if the codec is waiting for more bytes to decode a
+    /// frame.
+    pub fn wants_read(&self) -> bool {
+        self.wants_read
+    }
+
+    /// Returns the bytes read that were not decoded yet.
+    pub fn read_buf(&mut self) -> &mut BytesMut {
+        &mut self.buf
+    }
+
+    /// Reads more bytes from `io` into the read buffer, returning the number
+    /// of bytes read.
+    pub fn poll_read<I: Io>(
+        &mut self,
+        io: &mut I,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<usize>> {
+        self.buf.reserve(1);
+        let n = ready!(io.poll_read_buf(cx, &mut self.buf))?;
+        if n > 0 {
+            self.is_readable = true;
+            self.eof = false;
+            self.wants_read = false;
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    /// Reads more bytes from `io` if the codec is waiting for them.
+    ///
+    /// Bytes are read until `io` blocks, up to `INITIAL_BUFFER_CAPACITY`, so
+    /// that the end of the I/O is seen along with the frames sent before it.
+    ///
+    /// Returns `Ready` if this made progress. The end of the I/O and errors
+    /// are returned by the codec once the bytes read before them are
+    /// decoded.
+    pub fn poll_io<I: Io>(&mut self, io: &mut I, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.wants_read {
+            return Poll::Pending;
+        }
+
+        let len = self.buf.len();
+        loop {
+            match self.poll_read(io, cx) {
+                Poll::Ready(Ok(0)) => self.recv_eof(),
+                Poll::Ready(Ok(_)) if self.buf.len() - len < INITIAL_BUFFER_CAPACITY => continue,
+                Poll::Ready(Ok(_)) => {}
+                Poll::Ready(Err(e)) => {
+                    self.error = Some(e);
+                    self.wants_read = false;
+                }
+                Poll::Pending if self.buf.len() == len => return Poll::Pending,
+                Poll::Pending => {}
+            }
+            return Poll::Ready(());
+        }
+    }
+
+    /// Returns the counters shared with the write half.
+    pub(crate) fn stats(&self) -> &Arc<CodecStats> {
+        &self.stats
     }
 
     /// Returns the current max frame size setting
     #[inline]
     pub fn max_frame_size(&self) -> usize {
-        self.inner.decoder().max_frame_length()
+        self.delimiter.max_frame_length()
     }
 
     /// Updates the max frame size setting.
@@ -88,9 +224,9 @@ impl<T> FramedRead<T> {
     #[inline]
     pub fn set_max_frame_size(&mut self, val: usize) {
         assert!(DEFAULT_MAX_FRAME_SIZE as usize <= val && val <= MAX_MAX_FRAME_SIZE as usize);
-        self.inner.decoder_mut().set_max_frame_length(val);
+        self.delimiter.set_max_frame_length(val);
         // Update max CONTINUATION frames too, since its based on this
-        self.max_continuation_frames = calc_max_continuation_frames(self.max_header_list_size, val);
+        self.update_max_continuation_frames();
     }
 
     /// Update the max header list size setting.
@@ -98,7 +234,29 @@ impl<T> FramedRead<T> {
     pub fn set_max_header_list_size(&mut self, val: usize) {
         self.max_header_list_size = val;
         // Update max CONTINUATION frames too, since its based on this
-        self.max_continuation_frames = calc_max_continuation_frames(val, self.max_frame_size());
+        self.update_max_continuation_frames();
+    }
+
+    /// Sets the maximum number of CONTINUATION frames in a header block.
+    ///
+    /// This replaces the limit derived from the max header list size and max
+    /// frame size.
+    #[inline]
+    pub fn set_max_continuation_frames(&mut self, val: usize) {
+        self.max_continuation_frames_override = Some(val);
+        self.update_max_continuation_frames();
+    }
+
+    /// Sets the maximum number of undecoded bytes in a header block.
+    #[inline]
+    pub fn set_max_header_block_size(&mut self, val: usize) {
+        self.max_header_block_size = Some(val);
+    }
+
+    fn update_max_continuation_frames(&mut self) {
+        self.max_continuation_frames = self.max_continuation_frames_override.unwrap_or_else(|| {
+            calc_max_continuation_frames(self.max_header_list_size, self.max_frame_size())
+        });
     }
 
     /// Update the header table size setting.
@@ -106,6 +264,19 @@ impl<T> FramedRead<T> {
     pub fn set_header_table_size(&mut self, val: usize) {
         self.hpack.queue_size_update(val);
     }
+
+    /// Sets the max ratio of the decoded header list size to the encoded
+    /// header block size.
+    #[inline]
+    pub fn set_max_header_decoded_ratio(&mut self, val: usize) {
+        self.hpack.set_max_decoded_ratio(Some(val));
+    }
+
+    /// Sets the observer called with every frame read.
+    pub fn set_observer(&mut self, observer: SharedObserver) {
+        self.hpack.set_capture(true);
+        self.observer = Some(observer);
+    }
 }
 
 fn calc_max_continuation_frames(header_max: usize, frame_max: usize) -> usize {
@@ -124,7 +295,9 @@ fn decode_frame(
     hpack: &mut hpack::Decoder,
     max_header_list_size: usize,
     max_continuation_frames: usize,
+    max_header_block_size: Option<usize>,
     partial_inout: &mut Option<Partial>,
+    stats: &CodecStats,
     mut bytes: BytesMut,
 ) -> Result<Option<Frame>, Error> {
     let _span = tracing::trace_span!("FramedRead::decode_frame", offset = bytes.len());
@@ -143,8 +316,28 @@ fn decode_frame(
 
     tracing::trace!(frame.kind = ?kind);
 
+    stats.received_frame(kind, bytes.len());
+
+    // Check for header blocks with too many undecoded bytes
+    macro_rules! check_header_block_size {
+        ($size:expr) => {
+            if let Some(max) = max_header_block_size {
+                if $size > max {
+                    tracing::debug!("header_block_too_large, max = {}", max);
+                    return Err(Error::library_go_away_data(
+                        Reason::ENHANCE_YOUR_CALM,
+                        "header_block_too_large",
+                    ));
+                }
+            }
+        };
+    }
+
     macro_rules! header_block {
         ($frame:ident, $head:ident, $bytes:ident) => ({
+            let header_block_size = $bytes.len() - frame::HEADER_LEN;
+            check_header_block_size!(header_block_size);
+
             // Drop the frame header
             $bytes.advance(frame::HEADER_LEN);
 
@@ -167,7 +360,8 @@ fn decode_frame(
             let is_end_headers = frame.is_end_headers();
 
             // Load the HPACK encoded headers
-            match frame.load_hpack(&mut payload, max_header_list_size, hpack) {
+            let mut header_list_limit = hpack::HeaderListLimit::new(max_header_list_size);
+            match frame.load_hpack(&mut payload, &mut header_list_limit, hpack) {
                 Ok(_) => {},
                 Err(frame::Error::Hpack(hpack::DecoderError::NeedMore(_))) if !is_end_headers => {},
                 Err(frame::Error::MalformedMessage) => {
@@ -182,6 +376,7 @@ fn decode_frame(
             }
 
             if is_end_headers {
+                stats.received_header_block(hpack, &header_list_limit);
                 frame.into()
             } else {
                 tracing::trace!("loaded partial header block");
@@ -190,6 +385,8 @@ fn decode_frame(
                     frame: Continuable::$frame(frame),
                     buf: payload,
                     continuation_frames_count: 0,
+                    header_block_size,
+                    header_list_limit,
                 });
 
                 return Ok(None);
@@ -239,11 +436,12 @@ fn decode_frame(
         Kind::Headers => header_block!(Headers, head, bytes),
         Kind::Reset => {
             let res = frame::Reset::load(head, &bytes[frame::HEADER_LEN..]);
-            res.map_err(|_e| {
+            let frame = res.map_err(|_e| {
                 proto_err!(conn: "failed to load RESET frame; err={:?}", _e);
                 Error::library_go_away(Reason::PROTOCOL_ERROR)
-            })?
-            .into()
+            })?;
+            stats.received_reset(frame.reason());
+            frame.into()
         }
         Kind::GoAway => {
             let res = frame::GoAway::load(&bytes[frame::HEADER_LEN..]);
@@ -310,6 +508,9 @@ fn decode_frame(
                 }
             }
 
+            partial.header_block_size += bytes.len() - frame::HEADER_LEN;
+            check_header_block_size!(partial.header_block_size);
+
             // Extend the buf
             if partial.buf.is_empty() {
                 partial.buf = bytes.split_off(frame::HEADER_LEN);
@@ -338,7 +539,7 @@ fn decode_frame(
 
             match partial
                 .frame
-                .load_hpack(&mut partial.buf, max_header_list_size, hpack)
+                .load_hpack(&mut partial.buf, &mut partial.header_list_limit, hpack)
             {
                 Ok(_) => {}
                 Err(frame::Error::Hpack(hpack::DecoderError::NeedMore(_))) if !is_end_headers => {}
@@ -354,6 +555,7 @@ fn decode_frame(
             }
 
             if is_end_headers {
+                stats.received_header_block(hpack, &partial.header_list_limit);
                 partial.frame.into()
             } else {
                 *partial_inout = Some(partial);
@@ -369,17 +571,60 @@ fn decode_frame(
     Ok(Some(frame))
 }
 
-impl<T> Stream for FramedRead<T>
-where
-    T: AsyncRead + Unpin,
-{
+impl<T> FramedRead<T> {
+    /// Splits the next whole frame off the read buffer.
+    ///
+    /// Returns `Pending` when more bytes must be read.
+    fn poll_next_frame(&mut self) -> Poll<Option<io::Result<BytesMut>>> {
+        if self.has_errored {
+            self.is_readable = false;
+            self.has_errored = false;
+            return Poll::Ready(None);
+        }
+
+        if self.is_readable {
+            let res = if self.eof {
+                self.delimiter.decode_eof(&mut self.buf)
+            } else {
+                self.delimiter.decode(&mut self.buf)
+            };
+
+            match res {
+                Ok(Some(bytes)) => return Poll::Ready(Some(Ok(bytes))),
+                Ok(None) if self.eof => {
+                    self.is_readable = false;
+                    return Poll::Ready(None);
+                }
+                Ok(None) => self.is_readable = false,
+                Err(e) => {
+                    self.has_errored = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+
+        if let Some(e) = self.error.take() {
+            self.has_errored = true;
+            return Poll::Ready(Some(Err(e)));
+        }
+
+        if self.eof {
+            return Poll::Ready(None);
+        }
+
+        self.wants_read = true;
+        Poll::Pending
+    }
+}
+
+impl<T: Unpin> Stream for FramedRead<T> {
     type Item = Result<Frame, Error>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let _span = tracing::trace_span!("FramedRead::poll_next");
         loop {
             tracing::trace!("poll");
-            let bytes = match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+            let bytes = match ready!(self.poll_next_frame()) {
                 Some(Ok(bytes)) => bytes,
                 Some(Err(e)) => return Poll::Ready(Some(Err(map_err(e)))),
                 None => return Poll::Ready(None),
@@ -391,15 +636,54 @@ where
                 max_header_list_size,
                 ref mut partial,
                 max_continuation_frames,
+                max_header_block_size,
+                ref stats,
+                ref observer,
                 ..
             } = *self;
-            if let Some(frame) = decode_frame(
+
+            // The frame is consumed by decoding, so keep what the observer
+            // needs first.
+            let observed = observer.as_ref().map(|observer| {
+                let mut head = [0; frame::HEADER_LEN];
+                head.copy_from_slice(&bytes[..frame::HEADER_LEN]);
+                let raw = if observer.wants_raw_bytes(&head) {
+                    Some(Bytes::copy_from_slice(&bytes))
+                } else {
+                    None
+                };
+                (observer, head, raw)
+            });
+
+            let res = decode_frame(
                 hpack,
                 max_header_list_size,
                 max_continuation_frames,
+                max_header_block_size,
                 partial,
+                stats,
                 bytes,
-            )? {
+            );
+
+            if let Some((observer, head, raw)) = observed {
+                // Headers are only complete once the header block ends.
+                let headers = if partial.is_none() {
+                    hpack
+                        .take_captured()
+                        .filter(|headers| !headers.is_empty())
+                        .map(observer::header_list)
+                } else {
+                    None
+                };
+                observer.observe(
+                    Direction::Received,
+                    &head,
+                    headers.as_deref(),
+                    raw.as_deref(),
+                );
+            }
+
+            if let Some(frame) = res? {
                 tracing::debug!(?frame, "received");
                 return Poll::Ready(Some(Ok(frame)));
             }
@@ -438,12 +722,12 @@ impl Continuable {
     fn load_hpack(
         &mut self,
         src: &mut BytesMut,
-        max_header_list_size: usize,
+        limit: &mut hpack::HeaderListLimit,
         decoder: &mut hpack::Decoder,
     ) -> Result<(), frame::Error> {
         match *self {
-            Continuable::Headers(ref mut h) => h.load_hpack(src, max_header_list_size, decoder),
-            Continuable::PushPromise(ref mut p) => p.load_hpack(src, max_header_list_size, decoder),
+            Continuable::Headers(ref mut h) => h.load_hpack(src, limit, decoder),
+            Continuable::PushPromise(ref mut p) => p.load_hpack(src, limit, decoder),
         }
     }
 }
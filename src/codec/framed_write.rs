@@ -1,15 +1,16 @@
+use crate::codec::io::{poll_write_buf, Io};
+use crate::codec::stats::CodecStats;
 use crate::codec::UserError;
 use crate::codec::UserError::*;
-use crate::frame::{self, Frame, FrameSize};
+use crate::frame::{self, Frame, FrameSize, Kind};
+use crate::observer::{self, Direction, SharedObserver};
 use crate::{hpack, tracing};
 
-use bytes::{Buf, BufMut, BytesMut};
-use std::pin::Pin;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use std::task::{Context, Poll};
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
-use tokio_util::io::poll_write_buf;
 
 use std::io::{self, Cursor};
+use std::sync::Arc;
 
 // A macro to get around a method needing to borrow &mut self
 macro_rules! limited_write_buf {
@@ -20,12 +21,30 @@ macro_rules! limited_write_buf {
 }
 
 #[derive(Debug)]
-pub struct FramedWrite<T, B> {
-    /// Upstream `AsyncWrite`
-    inner: T,
-    final_flush_done: bool,
-
+pub struct FramedWrite<B> {
     encoder: Encoder<B>,
+
+    /// Bytes taken by the caller with `transmit` instead of being written to
+    /// an I/O, if any.
+    transmit: Option<BytesMut>,
+
+    /// Whether bytes were written to the I/O since it was last flushed
+    is_unflushed: bool,
+
+    shutdown: Shutdown,
+
+    /// Error writing to the I/O, returned by the next call to the codec
+    error: Option<io::Error>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shutdown {
+    Open,
+
+    /// The codec is closed, and waits for the I/O to be shut down
+    Requested,
+
+    Done,
 }
 
 #[derive(Debug)]
@@ -52,6 +71,20 @@ struct Encoder<B> {
 
     /// Min buffer required to attempt to write a frame
     min_buffer_capacity: usize,
+
+    /// Connection statistics
+    stats: Arc<CodecStats>,
+
+    /// Called with every frame written
+    observer: Option<SharedObserver>,
+
+    /// Header list of a header block that is continued in CONTINUATION
+    /// frames, reported to the observer with the last one.
+    observed_headers: Option<Vec<hpack::Header>>,
+
+    /// Called with every frame before it is encoded
+    #[cfg(feature = "unstable")]
+    interception: Option<unstable::Interception>,
 }
 
 #[derive(Debug)]
@@ -76,41 +109,51 @@ const CHAIN_THRESHOLD: usize = 256;
 /// fragmented data being sent, and hereby improve the throughput.
 const CHAIN_THRESHOLD_WITHOUT_VECTORED_IO: usize = 1024;
 
-// TODO: Make generic
-impl<T, B> FramedWrite<T, B>
+impl<B> FramedWrite<B>
 where
-    T: AsyncWrite + Unpin,
     B: Buf,
 {
-    pub fn new(inner: T) -> FramedWrite<T, B> {
-        let chain_threshold = if inner.is_write_vectored() {
+    pub fn new(is_write_vectored: bool, stats: Arc<CodecStats>) -> FramedWrite<B> {
+        let hpack = hpack::Encoder::default();
+        stats.update_encoder_table(&hpack);
+
+        let chain_threshold = if is_write_vectored {
             CHAIN_THRESHOLD
         } else {
             CHAIN_THRESHOLD_WITHOUT_VECTORED_IO
         };
         FramedWrite {
-            inner,
-            final_flush_done: false,
             encoder: Encoder {
-                hpack: hpack::Encoder::default(),
+                hpack,
                 buf: Cursor::new(BytesMut::with_capacity(DEFAULT_BUFFER_CAPACITY)),
                 next: None,
                 last_data_frame: None,
                 max_frame_size: frame::DEFAULT_MAX_FRAME_SIZE,
                 chain_threshold,
                 min_buffer_capacity: chain_threshold + frame::HEADER_LEN,
+                stats,
+                observer: None,
+                observed_headers: None,
+                #[cfg(feature = "unstable")]
+                interception: None,
             },
+            transmit: None,
+            is_unflushed: false,
+            shutdown: Shutdown::Open,
+            error: None,
         }
     }
 
     /// Returns `Ready` when `send` is able to accept a frame
     ///
-    /// Calling this function may result in the current contents of the buffer
-    /// to be flushed to `T`.
-    pub fn poll_ready(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+    /// Returns `Pending` while the buffered frames must be written first.
+    pub fn poll_ready(&mut self, _: &mut Context) -> Poll<io::Result<()>> {
+        if let Some(e) = self.error.take() {
+            return Poll::Ready(Err(e));
+        }
+
         if !self.encoder.has_capacity() {
-            // Try flushing
-            ready!(self.flush(cx))?;
+            self.release();
 
             if !self.encoder.has_capacity() {
                 return Poll::Pending;
@@ -129,7 +172,67 @@ where
     }
 
     /// Flush buffered data to the wire
-    pub fn flush(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+    ///
+    /// Returns `Pending` until the buffered frames are written and the I/O
+    /// is flushed.
+    pub fn flush(&mut self, _: &mut Context) -> Poll<io::Result<()>> {
+        if let Some(e) = self.error.take() {
+            return Poll::Ready(Err(e));
+        }
+
+        self.release();
+
+        if self.encoder.has_pending() || self.is_unflushed {
+            return Poll::Pending;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Close the codec
+    pub fn shutdown(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        ready!(self.flush(cx))?;
+
+        if self.transmit.is_some() || self.shutdown == Shutdown::Done {
+            return Poll::Ready(Ok(()));
+        }
+
+        self.shutdown = Shutdown::Requested;
+        Poll::Pending
+    }
+
+    /// Makes the bytes to write wait to be taken with `transmit`, after
+    /// `prefix`, instead of being written to an I/O.
+    pub fn set_transmit(&mut self, prefix: &[u8]) {
+        self.transmit = Some(BytesMut::from(prefix));
+    }
+
+    /// Returns the bytes to send to the peer since the last call, if any.
+    pub fn transmit(&mut self) -> Option<Bytes> {
+        self.write_transmit();
+        self.transmit
+            .as_mut()
+            .filter(|buf| !buf.is_empty())
+            .map(|buf| buf.split().freeze())
+    }
+
+    /// Releases the frames delayed by the interceptor, and moves the
+    /// buffered bytes to be transmitted.
+    fn release(&mut self) {
+        #[cfg(feature = "unstable")]
+        self.encoder.release_delayed();
+
+        self.write_transmit();
+    }
+
+    fn write_transmit(&mut self) {
+        if let Some(ref mut dst) = self.transmit {
+            self.encoder.write_to_buf(dst);
+        }
+    }
+
+    /// Writes the buffered frames to `io`, and flushes it.
+    pub fn poll_write_to<T: Io>(&mut self, io: &mut T, cx: &mut Context) -> Poll<io::Result<()>> {
         let _span = tracing::trace_span!("FramedWrite::flush");
 
         loop {
@@ -138,15 +241,11 @@ where
                     Some(Next::Data(ref mut frame)) => {
                         tracing::trace!(queued_data_frame = true);
                         let mut buf = (&mut self.encoder.buf).chain(frame.payload_mut());
-                        ready!(poll_write_buf(Pin::new(&mut self.inner), cx, &mut buf))?
+                        ready!(poll_write_buf(io, cx, &mut buf))?
                     }
                     _ => {
                         tracing::trace!(queued_data_frame = false);
-                        ready!(poll_write_buf(
-                            Pin::new(&mut self.inner),
-                            cx,
-                            &mut self.encoder.buf
-                        ))?
+                        ready!(poll_write_buf(io, cx, &mut self.encoder.buf))?
                     }
                 };
                 if n == 0 {
@@ -155,6 +254,7 @@ where
                         "failed to write frame to socket",
                     )));
                 }
+                self.is_unflushed = true;
             }
 
             match self.encoder.unset_frame() {
@@ -163,20 +263,37 @@ where
             }
         }
 
-        tracing::trace!("flushing buffer");
-        // Flush the upstream
-        ready!(Pin::new(&mut self.inner).poll_flush(cx))?;
+        if self.is_unflushed {
+            tracing::trace!("flushing buffer");
+            // Flush the upstream
+            ready!(io.poll_flush(cx))?;
+            self.is_unflushed = false;
+        }
 
         Poll::Ready(Ok(()))
     }
 
-    /// Close the codec
-    pub fn shutdown(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
-        if !self.final_flush_done {
-            ready!(self.flush(cx))?;
-            self.final_flush_done = true;
+    /// Writes the buffered frames to `io`, or shuts it down once the codec
+    /// is closed.
+    ///
+    /// Returns `Ready` if this made progress. Errors are returned by the
+    /// next call to the codec.
+    pub fn poll_io<T: Io>(&mut self, io: &mut T, cx: &mut Context) -> Poll<()> {
+        let res = if self.encoder.has_pending() || self.is_unflushed {
+            ready!(self.poll_write_to(io, cx))
+        } else if self.shutdown == Shutdown::Requested {
+            let res = ready!(io.poll_shutdown(cx));
+            self.shutdown = Shutdown::Done;
+            res
+        } else {
+            return Poll::Pending;
+        };
+
+        if let Err(e) = res {
+            self.error = Some(e);
         }
-        Pin::new(&mut self.inner).poll_shutdown(cx)
+
+        Poll::Ready(())
     }
 }
 
@@ -208,6 +325,15 @@ where
                 if let Some(continuation) = frame.encode(&mut buf) {
                     self.next = Some(Next::Continuation(continuation));
                 }
+                self.stats
+                    .sent_frame(Kind::Continuation, self.buf.get_ref().len());
+                let headers = self.observed_headers.take();
+                self.observe(0, headers);
+
+                // Frames that were held back until the header block is done
+                #[cfg(feature = "unstable")]
+                self.encode_queued();
+
                 ControlFlow::Continue
             }
             None => ControlFlow::Break,
@@ -221,6 +347,16 @@ where
 
         tracing::debug!(frame = ?item, "send");
 
+        #[cfg(feature = "unstable")]
+        if self.interception.is_some() {
+            return self.intercept(item);
+        }
+
+        // Used to count the bytes of each encoded frame
+        let start = self.buf.get_ref().len();
+        // Header list encoded by HEADERS and PUSH_PROMISE frames
+        let mut headers = None;
+
         match item {
             Frame::Data(mut v) => {
                 // Ensure that the payload is not greater than the max frame.
@@ -243,8 +379,10 @@ where
 
                     // Save the data frame
                     self.next = Some(Next::Data(v));
+                    self.stats.sent_frame(Kind::Data, frame::HEADER_LEN + len);
                 } else {
                     v.encode_chunk(self.buf.get_mut());
+                    self.stats.sent_frame(Kind::Data, self.frame_len(start));
 
                     // The chunk has been fully encoded, so there is no need to
                     // keep it around
@@ -254,46 +392,141 @@ where
                     self.last_data_frame = Some(v);
                 }
             }
+            item => headers = self.encode_control(item, start),
+        }
+
+        self.observe(start, headers);
+
+        Ok(())
+    }
+
+    /// Encodes a frame that is not DATA, and returns the header list of
+    /// HEADERS and PUSH_PROMISE frames.
+    fn encode_control<T>(&mut self, item: Frame<T>, start: usize) -> Option<Vec<hpack::Header>> {
+        let mut headers = None;
+
+        match item {
+            Frame::Data(_) => unreachable!("DATA frames are encoded by the caller"),
             Frame::Headers(v) => {
+                let header_list_size = v.header_list_size();
+                let encoded_size = self.hpack.encoded_size();
                 let mut buf = limited_write_buf!(self);
                 if let Some(continuation) = v.encode(&mut self.hpack, &mut buf) {
                     self.next = Some(Next::Continuation(continuation));
                 }
+                self.stats.sent_frame(Kind::Headers, self.frame_len(start));
+                self.stats.sent_header_block(
+                    &self.hpack,
+                    header_list_size,
+                    self.hpack.encoded_size() - encoded_size,
+                );
+                headers = self.hpack.take_captured();
             }
             Frame::PushPromise(v) => {
+                let header_list_size = v.header_list_size();
+                let encoded_size = self.hpack.encoded_size();
                 let mut buf = limited_write_buf!(self);
                 if let Some(continuation) = v.encode(&mut self.hpack, &mut buf) {
                     self.next = Some(Next::Continuation(continuation));
                 }
+                self.stats
+                    .sent_frame(Kind::PushPromise, self.frame_len(start));
+                self.stats.sent_header_block(
+                    &self.hpack,
+                    header_list_size,
+                    self.hpack.encoded_size() - encoded_size,
+                );
+                headers = self.hpack.take_captured();
             }
             Frame::Settings(v) => {
                 v.encode(self.buf.get_mut());
                 tracing::trace!(rem = self.buf.remaining(), "encoded settings");
+                self.stats.sent_frame(Kind::Settings, self.frame_len(start));
             }
             Frame::GoAway(v) => {
                 v.encode(self.buf.get_mut());
                 tracing::trace!(rem = self.buf.remaining(), "encoded go_away");
+                self.stats.sent_frame(Kind::GoAway, self.frame_len(start));
             }
             Frame::Ping(v) => {
                 v.encode(self.buf.get_mut());
                 tracing::trace!(rem = self.buf.remaining(), "encoded ping");
+                self.stats.sent_frame(Kind::Ping, self.frame_len(start));
             }
             Frame::WindowUpdate(v) => {
                 v.encode(self.buf.get_mut());
                 tracing::trace!(rem = self.buf.remaining(), "encoded window_update");
+                self.stats
+                    .sent_frame(Kind::WindowUpdate, self.frame_len(start));
             }
 
             Frame::Priority(v) => {
                 v.encode(self.buf.get_mut());
                 tracing::trace!("encoded priority; rem={:?}", self.buf.remaining());
+                self.stats.sent_frame(Kind::Priority, self.frame_len(start));
             }
             Frame::Reset(v) => {
                 v.encode(self.buf.get_mut());
                 tracing::trace!(rem = self.buf.remaining(), "encoded reset");
+                self.stats.sent_frame(Kind::Reset, self.frame_len(start));
+                self.stats.sent_reset(v.reason());
             }
         }
 
-        Ok(())
+        headers
+    }
+
+    /// Calls the observer, if any, with the frame buffered since `start`.
+    fn observe(&mut self, start: usize, headers: Option<Vec<hpack::Header>>) {
+        let observer = match self.observer {
+            Some(ref observer) => observer,
+            None => return,
+        };
+
+        // The header list is reported with the frame that ends the block.
+        let headers = match self.next {
+            Some(Next::Continuation(_)) => {
+                self.observed_headers = headers;
+                None
+            }
+            _ => headers
+                .filter(|headers| !headers.is_empty())
+                .map(observer::header_list),
+        };
+
+        let buf = &self.buf.get_ref()[start..];
+        let raw = if observer.wants_raw_bytes(buf) {
+            let mut raw = buf.to_vec();
+            // Large DATA payloads are written from the frame, not the buffer.
+            if let Some(Next::Data(ref frame)) = self.next {
+                raw.extend_from_slice(frame.payload().chunk());
+            }
+            Some(raw)
+        } else {
+            None
+        };
+
+        observer.observe(Direction::Sent, buf, headers.as_deref(), raw.as_deref());
+    }
+
+    /// Moves the buffered frames to `dst`.
+    fn write_to_buf(&mut self, dst: &mut BytesMut) {
+        loop {
+            dst.put(&mut self.buf);
+            if let Some(Next::Data(ref mut frame)) = self.next {
+                dst.put(frame.payload_mut());
+            }
+
+            match self.unset_frame() {
+                ControlFlow::Continue => (),
+                ControlFlow::Break => break,
+            }
+        }
+    }
+
+    /// Returns true if frames were buffered that were not written yet.
+    fn has_pending(&self) -> bool {
+        !self.is_empty() || self.next.is_some()
     }
 
     fn has_capacity(&self) -> bool {
@@ -314,9 +547,14 @@ impl<B> Encoder<B> {
     fn max_frame_size(&self) -> usize {
         self.max_frame_size as usize
     }
+
+    /// Returns the number of bytes buffered since `start`.
+    fn frame_len(&self, start: usize) -> usize {
+        self.buf.get_ref().len() - start
+    }
 }
 
-impl<T, B> FramedWrite<T, B> {
+impl<B> FramedWrite<B> {
     /// Returns the max frame size that can be sent
     pub fn max_frame_size(&self) -> usize {
         self.encoder.max_frame_size()
@@ -333,36 +571,175 @@ impl<T, B> FramedWrite<T, B> {
         self.encoder.hpack.update_max_size(val);
     }
 
+    /// Sets the observer called with every frame written.
+    pub fn set_observer(&mut self, observer: SharedObserver) {
+        self.encoder.hpack.set_capture(true);
+        self.encoder.observer = Some(observer);
+    }
+
     /// Retrieve the last data frame that has been sent
     pub fn take_last_data_frame(&mut self) -> Option<frame::Data<B>> {
         self.encoder.last_data_frame.take()
     }
-
-    pub fn get_mut(&mut self) -> &mut T {
-        &mut self.inner
-    }
-}
-
-impl<T: AsyncRead + Unpin, B> AsyncRead for FramedWrite<T, B> {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &mut ReadBuf,
-    ) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.inner).poll_read(cx, buf)
-    }
 }
 
 // We never project the Pin to `B`.
-impl<T: Unpin, B> Unpin for FramedWrite<T, B> {}
+impl<B> Unpin for FramedWrite<B> {}
 
 #[cfg(feature = "unstable")]
 mod unstable {
     use super::*;
+    use crate::intercept::{self, SharedInterceptor};
+
+    use std::collections::VecDeque;
+
+    #[derive(Debug)]
+    pub(super) struct Interception {
+        interceptor: SharedInterceptor,
+
+        /// Frames delayed until the next frame is intercepted or the codec is
+        /// flushed.
+        delayed: Vec<Frame>,
+
+        /// Frames waiting for a header block that is continued in
+        /// CONTINUATION frames to be written.
+        queued: VecDeque<Frame>,
+
+        /// Padding that may be added to the next DATA frame.
+        max_padding: usize,
+
+        /// Padding added to DATA frames since the last `take_padding`.
+        padding: usize,
+    }
+
+    impl<B> FramedWrite<B> {
+        /// Sets the interceptor called with every frame before it is written.
+        pub fn set_interceptor(&mut self, interceptor: SharedInterceptor) {
+            self.encoder.interception = Some(Interception {
+                interceptor,
+                delayed: Vec::new(),
+                queued: VecDeque::new(),
+                max_padding: 0,
+                padding: 0,
+            });
+        }
+
+        /// Sets how much padding the interceptor may add to the next DATA
+        /// frame.
+        pub fn set_max_padding(&mut self, max_padding: usize) {
+            if let Some(ref mut interception) = self.encoder.interception {
+                interception.max_padding = max_padding;
+            }
+        }
+
+        /// Returns the padding added to DATA frames since the last call.
+        pub fn take_padding(&mut self) -> usize {
+            self.encoder
+                .interception
+                .as_mut()
+                .map_or(0, |interception| std::mem::take(&mut interception.padding))
+        }
+    }
+
+    impl<B> Encoder<B>
+    where
+        B: Buf,
+    {
+        /// Passes a frame through the interceptor, then encodes the frames
+        /// that are written in its place.
+        pub(super) fn intercept(&mut self, item: Frame<B>) -> Result<(), UserError> {
+            let max_frame_size = self.max_frame_size();
+
+            let frame: Frame = match item {
+                Frame::Data(mut v) => {
+                    let len = v.payload().remaining();
+
+                    if len > max_frame_size {
+                        return Err(PayloadTooBig);
+                    }
+
+                    let mut data =
+                        frame::Data::new(v.stream_id(), v.payload_mut().copy_to_bytes(len));
+                    data.set_end_stream(v.is_end_stream());
+
+                    // The payload has been copied, so the frame is done.
+                    self.last_data_frame = Some(v);
+                    data.into()
+                }
+                item => item.map(|_| unreachable!("DATA frames are copied above")),
+            };
+
+            let interception = self.interception.as_mut().expect("interceptor not set");
+
+            if interception
+                .delayed
+                .iter()
+                .any(|delayed| intercept::must_precede(delayed, &frame))
+            {
+                interception.release_delayed();
+            }
+
+            let max_padding = std::mem::take(&mut interception.max_padding);
+            let (frames, delay) =
+                interception
+                    .interceptor
+                    .intercept(frame, max_frame_size, max_padding);
+            interception.padding += intercept::padding(&frames);
+
+            if delay {
+                interception.delayed.extend(frames);
+            } else {
+                interception.queued.extend(frames);
+                interception.release_delayed();
+            }
+
+            self.encode_queued();
+
+            Ok(())
+        }
+
+        /// Queues the delayed frames to be written.
+        pub(super) fn release_delayed(&mut self) {
+            if let Some(ref mut interception) = self.interception {
+                interception.release_delayed();
+            }
+            self.encode_queued();
+        }
+
+        /// Encodes queued frames until a header block needs CONTINUATION
+        /// frames.
+        pub(super) fn encode_queued(&mut self) {
+            while self.next.is_none() {
+                let frame = match self
+                    .interception
+                    .as_mut()
+                    .and_then(|interception| interception.queued.pop_front())
+                {
+                    Some(frame) => frame,
+                    None => return,
+                };
+
+                let start = self.buf.get_ref().len();
+
+                let headers = match frame {
+                    Frame::Data(mut v) => {
+                        v.encode_chunk(self.buf.get_mut());
+                        self.stats.sent_frame(Kind::Data, self.frame_len(start));
+                        None
+                    }
+                    frame => self.encode_control(frame, start),
+                };
+
+                self.observe(start, headers);
+            }
+        }
+    }
+
+    // ===== impl Interception =====
 
-    impl<T, B> FramedWrite<T, B> {
-        pub fn get_ref(&self) -> &T {
-            &self.inner
+    impl Interception {
+        fn release_delayed(&mut self) {
+            self.queued.extend(self.delayed.drain(..));
         }
     }
 }
@@ -0,0 +1,176 @@
+use bytes::{Buf, BytesMut};
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// The I/O operations the codec reads and writes frames with.
+///
+/// This is implemented once for types implementing the tokio I/O traits, and
+/// once for types implementing the `futures-io` ones, through the
+/// [`FuturesIo`] newtype. The trait is not exported: connections accept any
+/// type implementing either of them.
+pub trait Io: Unpin {
+    /// Reads into the spare capacity of `buf`, returning the number of bytes
+    /// read. Zero means that the peer closed its side of the I/O.
+    fn poll_read_buf(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut BytesMut,
+    ) -> Poll<io::Result<usize>>;
+
+    /// Writes bytes from `buf`, returning how many were written.
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>>;
+
+    /// Writes bytes from `bufs`, in order, returning how many were written.
+    fn poll_write_vectored(
+        &mut self,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>>;
+
+    /// Returns whether `poll_write_vectored` writes more than the first
+    /// buffer.
+    fn is_write_vectored(&self) -> bool;
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+}
+
+/// Writes bytes from `buf`, using vectored writes if the I/O supports them,
+/// and advances it past the bytes written.
+pub(super) fn poll_write_buf<T: Io, B: Buf>(
+    io: &mut T,
+    cx: &mut Context<'_>,
+    buf: &mut B,
+) -> Poll<io::Result<usize>> {
+    const MAX_BUFS: usize = 64;
+
+    if !buf.has_remaining() {
+        return Poll::Ready(Ok(0));
+    }
+
+    let n = if io.is_write_vectored() {
+        let mut slices = [IoSlice::new(&[]); MAX_BUFS];
+        let cnt = buf.chunks_vectored(&mut slices);
+        ready!(io.poll_write_vectored(cx, &slices[..cnt]))?
+    } else {
+        ready!(io.poll_write(cx, buf.chunk()))?
+    };
+
+    buf.advance(n);
+
+    Poll::Ready(Ok(n))
+}
+
+// ===== impl tokio I/O =====
+
+impl<T> Io for T
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read_buf(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut BytesMut,
+    ) -> Poll<io::Result<usize>> {
+        tokio_util::io::poll_read_buf(Pin::new(self), cx, buf)
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(Pin::new(self), cx, buf)
+    }
+
+    fn poll_write_vectored(
+        &mut self,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write_vectored(Pin::new(self), cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        AsyncWrite::is_write_vectored(self)
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(Pin::new(self), cx)
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_shutdown(Pin::new(self), cx)
+    }
+}
+
+// ===== impl FuturesIo =====
+
+/// An I/O type implementing the `futures-io` traits.
+///
+/// This is the `T` parameter of the connections returned by the client and
+/// server `handshake_futures_io` methods.
+#[cfg(feature = "futures-io")]
+#[derive(Debug)]
+pub struct FuturesIo<T>(pub(crate) T);
+
+#[cfg(feature = "futures-io")]
+impl<T> Io for FuturesIo<T>
+where
+    T: futures_io::AsyncRead + futures_io::AsyncWrite + Unpin,
+{
+    fn poll_read_buf(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut BytesMut,
+    ) -> Poll<io::Result<usize>> {
+        use bytes::BufMut;
+
+        if !buf.has_remaining_mut() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let n = {
+            let dst = buf.chunk_mut();
+            // SAFETY: the slice covers the spare capacity of `buf`, which is
+            // only written to by the reader. Its bytes are exposed below,
+            // once the reader reports having written them.
+            let dst = unsafe { std::slice::from_raw_parts_mut(dst.as_mut_ptr(), dst.len()) };
+            let n = ready!(Pin::new(&mut self.0).poll_read(cx, dst))?;
+            assert!(n <= dst.len(), "read more bytes than the buffer holds");
+            n
+        };
+
+        // SAFETY: the reader initialized the first `n` bytes of the spare
+        // capacity.
+        unsafe { buf.advance_mut(n) };
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        futures_io::AsyncWrite::poll_write(Pin::new(&mut self.0), cx, buf)
+    }
+
+    fn poll_write_vectored(
+        &mut self,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        futures_io::AsyncWrite::poll_write_vectored(Pin::new(&mut self.0), cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        // Types without vectored writes fall back to writing the first
+        // non-empty buffer, so at worst the head of a DATA frame and its
+        // payload are written separately.
+        true
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures_io::AsyncWrite::poll_flush(Pin::new(&mut self.0), cx)
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures_io::AsyncWrite::poll_close(Pin::new(&mut self.0), cx)
+    }
+}
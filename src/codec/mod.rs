@@ -1,45 +1,70 @@
 mod error;
 mod framed_read;
 mod framed_write;
+mod io;
+mod stats;
 
 pub use self::error::{SendError, UserError};
+#[cfg(feature = "futures-io")]
+pub use self::io::FuturesIo;
+pub use self::io::Io;
+pub(crate) use self::stats::CodecStats;
 
 use self::framed_read::FramedRead;
 use self::framed_write::FramedWrite;
 
 use crate::frame::{self, Data, Frame};
+#[cfg(feature = "unstable")]
+use crate::intercept::SharedInterceptor;
+#[cfg(feature = "unstable")]
+use crate::observer::FrameObserver;
+use crate::observer::SharedObserver;
 use crate::proto::Error;
 
-use bytes::Buf;
+use bytes::{Buf, Bytes, BytesMut};
 use futures_core::Stream;
 use futures_sink::Sink;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
-use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_util::codec::length_delimited;
 
-use std::io;
+/// Reads and writes frames, without doing any I/O.
+///
+/// Bytes received from the peer are passed to the codec, and the bytes of the
+/// frames it buffers are written by its caller, either to an I/O with
+/// `poll_io` or by taking them with `transmit`.
+#[derive(Debug)]
+pub struct Framed<B> {
+    inner: FramedRead<FramedWrite<B>>,
+}
 
+/// Reads and writes frames on an I/O.
 #[derive(Debug)]
 pub struct Codec<T, B> {
-    inner: FramedRead<FramedWrite<T, B>>,
+    io: T,
+    framed: Framed<B>,
 }
 
-impl<T, B> Codec<T, B>
+// ===== impl Framed =====
+
+impl<B> Framed<B>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
     B: Buf,
 {
-    /// Returns a new `Codec` with the default max frame size
-    #[inline]
-    pub fn new(io: T) -> Self {
-        Self::with_max_recv_frame_size(io, frame::DEFAULT_MAX_FRAME_SIZE as usize)
+    /// Returns a new `Framed` with the default max frame size
+    ///
+    /// Frames are written in fewer parts if the I/O supports vectored writes.
+    pub fn new(is_write_vectored: bool) -> Self {
+        Self::with_max_recv_frame_size(is_write_vectored, frame::DEFAULT_MAX_FRAME_SIZE as usize)
     }
 
-    /// Returns a new `Codec` with the given maximum frame size
-    pub fn with_max_recv_frame_size(io: T, max_frame_size: usize) -> Self {
+    /// Returns a new `Framed` with the given maximum frame size
+    pub fn with_max_recv_frame_size(is_write_vectored: bool, max_frame_size: usize) -> Self {
+        let stats = Arc::new(CodecStats::default());
+
         // Wrap with writer
-        let framed_write = FramedWrite::new(io);
+        let framed_write = FramedWrite::new(is_write_vectored, stats.clone());
 
         // Delimit the frames
         let delimited = length_delimited::Builder::new()
@@ -47,18 +72,18 @@ where
             .length_field_length(3)
             .length_adjustment(9)
             .num_skip(0) // Don't skip the header
-            .new_read(framed_write);
+            .new_codec();
 
-        let mut inner = FramedRead::new(delimited);
+        let mut inner = FramedRead::new(framed_write, delimited, stats);
 
         // Use FramedRead's method since it checks the value is within range.
         inner.set_max_frame_size(max_frame_size);
 
-        Codec { inner }
+        Framed { inner }
     }
 }
 
-impl<T, B> Codec<T, B> {
+impl<B> Framed<B> {
     /// Updates the max received frame size.
     ///
     /// The change takes effect the next time a frame is decoded. In other
@@ -105,15 +130,52 @@ impl<T, B> Codec<T, B> {
         self.inner.set_max_header_list_size(val);
     }
 
-    /// Get a reference to the inner stream.
+    /// Set the max number of CONTINUATION frames that can be received in a
+    /// single header block.
+    pub fn set_max_recv_continuation_frames(&mut self, val: usize) {
+        self.inner.set_max_continuation_frames(val);
+    }
+
+    /// Set the max number of undecoded bytes that can be received in a single
+    /// header block.
+    pub fn set_max_recv_header_block_size(&mut self, val: usize) {
+        self.inner.set_max_header_block_size(val);
+    }
+
+    /// Set the max ratio of the decoded header list size to the encoded header
+    /// block size that can be received.
+    pub fn set_max_recv_header_decoded_ratio(&mut self, val: usize) {
+        self.inner.set_max_header_decoded_ratio(val);
+    }
+
+    /// Sets the observer called with every frame read and written.
+    pub(crate) fn set_frame_observer(&mut self, observer: SharedObserver) {
+        self.framed_write().set_observer(observer.clone());
+        self.inner.set_observer(observer);
+    }
+
+    /// Sets the interceptor called with every frame before it is written.
     #[cfg(feature = "unstable")]
-    pub fn get_ref(&self) -> &T {
-        self.inner.get_ref().get_ref()
+    pub(crate) fn set_frame_interceptor(&mut self, interceptor: SharedInterceptor) {
+        self.framed_write().set_interceptor(interceptor);
     }
 
-    /// Get a mutable reference to the inner stream.
-    pub fn get_mut(&mut self) -> &mut T {
-        self.inner.get_mut().get_mut()
+    /// Sets how much padding the interceptor may add to the next DATA frame.
+    #[cfg(feature = "unstable")]
+    pub(crate) fn set_max_padding(&mut self, max_padding: usize) {
+        self.framed_write().set_max_padding(max_padding);
+    }
+
+    /// Returns the padding the interceptor added to DATA frames since the
+    /// last call.
+    #[cfg(feature = "unstable")]
+    pub(crate) fn take_padding(&mut self) -> usize {
+        self.framed_write().take_padding()
+    }
+
+    /// Returns the counters shared by the read and write halves.
+    pub(crate) fn stats(&self) -> &Arc<CodecStats> {
+        self.inner.stats()
     }
 
     /// Takes the data payload value that was fully written to the socket
@@ -121,18 +183,29 @@ impl<T, B> Codec<T, B> {
         self.framed_write().take_last_data_frame()
     }
 
-    fn framed_write(&mut self) -> &mut FramedWrite<T, B> {
+    /// Passes bytes received from the peer to the codec.
+    ///
+    /// They are decoded the next time the codec is polled for a frame.
+    pub fn recv(&mut self, data: &[u8]) {
+        self.inner.recv(data)
+    }
+
+    /// Tells the codec that the peer closed its side of the I/O.
+    pub fn recv_eof(&mut self) {
+        self.inner.recv_eof()
+    }
+
+    fn framed_write(&mut self) -> &mut FramedWrite<B> {
         self.inner.get_mut()
     }
 }
 
-impl<T, B> Codec<T, B>
+impl<B> Framed<B>
 where
-    T: AsyncWrite + Unpin,
     B: Buf,
 {
     /// Returns `Ready` when the codec can buffer a frame
-    pub fn poll_ready(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+    pub fn poll_ready(&mut self, cx: &mut Context) -> Poll<std::io::Result<()>> {
         self.framed_write().poll_ready(cx)
     }
 
@@ -147,30 +220,240 @@ where
     }
 
     /// Flush buffered data to the wire
-    pub fn flush(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+    pub fn flush(&mut self, cx: &mut Context) -> Poll<std::io::Result<()>> {
         self.framed_write().flush(cx)
     }
 
     /// Shutdown the send half
-    pub fn shutdown(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+    pub fn shutdown(&mut self, cx: &mut Context) -> Poll<std::io::Result<()>> {
         self.framed_write().shutdown(cx)
     }
+
+    /// Makes the bytes to send wait to be taken with `transmit`, after
+    /// `prefix`, instead of being written to an I/O.
+    ///
+    /// Writing then never blocks the codec.
+    pub fn set_transmit(&mut self, prefix: &[u8]) {
+        self.framed_write().set_transmit(prefix)
+    }
+
+    /// Returns the bytes to send to the peer since the last call, if any.
+    pub fn transmit(&mut self) -> Option<Bytes> {
+        self.framed_write().transmit()
+    }
+
+    /// Reads more bytes from `io` if the codec is waiting for them, and
+    /// writes the buffered frames to it.
+    ///
+    /// Returns `Ready` if this made progress, in which case the codec should
+    /// be polled again, and `Pending` once `io` is blocked. I/O errors are
+    /// returned by the next call to the codec.
+    pub fn poll_io<T: Io>(&mut self, io: &mut T, cx: &mut Context) -> Poll<()> {
+        let read = self.inner.poll_io(io, cx);
+        let write = self.framed_write().poll_io(io, cx);
+
+        if write.is_pending() && read.is_pending() {
+            return Poll::Pending;
+        }
+        Poll::Ready(())
+    }
+}
+
+impl<B> Stream for Framed<B> {
+    type Item = Result<Frame, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+// ===== impl Codec =====
+
+impl<T, B> Codec<T, B>
+where
+    T: Io,
+    B: Buf,
+{
+    /// Returns a new `Codec` with the default max frame size
+    #[inline]
+    pub fn new(io: T) -> Self {
+        Self::with_max_recv_frame_size(io, frame::DEFAULT_MAX_FRAME_SIZE as usize)
+    }
+
+    /// Returns a new `Codec` with the given maximum frame size
+    pub fn with_max_recv_frame_size(io: T, max_frame_size: usize) -> Self {
+        let framed = Framed::with_max_recv_frame_size(io.is_write_vectored(), max_frame_size);
+        Codec { io, framed }
+    }
+}
+
+impl<T, B> Codec<T, B> {
+    /// Returns a codec reading and writing the frames of `framed` on `io`.
+    pub(crate) fn from_parts(io: T, framed: Framed<B>) -> Self {
+        Codec { io, framed }
+    }
+
+    /// Returns the I/O and the frame state of the codec.
+    pub(crate) fn into_parts(self) -> (T, Framed<B>) {
+        (self.io, self.framed)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl<T, B> Codec<T, B> {
+    /// Updates the max received frame size.
+    ///
+    /// The change takes effect the next time a frame is decoded.
+    #[inline]
+    pub fn set_max_recv_frame_size(&mut self, val: usize) {
+        self.framed.set_max_recv_frame_size(val)
+    }
+
+    /// Returns the current max received frame size setting.
+    #[inline]
+    pub fn max_recv_frame_size(&self) -> usize {
+        self.framed.max_recv_frame_size()
+    }
+
+    /// Returns the max frame size that can be sent to the peer.
+    pub fn max_send_frame_size(&self) -> usize {
+        self.framed.max_send_frame_size()
+    }
+
+    /// Set the peer's max frame size.
+    pub fn set_max_send_frame_size(&mut self, val: usize) {
+        self.framed.set_max_send_frame_size(val)
+    }
+
+    /// Set the peer's header table size size.
+    pub fn set_send_header_table_size(&mut self, val: usize) {
+        self.framed.set_send_header_table_size(val)
+    }
+
+    /// Set the decoder header table size size.
+    pub fn set_recv_header_table_size(&mut self, val: usize) {
+        self.framed.set_recv_header_table_size(val)
+    }
+
+    /// Set the max header list size that can be received.
+    pub fn set_max_recv_header_list_size(&mut self, val: usize) {
+        self.framed.set_max_recv_header_list_size(val);
+    }
+
+    /// Set the max number of CONTINUATION frames that can be received in a
+    /// single header block.
+    pub fn set_max_recv_continuation_frames(&mut self, val: usize) {
+        self.framed.set_max_recv_continuation_frames(val);
+    }
+
+    /// Set the max number of undecoded bytes that can be received in a single
+    /// header block.
+    pub fn set_max_recv_header_block_size(&mut self, val: usize) {
+        self.framed.set_max_recv_header_block_size(val);
+    }
+
+    /// Set the max ratio of the decoded header list size to the encoded header
+    /// block size that can be received.
+    pub fn set_max_recv_header_decoded_ratio(&mut self, val: usize) {
+        self.framed.set_max_recv_header_decoded_ratio(val);
+    }
+
+    /// Sets an observer that is called with every frame read and written.
+    pub fn set_observer<O: FrameObserver + 'static>(&mut self, observer: O) {
+        self.framed
+            .set_frame_observer(SharedObserver::new(observer));
+    }
+
+    /// Get a reference to the inner stream.
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    /// Get a mutable reference to the inner stream.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+}
+
+impl<T, B> Codec<T, B>
+where
+    T: Io,
+    B: Buf,
+{
+    /// Reads more bytes from the socket, without decoding them, returning
+    /// the number of bytes read.
+    pub fn poll_read(&mut self, cx: &mut Context) -> Poll<std::io::Result<usize>> {
+        self.framed.inner.poll_read(&mut self.io, cx)
+    }
+
+    /// Returns the bytes read from the socket that were not decoded yet.
+    pub fn read_buf(&mut self) -> &mut BytesMut {
+        self.framed.inner.read_buf()
+    }
+
+    /// Returns `Ready` when the codec can buffer a frame
+    pub fn poll_ready(&mut self, cx: &mut Context) -> Poll<std::io::Result<()>> {
+        loop {
+            match self.framed.poll_ready(cx) {
+                Poll::Pending => {
+                    ready!(self.framed.framed_write().poll_write_to(&mut self.io, cx))?;
+                }
+                ready => return ready,
+            }
+        }
+    }
+
+    /// Buffer a frame.
+    ///
+    /// `poll_ready` must be called first to ensure that a frame may be
+    /// accepted.
+    ///
+    /// TODO: Rename this to avoid conflicts with Sink::buffer
+    pub fn buffer(&mut self, item: Frame<B>) -> Result<(), UserError> {
+        self.framed.buffer(item)
+    }
+
+    /// Flush buffered data to the wire
+    pub fn flush(&mut self, cx: &mut Context) -> Poll<std::io::Result<()>> {
+        loop {
+            match self.framed.flush(cx) {
+                Poll::Pending => {
+                    ready!(self.framed.framed_write().poll_write_to(&mut self.io, cx))?;
+                }
+                ready => return ready,
+            }
+        }
+    }
+
+    /// Shutdown the send half
+    pub fn shutdown(&mut self, cx: &mut Context) -> Poll<std::io::Result<()>> {
+        ready!(self.flush(cx))?;
+        self.io.poll_shutdown(cx)
+    }
 }
 
 impl<T, B> Stream for Codec<T, B>
 where
-    T: AsyncRead + Unpin,
+    T: Io,
 {
     type Item = Result<Frame, Error>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.inner).poll_next(cx)
+        let me = &mut *self;
+        loop {
+            match Pin::new(&mut me.framed).poll_next(cx) {
+                Poll::Pending if me.framed.inner.wants_read() => {
+                    ready!(me.framed.inner.poll_io(&mut me.io, cx));
+                }
+                poll => return poll,
+            }
+        }
     }
 }
 
 impl<T, B> Sink<Frame<B>> for Codec<T, B>
 where
-    T: AsyncWrite + Unpin,
+    T: Io,
     B: Buf,
 {
     type Error = SendError;
@@ -181,12 +464,12 @@ where
     }
     /// Returns `Ready` when the codec can buffer a frame
     fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.framed_write().poll_ready(cx).map_err(Into::into)
+        Codec::poll_ready(&mut self, cx).map_err(Into::into)
     }
 
     /// Flush buffered data to the wire
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.framed_write().flush(cx).map_err(Into::into)
+        Codec::flush(&mut self, cx).map_err(Into::into)
     }
 
     fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
@@ -198,7 +481,7 @@ where
 // TODO: remove (or improve) this
 impl<T> From<T> for Codec<T, bytes::Bytes>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Io,
 {
     fn from(src: T) -> Self {
         Self::new(src)
@@ -0,0 +1,173 @@
+use crate::frame::{Kind, Reason};
+use crate::hpack;
+use crate::stats::{FrameCount, FrameStats, HpackStats, ResetCounts};
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Number of frame kinds, including `Kind::Unknown`.
+const NUM_KINDS: usize = Kind::Unknown as usize + 1;
+
+/// Counters updated by the codec as frames are read and written.
+///
+/// These are shared with the streams so that a snapshot can be taken from any
+/// handle to the connection. All counters use relaxed atomics since they are
+/// only ever read as a snapshot.
+#[derive(Debug, Default)]
+pub(crate) struct CodecStats {
+    sent: FrameCounters,
+    received: FrameCounters,
+    encoder: HpackCounters,
+    decoder: HpackCounters,
+}
+
+#[derive(Debug, Default)]
+struct FrameCounters {
+    frames: [AtomicU64; NUM_KINDS],
+    bytes: [AtomicU64; NUM_KINDS],
+    resets: [AtomicU64; ResetCounts::BUCKETS],
+}
+
+#[derive(Debug, Default)]
+struct HpackCounters {
+    header_list_bytes: AtomicU64,
+    encoded_bytes: AtomicU64,
+    table_size: AtomicUsize,
+    max_table_size: AtomicUsize,
+}
+
+// ===== impl CodecStats =====
+
+impl CodecStats {
+    pub fn sent_frame(&self, kind: Kind, len: usize) {
+        self.sent.record(kind, len);
+    }
+
+    pub fn received_frame(&self, kind: Kind, len: usize) {
+        self.received.record(kind, len);
+    }
+
+    pub fn sent_reset(&self, reason: Reason) {
+        self.sent.record_reset(reason);
+    }
+
+    pub fn received_reset(&self, reason: Reason) {
+        self.received.record_reset(reason);
+    }
+
+    /// Records a header list encoded with `encoder`.
+    ///
+    /// `encoded` is the number of bytes the header list was encoded to.
+    pub fn sent_header_block(&self, encoder: &hpack::Encoder, header_list: usize, encoded: u64) {
+        self.encoder.record(header_list as u64, encoded);
+        self.update_encoder_table(encoder);
+    }
+
+    /// Records a header block that was fully decoded with `decoder`.
+    pub fn received_header_block(&self, decoder: &hpack::Decoder, limit: &hpack::HeaderListLimit) {
+        self.decoder
+            .record(limit.decoded_size() as u64, limit.encoded_size() as u64);
+        self.update_decoder_table(decoder);
+    }
+
+    pub fn update_encoder_table(&self, encoder: &hpack::Encoder) {
+        self.encoder
+            .set_table(encoder.table_size(), encoder.max_table_size());
+    }
+
+    pub fn update_decoder_table(&self, decoder: &hpack::Decoder) {
+        self.decoder
+            .set_table(decoder.table_size(), decoder.max_table_size());
+    }
+
+    pub fn sent(&self) -> FrameStats {
+        self.sent.snapshot()
+    }
+
+    pub fn received(&self) -> FrameStats {
+        self.received.snapshot()
+    }
+
+    pub fn sent_resets(&self) -> ResetCounts {
+        self.sent.resets()
+    }
+
+    pub fn received_resets(&self) -> ResetCounts {
+        self.received.resets()
+    }
+
+    pub fn hpack_encoder(&self) -> HpackStats {
+        self.encoder.snapshot()
+    }
+
+    pub fn hpack_decoder(&self) -> HpackStats {
+        self.decoder.snapshot()
+    }
+}
+
+// ===== impl FrameCounters =====
+
+impl FrameCounters {
+    fn record(&self, kind: Kind, len: usize) {
+        let i = kind as usize;
+        self.frames[i].fetch_add(1, Ordering::Relaxed);
+        self.bytes[i].fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    fn record_reset(&self, reason: Reason) {
+        self.resets[ResetCounts::bucket(reason)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn resets(&self) -> ResetCounts {
+        ResetCounts::from_counts(std::array::from_fn(|i| {
+            self.resets[i].load(Ordering::Relaxed)
+        }))
+    }
+
+    fn snapshot(&self) -> FrameStats {
+        let count = |kind: Kind| {
+            let i = kind as usize;
+            FrameCount {
+                frames: self.frames[i].load(Ordering::Relaxed),
+                bytes: self.bytes[i].load(Ordering::Relaxed),
+            }
+        };
+
+        FrameStats {
+            data: count(Kind::Data),
+            headers: count(Kind::Headers),
+            priority: count(Kind::Priority),
+            reset: count(Kind::Reset),
+            settings: count(Kind::Settings),
+            push_promise: count(Kind::PushPromise),
+            ping: count(Kind::Ping),
+            go_away: count(Kind::GoAway),
+            window_update: count(Kind::WindowUpdate),
+            continuation: count(Kind::Continuation),
+            unknown: count(Kind::Unknown),
+        }
+    }
+}
+
+// ===== impl HpackCounters =====
+
+impl HpackCounters {
+    fn record(&self, header_list: u64, encoded: u64) {
+        self.header_list_bytes
+            .fetch_add(header_list, Ordering::Relaxed);
+        self.encoded_bytes.fetch_add(encoded, Ordering::Relaxed);
+    }
+
+    fn set_table(&self, size: usize, max_size: usize) {
+        self.table_size.store(size, Ordering::Relaxed);
+        self.max_table_size.store(max_size, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HpackStats {
+        HpackStats {
+            header_list_bytes: self.header_list_bytes.load(Ordering::Relaxed),
+            encoded_bytes: self.encoded_bytes.load(Ordering::Relaxed),
+            table_size: self.table_size.load(Ordering::Relaxed),
+            max_table_size: self.max_table_size.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -3,6 +3,7 @@ use crate::frame::StreamId;
 use crate::proto::{self, Initiator};
 
 use bytes::Bytes;
+use http::Request;
 use std::{error, fmt, io};
 
 pub use crate::frame::Reason;
@@ -20,6 +21,36 @@ pub use crate::frame::Reason;
 #[derive(Debug)]
 pub struct Error {
     kind: Kind,
+
+    /// Set when the peer did not process the stream that failed.
+    is_unprocessed: bool,
+
+    /// The request of the unprocessed stream, if it was kept.
+    request: Option<Box<Request<()>>>,
+}
+
+/// A kind of control frame flood that a connection can be protected against.
+///
+/// Each limit caps how many frames of a kind the remote peer may send within a
+/// sliding window of time. See `flood_limit` on the client and server
+/// `Builder`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FloodLimit {
+    /// PING frames that are not acknowledgements.
+    Ping,
+
+    /// SETTINGS frames that are not acknowledgements.
+    Settings,
+
+    /// DATA frames with an empty payload and without the END_STREAM flag.
+    EmptyData,
+
+    /// WINDOW_UPDATE frames.
+    WindowUpdate,
+
+    /// PRIORITY frames.
+    Priority,
 }
 
 #[derive(Debug)]
@@ -45,6 +76,21 @@ enum Kind {
 // ===== impl Error =====
 
 impl Error {
+    fn new(kind: Kind) -> Error {
+        Error {
+            kind,
+            is_unprocessed: false,
+            request: None,
+        }
+    }
+
+    /// Marks the error as failing a stream the peer did not process.
+    pub(crate) fn with_unprocessed(mut self, request: Option<Request<()>>) -> Error {
+        self.is_unprocessed = true;
+        self.request = request.map(Box::new);
+        self
+    }
+
     /// If the error was caused by the remote peer, the error reason.
     ///
     /// This is either an error received by the peer or caused by an invalid
@@ -80,9 +126,7 @@ impl Error {
     }
 
     pub(crate) fn from_io(err: io::Error) -> Self {
-        Error {
-            kind: Kind::Io(err),
-        }
+        Error::new(Kind::Io(err))
     }
 
     /// Returns true if the error is from a `GOAWAY`.
@@ -114,31 +158,93 @@ impl Error {
             Kind::GoAway(_, _, Initiator::Library) | Kind::Reset(_, _, Initiator::Library)
         )
     }
+
+    /// Returns the flood limit that closed the connection, if any.
+    ///
+    /// This is set when the connection was closed with a GOAWAY frame using
+    /// `ENHANCE_YOUR_CALM` because the remote peer exceeded one of the limits
+    /// configured with `flood_limit` on the client or server `Builder`.
+    pub fn flood_limit(&self) -> Option<FloodLimit> {
+        match self.kind {
+            Kind::GoAway(ref debug_data, Reason::ENHANCE_YOUR_CALM, Initiator::Library) => {
+                FloodLimit::from_debug_data(debug_data)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the connection was closed because it was idle.
+    ///
+    /// See `idle_timeout` on the client and server `Builder`.
+    pub fn is_idle_timeout(&self) -> bool {
+        matches!(
+            self.kind,
+            Kind::GoAway(ref debug_data, Reason::NO_ERROR, Initiator::Library)
+                if debug_data == proto::IDLE_TIMEOUT
+        )
+    }
+
+    /// Returns true if the connection cannot open more streams, as it used
+    /// up its stream IDs.
+    ///
+    /// A new connection is needed to send more requests. See
+    /// `max_stream_id` on the client `Builder`.
+    pub fn is_connection_exhausted(&self) -> bool {
+        matches!(
+            self.kind,
+            Kind::User(UserError::ConnectionExhausted) | Kind::User(UserError::OverflowedStreamId)
+        )
+    }
+
+    /// Returns true if the peer did not process the request, so that it is
+    /// safe to send it again, possibly on another connection.
+    ///
+    /// This is the case when the stream was reset with `REFUSED_STREAM`, when
+    /// the `last_stream_id` of a received `GOAWAY` frame is lower than the
+    /// identifier of the stream, or when the connection closed before the
+    /// request was sent.
+    ///
+    /// This is only set on errors returned by a client `ResponseFuture`.
+    pub fn is_safe_to_retry(&self) -> bool {
+        self.is_unprocessed
+    }
+
+    /// Takes the request of a stream the peer did not process.
+    ///
+    /// The request has the method, URI, version, and headers passed to
+    /// `SendRequest::send_request`, but none of its extensions other than
+    /// [`Protocol`]. The body is not kept, so any data sent on the
+    /// `SendStream` has to be sent again.
+    ///
+    /// Returns `None` if [`is_safe_to_retry`] returns `false`, if the
+    /// request was already taken, or if the connection was not configured
+    /// with [`client::Builder::retain_unprocessed_requests`].
+    ///
+    /// [`Protocol`]: ext/struct.Protocol.html
+    /// [`is_safe_to_retry`]: #method.is_safe_to_retry
+    /// [`client::Builder::retain_unprocessed_requests`]: client/struct.Builder.html#method.retain_unprocessed_requests
+    pub fn take_request(&mut self) -> Option<Request<()>> {
+        self.request.take().map(|request| *request)
+    }
 }
 
 impl From<proto::Error> for Error {
     fn from(src: proto::Error) -> Error {
         use crate::proto::Error::*;
 
-        Error {
-            kind: match src {
-                Reset(stream_id, reason, initiator) => Kind::Reset(stream_id, reason, initiator),
-                GoAway(debug_data, reason, initiator) => {
-                    Kind::GoAway(debug_data, reason, initiator)
-                }
-                Io(kind, inner) => {
-                    Kind::Io(inner.map_or_else(|| kind.into(), |inner| io::Error::new(kind, inner)))
-                }
-            },
-        }
+        Error::new(match src {
+            Reset(stream_id, reason, initiator) => Kind::Reset(stream_id, reason, initiator),
+            GoAway(debug_data, reason, initiator) => Kind::GoAway(debug_data, reason, initiator),
+            Io(kind, inner) => {
+                Kind::Io(inner.map_or_else(|| kind.into(), |inner| io::Error::new(kind, inner)))
+            }
+        })
     }
 }
 
 impl From<Reason> for Error {
     fn from(src: Reason) -> Error {
-        Error {
-            kind: Kind::Reason(src),
-        }
+        Error::new(Kind::Reason(src))
     }
 }
 
@@ -153,14 +259,16 @@ impl From<SendError> for Error {
 
 impl From<UserError> for Error {
     fn from(src: UserError) -> Error {
-        Error {
-            kind: Kind::User(src),
-        }
+        Error::new(Kind::User(src))
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_idle_timeout() {
+            return fmt.write_str("connection closed after idle timeout");
+        }
+
         let debug_data = match self.kind {
             Kind::Reset(_, reason, Initiator::User) => {
                 return write!(fmt, "stream error sent by user: {}", reason)
@@ -198,6 +306,48 @@ impl fmt::Display for Error {
 
 impl error::Error for Error {}
 
+// ===== impl FloodLimit =====
+
+impl FloodLimit {
+    const ALL: [FloodLimit; 5] = [
+        FloodLimit::Ping,
+        FloodLimit::Settings,
+        FloodLimit::EmptyData,
+        FloodLimit::WindowUpdate,
+        FloodLimit::Priority,
+    ];
+
+    /// The GOAWAY debug data sent when this limit is exceeded.
+    pub(crate) fn debug_data(self) -> &'static str {
+        match self {
+            FloodLimit::Ping => "ping_flood",
+            FloodLimit::Settings => "settings_flood",
+            FloodLimit::EmptyData => "empty_data_flood",
+            FloodLimit::WindowUpdate => "window_update_flood",
+            FloodLimit::Priority => "priority_flood",
+        }
+    }
+
+    fn from_debug_data(debug_data: &[u8]) -> Option<FloodLimit> {
+        FloodLimit::ALL
+            .iter()
+            .copied()
+            .find(|limit| limit.debug_data().as_bytes() == debug_data)
+    }
+}
+
+impl fmt::Display for FloodLimit {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            FloodLimit::Ping => "PING flood",
+            FloodLimit::Settings => "SETTINGS flood",
+            FloodLimit::EmptyData => "empty DATA flood",
+            FloodLimit::WindowUpdate => "WINDOW_UPDATE flood",
+            FloodLimit::Priority => "PRIORITY flood",
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Error;
@@ -8,7 +8,7 @@ use std::fmt;
 /// Data frames convey arbitrary, variable-length sequences of octets associated
 /// with a stream. One or more DATA frames are used, for instance, to carry HTTP
 /// request or response payloads.
-#[derive(Eq, PartialEq)]
+#[derive(Clone, Eq, PartialEq)]
 pub struct Data<T = Bytes> {
     stream_id: StreamId,
     data: T,
@@ -75,6 +75,25 @@ impl<T> Data<T> {
         self.flags.set_padded();
     }
 
+    /// Returns the number of padding bytes that were received with this
+    /// frame, or that are sent with it.
+    #[cfg(feature = "unstable")]
+    pub fn pad_len(&self) -> Option<u8> {
+        self.pad_len
+    }
+
+    /// Sets the `PADDED` flag and the number of padding bytes to send with
+    /// this frame.
+    ///
+    /// Unlike `set_padded`, which leaves it to the payload to start with the
+    /// pad length and end with the padding, the padding is added when the
+    /// frame is encoded.
+    #[cfg(feature = "unstable")]
+    pub fn set_pad_len(&mut self, pad_len: u8) {
+        self.flags.set_padded();
+        self.pad_len = Some(pad_len);
+    }
+
     /// Returns a reference to this frame's payload.
     ///
     /// This does **not** include any padding that might have been originally
@@ -148,12 +167,28 @@ impl<T: Buf> Data<T> {
     ///
     /// Panics if `dst` cannot contain the data frame.
     pub(crate) fn encode_chunk<U: BufMut>(&mut self, dst: &mut U) {
-        let len = self.data.remaining();
+        // The pad length field and the padding count towards the length.
+        let pad_len = self.pad_len.filter(|_| self.flags.is_padded());
+        let len = self.data.remaining() + pad_len.map_or(0, |pad_len| 1 + pad_len as usize);
 
         assert!(dst.remaining_mut() >= len);
 
         self.head().encode(len, dst);
+        if let Some(pad_len) = pad_len {
+            dst.put_u8(pad_len);
+        }
         dst.put(&mut self.data);
+        if let Some(pad_len) = pad_len {
+            dst.put_slice(&[0; 255][..pad_len as usize]);
+        }
+    }
+
+    /// Returns the number of bytes this frame counts against flow control,
+    /// which includes any padding.
+    #[cfg(feature = "unstable")]
+    pub(crate) fn flow_len(&self) -> usize {
+        let padding = self.pad_len.filter(|_| self.flags.is_padded());
+        self.data.remaining() + padding.map_or(0, |pad_len| 1 + pad_len as usize)
     }
 }
 
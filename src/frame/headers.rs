@@ -17,7 +17,7 @@ type EncodeBuf<'a> = bytes::buf::Limit<&'a mut BytesMut>;
 /// Header frame
 ///
 /// This could be either a request or a response.
-#[derive(Eq, PartialEq)]
+#[derive(Clone, Eq, PartialEq)]
 pub struct Headers {
     /// The ID of the stream with which this frame is associated.
     stream_id: StreamId,
@@ -35,7 +35,7 @@ pub struct Headers {
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub struct HeadersFlag(u8);
 
-#[derive(Eq, PartialEq)]
+#[derive(Clone, Eq, PartialEq)]
 pub struct PushPromise {
     /// The ID of the stream with which this frame is associated.
     stream_id: StreamId,
@@ -62,7 +62,7 @@ pub struct Continuation {
 }
 
 // TODO: These fields shouldn't be `pub`
-#[derive(Debug, Default, Eq, PartialEq)]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
 pub struct Pseudo {
     // Request
     pub method: Option<Method>,
@@ -115,7 +115,7 @@ pub struct Iter {
     fields: header::IntoIter<HeaderValue>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct HeaderBlock {
     /// The decoded header fields
     fields: HeaderMap,
@@ -259,10 +259,10 @@ impl Headers {
     pub fn load_hpack(
         &mut self,
         src: &mut BytesMut,
-        max_header_list_size: usize,
+        limit: &mut hpack::HeaderListLimit,
         decoder: &mut hpack::Decoder,
     ) -> Result<(), Error> {
-        self.header_block.load(src, max_header_list_size, decoder)
+        self.header_block.load(src, limit, decoder)
     }
 
     pub fn stream_id(&self) -> StreamId {
@@ -289,6 +289,12 @@ impl Headers {
         self.header_block.is_over_size
     }
 
+    /// Returns the size of the header list, as defined by
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE`.
+    pub fn header_list_size(&self) -> usize {
+        self.header_block.calculate_header_list_size()
+    }
+
     pub fn into_parts(self) -> (Pseudo, HeaderMap) {
         (self.header_block.pseudo, self.header_block.fields)
     }
@@ -515,10 +521,10 @@ impl PushPromise {
     pub fn load_hpack(
         &mut self,
         src: &mut BytesMut,
-        max_header_list_size: usize,
+        limit: &mut hpack::HeaderListLimit,
         decoder: &mut hpack::Decoder,
     ) -> Result<(), Error> {
-        self.header_block.load(src, max_header_list_size, decoder)
+        self.header_block.load(src, limit, decoder)
     }
 
     pub fn stream_id(&self) -> StreamId {
@@ -541,6 +547,12 @@ impl PushPromise {
         self.header_block.is_over_size
     }
 
+    /// Returns the size of the promised request's header list, as defined by
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE`.
+    pub fn header_list_size(&self) -> usize {
+        self.header_block.calculate_header_list_size()
+    }
+
     pub fn encode(
         self,
         encoder: &mut hpack::Encoder,
@@ -911,12 +923,11 @@ impl HeaderBlock {
     fn load(
         &mut self,
         src: &mut BytesMut,
-        max_header_list_size: usize,
+        limit: &mut hpack::HeaderListLimit,
         decoder: &mut hpack::Decoder,
     ) -> Result<(), Error> {
         let mut reg = !self.fields.is_empty();
         let mut malformed = false;
-        let mut headers_size = self.calculate_header_list_size();
 
         macro_rules! set_pseudo {
             ($field:ident, $val:expr) => {{
@@ -927,15 +938,7 @@ impl HeaderBlock {
                     tracing::trace!("load_hpack; header malformed -- repeated pseudo");
                     malformed = true;
                 } else {
-                    let __val = $val;
-                    headers_size +=
-                        decoded_header_size(stringify!($field).len() + 1, __val.as_str().len());
-                    if headers_size < max_header_list_size {
-                        self.pseudo.$field = Some(__val);
-                    } else if !self.is_over_size {
-                        tracing::trace!("load_hpack; header list size over max");
-                        self.is_over_size = true;
-                    }
+                    self.pseudo.$field = Some($val);
                 }
             }};
         }
@@ -946,7 +949,10 @@ impl HeaderBlock {
         // the headers. A malformed header frame is a stream level error, but
         // the hpack state is connection level. In order to maintain correct
         // state for other streams, the hpack decoding process must complete.
-        let res = decoder.decode(&mut cursor, |header| {
+        //
+        // Headers past the limit are not passed to the closure, and the frame
+        // is marked as over size once decoding is done.
+        let res = decoder.decode_limited(&mut cursor, limit, |header| {
             use crate::hpack::Header::*;
 
             match header {
@@ -971,15 +977,8 @@ impl HeaderBlock {
                     } else {
                         reg = true;
 
-                        headers_size += decoded_header_size(name.as_str().len(), value.len());
-                        if headers_size < max_header_list_size {
-                            self.field_size +=
-                                decoded_header_size(name.as_str().len(), value.len());
-                            self.fields.append(name, value);
-                        } else if !self.is_over_size {
-                            tracing::trace!("load_hpack; header list size over max");
-                            self.is_over_size = true;
-                        }
+                        self.field_size += decoded_header_size(name.as_str().len(), value.len());
+                        self.fields.append(name, value);
                     }
                 }
                 Authority(v) => set_pseudo!(authority, v),
@@ -991,6 +990,8 @@ impl HeaderBlock {
             }
         });
 
+        self.is_over_size = limit.is_over_size();
+
         if let Err(e) = res {
             tracing::trace!("hpack decoding error; err={:?}", e);
             return Err(e.into());
@@ -1041,6 +1042,7 @@ impl HeaderBlock {
             + pseudo_size!(status)
             + pseudo_size!(authority)
             + pseudo_size!(path)
+            + pseudo_size!(protocol)
             + self.field_size
     }
 }
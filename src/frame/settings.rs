@@ -283,6 +283,14 @@ impl Settings {
         });
     }
 
+    /// Returns the identifier and value of each setting, in the order they
+    /// are encoded.
+    pub fn to_pairs(&self) -> Vec<(u16, u32)> {
+        let mut pairs = Vec::new();
+        self.for_each(|setting| pairs.push(setting.to_pair()));
+        pairs
+    }
+
     fn for_each<F: FnMut(Setting)>(&self, mut f: F) {
         use self::Setting::*;
 
@@ -414,9 +422,17 @@ impl Setting {
     }
 
     fn encode(&self, dst: &mut BytesMut) {
+        let (kind, val) = self.to_pair();
+
+        dst.put_u16(kind);
+        dst.put_u32(val);
+    }
+
+    /// Returns the setting id and value, as defined in section 6.5.2.
+    fn to_pair(&self) -> (u16, u32) {
         use self::Setting::*;
 
-        let (kind, val) = match *self {
+        match *self {
             HeaderTableSize(v) => (1, v),
             EnablePush(v) => (2, v),
             MaxConcurrentStreams(v) => (3, v),
@@ -425,10 +441,7 @@ impl Setting {
             MaxHeaderListSize(v) => (6, v),
             EnableConnectProtocol(v) => (8, v),
             UnknownSetting9(v) => (9, v),
-        };
-
-        dst.put_u16(kind);
-        dst.put_u32(val);
+        }
     }
 }
 
@@ -19,6 +19,23 @@ pub struct Decoder {
     last_max_update: usize,
     table: Table,
     buffer: BytesMut,
+    // Max ratio of decoded header list size to encoded header block size
+    max_decoded_ratio: Option<usize>,
+    // Decoded headers kept for observers, if enabled
+    captured: Option<Vec<Header>>,
+}
+
+/// Limits the size of a header list decoded over one or more calls to
+/// `Decoder::decode_limited`.
+///
+/// A header block may be decoded in pieces as CONTINUATION frames arrive, so
+/// the sizes seen so far are kept here between calls.
+#[derive(Debug)]
+pub struct HeaderListLimit {
+    max_size: usize,
+    encoded_size: usize,
+    decoded_size: usize,
+    is_over_size: bool,
 }
 
 /// Represents all errors that can be encountered while performing the decoding
@@ -37,6 +54,11 @@ pub enum DecoderError {
     NeedMore(NeedMore),
 }
 
+/// Decoded header lists smaller than this are never checked against the max
+/// decoded ratio, since small but well compressed header blocks can have a
+/// large ratio.
+const MIN_RATIO_CHECKED_SIZE: usize = 16 * 1024;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum NeedMore {
     UnexpectedEndOfStream,
@@ -158,9 +180,40 @@ impl Decoder {
             last_max_update: size,
             table: Table::new(size),
             buffer: BytesMut::with_capacity(4096),
+            max_decoded_ratio: None,
+            captured: None,
         }
     }
 
+    /// Sets the max ratio of the decoded header list size to the encoded
+    /// header block size.
+    pub fn set_max_decoded_ratio(&mut self, ratio: Option<usize>) {
+        self.max_decoded_ratio = ratio;
+    }
+
+    /// Sets whether decoded headers are kept until taken with `take_captured`.
+    ///
+    /// Headers dropped for being over a limit are not kept, so capturing is
+    /// bounded by the same limits as decoding.
+    pub fn set_capture(&mut self, enabled: bool) {
+        self.captured = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Takes the headers decoded since the last call, if capturing.
+    pub fn take_captured(&mut self) -> Option<Vec<Header>> {
+        self.captured.as_mut().map(std::mem::take)
+    }
+
+    /// Returns the size of the dynamic table.
+    pub fn table_size(&self) -> usize {
+        self.table.size()
+    }
+
+    /// Returns the max size of the dynamic table.
+    pub fn max_table_size(&self) -> usize {
+        self.table.max_size
+    }
+
     /// Queues a potential size update
     #[allow(dead_code)]
     pub fn queue_size_update(&mut self, size: usize) {
@@ -173,9 +226,24 @@ impl Decoder {
     }
 
     /// Decodes the headers found in the given buffer.
-    pub fn decode<F>(
+    #[cfg(any(test, fuzzing))]
+    pub fn decode<F>(&mut self, src: &mut Cursor<&mut BytesMut>, f: F) -> Result<(), DecoderError>
+    where
+        F: FnMut(Header),
+    {
+        self.decode_limited(src, &mut HeaderListLimit::new(usize::MAX), f)
+    }
+
+    /// Decodes the headers found in the given buffer, up to a limit.
+    ///
+    /// Once the decoded header list goes over `limit`, or over the max decoded
+    /// ratio, no more headers are passed to `f`. The rest of the buffer is
+    /// still decoded, since the dynamic table must be kept in sync with the
+    /// peer's encoder, but the decoded headers are dropped.
+    pub fn decode_limited<F>(
         &mut self,
         src: &mut Cursor<&mut BytesMut>,
+        limit: &mut HeaderListLimit,
         mut f: F,
     ) -> Result<(), DecoderError>
     where
@@ -183,6 +251,18 @@ impl Decoder {
     {
         use self::Representation::*;
 
+        macro_rules! emit {
+            ($entry:expr, $encoded:expr) => {{
+                let entry = $entry;
+                if limit.record($encoded, entry.len(), self.max_decoded_ratio) {
+                    if let Some(captured) = self.captured.as_mut() {
+                        captured.push(entry.clone());
+                    }
+                    f(entry);
+                }
+            }};
+        }
+
         let mut can_resize = true;
 
         if let Some(size) = self.max_size_update.take() {
@@ -194,19 +274,21 @@ impl Decoder {
         tracing::trace!("decode");
 
         while let Some(ty) = peek_u8(src) {
+            let rem = src.remaining();
+
             // At this point we are always at the beginning of the next block
             // within the HPACK data. The type of the block can always be
             // determined from the first byte.
             match Representation::load(ty)? {
                 Indexed => {
-                    tracing::trace!(rem = src.remaining(), kind = %"Indexed");
+                    tracing::trace!(rem, kind = %"Indexed");
                     can_resize = false;
                     let entry = self.decode_indexed(src)?;
                     consume(src);
-                    f(entry);
+                    emit!(entry, rem - src.remaining());
                 }
                 LiteralWithIndexing => {
-                    tracing::trace!(rem = src.remaining(), kind = %"LiteralWithIndexing");
+                    tracing::trace!(rem, kind = %"LiteralWithIndexing");
                     can_resize = false;
                     let entry = self.decode_literal(src, true)?;
 
@@ -214,27 +296,27 @@ impl Decoder {
                     self.table.insert(entry.clone());
                     consume(src);
 
-                    f(entry);
+                    emit!(entry, rem - src.remaining());
                 }
                 LiteralWithoutIndexing => {
-                    tracing::trace!(rem = src.remaining(), kind = %"LiteralWithoutIndexing");
+                    tracing::trace!(rem, kind = %"LiteralWithoutIndexing");
                     can_resize = false;
                     let entry = self.decode_literal(src, false)?;
                     consume(src);
-                    f(entry);
+                    emit!(entry, rem - src.remaining());
                 }
                 LiteralNeverIndexed => {
-                    tracing::trace!(rem = src.remaining(), kind = %"LiteralNeverIndexed");
+                    tracing::trace!(rem, kind = %"LiteralNeverIndexed");
                     can_resize = false;
                     let entry = self.decode_literal(src, false)?;
                     consume(src);
 
                     // TODO: Track that this should never be indexed
 
-                    f(entry);
+                    emit!(entry, rem - src.remaining());
                 }
                 SizeUpdate => {
-                    tracing::trace!(rem = src.remaining(), kind = %"SizeUpdate");
+                    tracing::trace!(rem, kind = %"SizeUpdate");
                     if !can_resize {
                         return Err(DecoderError::InvalidMaxDynamicSize);
                     }
@@ -242,6 +324,7 @@ impl Decoder {
                     // Handle the dynamic table size update
                     self.process_size_update(src)?;
                     consume(src);
+                    limit.encoded_size += rem - src.remaining();
                 }
             }
         }
@@ -482,6 +565,63 @@ fn consume(buf: &mut Cursor<&mut BytesMut>) {
     take(buf, 0);
 }
 
+// ===== impl HeaderListLimit =====
+
+impl HeaderListLimit {
+    pub fn new(max_size: usize) -> HeaderListLimit {
+        HeaderListLimit {
+            max_size,
+            encoded_size: 0,
+            decoded_size: 0,
+            is_over_size: false,
+        }
+    }
+
+    /// Returns true if the decoded header list went over a limit.
+    pub fn is_over_size(&self) -> bool {
+        self.is_over_size
+    }
+
+    /// Returns the number of HPACK-encoded bytes decoded so far.
+    pub fn encoded_size(&self) -> usize {
+        self.encoded_size
+    }
+
+    /// Returns the size of the header list decoded so far, including headers
+    /// that were dropped.
+    pub fn decoded_size(&self) -> usize {
+        self.decoded_size
+    }
+
+    /// Records a decoded header, returning `false` if it must be dropped.
+    fn record(&mut self, encoded: usize, decoded: usize, max_ratio: Option<usize>) -> bool {
+        self.encoded_size += encoded;
+        self.decoded_size += decoded;
+
+        if self.is_over_size {
+            return false;
+        }
+
+        if self.decoded_size >= self.max_size {
+            tracing::trace!("decode; header list size over max");
+            self.is_over_size = true;
+        } else if let Some(ratio) = max_ratio {
+            if self.decoded_size > MIN_RATIO_CHECKED_SIZE
+                && self.decoded_size > ratio.saturating_mul(self.encoded_size)
+            {
+                tracing::trace!(
+                    encoded = self.encoded_size,
+                    decoded = self.decoded_size,
+                    "decode; header list size over max decoded ratio"
+                );
+                self.is_over_size = true;
+            }
+        }
+
+        !self.is_over_size
+    }
+}
+
 // ===== impl Table =====
 
 impl Table {
@@ -493,7 +633,6 @@ impl Table {
         }
     }
 
-    #[cfg(any(feature = "tracing", test))]
     fn size(&self) -> usize {
         self.size
     }
@@ -885,6 +1024,57 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_decode_limited_drops_headers_but_updates_table() {
+        let mut de = Decoder::new(4096);
+
+        let mut buf = BytesMut::new();
+        // foo: bar, indexed, then referenced twice
+        buf.extend([0b01000000, 3]);
+        buf.extend(b"foo");
+        buf.extend([3]);
+        buf.extend(b"bar");
+        buf.extend([0x80 | 62, 0x80 | 62]);
+        // baz: qux, indexed
+        buf.extend([0b01000000, 3]);
+        buf.extend(b"baz");
+        buf.extend([3]);
+        buf.extend(b"qux");
+
+        // Each header is 38 bytes, so only the first two fit.
+        let mut limit = HeaderListLimit::new(100);
+        let mut res = vec![];
+        de.decode_limited(&mut Cursor::new(&mut buf), &mut limit, |h| res.push(h))
+            .unwrap();
+
+        assert_eq!(res.len(), 2);
+        assert!(limit.is_over_size());
+        assert_eq!(de.table.size(), 76);
+    }
+
+    #[test]
+    fn test_decode_limited_does_not_capture_dropped_headers() {
+        let mut de = Decoder::new(0);
+        de.set_capture(true);
+
+        let mut buf = BytesMut::new();
+        for _ in 0..4 {
+            // foo: bar, not indexed
+            buf.extend([0, 3]);
+            buf.extend(b"foo");
+            buf.extend([3]);
+            buf.extend(b"bar");
+        }
+
+        // Each header is 38 bytes, so only the first two fit.
+        let mut limit = HeaderListLimit::new(100);
+        de.decode_limited(&mut Cursor::new(&mut buf), &mut limit, |_| {})
+            .unwrap();
+
+        assert!(limit.is_over_size());
+        assert_eq!(de.take_captured().unwrap().len(), 2);
+    }
+
     fn huff_encode(src: &[u8]) -> BytesMut {
         let mut buf = BytesMut::new();
         huffman::encode(src, &mut buf);
@@ -9,6 +9,10 @@ use http::header::{HeaderName, HeaderValue};
 pub struct Encoder {
     table: Table,
     size_update: Option<SizeUpdate>,
+    // Total number of bytes encoded
+    encoded_size: u64,
+    // Encoded headers kept for observers, if enabled
+    captured: Option<Vec<Header>>,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -22,9 +26,37 @@ impl Encoder {
         Encoder {
             table: Table::new(max_size, capacity),
             size_update: None,
+            encoded_size: 0,
+            captured: None,
         }
     }
 
+    /// Returns the total number of bytes encoded so far.
+    pub fn encoded_size(&self) -> u64 {
+        self.encoded_size
+    }
+
+    /// Returns the size of the dynamic table.
+    pub fn table_size(&self) -> usize {
+        self.table.size()
+    }
+
+    /// Returns the max size of the dynamic table.
+    pub fn max_table_size(&self) -> usize {
+        self.table.max_size()
+    }
+
+    /// Sets whether encoded headers are kept until taken with
+    /// `take_captured`.
+    pub fn set_capture(&mut self, enabled: bool) {
+        self.captured = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Takes the headers encoded since the last call, if capturing.
+    pub fn take_captured(&mut self) -> Option<Vec<Header>> {
+        self.captured.as_mut().map(std::mem::take)
+    }
+
     /// Queues a max size update.
     ///
     /// The next call to `encode` will include a dynamic size update frame.
@@ -65,6 +97,8 @@ impl Encoder {
     {
         let _span = tracing::trace_span!("hpack::encode");
 
+        let start = dst.len();
+
         self.encode_size_updates(dst);
 
         let mut last_index = None;
@@ -74,6 +108,10 @@ impl Encoder {
                 // The header has an associated name. In which case, try to
                 // index it in the table.
                 Ok(header) => {
+                    if let Some(captured) = self.captured.as_mut() {
+                        captured.push(header.clone());
+                    }
+
                     let index = self.table.index(header);
                     self.encode_header(&index, dst);
 
@@ -84,6 +122,16 @@ impl Encoder {
                 // which case, we skip table lookup and just use the same index
                 // as the previous entry.
                 Err(value) => {
+                    if let Some(captured) = self.captured.as_mut() {
+                        if let Some(Header::Field { name, .. }) = captured.last() {
+                            let name = name.clone();
+                            captured.push(Header::Field {
+                                name,
+                                value: value.clone(),
+                            });
+                        }
+                    }
+
                     self.encode_header_without_name(
                         last_index.as_ref().unwrap_or_else(|| {
                             panic!("encoding header without name, but no previous index to use for name");
@@ -94,6 +142,8 @@ impl Encoder {
                 }
             }
         }
+
+        self.encoded_size += (dst.len() - start) as u64;
     }
 
     fn encode_size_updates(&mut self, dst: &mut BytesMut) {
@@ -105,6 +105,11 @@ impl Table {
         self.max_size
     }
 
+    /// Returns the table size
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
     /// Gets the header stored in the table
     pub fn resolve<'a>(&'a self, index: &'a Index) -> &'a Header {
         use self::Index::*;
@@ -627,11 +632,6 @@ impl Table {
     pub fn len(&self) -> usize {
         self.slots.len()
     }
-
-    /// Returns the table size
-    pub fn size(&self) -> usize {
-        self.size
-    }
 }
 
 impl Index {
@@ -0,0 +1,350 @@
+//! Rewriting the frames written to a connection.
+//!
+//! A [`FrameInterceptor`] is configured with the `frame_interceptor` method on
+//! the client or server `Builder`. It is called with every frame right before
+//! it is encoded, and decides what is written in its place: the frame itself,
+//! a modified copy, several frames, nothing at all, or the frame at a later
+//! point. This is intended for conformance and emulation testing, and is only
+//! available with the `unstable` feature.
+//!
+//! The connection has already updated its stream states by the time a frame is
+//! intercepted, so the frames written instead must have the same effect on the
+//! peer's view of the connection. The following is allowed:
+//!
+//! - Splitting a DATA frame into several DATA frames on the same stream, or
+//!   adding padding, as long as the payload bytes and the END_STREAM flag,
+//!   which must be on the last frame, are preserved. Padding is charged to
+//!   the send windows of the stream and the connection, and is rejected if
+//!   the windows are too small for it.
+//! - Changing the header fields of a HEADERS or PUSH_PROMISE frame, as long as
+//!   the stream identifiers and the END_STREAM flag are preserved.
+//! - Changing the reason of a RST_STREAM frame, and the reason and debug data
+//!   of a GOAWAY frame.
+//! - Dropping PRIORITY frames, and inserting PRIORITY frames, or PING frames
+//!   whose payload the connection does not use itself, anywhere.
+//!
+//! Anything else, such as dropping a SETTINGS frame or ending a stream early,
+//! is rejected: the original frame is written unchanged, and
+//! [`FrameInterceptor::on_rejected`] is called.
+//!
+//! # Examples
+//!
+//! ```
+//! use http2::frame::Frame;
+//! use http2::intercept::{FrameInterceptor, Intercept};
+//!
+//! #[derive(Debug)]
+//! struct PadData;
+//!
+//! impl FrameInterceptor for PadData {
+//!     fn intercept(&self, frame: Frame) -> Intercept {
+//!         match frame {
+//!             Frame::Data(mut data) => {
+//!                 data.set_pad_len(16);
+//!                 Intercept::Send(data.into())
+//!             }
+//!             frame => Intercept::Send(frame),
+//!         }
+//!     }
+//! }
+//!
+//! let mut builder = http2::client::Builder::new();
+//! builder.frame_interceptor(PadData);
+//! ```
+//!
+//! [`FrameInterceptor`]: trait.FrameInterceptor.html
+//! [`FrameInterceptor::on_rejected`]: trait.FrameInterceptor.html#method.on_rejected
+
+use crate::frame::{self, Frame, Ping};
+use crate::tracing;
+
+use bytes::BytesMut;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// Called with every frame before it is written to a connection.
+///
+/// Interceptors are called from the task driving the connection, so they
+/// should return quickly.
+pub trait FrameInterceptor: Send + Sync {
+    /// Returns what to write in place of `frame`.
+    fn intercept(&self, frame: Frame) -> Intercept;
+
+    /// Called when the result of [`intercept`] would make the connection
+    /// inconsistent. `frame` is written unchanged instead.
+    ///
+    /// [`intercept`]: #tymethod.intercept
+    fn on_rejected(&self, frame: &Frame, error: &InterceptError) {
+        let _ = (frame, error);
+    }
+}
+
+impl<T: FrameInterceptor + ?Sized> FrameInterceptor for Arc<T> {
+    fn intercept(&self, frame: Frame) -> Intercept {
+        (**self).intercept(frame)
+    }
+
+    fn on_rejected(&self, frame: &Frame, error: &InterceptError) {
+        (**self).on_rejected(frame, error)
+    }
+}
+
+/// What to write in place of an intercepted frame.
+#[derive(Debug)]
+pub enum Intercept {
+    /// Write this frame, which may be a modified copy of the intercepted one.
+    Send(Frame),
+
+    /// Write these frames, in order.
+    Replace(Vec<Frame>),
+
+    /// Write nothing.
+    Drop,
+
+    /// Write this frame after the next intercepted frame, or when the
+    /// connection is flushed, whichever comes first.
+    ///
+    /// A delayed frame is never written after a later frame on the same
+    /// stream, and a delayed HEADERS or PUSH_PROMISE frame is never written
+    /// after a later one.
+    Delay(Frame),
+}
+
+/// The result of [`FrameInterceptor::intercept`] was rejected.
+///
+/// [`FrameInterceptor::intercept`]: trait.FrameInterceptor.html#tymethod.intercept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterceptError {
+    msg: &'static str,
+}
+
+/// A shared `FrameInterceptor` that can be stored in builders and the codec.
+#[derive(Clone)]
+pub(crate) struct SharedInterceptor(Arc<dyn FrameInterceptor>);
+
+// ===== impl InterceptError =====
+
+impl InterceptError {
+    fn new(msg: &'static str) -> InterceptError {
+        InterceptError { msg }
+    }
+}
+
+impl fmt::Display for InterceptError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.msg)
+    }
+}
+
+impl Error for InterceptError {}
+
+// ===== impl SharedInterceptor =====
+
+impl SharedInterceptor {
+    pub fn new<T: FrameInterceptor + 'static>(interceptor: T) -> SharedInterceptor {
+        SharedInterceptor(Arc::new(interceptor))
+    }
+
+    /// Calls the interceptor with `frame`, and checks the frames it returns.
+    ///
+    /// Returns the frames to write, and whether they are delayed. At most
+    /// `max_padding` bytes of padding may be added to a DATA frame.
+    pub fn intercept(
+        &self,
+        frame: Frame,
+        max_frame_size: usize,
+        max_padding: usize,
+    ) -> (Vec<Frame>, bool) {
+        let original = frame.clone();
+
+        let (frames, delay) = match self.0.intercept(frame) {
+            Intercept::Send(frame) => (vec![frame], false),
+            Intercept::Replace(frames) => (frames, false),
+            Intercept::Drop => (vec![], false),
+            Intercept::Delay(frame) => (vec![frame], true),
+        };
+
+        match check(&original, &frames, max_frame_size, max_padding) {
+            Ok(()) => (frames, delay),
+            Err(error) => {
+                tracing::warn!(frame = ?original, %error, "intercepted frame rejected");
+                self.0.on_rejected(&original, &error);
+                (vec![original], false)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SharedInterceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedInterceptor").finish()
+    }
+}
+
+/// Returns whether a delayed frame must still be written before `frame`.
+///
+/// Frames on the same stream are never reordered, and neither are frames that
+/// may open streams, since stream identifiers must be used in order.
+pub(crate) fn must_precede(delayed: &Frame, frame: &Frame) -> bool {
+    let id = stream_id(frame);
+    if !id.is_zero() && id == stream_id(delayed) {
+        return true;
+    }
+
+    opens_stream(delayed) && opens_stream(frame)
+}
+
+fn opens_stream(frame: &Frame) -> bool {
+    matches!(*frame, Frame::Headers(_) | Frame::PushPromise(_))
+}
+
+/// Returns the stream a frame is sent on, or zero for connection frames.
+fn stream_id(frame: &Frame) -> frame::StreamId {
+    match *frame {
+        Frame::Data(ref v) => v.stream_id(),
+        Frame::Headers(ref v) => v.stream_id(),
+        Frame::Priority(ref v) => v.stream_id(),
+        Frame::PushPromise(ref v) => v.stream_id(),
+        Frame::Reset(ref v) => v.stream_id(),
+        Frame::WindowUpdate(ref v) => v.stream_id(),
+        Frame::Settings(_) | Frame::Ping(_) | Frame::GoAway(_) => frame::StreamId::ZERO,
+    }
+}
+
+/// Returns the bytes of padding added to the DATA frames in `frames`.
+pub(crate) fn padding(frames: &[Frame]) -> usize {
+    frames
+        .iter()
+        .map(|frame| match *frame {
+            Frame::Data(ref v) => v.flow_len() - v.payload().len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Checks that writing `frames` in place of `original` has the same effect on
+/// the peer's view of the stream states.
+fn check(
+    original: &Frame,
+    frames: &[Frame],
+    max_frame_size: usize,
+    max_padding: usize,
+) -> Result<(), InterceptError> {
+    for frame in frames {
+        if let Frame::Data(ref v) = *frame {
+            if v.flow_len() > max_frame_size {
+                return Err(InterceptError::new(
+                    "DATA frame larger than the peer's SETTINGS_MAX_FRAME_SIZE",
+                ));
+            }
+        }
+    }
+
+    if padding(frames) > max_padding {
+        return Err(InterceptError::new(
+            "DATA padding larger than the stream and connection send windows",
+        ));
+    }
+
+    // Frames that are not replacements for `original` must be harmless.
+    let extra = |frame: &Frame| match *frame {
+        Frame::Priority(_) => true,
+        Frame::Ping(ref v) => {
+            !v.is_ack() && *v.payload() != Ping::SHUTDOWN && *v.payload() != Ping::USER
+        }
+        _ => false,
+    };
+
+    match *original {
+        Frame::Data(ref original) => {
+            let mut payload = BytesMut::new();
+            let mut is_end_stream = false;
+
+            for frame in frames.iter().filter(|frame| !extra(frame)) {
+                match *frame {
+                    Frame::Data(ref v) if v.stream_id() == original.stream_id() => {
+                        if is_end_stream {
+                            return Err(InterceptError::new("DATA frame after END_STREAM"));
+                        }
+                        payload.extend_from_slice(v.payload());
+                        is_end_stream = v.is_end_stream();
+                    }
+                    _ => {
+                        return Err(InterceptError::new(
+                            "DATA frame replaced with a frame that is not DATA on the same stream",
+                        ));
+                    }
+                }
+            }
+
+            if payload != original.payload() {
+                return Err(InterceptError::new("DATA payload changed"));
+            }
+
+            if is_end_stream != original.is_end_stream() {
+                return Err(InterceptError::new("END_STREAM flag changed"));
+            }
+
+            Ok(())
+        }
+        Frame::Priority(_) => {
+            if frames.iter().all(extra) {
+                Ok(())
+            } else {
+                Err(InterceptError::new(
+                    "PRIORITY frame replaced with a frame that is not PRIORITY or PING",
+                ))
+            }
+        }
+        _ => {
+            let mut replacements = frames
+                .iter()
+                .filter(|frame| is_replacement(original, frame));
+
+            if replacements.next().is_none() {
+                return Err(InterceptError::new(
+                    "frame dropped or changed in a way that affects the connection state",
+                ));
+            }
+
+            if replacements.next().is_some() {
+                return Err(InterceptError::new("frame written more than once"));
+            }
+
+            let others = frames
+                .iter()
+                .filter(|frame| !is_replacement(original, frame));
+            for frame in others {
+                if !extra(frame) {
+                    return Err(InterceptError::new(
+                        "inserted a frame that affects the connection state",
+                    ));
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Returns whether `frame` has the same effect as `original`, a frame that is
+/// neither DATA nor PRIORITY.
+fn is_replacement(original: &Frame, frame: &Frame) -> bool {
+    match (original, frame) {
+        (Frame::Headers(a), Frame::Headers(b)) => {
+            a.stream_id() == b.stream_id()
+                && a.is_end_stream() == b.is_end_stream()
+                && a.is_informational() == b.is_informational()
+        }
+        (Frame::PushPromise(a), Frame::PushPromise(b)) => {
+            a.stream_id() == b.stream_id() && a.promised_id() == b.promised_id()
+        }
+        (Frame::Reset(a), Frame::Reset(b)) => a.stream_id() == b.stream_id(),
+        (Frame::GoAway(a), Frame::GoAway(b)) => a.last_stream_id() == b.last_stream_id(),
+        (Frame::Settings(a), Frame::Settings(b)) => a == b,
+        (Frame::Ping(a), Frame::Ping(b)) => a == b,
+        (Frame::WindowUpdate(a), Frame::WindowUpdate(b)) => a == b,
+        _ => false,
+    }
+}
@@ -126,17 +126,27 @@ mod frame;
 #[allow(missing_docs)]
 pub mod frame;
 
+pub mod capsule;
 pub mod client;
 pub mod ext;
+#[cfg(feature = "unstable")]
+pub mod intercept;
+pub mod observer;
+mod qlog;
+pub mod sans_io;
 pub mod server;
 mod share;
+pub mod stats;
+pub mod timer;
+mod upgraded;
 
 #[cfg(fuzzing)]
 #[cfg_attr(feature = "unstable", allow(missing_docs))]
 pub mod fuzz_bridge;
 
-pub use crate::error::{Error, Reason};
+pub use crate::error::{Error, FloodLimit, Reason};
 pub use crate::share::{FlowControl, Ping, PingPong, Pong, RecvStream, SendStream, StreamId};
+pub use crate::upgraded::Upgraded;
 
 #[cfg(feature = "unstable")]
 pub use codec::{Codec, SendError, UserError};
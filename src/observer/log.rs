@@ -0,0 +1,326 @@
+use super::{Direction, FrameObserver, FrameType, ObservedFrame};
+use crate::frame::{self, GoAway, Head, Ping, Reason, Reset, StreamDependency, WindowUpdate};
+
+use std::fmt::{self, Write as _};
+use std::io;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Indentation of the lines following the first line of a frame.
+const INDENT: &str = "          ";
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+/// A [`FrameObserver`] that writes a human-readable line for every frame, in
+/// the format of `nghttp -v`.
+///
+/// Each frame is written as a line with the time since the first frame was
+/// logged, the direction, the frame type and the frame header, followed by
+/// the names of the flags that are set and the decoded payload of the frame.
+/// The payload of DATA frames is not written.
+///
+/// ```text
+/// [  0.000] send SETTINGS frame <length=18, flags=0x00, stream_id=0>
+///           (niv=3)
+///           [SETTINGS_ENABLE_PUSH(0x02):0]
+///           [SETTINGS_INITIAL_WINDOW_SIZE(0x04):2097152]
+///           [SETTINGS_MAX_FRAME_SIZE(0x05):16384]
+/// [  0.012] recv SETTINGS frame <length=0, flags=0x01, stream_id=0>
+///           ; ACK
+///           (niv=0)
+/// [  0.012] send HEADERS frame <length=30, flags=0x05, stream_id=1>
+///           ; END_STREAM | END_HEADERS
+///           (padlen=0)
+///           :method: GET
+///           :scheme: https
+///           :authority: example.com
+///           :path: /
+/// ```
+///
+/// Errors returned by the writer are ignored.
+///
+/// [`FrameObserver`]: trait.FrameObserver.html
+pub struct FrameLog<W> {
+    inner: Mutex<Inner<W>>,
+}
+
+struct Inner<W> {
+    writer: W,
+
+    /// When the first frame was logged
+    start: Option<Instant>,
+}
+
+// ===== impl FrameLog =====
+
+impl<W: io::Write> FrameLog<W> {
+    /// Returns a new `FrameLog` that writes to `writer`.
+    pub fn new(writer: W) -> FrameLog<W> {
+        FrameLog {
+            inner: Mutex::new(Inner {
+                writer,
+                start: None,
+            }),
+        }
+    }
+}
+
+impl<W: io::Write + Send> FrameObserver for FrameLog<W> {
+    fn on_frame(&self, frame: &ObservedFrame<'_>) {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now - *inner.start.get_or_insert(now);
+
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "[{:3}.{:03}] ",
+            elapsed.as_secs(),
+            elapsed.subsec_millis()
+        );
+        let _ = format_frame(frame, &mut out);
+
+        let _ = inner.writer.write_all(out.as_bytes());
+        let _ = inner.writer.flush();
+    }
+
+    fn wants_raw_bytes(&self) -> bool {
+        true
+    }
+}
+
+impl<W> fmt::Debug for FrameLog<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FrameLog").finish()
+    }
+}
+
+/// Writes a frame, without the timestamp.
+fn format_frame(frame: &ObservedFrame<'_>, out: &mut String) -> fmt::Result {
+    let direction = match frame.direction() {
+        Direction::Received => "recv",
+        Direction::Sent => "send",
+    };
+
+    let name = match frame.frame_type() {
+        FrameType::Data => "DATA",
+        FrameType::Headers => "HEADERS",
+        FrameType::Priority => "PRIORITY",
+        FrameType::Reset => "RST_STREAM",
+        FrameType::Settings => "SETTINGS",
+        FrameType::PushPromise => "PUSH_PROMISE",
+        FrameType::Ping => "PING",
+        FrameType::GoAway => "GOAWAY",
+        FrameType::WindowUpdate => "WINDOW_UPDATE",
+        FrameType::Continuation => "CONTINUATION",
+        FrameType::Unknown(_) => "UNKNOWN",
+    };
+
+    writeln!(
+        out,
+        "{} {} frame <length={}, flags={:#04x}, stream_id={}>",
+        direction,
+        name,
+        frame.len(),
+        frame.flags(),
+        u32::from(frame.stream_id()),
+    )?;
+
+    let flags = flag_names(frame.frame_type(), frame.flags());
+    if !flags.is_empty() {
+        writeln!(out, "{}; {}", INDENT, flags.join(" | "))?;
+    }
+
+    if let Some(raw) = frame.raw() {
+        if raw.len() >= frame::HEADER_LEN {
+            let head = Head::parse(raw);
+            format_payload(frame.frame_type(), head, &raw[frame::HEADER_LEN..], out)?;
+        }
+    }
+
+    if let Some(headers) = frame.headers() {
+        for (name, value) in headers {
+            writeln!(
+                out,
+                "{}{}: {}",
+                INDENT,
+                String::from_utf8_lossy(name),
+                String::from_utf8_lossy(value)
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn flag_names(frame_type: FrameType, flags: u8) -> Vec<&'static str> {
+    let known: &[(u8, &str)] = match frame_type {
+        FrameType::Data => &[(END_STREAM, "END_STREAM"), (PADDED, "PADDED")],
+        FrameType::Headers => &[
+            (END_STREAM, "END_STREAM"),
+            (END_HEADERS, "END_HEADERS"),
+            (PADDED, "PADDED"),
+            (PRIORITY, "PRIORITY"),
+        ],
+        FrameType::Settings | FrameType::Ping => &[(ACK, "ACK")],
+        FrameType::PushPromise => &[(END_HEADERS, "END_HEADERS"), (PADDED, "PADDED")],
+        FrameType::Continuation => &[(END_HEADERS, "END_HEADERS")],
+        _ => &[],
+    };
+
+    known
+        .iter()
+        .filter(|&&(flag, _)| flags & flag == flag)
+        .map(|&(_, name)| name)
+        .collect()
+}
+
+/// Writes the decoded payload of a frame, other than its header list.
+///
+/// Frames that cannot be decoded are skipped; they are reported by the
+/// connection.
+fn format_payload(
+    frame_type: FrameType,
+    head: Head,
+    payload: &[u8],
+    out: &mut String,
+) -> fmt::Result {
+    let padded = head.flag() & PADDED == PADDED;
+    let pad_len = match payload.first() {
+        Some(&pad_len) if padded => pad_len,
+        _ => 0,
+    };
+    // The payload that follows the pad length field
+    let rest = if padded {
+        &payload[1.min(payload.len())..]
+    } else {
+        payload
+    };
+
+    match frame_type {
+        FrameType::Data if padded => writeln!(out, "{}(padlen={})", INDENT, pad_len),
+        FrameType::Headers => {
+            write!(out, "{}(padlen={}", INDENT, pad_len)?;
+            if head.flag() & PRIORITY == PRIORITY && rest.len() >= 5 {
+                if let Ok(dependency) = StreamDependency::load(&rest[..5]) {
+                    write_dependency(&dependency, ", ", out)?;
+                }
+            }
+            writeln!(out, ")")
+        }
+        FrameType::Priority => match StreamDependency::load(payload) {
+            Ok(dependency) => {
+                write!(out, "{}(", INDENT)?;
+                write_dependency(&dependency, "", out)?;
+                writeln!(out, ")")
+            }
+            Err(_) => Ok(()),
+        },
+        FrameType::PushPromise if rest.len() >= 4 => {
+            let (promised_id, _) = frame::StreamId::parse(&rest[..4]);
+            writeln!(
+                out,
+                "{}(padlen={}, promised_stream_id={})",
+                INDENT,
+                pad_len,
+                u32::from(promised_id)
+            )
+        }
+        FrameType::Settings if payload.len() % 6 == 0 => {
+            writeln!(out, "{}(niv={})", INDENT, payload.len() / 6)?;
+            for raw in payload.chunks(6) {
+                let id = u16::from(raw[0]) << 8 | u16::from(raw[1]);
+                let value = u32::from_be_bytes([raw[2], raw[3], raw[4], raw[5]]);
+                writeln!(
+                    out,
+                    "{}[{}({:#04x}):{}]",
+                    INDENT,
+                    setting_name(id),
+                    id,
+                    value
+                )?;
+            }
+            Ok(())
+        }
+        FrameType::Ping => match Ping::load(head, payload) {
+            Ok(ping) => {
+                write!(out, "{}(opaque_data=", INDENT)?;
+                for byte in ping.payload() {
+                    write!(out, "{:02x}", byte)?;
+                }
+                writeln!(out, ")")
+            }
+            Err(_) => Ok(()),
+        },
+        FrameType::GoAway => match GoAway::load(payload) {
+            Ok(go_away) => writeln!(
+                out,
+                "{}(last_stream_id={}, error_code={}, opaque_data({})=[{}])",
+                INDENT,
+                u32::from(go_away.last_stream_id()),
+                ErrorCode(go_away.reason()),
+                go_away.debug_data().len(),
+                String::from_utf8_lossy(go_away.debug_data())
+            ),
+            Err(_) => Ok(()),
+        },
+        FrameType::Reset => match Reset::load(head, payload) {
+            Ok(reset) => writeln!(out, "{}(error_code={})", INDENT, ErrorCode(reset.reason())),
+            Err(_) => Ok(()),
+        },
+        FrameType::WindowUpdate => match WindowUpdate::load(head, payload) {
+            Ok(window_update) => writeln!(
+                out,
+                "{}(window_size_increment={})",
+                INDENT,
+                window_update.size_increment()
+            ),
+            Err(_) => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn write_dependency(dependency: &StreamDependency, prefix: &str, out: &mut String) -> fmt::Result {
+    // The weight is sent as one less than its value.
+    write!(
+        out,
+        "{}dep_stream_id={}, weight={}, exclusive={}",
+        prefix,
+        u32::from(dependency.dependency_id()),
+        u16::from(dependency.weight()) + 1,
+        u8::from(dependency.is_exclusive())
+    )
+}
+
+pub(crate) fn setting_name(id: u16) -> &'static str {
+    match id {
+        1 => "SETTINGS_HEADER_TABLE_SIZE",
+        2 => "SETTINGS_ENABLE_PUSH",
+        3 => "SETTINGS_MAX_CONCURRENT_STREAMS",
+        4 => "SETTINGS_INITIAL_WINDOW_SIZE",
+        5 => "SETTINGS_MAX_FRAME_SIZE",
+        6 => "SETTINGS_MAX_HEADER_LIST_SIZE",
+        8 => "SETTINGS_ENABLE_CONNECT_PROTOCOL",
+        9 => "SETTINGS_NO_RFC7540_PRIORITIES",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Formats a reason like `PROTOCOL_ERROR(0x01)`.
+struct ErrorCode(Reason);
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = u32::from(self.0);
+        if code <= u32::from(Reason::HTTP_1_1_REQUIRED) {
+            write!(f, "{:?}({:#04x})", self.0, code)
+        } else {
+            write!(f, "UNKNOWN({:#04x})", code)
+        }
+    }
+}
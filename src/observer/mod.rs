@@ -0,0 +1,315 @@
+//! Observing the frames read from and written to a connection.
+//!
+//! A [`FrameObserver`] is configured with the `frame_observer` method on the
+//! client or server `Builder`. It is called for every frame the connection
+//! reads or writes, in the order they appear on the wire, which is useful
+//! when debugging or when verifying the exact frames sent to a peer.
+//!
+//! # Examples
+//!
+//! ```
+//! use http2::observer::{FrameObserver, ObservedFrame};
+//!
+//! #[derive(Debug)]
+//! struct PrintFrames;
+//!
+//! impl FrameObserver for PrintFrames {
+//!     fn on_frame(&self, frame: &ObservedFrame<'_>) {
+//!         println!(
+//!             "{:?} {:?} stream={:?} len={} flags={:#04x}",
+//!             frame.direction(),
+//!             frame.frame_type(),
+//!             frame.stream_id(),
+//!             frame.len(),
+//!             frame.flags(),
+//!         );
+//!     }
+//! }
+//!
+//! let mut builder = http2::client::Builder::new();
+//! builder.frame_observer(PrintFrames);
+//! ```
+//!
+//! A [`FrameLog`] is a built-in observer that writes a human-readable line for
+//! every frame, like `nghttp -v` does. It is configured with the `frame_log`
+//! method on the client or server `Builder`.
+//!
+//! [`FrameObserver`]: trait.FrameObserver.html
+//! [`FrameLog`]: struct.FrameLog.html
+
+mod log;
+
+pub(crate) use self::log::setting_name;
+pub use self::log::FrameLog;
+
+use crate::frame::{self, Kind, StreamId};
+use crate::hpack;
+
+use bytes::Bytes;
+use std::fmt;
+use std::sync::Arc;
+
+/// Called for every frame read from or written to a connection.
+///
+/// Observers are called from the task driving the connection, so they should
+/// return quickly.
+pub trait FrameObserver: Send + Sync {
+    /// Called with each frame read from or written to the connection.
+    fn on_frame(&self, frame: &ObservedFrame<'_>);
+
+    /// Returns whether [`ObservedFrame::raw`] should be populated.
+    ///
+    /// Copying the raw bytes of every frame is expensive, so this is `false`
+    /// by default.
+    ///
+    /// [`ObservedFrame::raw`]: struct.ObservedFrame.html#method.raw
+    fn wants_raw_bytes(&self) -> bool {
+        false
+    }
+
+    /// Returns whether [`ObservedFrame::raw`] should be populated for DATA
+    /// frames.
+    ///
+    /// DATA payloads make up most of the bytes of a connection, so observers
+    /// that only decode the other frames can skip copying them. This is the
+    /// value of [`wants_raw_bytes`] by default.
+    ///
+    /// [`ObservedFrame::raw`]: struct.ObservedFrame.html#method.raw
+    /// [`wants_raw_bytes`]: #method.wants_raw_bytes
+    fn wants_raw_data(&self) -> bool {
+        self.wants_raw_bytes()
+    }
+}
+
+impl<T: FrameObserver + ?Sized> FrameObserver for Arc<T> {
+    fn on_frame(&self, frame: &ObservedFrame<'_>) {
+        (**self).on_frame(frame)
+    }
+
+    fn wants_raw_bytes(&self) -> bool {
+        (**self).wants_raw_bytes()
+    }
+
+    fn wants_raw_data(&self) -> bool {
+        (**self).wants_raw_data()
+    }
+}
+
+/// Whether a frame was read or written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The frame was read from the connection.
+    Received,
+
+    /// The frame was written to the connection.
+    Sent,
+}
+
+/// The type of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FrameType {
+    /// DATA
+    Data,
+    /// HEADERS
+    Headers,
+    /// PRIORITY
+    Priority,
+    /// RST_STREAM
+    Reset,
+    /// SETTINGS
+    Settings,
+    /// PUSH_PROMISE
+    PushPromise,
+    /// PING
+    Ping,
+    /// GOAWAY
+    GoAway,
+    /// WINDOW_UPDATE
+    WindowUpdate,
+    /// CONTINUATION
+    Continuation,
+    /// A frame type this library does not know about.
+    Unknown(u8),
+}
+
+/// A frame read from or written to a connection.
+#[derive(Debug)]
+pub struct ObservedFrame<'a> {
+    direction: Direction,
+    frame_type: FrameType,
+    flags: u8,
+    stream_id: StreamId,
+    len: usize,
+    headers: Option<&'a [(Bytes, Bytes)]>,
+    raw: Option<&'a [u8]>,
+}
+
+/// A shared `FrameObserver` that can be stored in builders and the codec.
+#[derive(Clone)]
+pub(crate) struct SharedObserver(Arc<dyn FrameObserver>);
+
+/// Calls two observers with every frame.
+struct Join(SharedObserver, SharedObserver);
+
+// ===== impl FrameType =====
+
+impl FrameType {
+    fn from_byte(src: u8) -> FrameType {
+        match Kind::new(src) {
+            Kind::Data => FrameType::Data,
+            Kind::Headers => FrameType::Headers,
+            Kind::Priority => FrameType::Priority,
+            Kind::Reset => FrameType::Reset,
+            Kind::Settings => FrameType::Settings,
+            Kind::PushPromise => FrameType::PushPromise,
+            Kind::Ping => FrameType::Ping,
+            Kind::GoAway => FrameType::GoAway,
+            Kind::WindowUpdate => FrameType::WindowUpdate,
+            Kind::Continuation => FrameType::Continuation,
+            Kind::Unknown => FrameType::Unknown(src),
+        }
+    }
+}
+
+// ===== impl ObservedFrame =====
+
+impl<'a> ObservedFrame<'a> {
+    /// Returns whether the frame was read or written.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Returns the frame type.
+    pub fn frame_type(&self) -> FrameType {
+        self.frame_type
+    }
+
+    /// Returns the frame flags.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns the stream identifier of the frame.
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Returns the length of the frame payload, excluding the 9 byte frame
+    /// header.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the frame payload is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the decoded header list, as name and value pairs in the order
+    /// they were encoded.
+    ///
+    /// The header list is only available on the frame that ends a header
+    /// block: a HEADERS or PUSH_PROMISE frame with the END_HEADERS flag, or
+    /// the last CONTINUATION frame following one. Pseudo-headers are
+    /// included.
+    pub fn headers(&self) -> Option<&'a [(Bytes, Bytes)]> {
+        self.headers
+    }
+
+    /// Returns the raw bytes of the frame, including the frame header.
+    ///
+    /// This is only available if [`FrameObserver::wants_raw_bytes`] returns
+    /// `true`, or for DATA frames, [`FrameObserver::wants_raw_data`]. For sent
+    /// DATA frames whose payload buffer is not contiguous, only the first
+    /// chunk of the payload is included.
+    ///
+    /// [`FrameObserver::wants_raw_bytes`]: trait.FrameObserver.html#method.wants_raw_bytes
+    /// [`FrameObserver::wants_raw_data`]: trait.FrameObserver.html#method.wants_raw_data
+    pub fn raw(&self) -> Option<&'a [u8]> {
+        self.raw
+    }
+}
+
+// ===== impl SharedObserver =====
+
+impl SharedObserver {
+    pub fn new<T: FrameObserver + 'static>(observer: T) -> SharedObserver {
+        SharedObserver(Arc::new(observer))
+    }
+
+    /// Returns an observer that calls `self`, then `other`, with every frame.
+    pub fn join(self, other: SharedObserver) -> SharedObserver {
+        SharedObserver::new(Join(self, other))
+    }
+
+    /// Returns whether the observer wants the raw bytes of a frame, given
+    /// its encoded frame header.
+    pub fn wants_raw_bytes(&self, head: &[u8]) -> bool {
+        match FrameType::from_byte(head[3]) {
+            FrameType::Data => self.0.wants_raw_data(),
+            _ => self.0.wants_raw_bytes(),
+        }
+    }
+
+    /// Calls the observer with a frame, given its encoded frame header.
+    pub fn observe(
+        &self,
+        direction: Direction,
+        head: &[u8],
+        headers: Option<&[(Bytes, Bytes)]>,
+        raw: Option<&[u8]>,
+    ) {
+        debug_assert!(head.len() >= frame::HEADER_LEN);
+
+        let len = (head[0] as usize) << 16 | (head[1] as usize) << 8 | head[2] as usize;
+        let parsed = frame::Head::parse(head);
+
+        self.0.on_frame(&ObservedFrame {
+            direction,
+            frame_type: FrameType::from_byte(head[3]),
+            flags: parsed.flag(),
+            stream_id: parsed.stream_id(),
+            len,
+            headers,
+            raw,
+        });
+    }
+}
+
+impl fmt::Debug for SharedObserver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedObserver").finish()
+    }
+}
+
+// ===== impl Join =====
+
+impl FrameObserver for Join {
+    fn on_frame(&self, frame: &ObservedFrame<'_>) {
+        self.0 .0.on_frame(frame);
+        self.1 .0.on_frame(frame);
+    }
+
+    fn wants_raw_bytes(&self) -> bool {
+        self.0 .0.wants_raw_bytes() || self.1 .0.wants_raw_bytes()
+    }
+
+    fn wants_raw_data(&self) -> bool {
+        self.0 .0.wants_raw_data() || self.1 .0.wants_raw_data()
+    }
+}
+
+/// Converts header fields captured by the HPACK encoder or decoder into name
+/// and value pairs.
+pub(crate) fn header_list(headers: Vec<hpack::Header>) -> Vec<(Bytes, Bytes)> {
+    headers
+        .iter()
+        .map(|header| {
+            (
+                Bytes::copy_from_slice(header.name().as_slice()),
+                Bytes::copy_from_slice(header.value_slice()),
+            )
+        })
+        .collect()
+}
@@ -1,6 +1,11 @@
-use crate::codec::UserError;
+use crate::client;
+use crate::codec::{Io, UserError};
 use crate::frame::{Reason, StreamId};
-use crate::{client, server, tracing};
+use crate::qlog::Qlog;
+use crate::server::{self, SharedAdmission};
+use crate::stats::ConnectionStats;
+use crate::timer::{Alarm, SharedTimer};
+use crate::{tracing, FloodLimit};
 
 use crate::frame::DEFAULT_INITIAL_WINDOW_SIZE;
 use crate::proto::*;
@@ -9,27 +14,44 @@ use bytes::Bytes;
 use frame::{Priority, PseudoOrders, StreamDependency};
 use futures_core::Stream;
 use std::borrow::Cow;
+use std::cmp;
+use std::collections::VecDeque;
 use std::io;
 use std::marker::PhantomData;
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use std::time::Duration;
-use tokio::io::AsyncRead;
+use std::time::{Duration, Instant};
 
 /// An H2 connection
+///
+/// The connection does no I/O: the bytes of its frames are moved by its
+/// caller, either to and from an I/O with `poll_io`, or with `recv` and
+/// `transmit`.
 #[derive(Debug)]
-pub(crate) struct Connection<T, P, B: Buf = Bytes>
+pub(crate) struct Connection<P, B: Buf = Bytes>
 where
     P: Peer,
 {
     /// Read / write frame values
-    codec: Codec<T, Prioritized<B>>,
+    codec: Framed<Prioritized<B>>,
 
     inner: ConnectionInner<P, B>,
 }
 
-// Extracted part of `Connection` which does not depend on `T`. Reduces the amount of duplicated
-// method instantiations.
+/// A frame read by a connection that reports them, once it was processed.
+#[derive(Debug)]
+pub(crate) enum Received {
+    /// A frame of the stream, or of the connection if the identifier is zero.
+    Frame(StreamId),
+
+    /// A SETTINGS frame that is not an acknowledgement.
+    Settings(frame::Settings),
+
+    GoAway(frame::GoAway),
+}
+
+// Extracted part of `Connection` which does not depend on the peer type. Reduces the amount of
+// duplicated method instantiations.
 #[derive(Debug)]
 struct ConnectionInner<P, B: Buf = Bytes>
 where
@@ -50,12 +72,28 @@ where
     /// Ping/pong handler
     ping_pong: PingPong,
 
+    /// Tracks received control frames against the configured flood limits
+    flood: FloodGuard,
+
+    /// Closes the connection once it has been idle for too long
+    idle: Option<IdleTimeout>,
+
+    /// Fails requests that waited too long to be opened
+    queue_timeout: Option<QueueTimeout>,
+
     /// Connection settings
     settings: Settings,
 
     /// Stream state handler
     streams: Streams<B, P>,
 
+    /// Whether each frame read is reported, and no other frame is read until
+    /// the report was taken.
+    report_frames: bool,
+
+    /// The last frame read, until it is taken.
+    received: Option<Received>,
+
     /// A `tracing` span tracking the lifetime of the connection.
     #[cfg(feature = "tracing")]
     span: ::tracing::Span,
@@ -74,11 +112,14 @@ struct DynConnection<'a, B: Buf = Bytes> {
     error: &'a mut Option<frame::GoAway>,
 
     ping_pong: &'a mut PingPong,
+
+    flood: &'a mut FloodGuard,
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct Config {
     pub next_stream_id: StreamId,
+    pub max_stream_id: StreamId,
     pub initial_max_send_streams: usize,
     pub max_send_buffer_size: usize,
     pub reset_stream_duration: Duration,
@@ -89,6 +130,74 @@ pub(crate) struct Config {
     pub headers_pseudo_order: Option<PseudoOrders>,
     pub headers_priority: Option<StreamDependency>,
     pub priority: Option<Cow<'static, [Priority]>>,
+    pub expect_continue: bool,
+    pub retain_unprocessed_requests: bool,
+    pub oversized_response_431: bool,
+    pub flood_limits: FloodLimits,
+    pub qlog: Option<Qlog>,
+    pub idle_timeout: Option<Duration>,
+    pub timer: Option<SharedTimer>,
+    pub admission: Option<SharedAdmission>,
+    pub max_queued_requests: Option<usize>,
+    pub request_queue_timeout: Option<Duration>,
+}
+
+/// The configured flood limits, one per `FloodLimit`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FloodLimits {
+    ping: Option<FrameRate>,
+    settings: Option<FrameRate>,
+    empty_data: Option<FrameRate>,
+    window_update: Option<FrameRate>,
+    priority: Option<FrameRate>,
+}
+
+/// At most `max` frames within any `window` of time.
+#[derive(Debug, Clone, Copy)]
+struct FrameRate {
+    max: usize,
+    window: Duration,
+}
+
+/// Counts received frames of each kind over a sliding window.
+#[derive(Debug)]
+struct FloodGuard {
+    ping: Option<SlidingWindow>,
+    settings: Option<SlidingWindow>,
+    empty_data: Option<SlidingWindow>,
+    window_update: Option<SlidingWindow>,
+    priority: Option<SlidingWindow>,
+}
+
+#[derive(Debug)]
+struct SlidingWindow {
+    rate: FrameRate,
+
+    /// When each frame still inside the window was received, oldest first.
+    received: VecDeque<Instant>,
+}
+
+/// How long the connection may go without streams or received frames.
+#[derive(Debug)]
+struct IdleTimeout {
+    timeout: Duration,
+
+    /// Set once the connection becomes idle.
+    deadline: Option<Instant>,
+
+    alarm: Alarm,
+
+    /// Set once the connection was closed for being idle.
+    expired: bool,
+}
+
+/// How long a request may wait for the peer's concurrency limit.
+#[derive(Debug)]
+struct QueueTimeout {
+    /// When the oldest waiting request times out.
+    deadline: Option<Instant>,
+
+    alarm: Alarm,
 }
 
 #[derive(Debug)]
@@ -103,18 +212,18 @@ enum State {
     Closed(Reason, Initiator),
 }
 
-impl<T, P, B> Connection<T, P, B>
+impl<P, B> Connection<P, B>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
     P: Peer,
     B: Buf,
 {
-    pub fn new(codec: Codec<T, Prioritized<B>>, config: Config) -> Connection<T, P, B> {
+    pub fn new(codec: Framed<Prioritized<B>>, config: Config) -> Connection<P, B> {
         fn streams_config(config: &Config) -> streams::Config {
             streams::Config {
                 initial_max_send_streams: config.initial_max_send_streams,
                 local_max_buffer_size: config.max_send_buffer_size,
                 local_next_stream_id: config.next_stream_id,
+                local_max_stream_id: config.max_stream_id,
                 local_push_enabled: config.settings.is_push_enabled().unwrap_or(true),
                 extended_connect_protocol_enabled: config
                     .settings
@@ -132,9 +241,16 @@ where
                 headers_priority: config.headers_priority,
                 headers_pseudo_order: config.headers_pseudo_order,
                 priority: config.priority.clone(),
+                expect_continue: config.expect_continue,
+                retain_unprocessed_requests: config.retain_unprocessed_requests,
+                oversized_response_431: config.oversized_response_431,
+                qlog: config.qlog.clone(),
+                admission: config.admission.clone(),
+                max_queued_requests: config.max_queued_requests,
+                request_queue_timeout: config.request_queue_timeout,
             }
         }
-        let streams = Streams::new(streams_config(&config));
+        let streams = Streams::new(streams_config(&config), codec.stats().clone());
         Connection {
             codec,
             inner: ConnectionInner {
@@ -142,8 +258,21 @@ where
                 error: None,
                 go_away: GoAway::new(),
                 ping_pong: PingPong::new(),
+                flood: FloodGuard::new(&config.flood_limits),
+                idle: config.idle_timeout.map(|timeout| IdleTimeout {
+                    timeout,
+                    deadline: None,
+                    alarm: Alarm::new(config.timer.clone()),
+                    expired: false,
+                }),
+                queue_timeout: config.request_queue_timeout.map(|_| QueueTimeout {
+                    deadline: None,
+                    alarm: Alarm::new(config.timer.clone()),
+                }),
                 settings: Settings::new(config.settings),
                 streams,
+                report_frames: false,
+                received: None,
                 #[cfg(feature = "tracing")]
                 span: ::tracing::debug_span!("Connection", peer = %P::NAME),
                 _phantom: PhantomData,
@@ -184,6 +313,11 @@ where
         self.inner.streams.max_recv_streams()
     }
 
+    /// Returns a snapshot of the connection's statistics.
+    pub(crate) fn stats(&self) -> ConnectionStats {
+        self.inner.streams.stats()
+    }
+
     #[cfg(feature = "unstable")]
     pub fn num_wired_streams(&self) -> usize {
         self.inner.streams.num_wired_streams()
@@ -221,7 +355,84 @@ where
         self.inner.as_dyn().go_away_from_user(e)
     }
 
+    /// Fails the requests that waited to be opened for longer than the
+    /// configured timeout.
+    fn poll_queue_timeout(&mut self, cx: &mut Context) {
+        let queue = match self.inner.queue_timeout {
+            Some(ref mut queue) => queue,
+            None => return,
+        };
+
+        let mut now = Instant::now();
+        loop {
+            let deadline = match self.inner.streams.expire_pending_open(now) {
+                Some(deadline) => deadline,
+                None => {
+                    queue.deadline = None;
+                    queue.alarm.cancel();
+                    return;
+                }
+            };
+
+            queue.deadline = Some(deadline);
+            if !queue.alarm.poll_expired(cx, deadline) {
+                return;
+            }
+
+            now = cmp::max(Instant::now(), deadline);
+        }
+    }
+
+    /// Closes the connection if it has been idle for the configured timeout.
+    ///
+    /// Returns `true` if the connection is closing.
+    fn poll_idle_timeout(&mut self, cx: &mut Context) -> bool {
+        let idle = match self.inner.idle {
+            Some(ref mut idle) => idle,
+            None => return false,
+        };
+
+        // The connection is not idle while a stream is open or a PING has not
+        // been acknowledged. A GOAWAY in progress closes it anyway.
+        if self.inner.streams.has_streams()
+            || self.inner.ping_pong.is_ping_in_flight()
+            || self.inner.go_away.is_going_away()
+        {
+            idle.deadline = None;
+            idle.alarm.cancel();
+            return false;
+        }
+
+        let deadline = *idle
+            .deadline
+            .get_or_insert_with(|| Instant::now() + idle.timeout);
+        if !idle.alarm.poll_expired(cx, deadline) {
+            return false;
+        }
+
+        tracing::debug!("connection idle timeout");
+        idle.deadline = None;
+        idle.expired = true;
+
+        let mut inner = self.inner.as_dyn();
+        inner.streams.handle_error(Error::idle_timeout());
+
+        // Servers say goodbye with a GOAWAY; clients just close the
+        // connection.
+        if inner.streams.is_server() {
+            inner.go_away_now_data(Reason::NO_ERROR, Bytes::from_static(IDLE_TIMEOUT));
+        } else {
+            *inner.state = State::Closing(Reason::NO_ERROR, Initiator::Library);
+        }
+
+        true
+    }
+
     fn take_error(&mut self, ours: Reason, initiator: Initiator) -> Result<(), Error> {
+        if ours == Reason::NO_ERROR && self.inner.idle.as_ref().map_or(false, |idle| idle.expired) {
+            return Err(Error::idle_timeout());
+        }
+
         let (debug_data, theirs) = self
             .inner
             .error
@@ -233,7 +444,16 @@ where
 
         match (ours, theirs) {
             (Reason::NO_ERROR, Reason::NO_ERROR) => Ok(()),
-            (ours, Reason::NO_ERROR) => Err(Error::GoAway(Bytes::new(), ours, initiator)),
+            (ours, Reason::NO_ERROR) => {
+                // Keep the debug data of the GOAWAY we sent, if any.
+                let debug_data = self
+                    .inner
+                    .go_away
+                    .going_away()
+                    .filter(|going_away| going_away.reason() == ours)
+                    .map_or_else(Bytes::new, |going_away| going_away.debug_data().clone());
+                Err(Error::GoAway(debug_data, ours, initiator))
+            }
             // If both sides reported an error, give their
             // error back to th user. We assume our error
             // was a consequence of their error, and less
@@ -252,6 +472,33 @@ where
         }
     }
 
+    /// Closes the connection once no more streams can be opened and the
+    /// active streams are done, even if references are left.
+    pub fn maybe_close_connection_if_exhausted(&mut self) {
+        if self.is_exhausted_and_idle() {
+            self.inner.as_dyn().go_away_now(Reason::NO_ERROR);
+        }
+    }
+
+    /// Returns when a timeout next needs the connection to be polled.
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        let idle = self.inner.idle.as_ref().and_then(|idle| idle.deadline);
+        let queue = self
+            .inner
+            .queue_timeout
+            .as_ref()
+            .and_then(|queue| queue.deadline);
+        match (idle, queue) {
+            (Some(idle), Some(queue)) => Some(cmp::min(idle, queue)),
+            (deadline, None) | (None, deadline) => deadline,
+        }
+    }
+
+    /// Returns true if no more streams can be opened and no stream is active.
+    pub fn is_exhausted_and_idle(&self) -> bool {
+        self.inner.streams.is_exhausted_and_idle()
+    }
+
     /// Checks if there are any streams or references left
     pub fn has_streams_or_other_references(&self) -> bool {
         // If we poll() and realize that there are no streams or references
@@ -296,6 +543,12 @@ where
                                 continue;
                             }
 
+                            if self.poll_idle_timeout(cx) {
+                                continue;
+                            }
+
+                            self.poll_queue_timeout(cx);
+
                             return Poll::Pending;
                         }
                     };
@@ -348,11 +601,16 @@ where
             }
             ready!(self.poll_ready(cx))?;
 
-            match self
-                .inner
-                .as_dyn()
-                .recv_frame(ready!(Pin::new(&mut self.codec).poll_next(cx)?))?
-            {
+            if self.inner.received.is_some() {
+                return Poll::Pending;
+            }
+
+            let frame = ready!(Pin::new(&mut self.codec).poll_next(cx)?);
+            if self.inner.report_frames {
+                self.inner.received = frame.as_ref().map(Received::new);
+            }
+
+            match self.inner.as_dyn().recv_frame(frame)? {
                 ReceivedFrame::Settings(frame) => {
                     self.inner.settings.recv_settings(
                         frame,
@@ -365,6 +623,11 @@ where
                     return Poll::Ready(Ok(()));
                 }
             }
+
+            // Any received frame restarts the idle timeout.
+            if let Some(ref mut idle) = self.inner.idle {
+                idle.deadline = None;
+            }
         }
     }
 
@@ -373,6 +636,51 @@ where
     }
 }
 
+impl<P, B> Connection<P, B>
+where
+    P: Peer,
+    B: Buf,
+{
+    /// Writes the frames buffered by the connection to `io`, and reads more
+    /// bytes from it if the connection is waiting for them.
+    ///
+    /// Returns `Ready` if the connection should be polled again, and
+    /// `Pending` once `io` is blocked.
+    pub fn poll_io<T: Io>(&mut self, io: &mut T, cx: &mut Context) -> Poll<()> {
+        self.codec.poll_io(io, cx)
+    }
+
+    /// Makes the bytes to send wait to be taken with `transmit`, after
+    /// `prefix`, and each frame read be reported with `take_received`.
+    pub fn set_transmit(&mut self, prefix: &[u8]) {
+        self.codec.set_transmit(prefix);
+        self.inner.report_frames = true;
+    }
+
+    /// Passes bytes received from the peer to the connection.
+    ///
+    /// They are processed the next time the connection is polled.
+    pub fn recv(&mut self, data: &[u8]) {
+        self.codec.recv(data);
+    }
+
+    /// Tells the connection that the peer closed its side of the transport.
+    pub fn recv_eof(&mut self) {
+        self.codec.recv_eof();
+    }
+
+    /// Returns the bytes to send to the peer since the last call, if any.
+    pub fn transmit(&mut self) -> Option<Bytes> {
+        self.codec.transmit()
+    }
+
+    /// Takes the report of the last frame read, which lets the connection
+    /// read the next one the next time it is polled.
+    pub fn take_received(&mut self) -> Option<Received> {
+        self.inner.received.take()
+    }
+}
+
 impl<P, B> ConnectionInner<P, B>
 where
     P: Peer,
@@ -385,6 +693,7 @@ where
             streams,
             error,
             ping_pong,
+            flood,
             ..
         } = self;
         let streams = streams.as_dyn();
@@ -394,6 +703,7 @@ where
             streams,
             error,
             ping_pong,
+            flood,
         }
     }
 }
@@ -502,6 +812,11 @@ where
 
     fn recv_frame(&mut self, frame: Option<Frame>) -> Result<ReceivedFrame, Error> {
         use crate::frame::Frame::*;
+
+        if let Some(ref frame) = frame {
+            self.flood.recv_frame(frame)?;
+        }
+
         match frame {
             Some(Headers(frame)) => {
                 tracing::trace!(?frame, "recv HEADERS");
@@ -569,25 +884,188 @@ enum ReceivedFrame {
     Done,
 }
 
-impl<T, B> Connection<T, client::Peer, B>
+// ===== impl Received =====
+
+impl Received {
+    fn new(frame: &Frame) -> Received {
+        let id = match *frame {
+            Frame::Settings(ref frame) if !frame.is_ack() => {
+                return Received::Settings(frame.clone())
+            }
+            Frame::GoAway(ref frame) => return Received::GoAway(frame.clone()),
+            Frame::Data(ref frame) => frame.stream_id(),
+            Frame::Headers(ref frame) => frame.stream_id(),
+            Frame::Priority(ref frame) => frame.stream_id(),
+            Frame::PushPromise(ref frame) => frame.stream_id(),
+            Frame::WindowUpdate(ref frame) => frame.stream_id(),
+            Frame::Reset(ref frame) => frame.stream_id(),
+            Frame::Settings(_) | Frame::Ping(_) => StreamId::ZERO,
+        };
+        Received::Frame(id)
+    }
+}
+
+// ===== impl FloodLimits =====
+
+impl FloodLimits {
+    pub(crate) fn set(&mut self, limit: FloodLimit, max: usize, window: Duration) {
+        let rate = Some(FrameRate { max, window });
+        match limit {
+            FloodLimit::Ping => self.ping = rate,
+            FloodLimit::Settings => self.settings = rate,
+            FloodLimit::EmptyData => self.empty_data = rate,
+            FloodLimit::WindowUpdate => self.window_update = rate,
+            FloodLimit::Priority => self.priority = rate,
+        }
+    }
+}
+
+// ===== impl FloodGuard =====
+
+impl FloodGuard {
+    fn new(limits: &FloodLimits) -> FloodGuard {
+        FloodGuard {
+            ping: limits.ping.map(SlidingWindow::new),
+            settings: limits.settings.map(SlidingWindow::new),
+            empty_data: limits.empty_data.map(SlidingWindow::new),
+            window_update: limits.window_update.map(SlidingWindow::new),
+            priority: limits.priority.map(SlidingWindow::new),
+        }
+    }
+
+    /// Records a received frame, returning an error if it exceeds the limit
+    /// for its kind.
+    fn recv_frame(&mut self, frame: &Frame) -> Result<(), Error> {
+        let (limit, window) = match *frame {
+            Frame::Ping(ref ping) if !ping.is_ack() => (FloodLimit::Ping, &mut self.ping),
+            Frame::Settings(ref settings) if !settings.is_ack() => {
+                (FloodLimit::Settings, &mut self.settings)
+            }
+            Frame::Data(ref data) if data.payload().is_empty() && !data.is_end_stream() => {
+                (FloodLimit::EmptyData, &mut self.empty_data)
+            }
+            Frame::WindowUpdate(_) => (FloodLimit::WindowUpdate, &mut self.window_update),
+            Frame::Priority(_) => (FloodLimit::Priority, &mut self.priority),
+            _ => return Ok(()),
+        };
+
+        let window = match window {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+
+        if window.record(Instant::now()) {
+            return Ok(());
+        }
+
+        tracing::debug!(
+            "connection error ENHANCE_YOUR_CALM -- {}; max = {}, window = {:?}",
+            limit,
+            window.rate.max,
+            window.rate.window,
+        );
+        Err(Error::library_go_away_data(
+            Reason::ENHANCE_YOUR_CALM,
+            limit.debug_data(),
+        ))
+    }
+}
+
+// ===== impl SlidingWindow =====
+
+impl SlidingWindow {
+    fn new(rate: FrameRate) -> SlidingWindow {
+        SlidingWindow {
+            rate,
+            received: VecDeque::new(),
+        }
+    }
+
+    /// Records a frame received at `now`, returning `false` if this exceeds
+    /// the rate.
+    fn record(&mut self, now: Instant) -> bool {
+        while let Some(&oldest) = self.received.front() {
+            if now.saturating_duration_since(oldest) < self.rate.window {
+                break;
+            }
+            self.received.pop_front();
+        }
+
+        if self.received.len() >= self.rate.max {
+            return false;
+        }
+
+        self.received.push_back(now);
+        true
+    }
+}
+
+impl<P, B> Connection<P, B>
 where
-    T: AsyncRead + AsyncWrite,
+    P: Peer,
     B: Buf,
 {
-    pub(crate) fn streams(&self) -> &Streams<B, client::Peer> {
+    pub(crate) fn streams(&self) -> &Streams<B, P> {
         &self.inner.streams
     }
 }
 
-impl<T, B> Connection<T, server::Peer, B>
+impl<B> Connection<client::Peer, B>
+where
+    B: Buf,
+{
+    /// Polls the connection once.
+    ///
+    /// Also returns whether the connection must be polled again, because its
+    /// last stream closed while it was polled.
+    pub fn poll_once(&mut self, cx: &mut Context) -> (Poll<Result<(), Error>>, bool) {
+        self.maybe_close_connection_if_no_streams();
+        self.maybe_close_connection_if_exhausted();
+        let had_streams_or_refs = self.has_streams_or_other_references();
+        let was_exhausted_and_idle = self.is_exhausted_and_idle();
+        let result = self.poll(cx);
+        if result.is_ready() {
+            return (result, false);
+        }
+
+        // if we had streams/refs, and don't anymore, poll one more time to
+        // ensure proper shutdown
+        if had_streams_or_refs && !self.has_streams_or_other_references() {
+            tracing::trace!("last stream closed during poll, wake again");
+            return (result, true);
+        }
+        if !was_exhausted_and_idle && self.is_exhausted_and_idle() {
+            tracing::trace!("last stream of exhausted connection closed, wake again");
+            return (result, true);
+        }
+        (result, false)
+    }
+}
+
+impl<B> Connection<server::Peer, B>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
     B: Buf,
 {
     pub fn next_incoming(&mut self) -> Option<StreamRef<B>> {
         self.inner.streams.next_incoming()
     }
 
+    /// Returns the number of requests received over the lifetime of the
+    /// connection.
+    pub(crate) fn num_requests(&self) -> u64 {
+        self.inner.streams.total_recv_streams()
+    }
+
+    pub(crate) fn is_going_away(&self) -> bool {
+        self.inner.go_away.is_going_away()
+    }
+
+    /// Returns if a graceful shutdown is waiting for the active streams to
+    /// complete.
+    pub(crate) fn is_going_away_gracefully(&self) -> bool {
+        self.inner.go_away.is_graceful()
+    }
+
     // Graceful shutdown only makes sense for server peers.
     pub fn go_away_gracefully(&mut self) {
         if self.inner.go_away.is_going_away() {
@@ -614,7 +1092,7 @@ where
     }
 }
 
-impl<T, P, B> Drop for Connection<T, P, B>
+impl<P, B> Drop for Connection<P, B>
 where
     P: Peer,
     B: Buf,
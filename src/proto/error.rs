@@ -13,6 +13,9 @@ pub enum Error {
     Io(io::ErrorKind, Option<String>),
 }
 
+/// The GOAWAY debug data sent when a connection is closed for being idle.
+pub(crate) const IDLE_TIMEOUT: &[u8] = b"idle_timeout";
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Initiator {
     User,
@@ -44,6 +47,10 @@ impl Error {
         Self::GoAway(debug_data.into(), reason, Initiator::Library)
     }
 
+    pub(crate) fn idle_timeout() -> Self {
+        Self::library_go_away_data(Reason::NO_ERROR, IDLE_TIMEOUT)
+    }
+
     pub(crate) fn remote_reset(stream_id: StreamId, reason: Reason) -> Self {
         Self::Reset(stream_id, reason, Initiator::Remote)
     }
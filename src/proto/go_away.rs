@@ -1,10 +1,9 @@
-use crate::codec::Codec;
+use crate::codec::Framed;
 use crate::frame::{self, Reason, StreamId};
 
-use bytes::Buf;
+use bytes::{Buf, Bytes};
 use std::io;
 use std::task::{Context, Poll};
-use tokio::io::AsyncWrite;
 
 /// Manages our sending of GOAWAY frames.
 #[derive(Debug)]
@@ -35,6 +34,9 @@ pub(crate) struct GoingAway {
 
     /// Records the error code of any GOAWAY frame sent.
     reason: Reason,
+
+    /// Records the debug data of any GOAWAY frame sent.
+    debug_data: Bytes,
 }
 
 impl GoAway {
@@ -64,6 +66,7 @@ impl GoAway {
         self.going_away = Some(GoingAway {
             last_processed_id: f.last_stream_id(),
             reason: f.reason(),
+            debug_data: f.debug_data().clone(),
         });
         self.pending = Some(f);
     }
@@ -90,6 +93,12 @@ impl GoAway {
         self.going_away.is_some()
     }
 
+    /// Returns if a GOAWAY has been scheduled, and the connection is waiting
+    /// for the active streams to complete before closing.
+    pub fn is_graceful(&self) -> bool {
+        self.going_away.is_some() && !self.close_now
+    }
+
     pub fn is_user_initiated(&self) -> bool {
         self.is_user_initiated
     }
@@ -117,13 +126,12 @@ impl GoAway {
     /// Try to write a pending GOAWAY frame to the buffer.
     ///
     /// If a frame is written, the `Reason` of the GOAWAY is returned.
-    pub fn send_pending_go_away<T, B>(
+    pub fn send_pending_go_away<B>(
         &mut self,
         cx: &mut Context,
-        dst: &mut Codec<T, B>,
+        dst: &mut Framed<B>,
     ) -> Poll<Option<io::Result<Reason>>>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
     {
         if let Some(frame) = self.pending.take() {
@@ -151,4 +159,8 @@ impl GoingAway {
     pub(crate) fn reason(&self) -> Reason {
         self.reason
     }
+
+    pub(crate) fn debug_data(&self) -> &Bytes {
+        &self.debug_data
+    }
 }
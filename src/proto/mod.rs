@@ -6,14 +6,15 @@ mod ping_pong;
 mod settings;
 mod streams;
 
-pub(crate) use self::connection::{Config, Connection};
+pub(crate) use self::connection::{Config, Connection, FloodLimits, Received};
+pub(crate) use self::error::IDLE_TIMEOUT;
 pub use self::error::{Error, Initiator};
 pub(crate) use self::peer::{Dyn as DynPeer, Peer};
 pub(crate) use self::ping_pong::UserPings;
 pub(crate) use self::streams::{DynStreams, OpaqueStreamRef, StreamRef, Streams};
 pub(crate) use self::streams::{Open, PollReset, Prioritized};
 
-use crate::codec::Codec;
+use crate::codec::Framed;
 
 use self::go_away::GoAway;
 use self::ping_pong::PingPong;
@@ -23,12 +24,15 @@ use crate::frame::{self, Frame};
 
 use bytes::Buf;
 
-use tokio::io::AsyncWrite;
+use http::HeaderMap;
 
 pub type PingPayload = [u8; 8];
 
 pub type WindowSize = u32;
 
+/// The connection preface sent by clients.
+pub const PREFACE: [u8; 24] = *b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
 // Constants
 pub const MAX_WINDOW_SIZE: WindowSize = (1 << 31) - 1; // i32::MAX as u32
 pub const DEFAULT_REMOTE_RESET_STREAM_MAX: usize = 20;
@@ -36,3 +40,10 @@ pub const DEFAULT_LOCAL_RESET_COUNT_MAX: usize = 1024;
 pub const DEFAULT_RESET_STREAM_MAX: usize = 10;
 pub const DEFAULT_RESET_STREAM_SECS: u64 = 30;
 pub const DEFAULT_MAX_SEND_BUFFER_SIZE: usize = 1024 * 400;
+
+/// Returns true if the headers carry `Expect: 100-continue`.
+pub(crate) fn expects_continue(headers: &HeaderMap) -> bool {
+    headers.get(http::header::EXPECT).map_or(false, |value| {
+        value.as_bytes().eq_ignore_ascii_case(b"100-continue")
+    })
+}
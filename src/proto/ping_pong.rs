@@ -1,4 +1,4 @@
-use crate::codec::Codec;
+use crate::codec::Framed;
 use crate::frame::Ping;
 use crate::proto::{self, PingPayload};
 use crate::tracing;
@@ -9,7 +9,6 @@ use std::io;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use tokio::io::AsyncWrite;
 
 /// Acknowledges ping requests from the remote.
 #[derive(Debug)]
@@ -94,6 +93,17 @@ impl PingPong {
         });
     }
 
+    /// Returns true if a PING is waiting to be sent or acknowledged.
+    pub(crate) fn is_ping_in_flight(&self) -> bool {
+        self.pending_ping.is_some()
+            || self.user_pings.as_ref().map_or(false, |users| {
+                matches!(
+                    users.0.state.load(Ordering::Acquire),
+                    USER_STATE_PENDING_PING | USER_STATE_PENDING_PONG
+                )
+            })
+    }
+
     /// Process a ping
     pub(crate) fn recv_ping(&mut self, ping: Ping) -> ReceivedPing {
         // The caller should always check that `send_pongs` returns ready before
@@ -136,13 +146,12 @@ impl PingPong {
     }
 
     /// Send any pending pongs.
-    pub(crate) fn send_pending_pong<T, B>(
+    pub(crate) fn send_pending_pong<B>(
         &mut self,
         cx: &mut Context,
-        dst: &mut Codec<T, B>,
+        dst: &mut Framed<B>,
     ) -> Poll<io::Result<()>>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
     {
         if let Some(pong) = self.pending_pong.take() {
@@ -159,13 +168,12 @@ impl PingPong {
     }
 
     /// Send any pending pings.
-    pub(crate) fn send_pending_ping<T, B>(
+    pub(crate) fn send_pending_ping<B>(
         &mut self,
         cx: &mut Context,
-        dst: &mut Codec<T, B>,
+        dst: &mut Framed<B>,
     ) -> Poll<io::Result<()>>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
     {
         if let Some(ref mut ping) = self.pending_ping {
@@ -39,14 +39,13 @@ impl Settings {
         }
     }
 
-    pub(crate) fn recv_settings<T, B, C, P>(
+    pub(crate) fn recv_settings<B, C, P>(
         &mut self,
         frame: frame::Settings,
-        codec: &mut Codec<T, B>,
+        codec: &mut Framed<B>,
         streams: &mut Streams<C, P>,
     ) -> Result<(), Error>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
         C: Buf,
         P: Peer,
@@ -109,14 +108,13 @@ impl Settings {
         !has_received
     }
 
-    pub(crate) fn poll_send<T, B, C, P>(
+    pub(crate) fn poll_send<B, C, P>(
         &mut self,
         cx: &mut Context,
-        dst: &mut Codec<T, B>,
+        dst: &mut Framed<B>,
         streams: &mut Streams<C, P>,
     ) -> Poll<Result<(), Error>>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
         C: Buf,
         P: Peer,
@@ -40,6 +40,21 @@ pub(super) struct Counts {
     /// Total number of locally reset streams due to protocol error across the
     /// lifetime of the connection.
     num_local_error_reset_streams: usize,
+
+    /// Total number of locally initiated streams opened across the lifetime
+    /// of the connection.
+    total_send_streams: u64,
+
+    /// Total number of remote initiated streams opened across the lifetime
+    /// of the connection.
+    total_recv_streams: u64,
+
+    /// Total number of counted streams closed across the lifetime of the
+    /// connection.
+    total_closed_streams: u64,
+
+    /// Where to record stream state changes.
+    qlog: Option<Qlog>,
 }
 
 impl Counts {
@@ -57,6 +72,10 @@ impl Counts {
             num_remote_reset_streams: 0,
             max_local_error_reset_streams: config.local_max_error_reset_streams,
             num_local_error_reset_streams: 0,
+            total_send_streams: 0,
+            total_recv_streams: 0,
+            total_closed_streams: 0,
+            qlog: config.qlog.clone(),
         }
     }
 
@@ -114,9 +133,26 @@ impl Counts {
 
         // Increment the number of remote initiated streams
         self.num_recv_streams += 1;
+        self.total_recv_streams += 1;
         stream.is_counted = true;
     }
 
+    /// Returns the number of concurrent locally initiated streams.
+    pub fn num_send_streams(&self) -> usize {
+        self.num_send_streams
+    }
+
+    /// Returns the number of concurrent remote initiated streams.
+    pub fn num_recv_streams(&self) -> usize {
+        self.num_recv_streams
+    }
+
+    /// Returns the number of remote initiated streams opened over the
+    /// lifetime of the connection.
+    pub fn total_recv_streams(&self) -> u64 {
+        self.total_recv_streams
+    }
+
     /// Returns true if the send stream concurrency can be incremented
     pub fn can_inc_num_send_streams(&self) -> bool {
         self.max_send_streams > self.num_send_streams
@@ -133,6 +169,7 @@ impl Counts {
 
         // Increment the number of remote initiated streams
         self.num_send_streams += 1;
+        self.total_send_streams += 1;
         stream.is_counted = true;
     }
 
@@ -224,7 +261,11 @@ impl Counts {
             self.num_send_streams
         );
 
+        self.record_state(&mut stream);
+
         if stream.is_closed() {
+            stream.metrics.set_send_blocked(false);
+
             if !stream.is_pending_reset_expiration() {
                 stream.unlink();
                 if is_reset_counted {
@@ -245,6 +286,18 @@ impl Counts {
         }
     }
 
+    /// Records the state of the stream in the qlog trace, if it changed since
+    /// it was last recorded.
+    pub fn record_state(&self, stream: &mut store::Ptr) {
+        if let Some(ref qlog) = self.qlog {
+            let state = stream.state.name();
+            if state != stream.qlog_state {
+                qlog.stream_state_updated(stream.id, stream.qlog_state, state);
+                stream.qlog_state = state;
+            }
+        }
+    }
+
     /// Returns the maximum number of streams that can be initiated by this
     /// peer.
     pub(crate) fn max_send_streams(&self) -> usize {
@@ -257,9 +310,19 @@ impl Counts {
         self.max_recv_streams
     }
 
+    /// Fills in the stream lifecycle counters.
+    pub(crate) fn stats(&self, stats: &mut crate::stats::StreamStats) {
+        stats.opened_local = self.total_send_streams;
+        stats.opened_remote = self.total_recv_streams;
+        stats.closed = self.total_closed_streams;
+        stats.active = self.num_send_streams + self.num_recv_streams;
+    }
+
     fn dec_num_streams(&mut self, stream: &mut store::Ptr) {
         assert!(stream.is_counted);
 
+        self.total_closed_streams += 1;
+
         if self.peer.is_local_init(stream.id) {
             assert!(self.num_send_streams > 0);
             self.num_send_streams -= 1;
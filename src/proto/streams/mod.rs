@@ -27,6 +27,8 @@ use self::stream::Stream;
 
 use crate::frame::{StreamId, StreamIdOverflow};
 use crate::proto::*;
+use crate::qlog::Qlog;
+use crate::server::SharedAdmission;
 
 use bytes::Bytes;
 use frame::{Priority, PseudoOrders, StreamDependency};
@@ -47,6 +49,9 @@ pub struct Config {
     /// The stream ID to start the next local stream with
     pub local_next_stream_id: StreamId,
 
+    /// Highest stream ID to allocate locally.
+    pub local_max_stream_id: StreamId,
+
     /// If the local peer is willing to receive push promises
     pub local_push_enabled: bool,
 
@@ -83,4 +88,29 @@ pub struct Config {
 
     /// Priority stream list
     pub priority: Option<Cow<'static, [Priority]>>,
+
+    /// Hold back request bodies of `Expect: 100-continue` requests until the
+    /// peer responds.
+    pub expect_continue: bool,
+
+    /// Keep a copy of each request until the peer starts processing it, so
+    /// that it can be returned if the peer never does.
+    pub retain_unprocessed_requests: bool,
+
+    /// Answer with a `431` when a response header list exceeds the peer's
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE`.
+    pub oversized_response_431: bool,
+
+    /// Where to record stream state changes and window updates.
+    pub qlog: Option<Qlog>,
+
+    /// Decides whether new remote streams are accepted.
+    pub admission: Option<SharedAdmission>,
+
+    /// Maximum number of locally initiated streams waiting for the remote's
+    /// concurrency limit, if the request queue is bounded.
+    pub max_queued_requests: Option<usize>,
+
+    /// How long a locally initiated stream may wait to be opened.
+    pub request_queue_timeout: Option<Duration>,
 }
@@ -13,6 +13,7 @@ use std::{
     cmp::{self, Ordering},
     fmt, io, mem,
     task::{Context, Poll, Waker},
+    time::{Duration, Instant},
 };
 
 /// # Warning
@@ -45,17 +46,49 @@ pub(super) struct Prioritize {
     /// request. A `SendRequest` handle is ready to send a request if it has no
     /// associated buffered requests. This is the same strategy as `mpsc` in the
     /// futures library.
+    ///
+    /// When a request queue is configured, handles may instead buffer any
+    /// number of requests, as long as the queue is not full.
     pending_open: store::Queue<stream::NextOpen>,
 
+    /// Number of streams in `pending_open`.
+    num_pending_open: usize,
+
+    /// Maximum number of streams in `pending_open` beyond the remote's
+    /// concurrency limit, if the request queue is bounded.
+    max_queued_open: Option<usize>,
+
+    /// How long a stream may wait in `pending_open`.
+    queued_open_timeout: Option<Duration>,
+
+    /// Tasks waiting for room in the request queue.
+    open_queue_tasks: Vec<Waker>,
+
     /// Connection level flow control governing sent data
     flow: FlowControl,
 
+    /// When streams started waiting on the connection level window, if they
+    /// are currently waiting.
+    conn_blocked_since: Option<Instant>,
+
+    /// Total time streams spent waiting on the connection level window.
+    conn_blocked: Duration,
+
     /// Stream ID of the last stream opened.
     last_opened_id: StreamId,
 
+    /// Streams whose first HEADERS frame was buffered in the codec, but not
+    /// flushed yet.
+    unflushed_headers: Vec<StreamId>,
+
     /// What `DATA` frame is currently being sent in the codec.
     in_flight_data_frame: InFlightData,
 
+    /// How much padding a frame interceptor may add to the `DATA` frame
+    /// being sent.
+    #[cfg(feature = "unstable")]
+    max_padding: usize,
+
     /// The maximum amount of bytes a stream should buffer.
     max_buffer_size: usize,
 }
@@ -99,9 +132,18 @@ impl Prioritize {
             pending_send: store::Queue::new(),
             pending_capacity: store::Queue::new(),
             pending_open: store::Queue::new(),
+            num_pending_open: 0,
+            max_queued_open: config.max_queued_requests,
+            queued_open_timeout: config.request_queue_timeout,
+            open_queue_tasks: Vec::new(),
             flow,
+            conn_blocked_since: None,
+            conn_blocked: Duration::ZERO,
             last_opened_id: StreamId::ZERO,
+            unflushed_headers: Vec::new(),
             in_flight_data_frame: InFlightData::Nothing,
+            #[cfg(feature = "unstable")]
+            max_padding: 0,
             max_buffer_size: config.local_max_buffer_size,
         }
     }
@@ -110,6 +152,16 @@ impl Prioritize {
         self.max_buffer_size
     }
 
+    /// Returns the connection level send window, and the total time streams
+    /// spent waiting on it.
+    pub(crate) fn connection_window_stats(&self) -> (WindowSize, Duration) {
+        let blocked = match self.conn_blocked_since {
+            Some(since) => self.conn_blocked + since.elapsed(),
+            None => self.conn_blocked,
+        };
+        (self.flow.window_size(), blocked)
+    }
+
     /// Queue a frame to be sent to the remote
     pub fn queue_frame<B>(
         &mut self,
@@ -139,7 +191,109 @@ impl Prioritize {
     }
 
     pub fn queue_open(&mut self, stream: &mut store::Ptr) {
-        self.pending_open.push(stream);
+        if self.pending_open.push(stream) {
+            self.num_pending_open += 1;
+            stream.open_deadline = self
+                .queued_open_timeout
+                .map(|timeout| Instant::now() + timeout);
+        }
+    }
+
+    /// Returns true if any stream is waiting to be opened.
+    pub fn has_pending_open(&self) -> bool {
+        !self.pending_open.is_empty()
+    }
+
+    /// Returns true if a request queue is configured.
+    pub fn is_open_queue_bounded(&self) -> bool {
+        self.max_queued_open.is_some()
+    }
+
+    /// Returns true if a new stream would not fit in the request queue.
+    pub fn is_open_queue_full(&self, counts: &Counts) -> bool {
+        match self.max_queued_open {
+            Some(max) => {
+                let available = counts
+                    .max_send_streams()
+                    .saturating_sub(counts.num_send_streams());
+                self.num_pending_open >= available.saturating_add(max)
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `Pending` while the request queue is full.
+    pub fn poll_open_queue(&mut self, cx: &Context, counts: &Counts) -> Poll<()> {
+        if !self.is_open_queue_full(counts) {
+            return Poll::Ready(());
+        }
+
+        if !self
+            .open_queue_tasks
+            .iter()
+            .any(|task| task.will_wake(cx.waker()))
+        {
+            self.open_queue_tasks.push(cx.waker().clone());
+        }
+
+        Poll::Pending
+    }
+
+    /// Wakes the tasks waiting for room in the request queue, if there is.
+    fn notify_open_queue(&mut self, counts: &Counts) {
+        if self.open_queue_tasks.is_empty() || self.is_open_queue_full(counts) {
+            return;
+        }
+
+        for task in self.open_queue_tasks.drain(..) {
+            task.wake();
+        }
+    }
+
+    /// Fails the streams that waited to be opened until their deadline,
+    /// given the current time.
+    ///
+    /// Returns the deadline of the next stream waiting to be opened, if any.
+    pub fn expire_pending_open<B>(
+        &mut self,
+        now: Instant,
+        buffer: &mut Buffer<Frame<B>>,
+        store: &mut Store,
+        counts: &mut Counts,
+    ) -> Option<Instant> {
+        let mut next = None;
+
+        // Streams are opened in order, so the oldest ones are first.
+        while let Some(mut stream) = self.pending_open.pop(store) {
+            match stream.open_deadline {
+                Some(deadline) if deadline <= now => {}
+                deadline => {
+                    self.pending_open.push_front(&mut stream);
+                    next = deadline;
+                    break;
+                }
+            }
+
+            tracing::debug!("request queue timeout; stream={:?}", stream.id);
+            self.num_pending_open -= 1;
+
+            // Nothing was sent on the stream, so the peer never learns of it.
+            self.clear_queue(buffer, &mut stream);
+            self.reclaim_all_capacity(&mut stream, counts);
+
+            let err = Error::library_reset(stream.id, Reason::REFUSED_STREAM);
+            stream.is_unprocessed = true;
+            stream.state.handle_error(&err);
+            stream.notify_send();
+            stream.notify_recv();
+            stream.notify_push();
+
+            let is_pending_reset = stream.is_pending_reset_expiration();
+            counts.transition_after(stream, is_pending_reset);
+        }
+
+        self.notify_open_queue(counts);
+        next
     }
 
     /// Send a data frame
@@ -384,6 +538,12 @@ impl Prioritize {
         let _res = self.flow.assign_capacity(inc);
         debug_assert!(_res.is_ok());
 
+        if self.flow.available() > 0 {
+            if let Some(since) = self.conn_blocked_since.take() {
+                self.conn_blocked += since.elapsed();
+            }
+        }
+
         // Assign newly acquired capacity to streams pending capacity.
         while self.flow.available() > 0 {
             let stream = match self.pending_capacity.pop(store) {
@@ -412,6 +572,11 @@ impl Prioritize {
     fn try_assign_capacity(&mut self, stream: &mut store::Ptr) {
         let total_requested = stream.requested_send_capacity;
 
+        // The stream is blocked on its own window if it requested more than
+        // the window allows.
+        let is_blocked = total_requested > stream.send_flow.window_size();
+        stream.metrics.set_send_blocked(is_blocked);
+
         // Total requested should never go below actual assigned
         // (Note: the window size can go lower than assigned)
         debug_assert!(stream.send_flow.available() <= total_requested as usize);
@@ -483,6 +648,10 @@ impl Prioritize {
             // In this case, the stream needs to be queued up for when the
             // connection has more capacity.
             self.pending_capacity.push(stream);
+
+            if self.conn_blocked_since.is_none() {
+                self.conn_blocked_since = Some(Instant::now());
+            }
         }
 
         // If data is buffered and the stream is send ready, then
@@ -504,16 +673,15 @@ impl Prioritize {
         }
     }
 
-    pub fn poll_complete<T, B>(
+    pub fn poll_complete<B>(
         &mut self,
         cx: &mut Context,
         buffer: &mut Buffer<Frame<B>>,
         store: &mut Store,
         counts: &mut Counts,
-        dst: &mut Codec<T, Prioritized<B>>,
+        dst: &mut Framed<Prioritized<B>>,
     ) -> Poll<io::Result<()>>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
     {
         // Ensure codec is ready
@@ -533,6 +701,8 @@ impl Prioritize {
                 self.try_assign_capacity(&mut stream);
             }
 
+            self.notify_open_queue(counts);
+
             match self.pop_frame(buffer, store, max_frame_len, counts) {
                 Some(frame) => {
                     tracing::trace!(?frame, "writing");
@@ -541,8 +711,25 @@ impl Prioritize {
                     if let Frame::Data(ref frame) = frame {
                         self.in_flight_data_frame = InFlightData::DataFrame(frame.payload().stream);
                     }
+
+                    // A frame interceptor may pad DATA frames, which must fit
+                    // in the flow control windows.
+                    #[cfg(feature = "unstable")]
+                    let padded = match frame {
+                        Frame::Data(ref frame) => {
+                            dst.set_max_padding(self.max_padding);
+                            Some(frame.stream_id())
+                        }
+                        _ => None,
+                    };
+
                     dst.buffer(frame).expect("invalid frame");
 
+                    #[cfg(feature = "unstable")]
+                    if let Some(id) = padded {
+                        self.send_padding(store, id, dst.take_padding());
+                    }
+
                     // Ensure the codec is ready to try the loop again.
                     ready!(dst.poll_ready(cx))?;
 
@@ -553,6 +740,12 @@ impl Prioritize {
                     // Try to flush the codec.
                     ready!(dst.flush(cx))?;
 
+                    for id in self.unflushed_headers.drain(..) {
+                        if let Some(mut stream) = store.find_mut(&id) {
+                            stream.metrics.flushed_headers();
+                        }
+                    }
+
                     // This might release a data frame...
                     if !self.reclaim_frame(buffer, store, dst) {
                         return Poll::Ready(Ok(()));
@@ -565,6 +758,44 @@ impl Prioritize {
         }
     }
 
+    /// Returns how much padding may be sent on `stream`.
+    ///
+    /// Padding is only sent with capacity that is not assigned to any stream,
+    /// so that the data already buffered can still be sent.
+    #[cfg(feature = "unstable")]
+    fn padding_capacity(&self, stream: &Stream) -> usize {
+        let unassigned = stream
+            .send_flow
+            .window_size()
+            .saturating_sub(stream.send_flow.available().as_size());
+        cmp::min(unassigned, self.flow.available().as_size()) as usize
+    }
+
+    /// Charges `len` bytes of padding sent on stream `id` to the stream and
+    /// connection send windows.
+    #[cfg(feature = "unstable")]
+    fn send_padding(&mut self, store: &mut Store, id: StreamId, len: usize) {
+        if len == 0 {
+            return;
+        }
+
+        tracing::trace!(?id, len, "sent padding");
+
+        let len = len as WindowSize;
+
+        // The stream is gone if this was its last frame, in which case its
+        // window no longer matters.
+        if let Some(mut stream) = store.find_mut(&id) {
+            // TODO: proper error handling
+            let _res = stream.send_flow.dec_send_window(len);
+            debug_assert!(_res.is_ok());
+        }
+
+        // TODO: proper error handling
+        let _res = self.flow.send_data(len);
+        debug_assert!(_res.is_ok());
+    }
+
     /// Tries to reclaim a pending data frame from the codec.
     ///
     /// Returns true if a frame was reclaimed.
@@ -572,11 +803,11 @@ impl Prioritize {
     /// When a data frame is written to the codec, it may not be written in its
     /// entirety (large chunks are split up into potentially many data frames).
     /// In this case, the stream needs to be reprioritized.
-    fn reclaim_frame<T, B>(
+    fn reclaim_frame<B>(
         &mut self,
         buffer: &mut Buffer<Frame<B>>,
         store: &mut Store,
-        dst: &mut Codec<T, Prioritized<B>>,
+        dst: &mut Framed<Prioritized<B>>,
     ) -> bool
     where
         B: Buf,
@@ -689,9 +920,12 @@ impl Prioritize {
 
     pub fn clear_pending_open(&mut self, store: &mut Store, counts: &mut Counts) {
         while let Some(stream) = self.pending_open.pop(store) {
+            self.num_pending_open -= 1;
             let is_pending_reset = stream.is_pending_reset_expiration();
             counts.transition_after(stream, is_pending_reset);
         }
+
+        self.notify_open_queue(counts);
     }
 
     fn pop_frame<B>(
@@ -721,6 +955,15 @@ impl Prioritize {
                     tracing::trace!(is_pending_reset);
 
                     let frame = match stream.pending_send.pop_front(buffer) {
+                        Some(Frame::Data(frame)) if stream.is_awaiting_continue => {
+                            // The request body is held back until the peer
+                            // answers `Expect: 100-continue`. The stream is
+                            // scheduled again once it does.
+                            tracing::trace!("awaiting 100-continue");
+                            stream.pending_send.push_front(buffer, frame.into());
+
+                            continue;
+                        }
                         Some(Frame::Data(mut frame)) => {
                             // Get the amount of capacity remaining for stream's
                             // window.
@@ -799,6 +1042,11 @@ impl Prioritize {
                                 let _res = self.flow.send_data(len);
                                 debug_assert!(_res.is_ok());
 
+                                #[cfg(feature = "unstable")]
+                                {
+                                    self.max_padding = self.padding_capacity(&stream);
+                                }
+
                                 // Wrap the frame's data payload to ensure that the
                                 // correct amount of data gets written.
 
@@ -860,6 +1108,18 @@ impl Prioritize {
                         }
                     };
 
+                    match frame {
+                        Frame::Data(ref frame) => {
+                            stream.metrics.sent_data(frame.payload().remaining())
+                        }
+                        Frame::Headers(_) => {
+                            if stream.metrics.sent_headers() {
+                                self.unflushed_headers.push(stream.id);
+                            }
+                        }
+                        _ => stream.metrics.sent_frame(),
+                    }
+
                     tracing::trace!("pop_frame; frame={:?}", frame);
 
                     if cfg!(debug_assertions) && stream.state.is_idle() {
@@ -894,6 +1154,7 @@ impl Prioritize {
         if counts.can_inc_num_send_streams() {
             if let Some(mut stream) = self.pending_open.pop(store) {
                 tracing::trace!("schedule_pending_open; stream={:?}", stream.id);
+                self.num_pending_open -= 1;
 
                 counts.inc_num_send_streams(&mut stream);
                 stream.notify_send();
@@ -2,6 +2,8 @@ use super::*;
 use crate::codec::UserError;
 use crate::frame::{PushPromiseHeaderError, Reason, DEFAULT_INITIAL_WINDOW_SIZE};
 use crate::proto;
+use crate::qlog::Owner;
+use crate::server::{Admission, AdmissionContext};
 use crate::tracing;
 
 use http::{HeaderMap, Request, Response};
@@ -43,6 +45,13 @@ pub(super) struct Recv {
     /// New streams to be accepted
     pending_accept: store::Queue<stream::NextAccept>,
 
+    /// Tasks waiting for a new stream to be accepted
+    accept_tasks: Vec<Waker>,
+
+    /// Set once the connection has closed and no more streams will be queued
+    /// to be accepted.
+    is_accept_closed: bool,
+
     /// Locally reset streams that should be reaped when they expire
     pending_reset_expired: store::Queue<stream::NextResetExpire>,
 
@@ -60,11 +69,22 @@ pub(super) struct Recv {
 
     /// If extended connect protocol is enabled.
     is_extended_connect_protocol_enabled: bool,
+
+    /// Where to record window updates sent to the peer.
+    qlog: Option<Qlog>,
+
+    /// Decides whether new remote streams are accepted.
+    admission: Option<SharedAdmission>,
 }
 
+/// Maximum number of informational responses buffered per stream before
+/// further ones are dropped.
+const MAX_BUFFERED_INFORMATIONAL: usize = 16;
+
 #[derive(Debug)]
 pub(super) enum Event {
     Headers(peer::PollMessage),
+    Informational(Response<()>),
     Data(Bytes),
     Trailers(HeaderMap),
 }
@@ -73,6 +93,16 @@ pub(super) enum Event {
 pub(super) enum RecvHeaderBlockError<T> {
     Oversize(T),
     State(Error),
+    /// The admission callback did not accept the stream.
+    NotAdmitted(Rejection),
+}
+
+/// How a stream that was not admitted is answered.
+#[derive(Debug)]
+pub(super) enum Rejection {
+    Refuse,
+    Reset(Reason),
+    Respond(Response<()>),
 }
 
 #[derive(Debug)]
@@ -102,12 +132,16 @@ impl Recv {
             last_processed_id: StreamId::ZERO,
             max_stream_id: StreamId::MAX,
             pending_accept: store::Queue::new(),
+            accept_tasks: Vec::new(),
+            is_accept_closed: false,
             pending_reset_expired: store::Queue::new(),
             reset_duration: config.local_reset_duration,
             buffer: Buffer::new(),
             refused: None,
             is_push_enabled: config.local_push_enabled,
             is_extended_connect_protocol_enabled: config.extended_connect_protocol_enabled,
+            qlog: config.qlog.clone(),
+            admission: config.admission.clone(),
         }
     }
 
@@ -116,6 +150,11 @@ impl Recv {
         self.init_window_sz
     }
 
+    /// Returns the connection level receive window.
+    pub(super) fn connection_window_size(&self) -> WindowSize {
+        self.flow.window_size()
+    }
+
     /// Returns the ID of the last processed stream
     pub fn last_processed_id(&self) -> StreamId {
         self.last_processed_id
@@ -160,6 +199,10 @@ impl Recv {
         counts: &mut Counts,
     ) -> Result<(), RecvHeaderBlockError<Option<frame::Headers>>> {
         tracing::trace!("opening stream; init_window={}", self.init_window_sz);
+        stream.metrics.recv_headers(frame.is_informational());
+
+        // The peer has started processing the request.
+        stream.request = None;
         let is_initial = stream.state.recv_open(&frame)?;
 
         if is_initial {
@@ -253,6 +296,32 @@ impl Recv {
                 .peer()
                 .convert_poll_message(pseudo, fields, stream_id)?;
 
+            if let (Some(admission), peer::PollMessage::Server(request)) =
+                (&self.admission, &message)
+            {
+                let context = AdmissionContext {
+                    stream_id: crate::StreamId::from_internal(stream_id),
+                    active_streams: counts.num_recv_streams(),
+                    max_concurrent_streams: counts.max_recv_streams(),
+                };
+
+                let rejection = match admission.admit(request, &context) {
+                    Admission::Accept => None,
+                    Admission::Refuse => Some(Rejection::Refuse),
+                    Admission::Reset(reason) => Some(Rejection::Reset(reason)),
+                    Admission::Respond(response) => Some(Rejection::Respond(response)),
+                };
+
+                if let Some(rejection) = rejection {
+                    tracing::debug!(
+                        "stream not admitted; stream={:?}; rejection={:?}",
+                        stream_id,
+                        rejection
+                    );
+                    return Err(RecvHeaderBlockError::NotAdmitted(rejection));
+                }
+            }
+
             // Push the frame onto the stream's recv buffer
             stream
                 .pending_recv
@@ -265,7 +334,27 @@ impl Recv {
                 // Correctness: never push a stream to `pending_accept` without having the
                 // corresponding headers frame pushed to `stream.pending_recv`.
                 self.pending_accept.push(stream);
+                self.notify_accept();
             }
+        } else if stream.buffered_informational < MAX_BUFFERED_INFORMATIONAL {
+            // Only clients get here, as servers reject `:status` above.
+            let message = counts
+                .peer()
+                .convert_poll_message(pseudo, fields, stream_id)?;
+
+            if let peer::PollMessage::Client(response) = message {
+                stream.buffered_informational += 1;
+                stream
+                    .pending_recv
+                    .push_back(&mut self.buffer, Event::Informational(response));
+                stream.notify_recv();
+            }
+        } else {
+            tracing::trace!(
+                "dropping informational response; stream={:?}; buffered={}",
+                stream.id,
+                stream.buffered_informational
+            );
         }
 
         Ok(())
@@ -327,17 +416,53 @@ impl Recv {
         use super::peer::PollMessage::*;
 
         // If the buffer is not empty, then the first frame must be a HEADERS
-        // frame or the user violated the contract.
+        // frame or the user violated the contract. Informational responses
+        // that were not polled by the user are discarded.
+        loop {
+            match stream.pending_recv.pop_front(&mut self.buffer) {
+                Some(Event::Headers(Client(response))) => return Poll::Ready(Ok(response)),
+                Some(Event::Informational(_)) => {
+                    stream.buffered_informational -= 1;
+                }
+                Some(_) => panic!("poll_response called after response returned"),
+                None => {
+                    if !stream.state.ensure_recv_open()? {
+                        proto_err!(stream: "poll_response: stream={:?} is not opened;",  stream.id);
+                        return Poll::Ready(Err(Error::library_reset(
+                            stream.id,
+                            Reason::PROTOCOL_ERROR,
+                        )));
+                    }
+
+                    stream.recv_task = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+
+    /// Called by the client to get the informational (1xx) responses received
+    /// ahead of the final response.
+    ///
+    /// Returns `None` once the final response has been received.
+    pub fn poll_informational(
+        &mut self,
+        cx: &Context,
+        stream: &mut store::Ptr,
+    ) -> Poll<Option<Result<Response<()>, proto::Error>>> {
         match stream.pending_recv.pop_front(&mut self.buffer) {
-            Some(Event::Headers(Client(response))) => Poll::Ready(Ok(response)),
-            Some(_) => panic!("poll_response called after response returned"),
+            Some(Event::Informational(response)) => {
+                stream.buffered_informational -= 1;
+                Poll::Ready(Some(Ok(response)))
+            }
+            Some(event) => {
+                // The final response is next, leave it for `poll_response`.
+                stream.pending_recv.push_front(&mut self.buffer, event);
+                Poll::Ready(None)
+            }
             None => {
                 if !stream.state.ensure_recv_open()? {
-                    proto_err!(stream: "poll_response: stream={:?} is not opened;",  stream.id);
-                    return Poll::Ready(Err(Error::library_reset(
-                        stream.id,
-                        Reason::PROTOCOL_ERROR,
-                    )));
+                    return Poll::Ready(None);
                 }
 
                 stream.recv_task = Some(cx.waker().clone());
@@ -352,6 +477,8 @@ impl Recv {
         frame: frame::Headers,
         stream: &mut store::Ptr,
     ) -> Result<(), Error> {
+        stream.metrics.recv_headers(false);
+
         // Transition the state
         stream.state.recv_close()?;
 
@@ -580,6 +707,7 @@ impl Recv {
 
     pub fn recv_data(&mut self, frame: frame::Data, stream: &mut store::Ptr) -> Result<(), Error> {
         let sz = frame.payload().len();
+        stream.metrics.recv_data(sz);
 
         // This should have been enforced at the codec::FramedRead layer, so
         // this is just a sanity check.
@@ -801,6 +929,8 @@ impl Recv {
         stream: &mut Stream,
         counts: &mut Counts,
     ) -> Result<(), Error> {
+        stream.metrics.recv_frame();
+
         // Reseting a stream that the user hasn't accepted is possible,
         // but should be done with care. These streams will continue
         // to take up memory in the accept queue, but will no longer be
@@ -824,6 +954,13 @@ impl Recv {
             }
         }
 
+        // > The REFUSED_STREAM error code can be included in a RST_STREAM
+        // > frame to indicate that the stream is being closed prior to any
+        // > processing having occurred.
+        if frame.reason() == Reason::REFUSED_STREAM {
+            stream.is_unprocessed = true;
+        }
+
         // Notify the stream
         stream.state.recv_reset(frame, stream.is_pending_send);
 
@@ -836,6 +973,11 @@ impl Recv {
 
     /// Handle a connection-level error
     pub fn handle_error(&mut self, err: &proto::Error, stream: &mut Stream) {
+        // Streams that were never sent cannot have been processed.
+        if stream.is_pending_open {
+            stream.is_unprocessed = true;
+        }
+
         // Receive an error
         stream.state.handle_error(err);
 
@@ -851,6 +993,10 @@ impl Recv {
     }
 
     pub fn recv_eof(&mut self, stream: &mut Stream) {
+        if stream.is_pending_open {
+            stream.is_unprocessed = true;
+        }
+
         stream.state.recv_eof();
         stream.notify_send();
         stream.notify_recv();
@@ -923,13 +1069,12 @@ impl Recv {
     }
 
     /// Send any pending refusals.
-    pub fn send_pending_refusal<T, B>(
+    pub fn send_pending_refusal<B>(
         &mut self,
         cx: &mut Context,
-        dst: &mut Codec<T, Prioritized<B>>,
+        dst: &mut Framed<Prioritized<B>>,
     ) -> Poll<io::Result<()>>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
     {
         if let Some(stream_id) = self.refused {
@@ -998,15 +1143,14 @@ impl Recv {
         }
     }
 
-    pub fn poll_complete<T, B>(
+    pub fn poll_complete<B>(
         &mut self,
         cx: &mut Context,
         store: &mut Store,
         counts: &mut Counts,
-        dst: &mut Codec<T, Prioritized<B>>,
+        dst: &mut Framed<Prioritized<B>>,
     ) -> Poll<io::Result<()>>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
     {
         // Send any pending connection level window updates
@@ -1019,13 +1163,12 @@ impl Recv {
     }
 
     /// Send connection level window update
-    fn send_connection_window_update<T, B>(
+    fn send_connection_window_update<B>(
         &mut self,
         cx: &mut Context,
-        dst: &mut Codec<T, Prioritized<B>>,
+        dst: &mut Framed<Prioritized<B>>,
     ) -> Poll<io::Result<()>>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
     {
         if let Some(incr) = self.flow.unclaimed_capacity() {
@@ -1042,21 +1185,25 @@ impl Recv {
             self.flow
                 .inc_window(incr)
                 .expect("unexpected flow control state");
+
+            if let Some(ref qlog) = self.qlog {
+                let window = self.flow.window_size();
+                qlog.flow_control_updated(Owner::Local, StreamId::ZERO, incr, window);
+            }
         }
 
         Poll::Ready(Ok(()))
     }
 
     /// Send stream level window update
-    pub fn send_stream_window_updates<T, B>(
+    pub fn send_stream_window_updates<B>(
         &mut self,
         cx: &mut Context,
         store: &mut Store,
         counts: &mut Counts,
-        dst: &mut Codec<T, Prioritized<B>>,
+        dst: &mut Framed<Prioritized<B>>,
     ) -> Poll<io::Result<()>>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
     {
         loop {
@@ -1091,12 +1238,18 @@ impl Recv {
                     // Buffer it
                     dst.buffer(frame.into())
                         .expect("invalid WINDOW_UPDATE frame");
+                    stream.metrics.sent_frame();
 
                     // Update flow control
                     stream
                         .recv_flow
                         .inc_window(incr)
                         .expect("unexpected flow control state");
+
+                    if let Some(ref qlog) = self.qlog {
+                        let window = stream.recv_flow.window_size();
+                        qlog.flow_control_updated(Owner::Local, stream.id, incr, window);
+                    }
                 }
             })
         }
@@ -1106,6 +1259,31 @@ impl Recv {
         self.pending_accept.pop(store).map(|ptr| ptr.key())
     }
 
+    /// Returns `true` once no more streams will be queued to be accepted.
+    pub fn is_accept_closed(&self) -> bool {
+        self.is_accept_closed
+    }
+
+    /// Registers a task to be notified when a stream is queued to be
+    /// accepted, or when the connection closes.
+    pub fn register_accept_task(&mut self, waker: &Waker) {
+        if !self.accept_tasks.iter().any(|task| task.will_wake(waker)) {
+            self.accept_tasks.push(waker.clone());
+        }
+    }
+
+    /// Called once the connection has closed.
+    pub fn close_accept(&mut self) {
+        self.is_accept_closed = true;
+        self.notify_accept();
+    }
+
+    fn notify_accept(&mut self) {
+        for task in self.accept_tasks.drain(..) {
+            task.wake();
+        }
+    }
+
     pub fn poll_data(
         &mut self,
         cx: &Context,
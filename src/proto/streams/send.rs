@@ -1,19 +1,20 @@
 use super::{
-    store, Buffer, Codec, Config, Counts, Frame, Prioritize, Prioritized, Store, Stream, StreamId,
+    store, Buffer, Config, Counts, Frame, Framed, Prioritize, Prioritized, Store, Stream, StreamId,
     StreamIdOverflow, WindowSize,
 };
 use crate::codec::UserError;
 use crate::frame::{self, Reason};
 use crate::proto::{self, Error, Initiator};
+use crate::qlog::{Owner, Qlog};
 use crate::tracing;
 
 use bytes::Buf;
-use tokio::io::AsyncWrite;
 
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::io;
 use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
 
 /// Manages state transitions related to outbound frames.
 #[derive(Debug)]
@@ -21,6 +22,9 @@ pub(super) struct Send {
     /// Stream identifier to use for next initialized stream.
     next_stream_id: Result<StreamId, StreamIdOverflow>,
 
+    /// Highest stream ID to allocate.
+    last_local_stream_id: StreamId,
+
     /// Any streams with a higher ID are ignored.
     ///
     /// This starts as MAX, but is lowered when a GOAWAY is received.
@@ -40,6 +44,15 @@ pub(super) struct Send {
 
     /// If extended connect protocol is enabled.
     is_extended_connect_protocol_enabled: bool,
+
+    /// The peer's `SETTINGS_MAX_HEADER_LIST_SIZE`, if advertised.
+    max_header_list_size: Option<usize>,
+
+    /// Answer with a `431` when a response header list is too large.
+    is_oversized_response_431: bool,
+
+    /// Where to record window updates received from the peer.
+    qlog: Option<Qlog>,
 }
 
 /// A value to detect which public API has called `poll_reset`.
@@ -56,9 +69,13 @@ impl Send {
             init_window_sz: config.remote_init_window_sz,
             max_stream_id: StreamId::MAX,
             next_stream_id: Ok(config.local_next_stream_id),
+            last_local_stream_id: config.local_max_stream_id,
             prioritize: Prioritize::new(config),
             is_push_enabled: true,
             is_extended_connect_protocol_enabled: false,
+            max_header_list_size: None,
+            is_oversized_response_431: config.oversized_response_431,
+            qlog: config.qlog.clone(),
         }
     }
 
@@ -98,6 +115,22 @@ impl Send {
         Ok(())
     }
 
+    /// Ensures a header list fits within the peer's
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE`.
+    fn check_header_list_size(&self, size: usize) -> Result<(), UserError> {
+        match self.max_header_list_size {
+            Some(max) if size > max => {
+                tracing::debug!(
+                    "header list too large; size={}; max_header_list_size={}",
+                    size,
+                    max
+                );
+                Err(UserError::HeaderListTooLarge)
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn send_push_promise<B>(
         &mut self,
         frame: frame::PushPromise,
@@ -116,6 +149,7 @@ impl Send {
         );
 
         Self::check_headers(frame.fields())?;
+        self.check_header_list_size(frame.header_list_size())?;
 
         // Queue the frame for sending
         self.prioritize
@@ -135,6 +169,38 @@ impl Send {
         self.send_priority_and_headers(None, frame, buffer, stream, counts, task)
     }
 
+    /// Queues an informational (1xx) HEADERS frame, leaving the stream ready
+    /// to send the final response.
+    pub fn send_informational<B>(
+        &mut self,
+        frame: frame::Headers,
+        buffer: &mut Buffer<Frame<B>>,
+        stream: &mut store::Ptr,
+        task: &mut Option<Waker>,
+    ) -> Result<(), UserError> {
+        Self::check_headers(frame.fields())?;
+        self.check_header_list_size(frame.header_list_size())?;
+
+        if !stream.state.is_send_headers() {
+            return Err(UserError::UnexpectedFrameType);
+        }
+
+        tracing::trace!("send_informational; frame={:?}", frame);
+
+        self.prioritize
+            .queue_frame(frame.into(), buffer, stream, task);
+
+        Ok(())
+    }
+
+    /// Schedules the stream's queued frames, after its request body was held
+    /// back for `Expect: 100-continue`.
+    pub fn schedule_continue(&mut self, stream: &mut store::Ptr, task: &mut Option<Waker>) {
+        if !stream.pending_send.is_empty() {
+            self.prioritize.schedule_send(stream, task);
+        }
+    }
+
     pub fn send_priority_and_headers<B>(
         &mut self,
         priority_frame: Option<Cow<'static, [frame::Priority]>>,
@@ -145,6 +211,7 @@ impl Send {
         task: &mut Option<Waker>,
     ) -> Result<(), UserError> {
         Self::check_headers(headers_frame.fields())?;
+        self.check_header_list_size(headers_frame.header_list_size())?;
 
         let end_stream = headers_frame.is_end_stream();
 
@@ -308,6 +375,8 @@ impl Send {
             return Err(UserError::UnexpectedFrameType);
         }
 
+        self.check_header_list_size(frame.header_list_size())?;
+
         stream.state.send_close();
 
         tracing::trace!("send_trailers -- queuing; frame={:?}", frame);
@@ -320,16 +389,15 @@ impl Send {
         Ok(())
     }
 
-    pub fn poll_complete<T, B>(
+    pub fn poll_complete<B>(
         &mut self,
         cx: &mut Context,
         buffer: &mut Buffer<Frame<B>>,
         store: &mut Store,
         counts: &mut Counts,
-        dst: &mut Codec<T, Prioritized<B>>,
+        dst: &mut Framed<Prioritized<B>>,
     ) -> Poll<io::Result<()>>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
     {
         self.prioritize
@@ -391,8 +459,16 @@ impl Send {
         store: &mut Store,
         counts: &mut Counts,
     ) -> Result<(), Reason> {
+        let inc = frame.size_increment();
         self.prioritize
-            .recv_connection_window_update(frame.size_increment(), store, counts)
+            .recv_connection_window_update(inc, store, counts)?;
+
+        if let Some(ref qlog) = self.qlog {
+            let (window, _) = self.prioritize.connection_window_stats();
+            qlog.flow_control_updated(Owner::Remote, StreamId::ZERO, inc, window);
+        }
+
+        Ok(())
     }
 
     pub fn recv_stream_window_update<B>(
@@ -403,6 +479,8 @@ impl Send {
         counts: &mut Counts,
         task: &mut Option<Waker>,
     ) -> Result<(), Reason> {
+        stream.metrics.recv_frame();
+
         if let Err(e) = self.prioritize.recv_stream_window_update(sz, stream) {
             tracing::debug!("recv_stream_window_update !!; err={:?}", e);
 
@@ -418,6 +496,11 @@ impl Send {
             return Err(e);
         }
 
+        if let Some(ref qlog) = self.qlog {
+            let window = stream.send_flow.window_size();
+            qlog.flow_control_updated(Owner::Remote, stream.id, sz, window);
+        }
+
         Ok(())
     }
 
@@ -464,6 +547,10 @@ impl Send {
             self.is_extended_connect_protocol_enabled = val;
         }
 
+        if let Some(val) = settings.max_header_list_size() {
+            self.max_header_list_size = Some(val as usize);
+        }
+
         // Applies an update to the remote endpoint's initial window size.
         //
         // Per RFC 7540 §6.9.2:
@@ -576,6 +663,33 @@ impl Send {
         Ok(())
     }
 
+    pub fn is_open_queue_bounded(&self) -> bool {
+        self.prioritize.is_open_queue_bounded()
+    }
+
+    pub fn has_pending_open(&self) -> bool {
+        self.prioritize.has_pending_open()
+    }
+
+    pub fn is_open_queue_full(&self, counts: &Counts) -> bool {
+        self.prioritize.is_open_queue_full(counts)
+    }
+
+    pub fn poll_open_queue(&mut self, cx: &Context, counts: &Counts) -> Poll<()> {
+        self.prioritize.poll_open_queue(cx, counts)
+    }
+
+    pub fn expire_pending_open<B>(
+        &mut self,
+        now: Instant,
+        buffer: &mut Buffer<Frame<B>>,
+        store: &mut Store,
+        counts: &mut Counts,
+    ) -> Option<Instant> {
+        self.prioritize
+            .expire_pending_open(now, buffer, store, counts)
+    }
+
     pub fn clear_queues(&mut self, store: &mut Store, counts: &mut Counts) {
         self.prioritize.clear_pending_capacity(store, counts);
         self.prioritize.clear_pending_send(store, counts);
@@ -594,8 +708,11 @@ impl Send {
     }
 
     pub fn ensure_next_stream_id(&self) -> Result<StreamId, UserError> {
-        self.next_stream_id
-            .map_err(|_| UserError::OverflowedStreamId)
+        match self.next_stream_id {
+            Ok(id) if id > self.last_local_stream_id => Err(UserError::ConnectionExhausted),
+            Ok(id) => Ok(id),
+            Err(_) => Err(UserError::OverflowedStreamId),
+        }
     }
 
     pub fn may_have_created_stream(&self, id: StreamId) -> bool {
@@ -621,4 +738,14 @@ impl Send {
     pub(crate) fn is_extended_connect_protocol_enabled(&self) -> bool {
         self.is_extended_connect_protocol_enabled
     }
+
+    pub(super) fn is_oversized_response_431(&self) -> bool {
+        self.is_oversized_response_431
+    }
+
+    /// Returns the connection level send window, and the total time streams
+    /// spent waiting on it.
+    pub(super) fn connection_window_stats(&self) -> (WindowSize, Duration) {
+        self.prioritize.connection_window_stats()
+    }
 }
@@ -388,6 +388,17 @@ impl State {
         )
     }
 
+    /// Returns true when the stream is in a state to send headers
+    pub fn is_send_headers(&self) -> bool {
+        matches!(
+            self.inner,
+            Open {
+                local: AwaitingHeaders,
+                ..
+            } | HalfClosedRemote(AwaitingHeaders)
+        )
+    }
+
     /// Returns true when the stream is in a state to receive headers
     pub fn is_recv_headers(&self) -> bool {
         matches!(
@@ -414,6 +425,19 @@ impl State {
         matches!(self.inner, Closed(_))
     }
 
+    /// Returns the name of the state, as used in RFC 9113.
+    pub fn name(&self) -> &'static str {
+        match self.inner {
+            Idle => "idle",
+            ReservedLocal => "reserved_local",
+            ReservedRemote => "reserved_remote",
+            Open { .. } => "open",
+            HalfClosedLocal(..) => "half_closed_local",
+            HalfClosedRemote(..) => "half_closed_remote",
+            Closed(..) => "closed",
+        }
+    }
+
     pub fn is_recv_closed(&self) -> bool {
         matches!(
             self.inner,
@@ -1,9 +1,12 @@
+use crate::stats::StreamMetrics;
 use crate::{tracing, Reason};
 
 use super::*;
 
+use http::Request;
+use std::mem;
 use std::task::{Context, Waker};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 /// Tracks Stream related state
 ///
@@ -24,6 +27,9 @@ pub(super) struct Stream {
     /// Current state of the stream
     pub state: State,
 
+    /// Name of the state last recorded in the qlog trace
+    pub qlog_state: &'static str,
+
     /// Set to `true` when the stream is counted against the connection's max
     /// concurrent streams.
     pub is_counted: bool,
@@ -73,6 +79,20 @@ pub(super) struct Stream {
     /// Set to true when a push is pending for this stream
     pub is_pending_push: bool,
 
+    /// Set to true when DATA frames are held back until the peer answers an
+    /// `Expect: 100-continue` request.
+    pub is_awaiting_continue: bool,
+
+    /// Copy of the request that opened the stream, kept until the peer
+    /// responds so that it can be returned if the peer did not process it.
+    pub request: Option<Request<()>>,
+
+    /// Set to true when the peer is known not to have processed the stream.
+    pub is_unprocessed: bool,
+
+    /// When the stream fails if it is still waiting to be opened.
+    pub open_deadline: Option<Instant>,
+
     // ===== Fields related to receiving =====
     /// Next node in the accept linked list
     pub next_pending_accept: Option<store::Key>,
@@ -100,6 +120,9 @@ pub(super) struct Stream {
     /// Frames pending for this stream to read
     pub pending_recv: buffer::Deque,
 
+    /// Number of informational (1xx) responses queued in `pending_recv`
+    pub buffered_informational: usize,
+
     /// When the RecvStream drop occurs, no data should be received.
     pub is_recv: bool,
 
@@ -114,6 +137,9 @@ pub(super) struct Stream {
 
     /// Validate content-length headers
     pub content_length: ContentLength,
+
+    /// Timings and frame counts reported to the user
+    pub metrics: Metrics,
 }
 
 /// State related to validating a stream's content-length
@@ -124,6 +150,32 @@ pub enum ContentLength {
     Remaining(u64),
 }
 
+/// Timings and frame counts of a stream.
+#[derive(Debug)]
+pub(super) struct Metrics {
+    /// When the stream was created
+    opened: Instant,
+
+    /// Whether the first HEADERS frame was buffered in the codec.
+    headers_buffered: bool,
+    headers_sent: Option<Instant>,
+    headers_received: Option<Instant>,
+    final_headers_received: Option<Instant>,
+    first_data_received: Option<Instant>,
+
+    frames_sent: u64,
+    frames_received: u64,
+    data_bytes_sent: u64,
+    data_bytes_received: u64,
+
+    /// Time spent waiting for the stream's send window, excluding the
+    /// current wait
+    send_blocked: Duration,
+
+    /// When the stream started waiting for its send window
+    send_blocked_since: Option<Instant>,
+}
+
 #[derive(Debug)]
 pub(super) struct NextAccept;
 
@@ -161,6 +213,7 @@ impl Stream {
         Stream {
             id,
             state: State::default(),
+            qlog_state: "idle",
             ref_count: 0,
             is_counted: false,
 
@@ -178,6 +231,10 @@ impl Stream {
             is_pending_open: false,
             next_open: None,
             is_pending_push: false,
+            is_awaiting_continue: false,
+            request: None,
+            is_unprocessed: false,
+            open_deadline: None,
 
             // ===== Fields related to receiving =====
             next_pending_accept: None,
@@ -189,11 +246,13 @@ impl Stream {
             reset_at: None,
             next_reset_expire: None,
             pending_recv: buffer::Deque::new(),
+            buffered_informational: 0,
             is_recv: true,
             recv_task: None,
             push_task: None,
             pending_push_promises: store::Queue::new(),
             content_length: ContentLength::Omitted,
+            metrics: Metrics::new(),
         }
     }
 
@@ -544,3 +603,103 @@ impl ContentLength {
         matches!(*self, Self::Head)
     }
 }
+
+// ===== impl Metrics =====
+
+impl Metrics {
+    fn new() -> Metrics {
+        Metrics {
+            opened: Instant::now(),
+            headers_buffered: false,
+            headers_sent: None,
+            headers_received: None,
+            final_headers_received: None,
+            first_data_received: None,
+            frames_sent: 0,
+            frames_received: 0,
+            data_bytes_sent: 0,
+            data_bytes_received: 0,
+            send_blocked: Duration::ZERO,
+            send_blocked_since: None,
+        }
+    }
+
+    /// Counts a HEADERS frame buffered in the codec, and returns true if it
+    /// is the first of the stream.
+    pub fn sent_headers(&mut self) -> bool {
+        self.frames_sent += 1;
+        !mem::replace(&mut self.headers_buffered, true)
+    }
+
+    /// Records that the first HEADERS frame was flushed to the I/O.
+    pub fn flushed_headers(&mut self) {
+        self.headers_sent.get_or_insert_with(Instant::now);
+    }
+
+    pub fn sent_data(&mut self, len: usize) {
+        self.data_bytes_sent += len as u64;
+        self.frames_sent += 1;
+    }
+
+    /// Counts a sent frame that is neither HEADERS nor DATA.
+    pub fn sent_frame(&mut self) {
+        self.frames_sent += 1;
+    }
+
+    /// Counts a received HEADERS frame, which is `informational` if it holds
+    /// a 1xx response.
+    pub fn recv_headers(&mut self, informational: bool) {
+        let now = Instant::now();
+        self.headers_received.get_or_insert(now);
+        if !informational {
+            self.final_headers_received.get_or_insert(now);
+        }
+        self.frames_received += 1;
+    }
+
+    pub fn recv_data(&mut self, len: usize) {
+        if len > 0 {
+            self.first_data_received.get_or_insert_with(Instant::now);
+        }
+        self.data_bytes_received += len as u64;
+        self.frames_received += 1;
+    }
+
+    /// Counts a received frame that is neither HEADERS nor DATA.
+    pub fn recv_frame(&mut self) {
+        self.frames_received += 1;
+    }
+
+    /// Records whether the stream is waiting for its send window to grow.
+    pub fn set_send_blocked(&mut self, blocked: bool) {
+        match (blocked, self.send_blocked_since) {
+            (true, None) => self.send_blocked_since = Some(Instant::now()),
+            (false, Some(since)) => {
+                self.send_blocked += since.elapsed();
+                self.send_blocked_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn snapshot(&self) -> StreamMetrics {
+        let since_opened = |at: Option<Instant>| at.map(|at| at - self.opened);
+
+        let send_blocked = match self.send_blocked_since {
+            Some(since) => self.send_blocked + since.elapsed(),
+            None => self.send_blocked,
+        };
+
+        StreamMetrics {
+            headers_sent: since_opened(self.headers_sent),
+            headers_received: since_opened(self.headers_received),
+            final_headers_received: since_opened(self.final_headers_received),
+            first_data_received: since_opened(self.first_data_received),
+            frames_sent: self.frames_sent,
+            frames_received: self.frames_received,
+            data_bytes_sent: self.data_bytes_sent,
+            data_bytes_received: self.data_bytes_received,
+            send_blocked,
+        }
+    }
+}
@@ -1,18 +1,19 @@
 use super::frame::{Priority, PseudoOrders, StreamDependency};
-use super::recv::RecvHeaderBlockError;
+use super::recv::{RecvHeaderBlockError, Rejection};
 use super::store::{self, Entry, Resolve, Store};
 use super::{Buffer, Config, Counts, Prioritized, Recv, Send, Stream, StreamId};
-use crate::codec::{Codec, SendError, UserError};
+use crate::codec::{CodecStats, Framed, SendError, UserError};
 use crate::ext::Protocol;
 use crate::frame::{self, Frame, Reason};
 use crate::proto::{peer, Error, Initiator, Open, Peer, WindowSize};
+use crate::stats::{ConnectionStats, FlowControlStats, StreamMetrics};
 use crate::{client, proto, server, tracing};
 
 use bytes::{Buf, Bytes};
-use http::{HeaderMap, Request, Response};
+use http::{HeaderMap, Request, Response, StatusCode};
 use std::borrow::Cow;
 use std::task::{Context, Poll, Waker};
-use tokio::io::AsyncWrite;
+use std::time::Instant;
 
 use std::sync::{Arc, Mutex};
 use std::{fmt, io};
@@ -36,6 +37,9 @@ where
     /// been shown to be necessary.
     send_buffer: Arc<SendBuffer<B>>,
 
+    /// Frame and HPACK counters maintained by the codec.
+    codec_stats: Arc<CodecStats>,
+
     _p: ::std::marker::PhantomData<P>,
 }
 
@@ -89,6 +93,12 @@ struct Inner {
 
     /// Priority of the headers stream
     priority: Option<Cow<'static, [Priority]>>,
+
+    /// Hold back request bodies until `100 Continue` is received.
+    expect_continue: bool,
+
+    /// Keep a copy of requests for errors of unprocessed streams.
+    retain_unprocessed_requests: bool,
 }
 
 #[derive(Debug)]
@@ -104,6 +114,10 @@ struct Actions {
 
     /// If the connection errors, a copy is kept for any StreamRefs.
     conn_error: Option<proto::Error>,
+
+    /// Set when a graceful shutdown is requested from outside of the task
+    /// driving the connection.
+    graceful_shutdown_requested: bool,
 }
 
 /// Contains the buffer of frames to be written to the wire.
@@ -119,12 +133,13 @@ where
     B: Buf,
     P: Peer,
 {
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: Config, codec_stats: Arc<CodecStats>) -> Self {
         let peer = P::r#dyn();
 
         Streams {
             inner: Inner::new(peer, config),
             send_buffer: Arc::new(SendBuffer::new()),
+            codec_stats,
 
             _p: ::std::marker::PhantomData,
         }
@@ -141,7 +156,42 @@ where
 
     pub fn next_incoming(&mut self) -> Option<StreamRef<B>> {
         let mut me = self.inner.lock().unwrap();
-        let me = &mut *me;
+        self.take_incoming(&mut me)
+    }
+
+    /// Returns the next stream to be accepted, or `None` once the connection
+    /// has closed.
+    pub fn poll_accept(&self, cx: &Context) -> Poll<Option<StreamRef<B>>> {
+        let mut me = self.inner.lock().unwrap();
+
+        if let Some(stream) = self.take_incoming(&mut me) {
+            return Poll::Ready(Some(stream));
+        }
+
+        if me.actions.recv.is_accept_closed() {
+            return Poll::Ready(None);
+        }
+
+        me.actions.recv.register_accept_task(cx.waker());
+        Poll::Pending
+    }
+
+    /// Asks the task driving the connection to start a graceful shutdown.
+    pub fn request_graceful_shutdown(&self) {
+        let mut me = self.inner.lock().unwrap();
+        me.actions.graceful_shutdown_requested = true;
+
+        if let Some(task) = me.actions.task.take() {
+            task.wake();
+        }
+    }
+
+    pub fn take_graceful_shutdown_request(&self) -> bool {
+        let mut me = self.inner.lock().unwrap();
+        std::mem::replace(&mut me.actions.graceful_shutdown_requested, false)
+    }
+
+    fn take_incoming(&self, me: &mut Inner) -> Option<StreamRef<B>> {
         me.actions.recv.next_incoming(&mut me.store).map(|key| {
             let stream = &mut me.store.resolve(key);
             tracing::trace!(
@@ -165,14 +215,12 @@ where
         })
     }
 
-    pub fn send_pending_refusal<T>(
+    pub fn send_pending_refusal(
         &mut self,
         cx: &mut Context,
-        dst: &mut Codec<T, Prioritized<B>>,
+        dst: &mut Framed<Prioritized<B>>,
     ) -> Poll<io::Result<()>>
-    where
-        T: AsyncWrite + Unpin,
-    {
+where {
         let mut me = self.inner.lock().unwrap();
         let me = &mut *me;
         me.actions.recv.send_pending_refusal(cx, dst)
@@ -186,14 +234,12 @@ where
             .clear_expired_reset_streams(&mut me.store, &mut me.counts);
     }
 
-    pub fn poll_complete<T>(
+    pub fn poll_complete(
         &mut self,
         cx: &mut Context,
-        dst: &mut Codec<T, Prioritized<B>>,
+        dst: &mut Framed<Prioritized<B>>,
     ) -> Poll<io::Result<()>>
-    where
-        T: AsyncWrite + Unpin,
-    {
+where {
         let mut me = self.inner.lock().unwrap();
         me.poll_complete(&self.send_buffer, cx, dst)
     }
@@ -267,6 +313,10 @@ where
             }
         }
 
+        if me.actions.send.is_open_queue_full(&me.counts) {
+            return Err(UserError::Rejected.into());
+        }
+
         if me.counts.peer().is_server() {
             // Servers cannot open streams. PushPromise must first be reserved.
             return Err(UserError::UnexpectedFrameType.into());
@@ -284,6 +334,14 @@ where
             stream.content_length = ContentLength::Head;
         }
 
+        if me.expect_continue && !end_of_stream && proto::expects_continue(request.headers()) {
+            stream.is_awaiting_continue = true;
+        }
+
+        if me.retain_unprocessed_requests {
+            stream.request = Some(unsent_request(&request, protocol.as_ref()));
+        }
+
         // Convert the message
         let (priority, headers) = client::Peer::convert_send_message(
             stream_id,
@@ -317,12 +375,16 @@ where
         // Given that the stream has been initialized, it should not be in the
         // closed state.
         debug_assert!(!stream.state.is_closed());
+        me.counts.record_state(&mut stream);
 
         // TODO: ideally, OpaqueStreamRefs::new would do this, but we're holding
         // the lock, so it can't.
         me.refs += 1;
 
-        let is_full = me.counts.next_send_stream_will_reach_capacity();
+        // With a request queue, handles are not limited to one buffered
+        // request.
+        let is_full = !me.actions.send.is_open_queue_bounded()
+            && me.counts.next_send_stream_will_reach_capacity();
         Ok((
             StreamRef {
                 opaque: OpaqueStreamRef::new(self.inner.clone(), &mut stream),
@@ -346,6 +408,20 @@ where
         me.counts.max_send_streams()
     }
 
+    /// Fails the streams that waited to be opened until their deadline, and
+    /// returns the deadline of the next one.
+    pub fn expire_pending_open(&mut self, now: Instant) -> Option<Instant> {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let mut send_buffer = self.send_buffer.inner.lock().unwrap();
+        let send_buffer = &mut *send_buffer;
+
+        me.actions
+            .send
+            .expire_pending_open(now, send_buffer, &mut me.store, &mut me.counts)
+    }
+
     pub fn current_max_recv_streams(&self) -> usize {
         let me = self.inner.lock().unwrap();
         me.counts.max_recv_streams()
@@ -428,12 +504,15 @@ impl Inner {
                 send: Send::new(&config),
                 task: None,
                 conn_error: None,
+                graceful_shutdown_requested: false,
             },
             store: Store::new(),
             refs: 1,
             headers_priority: config.headers_priority,
             headers_pseudo_order: config.headers_pseudo_order,
             priority: config.priority,
+            expect_continue: config.expect_continue,
+            retain_unprocessed_requests: config.retain_unprocessed_requests,
         }))
     }
 
@@ -516,9 +595,23 @@ impl Inner {
                 stream.state
             );
 
+            // A `100 Continue` or the final response releases a request body
+            // held back for `Expect: 100-continue`.
+            let releases_body = stream.is_awaiting_continue
+                && frame.pseudo().status.map_or(false, |status| {
+                    status == StatusCode::CONTINUE || !status.is_informational()
+                });
+
             let res = if stream.state.is_recv_headers() {
                 match actions.recv.recv_headers(frame, stream, counts) {
-                    Ok(()) => Ok(()),
+                    Ok(()) => {
+                        if releases_body {
+                            tracing::trace!("releasing request body; stream={:?}", stream.id);
+                            stream.is_awaiting_continue = false;
+                            actions.send.schedule_continue(stream, &mut actions.task);
+                        }
+                        Ok(())
+                    }
                     Err(RecvHeaderBlockError::Oversize(resp)) => {
                         if let Some(resp) = resp {
                             let sent = actions.send.send_headers(
@@ -539,6 +632,10 @@ impl Inner {
                         }
                     },
                     Err(RecvHeaderBlockError::State(err)) => Err(err),
+                    Err(RecvHeaderBlockError::NotAdmitted(rejection)) => {
+                        Inner::reject_admission(rejection, actions, send_buffer, stream, counts);
+                        Ok(())
+                    }
                 }
             } else {
                 if !frame.is_end_stream() {
@@ -555,6 +652,56 @@ impl Inner {
         })
     }
 
+    /// Answers a request that was not admitted, by sending the response
+    /// chosen by the admission callback or resetting the stream.
+    fn reject_admission<B>(
+        rejection: Rejection,
+        actions: &mut Actions,
+        send_buffer: &mut Buffer<Frame<B>>,
+        stream: &mut store::Ptr,
+        counts: &mut Counts,
+    ) {
+        let reason = match rejection {
+            Rejection::Refuse => Reason::REFUSED_STREAM,
+            Rejection::Reset(reason) => reason,
+            Rejection::Respond(response) => {
+                let frame = server::Peer::convert_send_message(stream.id, response, true);
+                match actions.send.send_headers(
+                    frame,
+                    send_buffer,
+                    stream,
+                    counts,
+                    &mut actions.task,
+                ) {
+                    // The request body, if any, is not wanted once the
+                    // response is sent.
+                    Ok(()) => Reason::NO_ERROR,
+                    Err(_e) => {
+                        tracing::debug!("failed to send admission response; err={:?}", _e);
+                        Reason::INTERNAL_ERROR
+                    }
+                }
+            }
+        };
+
+        if reason == Reason::NO_ERROR {
+            actions
+                .send
+                .schedule_implicit_reset(stream, reason, counts, &mut actions.task);
+        } else {
+            actions.send.send_reset(
+                reason,
+                Initiator::User,
+                send_buffer,
+                stream,
+                counts,
+                &mut actions.task,
+            );
+        }
+
+        actions.recv.enqueue_reset_expiration(stream, counts);
+    }
+
     fn recv_data<B>(
         &mut self,
         peer: peer::Dyn,
@@ -741,6 +888,7 @@ impl Inner {
         self.store.for_each(|stream| {
             if stream.id > last_stream_id {
                 counts.transition(stream, |counts, stream| {
+                    stream.is_unprocessed = true;
                     actions.recv.handle_error(&err, &mut *stream);
                     actions.send.handle_error(send_buffer, stream, counts);
                 })
@@ -887,17 +1035,17 @@ impl Inner {
         });
 
         actions.clear_queues(clear_pending_accept, &mut self.store, counts);
+        actions.recv.close_accept();
         Ok(())
     }
 
-    fn poll_complete<T, B>(
+    fn poll_complete<B>(
         &mut self,
         send_buffer: &SendBuffer<B>,
         cx: &mut Context,
-        dst: &mut Codec<T, Prioritized<B>>,
+        dst: &mut Framed<Prioritized<B>>,
     ) -> Poll<io::Result<()>>
     where
-        T: AsyncWrite + Unpin,
         B: Buf,
     {
         let mut send_buffer = send_buffer.inner.lock().unwrap();
@@ -995,6 +1143,8 @@ where
                 return Poll::Pending;
             }
         }
+
+        ready!(me.actions.send.poll_open_queue(cx, &me.counts));
         Poll::Ready(Ok(()))
     }
 }
@@ -1043,11 +1193,49 @@ where
         me.counts.has_streams()
     }
 
+    pub fn total_recv_streams(&self) -> u64 {
+        let me = self.inner.lock().unwrap();
+        me.counts.total_recv_streams()
+    }
+
+    /// Returns true if no more streams can be opened locally, and no stream
+    /// is active or waiting to be opened.
+    pub fn is_exhausted_and_idle(&self) -> bool {
+        let me = self.inner.lock().unwrap();
+        me.actions.send.ensure_next_stream_id().is_err()
+            && !me.counts.has_streams()
+            && !me.actions.send.has_pending_open()
+    }
+
     pub fn has_streams_or_other_references(&self) -> bool {
         let me = self.inner.lock().unwrap();
         me.counts.has_streams() || me.refs > 1
     }
 
+    pub fn stats(&self) -> ConnectionStats {
+        let mut stats = ConnectionStats {
+            sent: self.codec_stats.sent(),
+            received: self.codec_stats.received(),
+            hpack_encoder: self.codec_stats.hpack_encoder(),
+            hpack_decoder: self.codec_stats.hpack_decoder(),
+            ..Default::default()
+        };
+        stats.streams.reset_local = self.codec_stats.sent_resets();
+        stats.streams.reset_remote = self.codec_stats.received_resets();
+
+        let me = self.inner.lock().unwrap();
+        me.counts.stats(&mut stats.streams);
+
+        let (send_window, send_blocked) = me.actions.send.connection_window_stats();
+        stats.flow_control = FlowControlStats {
+            send_window,
+            recv_window: me.actions.recv.connection_window_size(),
+            send_blocked,
+        };
+
+        stats
+    }
+
     #[cfg(feature = "unstable")]
     pub fn num_wired_streams(&self) -> usize {
         let me = self.inner.lock().unwrap();
@@ -1065,6 +1253,7 @@ where
         Streams {
             inner: self.inner.clone(),
             send_buffer: self.send_buffer.clone(),
+            codec_stats: self.codec_stats.clone(),
             _p: ::std::marker::PhantomData,
         }
     }
@@ -1163,9 +1352,53 @@ impl<B> StreamRef<B> {
         me.counts.transition(stream, |counts, stream| {
             let frame = server::Peer::convert_send_message(stream.id, response, end_of_stream);
 
+            let res =
+                actions
+                    .send
+                    .send_headers(frame, send_buffer, stream, counts, &mut actions.task);
+
+            if let Err(UserError::HeaderListTooLarge) = res {
+                if actions.send.is_oversized_response_431() {
+                    let mut frame = frame::Headers::new(
+                        stream.id,
+                        frame::Pseudo::response(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE),
+                        HeaderMap::new(),
+                        None,
+                    );
+                    frame.set_end_stream();
+
+                    let sent = actions.send.send_headers(
+                        frame,
+                        send_buffer,
+                        stream,
+                        counts,
+                        &mut actions.task,
+                    );
+                    debug_assert!(sent.is_ok(), "431 response should not fail");
+                }
+            }
+
+            res
+        })
+    }
+
+    pub fn send_informational(&mut self, mut response: Response<()>) -> Result<(), UserError> {
+        // Clear before taking lock, incase extensions contain a StreamRef.
+        response.extensions_mut().clear();
+        let mut me = self.opaque.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let stream = me.store.resolve(self.opaque.key);
+        let actions = &mut me.actions;
+        let mut send_buffer = self.send_buffer.inner.lock().unwrap();
+        let send_buffer = &mut *send_buffer;
+
+        me.counts.transition(stream, |_, stream| {
+            let frame = server::Peer::convert_send_message(stream.id, response, false);
+
             actions
                 .send
-                .send_headers(frame, send_buffer, stream, counts, &mut actions.task)
+                .send_informational(frame, send_buffer, stream, &mut actions.task)
         })
     }
 
@@ -1215,9 +1448,11 @@ impl<B> StreamRef<B> {
             return Err(err);
         }
 
+        let mut child_stream = me.store.resolve(child_key);
+        me.counts.record_state(&mut child_stream);
+
         me.refs += 1;
-        let opaque =
-            OpaqueStreamRef::new(self.opaque.inner.clone(), &mut me.store.resolve(child_key));
+        let opaque = OpaqueStreamRef::new(self.opaque.inner.clone(), &mut child_stream);
 
         Ok(StreamRef {
             opaque,
@@ -1299,6 +1534,10 @@ impl<B> StreamRef<B> {
     pub fn stream_id(&self) -> StreamId {
         self.opaque.stream_id()
     }
+
+    pub fn metrics(&self) -> StreamMetrics {
+        self.opaque.metrics()
+    }
 }
 
 impl<B> Clone for StreamRef<B> {
@@ -1313,6 +1552,12 @@ impl<B> Clone for StreamRef<B> {
 // ===== impl OpaqueStreamRef =====
 
 impl OpaqueStreamRef {
+    /// Called by a client to see if the stream is waiting to be opened.
+    pub fn is_pending_open(&self) -> bool {
+        let mut me = self.inner.lock().unwrap();
+        me.store.resolve(self.key).is_pending_open
+    }
+
     fn new(inner: Arc<Mutex<Inner>>, stream: &mut store::Ptr) -> OpaqueStreamRef {
         stream.ref_inc();
         OpaqueStreamRef {
@@ -1321,13 +1566,35 @@ impl OpaqueStreamRef {
         }
     }
     /// Called by a client to check for a received response.
-    pub fn poll_response(&mut self, cx: &Context) -> Poll<Result<Response<()>, proto::Error>> {
+    pub fn poll_response(&mut self, cx: &Context) -> Poll<Result<Response<()>, crate::Error>> {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let mut stream = me.store.resolve(self.key);
+
+        me.actions
+            .recv
+            .poll_response(cx, &mut stream)
+            .map_err(|err| {
+                let err = crate::Error::from(err);
+                if stream.is_unprocessed {
+                    err.with_unprocessed(stream.request.take())
+                } else {
+                    err
+                }
+            })
+    }
+    /// Called by a client to check for a received informational response.
+    pub fn poll_informational(
+        &mut self,
+        cx: &Context,
+    ) -> Poll<Option<Result<Response<()>, proto::Error>>> {
         let mut me = self.inner.lock().unwrap();
         let me = &mut *me;
 
         let mut stream = me.store.resolve(self.key);
 
-        me.actions.recv.poll_response(cx, &mut stream)
+        me.actions.recv.poll_informational(cx, &mut stream)
     }
     /// Called by a client to check for a pushed request.
     pub fn poll_pushed(
@@ -1418,6 +1685,12 @@ impl OpaqueStreamRef {
     pub fn stream_id(&self) -> StreamId {
         self.inner.lock().unwrap().store[self.key].id
     }
+
+    pub fn metrics(&self) -> StreamMetrics {
+        self.inner.lock().unwrap().store[self.key]
+            .metrics
+            .snapshot()
+    }
 }
 
 impl fmt::Debug for OpaqueStreamRef {
@@ -1541,6 +1814,25 @@ fn maybe_cancel(stream: &mut store::Ptr, actions: &mut Actions, counts: &mut Cou
     }
 }
 
+/// Copies the request opening a stream, to be returned if the peer does not
+/// process it.
+///
+/// Extensions other than `Protocol` are not kept, as they were already
+/// cleared.
+fn unsent_request(request: &Request<()>, protocol: Option<&Protocol>) -> Request<()> {
+    let mut unsent = Request::new(());
+    *unsent.method_mut() = request.method().clone();
+    *unsent.uri_mut() = request.uri().clone();
+    *unsent.version_mut() = request.version();
+    *unsent.headers_mut() = request.headers().clone();
+
+    if let Some(protocol) = protocol {
+        unsent.extensions_mut().insert(protocol.clone());
+    }
+
+    unsent
+}
+
 // ===== impl SendBuffer =====
 
 impl<B> SendBuffer<B> {
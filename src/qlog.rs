@@ -0,0 +1,456 @@
+//! A qlog trace of a connection, written as JSON-SEQ.
+//!
+//! The trace starts with a header record naming the vantage point, followed by
+//! one record per event. Each record is prefixed with the record separator
+//! character (0x1E) and ends with a line feed, as described in RFC 7464.
+//! Event times are in milliseconds since the trace was started.
+//!
+//! The following events are written:
+//!
+//! - `http2:frame_created` and `http2:frame_parsed` for every frame written
+//!   and read, with its decoded payload, other than DATA.
+//! - `http2:settings_updated` for every SETTINGS frame that is not an ACK.
+//! - `http2:goaway` for every GOAWAY frame.
+//! - `http2:stream_state_updated` when a stream changes state.
+//! - `http2:flow_control_updated` when a WINDOW_UPDATE frame grows a window.
+
+use crate::frame::{self, GoAway, Head, Ping, Reset, StreamDependency, StreamId, WindowUpdate};
+use crate::observer::{self, Direction, FrameObserver, FrameType, ObservedFrame};
+
+use std::fmt::{self, Write as _};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// The JSON-SEQ record separator.
+const RS: u8 = 0x1e;
+
+const ACK: u8 = 0x1;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+/// A handle to the trace of a connection, shared by everything that writes
+/// events to it.
+#[derive(Clone)]
+pub(crate) struct Qlog {
+    inner: Arc<Mutex<Inner>>,
+}
+
+/// Creates the writer of the trace of each connection made by a builder.
+#[derive(Clone)]
+pub(crate) struct MakeWriter(Arc<dyn Fn() -> Box<dyn io::Write + Send> + Send + Sync>);
+
+struct Inner {
+    writer: Box<dyn io::Write + Send>,
+
+    /// When the header record was written
+    start: Instant,
+}
+
+/// Whether an event is about the local or the remote endpoint.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Owner {
+    Local,
+    Remote,
+}
+
+/// A `FrameObserver` that writes the frame events of a trace.
+struct Frames(Qlog);
+
+/// Builds a JSON object.
+struct Object {
+    buf: String,
+    empty: bool,
+}
+
+// ===== impl Qlog =====
+
+impl Qlog {
+    fn new(writer: Box<dyn io::Write + Send>) -> Qlog {
+        Qlog {
+            inner: Arc::new(Mutex::new(Inner {
+                writer,
+                start: Instant::now(),
+            })),
+        }
+    }
+
+    /// Writes the header record of a trace, and starts its clock.
+    fn start(&self, is_server: bool) {
+        let vantage_point = if is_server { "server" } else { "client" };
+
+        let mut common_fields = Object::new();
+        common_fields.str("time_format", "relative");
+        common_fields.num("reference_time", 0);
+
+        let mut trace = Object::new();
+        trace.raw(
+            "vantage_point",
+            Object::new().str("type", vantage_point).finish(),
+        );
+        trace.raw("common_fields", common_fields.finish());
+
+        let mut header = Object::new();
+        header.str("qlog_version", "0.3");
+        header.str("qlog_format", "JSON-SEQ");
+        header.raw("trace", trace.finish());
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.start = Instant::now();
+        inner.write(header.finish());
+    }
+
+    /// Returns an observer that writes the frame, settings and GOAWAY events.
+    pub fn frame_observer(&self) -> observer::SharedObserver {
+        observer::SharedObserver::new(Frames(self.clone()))
+    }
+
+    pub fn stream_state_updated(&self, id: StreamId, old: &str, new: &str) {
+        let mut data = Object::new();
+        data.num("stream_id", u32::from(id));
+        data.str("old", old);
+        data.str("new", new);
+        self.event("http2:stream_state_updated", data);
+    }
+
+    /// Records a window grown by a WINDOW_UPDATE frame sent by `owner`.
+    ///
+    /// The window of a remote update is the send window, and the window of a
+    /// local update is the receive window. Stream zero is the connection.
+    pub fn flow_control_updated(&self, owner: Owner, id: StreamId, increment: u32, window: u32) {
+        let mut data = Object::new();
+        data.num("stream_id", u32::from(id));
+        data.str("owner", owner.as_str());
+        data.num("increment", increment);
+        data.num("window", window);
+        self.event("http2:flow_control_updated", data);
+    }
+
+    fn event(&self, name: &str, mut data: Object) {
+        let mut inner = self.inner.lock().unwrap();
+        let elapsed = inner.start.elapsed();
+
+        let mut event = Object::new();
+        event.raw(
+            "time",
+            format!(
+                "{}.{:03}",
+                elapsed.as_millis(),
+                elapsed.subsec_micros() % 1000
+            ),
+        );
+        event.str("name", name);
+        event.raw("data", data.finish());
+        inner.write(event.finish());
+    }
+}
+
+impl fmt::Debug for Qlog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Qlog").finish()
+    }
+}
+
+// ===== impl MakeWriter =====
+
+impl MakeWriter {
+    pub fn new<F, W>(make_writer: F) -> MakeWriter
+    where
+        F: Fn() -> W + Send + Sync + 'static,
+        W: io::Write + Send + 'static,
+    {
+        MakeWriter(Arc::new(move || Box::new(make_writer())))
+    }
+
+    /// Starts the trace of a new connection, with a new writer.
+    pub fn start(&self, is_server: bool) -> Qlog {
+        let qlog = Qlog::new((self.0)());
+        qlog.start(is_server);
+        qlog
+    }
+}
+
+impl fmt::Debug for MakeWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MakeWriter").finish()
+    }
+}
+
+// ===== impl Inner =====
+
+impl Inner {
+    /// Writes a record. Errors returned by the writer are ignored.
+    ///
+    /// The writer is not flushed, as records are often written while the
+    /// streams are locked.
+    fn write(&mut self, record: String) {
+        let mut buf = Vec::with_capacity(record.len() + 2);
+        buf.push(RS);
+        buf.extend_from_slice(record.as_bytes());
+        buf.push(b'\n');
+
+        let _ = self.writer.write_all(&buf);
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+// ===== impl Owner =====
+
+impl Owner {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Owner::Local => "local",
+            Owner::Remote => "remote",
+        }
+    }
+}
+
+// ===== impl Frames =====
+
+impl FrameObserver for Frames {
+    fn on_frame(&self, frame: &ObservedFrame<'_>) {
+        let (name, owner) = match frame.direction() {
+            Direction::Sent => ("http2:frame_created", Owner::Local),
+            Direction::Received => ("http2:frame_parsed", Owner::Remote),
+        };
+
+        let mut payload = Object::new();
+        payload.str("frame_type", frame_type_name(frame.frame_type()));
+        let mut settings = None;
+        let mut go_away = None;
+
+        if let Some(raw) = frame.raw() {
+            if raw.len() >= frame::HEADER_LEN {
+                let head = Head::parse(raw);
+                let raw = &raw[frame::HEADER_LEN..];
+                decode_payload(frame.frame_type(), head, raw, &mut payload);
+
+                match frame.frame_type() {
+                    FrameType::Settings if head.flag() & ACK == 0 => {
+                        settings = Some(settings_list(raw));
+                    }
+                    FrameType::GoAway => go_away = GoAway::load(raw).ok(),
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(headers) = frame.headers() {
+            let fields = headers.iter().map(|(name, value)| {
+                let mut field = Object::new();
+                field.str("name", &String::from_utf8_lossy(name));
+                field.str("value", &String::from_utf8_lossy(value));
+                field.finish()
+            });
+            payload.raw("headers", array(fields));
+        }
+
+        let mut data = Object::new();
+        data.num("stream_id", u32::from(frame.stream_id()));
+        data.num("length", frame.len());
+        data.num("flags", frame.flags());
+        data.raw("frame", payload.finish());
+        self.0.event(name, data);
+
+        if let Some(settings) = settings {
+            let mut data = Object::new();
+            data.str("owner", owner.as_str());
+            data.raw("settings", settings);
+            self.0.event("http2:settings_updated", data);
+        }
+
+        if let Some(go_away) = go_away {
+            let mut data = Object::new();
+            data.str("owner", owner.as_str());
+            data.num("last_stream_id", u32::from(go_away.last_stream_id()));
+            data.num("error_code", u32::from(go_away.reason()));
+            data.str("debug_data", &String::from_utf8_lossy(go_away.debug_data()));
+            self.0.event("http2:goaway", data);
+        }
+    }
+
+    fn wants_raw_bytes(&self) -> bool {
+        true
+    }
+
+    fn wants_raw_data(&self) -> bool {
+        // Only the frame header of DATA frames is recorded.
+        false
+    }
+}
+
+fn frame_type_name(frame_type: FrameType) -> &'static str {
+    match frame_type {
+        FrameType::Data => "data",
+        FrameType::Headers => "headers",
+        FrameType::Priority => "priority",
+        FrameType::Reset => "rst_stream",
+        FrameType::Settings => "settings",
+        FrameType::PushPromise => "push_promise",
+        FrameType::Ping => "ping",
+        FrameType::GoAway => "goaway",
+        FrameType::WindowUpdate => "window_update",
+        FrameType::Continuation => "continuation",
+        FrameType::Unknown(_) => "unknown",
+    }
+}
+
+/// Adds the decoded payload of a frame, other than its header list, to
+/// `out`.
+///
+/// Frames that cannot be decoded are skipped; they are reported by the
+/// connection.
+fn decode_payload(frame_type: FrameType, head: Head, payload: &[u8], out: &mut Object) {
+    let padded = head.flag() & PADDED == PADDED;
+    // The payload that follows the pad length field
+    let rest = if padded {
+        &payload[1.min(payload.len())..]
+    } else {
+        payload
+    };
+
+    match frame_type {
+        FrameType::Headers if head.flag() & PRIORITY == PRIORITY && rest.len() >= 5 => {
+            if let Ok(dependency) = StreamDependency::load(&rest[..5]) {
+                add_dependency(&dependency, out);
+            }
+        }
+        FrameType::Priority => {
+            if let Ok(dependency) = StreamDependency::load(payload) {
+                add_dependency(&dependency, out);
+            }
+        }
+        FrameType::PushPromise if rest.len() >= 4 => {
+            let (promised_id, _) = StreamId::parse(&rest[..4]);
+            out.num("promised_stream_id", u32::from(promised_id));
+        }
+        FrameType::Settings => {
+            out.raw("settings", settings_list(payload));
+        }
+        FrameType::Ping => {
+            if let Ok(ping) = Ping::load(head, payload) {
+                let mut opaque_data = String::new();
+                for byte in ping.payload() {
+                    let _ = write!(opaque_data, "{:02x}", byte);
+                }
+                out.str("opaque_data", &opaque_data);
+            }
+        }
+        FrameType::GoAway => {
+            if let Ok(go_away) = GoAway::load(payload) {
+                out.num("last_stream_id", u32::from(go_away.last_stream_id()));
+                out.num("error_code", u32::from(go_away.reason()));
+            }
+        }
+        FrameType::Reset => {
+            if let Ok(reset) = Reset::load(head, payload) {
+                out.num("error_code", u32::from(reset.reason()));
+            }
+        }
+        FrameType::WindowUpdate => {
+            if let Ok(window_update) = WindowUpdate::load(head, payload) {
+                out.num("increment", window_update.size_increment());
+            }
+        }
+        FrameType::Unknown(frame_type) => {
+            out.num("frame_type_value", frame_type);
+        }
+        _ => {}
+    }
+}
+
+fn add_dependency(dependency: &StreamDependency, out: &mut Object) {
+    out.num("stream_dependency", u32::from(dependency.dependency_id()));
+    // The weight is sent as one less than its value.
+    out.num("weight", u16::from(dependency.weight()) + 1);
+    out.raw("exclusive", dependency.is_exclusive().to_string());
+}
+
+/// Returns the settings of a SETTINGS frame payload as a JSON array.
+fn settings_list(payload: &[u8]) -> String {
+    let settings = payload.chunks_exact(6).map(|raw| {
+        let id = u16::from(raw[0]) << 8 | u16::from(raw[1]);
+        let value = u32::from_be_bytes([raw[2], raw[3], raw[4], raw[5]]);
+
+        let mut setting = Object::new();
+        setting.str("name", observer::setting_name(id));
+        setting.num("id", id);
+        setting.num("value", value);
+        setting.finish()
+    });
+    array(settings)
+}
+
+// ===== impl Object =====
+
+impl Object {
+    fn new() -> Object {
+        Object {
+            buf: String::from("{"),
+            empty: true,
+        }
+    }
+
+    fn key(&mut self, key: &str) {
+        if !self.empty {
+            self.buf.push(',');
+        }
+        self.empty = false;
+        write_str(&mut self.buf, key);
+        self.buf.push(':');
+    }
+
+    fn str(&mut self, key: &str, value: &str) -> &mut Object {
+        self.key(key);
+        write_str(&mut self.buf, value);
+        self
+    }
+
+    fn num<N: fmt::Display>(&mut self, key: &str, value: N) -> &mut Object {
+        self.key(key);
+        let _ = write!(self.buf, "{}", value);
+        self
+    }
+
+    /// Adds a value that is already encoded as JSON.
+    fn raw(&mut self, key: &str, json: String) -> &mut Object {
+        self.key(key);
+        self.buf.push_str(&json);
+        self
+    }
+
+    fn finish(&mut self) -> String {
+        self.buf.push('}');
+        std::mem::take(&mut self.buf)
+    }
+}
+
+/// Encodes values that are already encoded as JSON as an array.
+fn array<I: Iterator<Item = String>>(values: I) -> String {
+    let values: Vec<String> = values.collect();
+    format!("[{}]", values.join(","))
+}
+
+/// Writes a JSON string literal.
+fn write_str(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -0,0 +1,714 @@
+//! HTTP/2 connections that do no I/O.
+//!
+//! A [`Connection`] runs the same protocol state machine as the connections
+//! returned by the `handshake` methods of the client and server `Builder`, but
+//! it does not read from or write to a transport. Instead, bytes received from
+//! the peer are passed to [`Connection::recv`], bytes to send to the peer are
+//! taken with [`Connection::transmit`], and what happened on the connection is
+//! returned as [`Event`]s by [`Connection::poll_event`]. This allows driving
+//! HTTP/2 from any event loop, without an async runtime.
+//!
+//! The connection runs the protocol core that the connections of the client
+//! and server modules read and write their I/O around. Received bytes are
+//! processed one frame at a time, so events are returned in the order their
+//! frames were received.
+//!
+//! Streams are identified by their [`StreamId`]. Received data counts against
+//! the flow control windows until it is released with
+//! [`Connection::release_capacity`], as with a [`RecvStream`].
+//!
+//! The connection does not use a [`Timer`]. Settings that need one, such as
+//! the idle timeout, work without it: [`Connection::poll_timeout`] returns
+//! when the connection next needs to act, and [`Connection::handle_timeout`]
+//! should be called once that time has passed.
+//!
+//! # Examples
+//!
+//! ```
+//! use http2::sans_io::{Connection, Event};
+//! use http::{Request, Response};
+//!
+//! let mut client = Connection::client(&http2::client::Builder::new());
+//! let mut server = Connection::server(&http2::server::Builder::new());
+//!
+//! let request = Request::get("https://example.com/").body(()).unwrap();
+//! let id = client.send_request(request, true).unwrap();
+//!
+//! // Move bytes between the connections until both are done.
+//! let mut responded = false;
+//! while let Some(bytes) = client.transmit() {
+//!     server.recv(&bytes);
+//!     while let Some(event) = server.poll_event() {
+//!         if let Event::NewStream(id, _request) = event {
+//!             let response = Response::new(());
+//!             server.send_response(id, response, true).unwrap();
+//!         }
+//!     }
+//!     while let Some(bytes) = server.transmit() {
+//!         client.recv(&bytes);
+//!     }
+//!     while let Some(event) = client.poll_event() {
+//!         if let Event::Headers(stream_id, response) = event {
+//!             assert_eq!(stream_id, id);
+//!             assert_eq!(response.status(), 200);
+//!             responded = true;
+//!         }
+//!     }
+//! }
+//! assert!(responded);
+//! ```
+//!
+//! [`Connection`]: struct.Connection.html
+//! [`Connection::recv`]: struct.Connection.html#method.recv
+//! [`Connection::transmit`]: struct.Connection.html#method.transmit
+//! [`Connection::poll_event`]: struct.Connection.html#method.poll_event
+//! [`Connection::release_capacity`]: struct.Connection.html#method.release_capacity
+//! [`Connection::poll_timeout`]: struct.Connection.html#method.poll_timeout
+//! [`Connection::handle_timeout`]: struct.Connection.html#method.handle_timeout
+//! [`Event`]: enum.Event.html
+//! [`StreamId`]: ../struct.StreamId.html
+//! [`RecvStream`]: ../struct.RecvStream.html
+//! [`Timer`]: ../timer/trait.Timer.html
+
+use crate::client::{self, ResponseFuture, SendRequest};
+use crate::codec::UserError;
+use crate::frame::Reason;
+use crate::proto::{self, Received, PREFACE};
+use crate::server::{self, Lifecycle, SendResponse};
+use crate::{tracing, RecvStream, SendStream, StreamId};
+
+use bytes::Bytes;
+use http::{HeaderMap, Request, Response};
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Instant;
+use std::{cmp, fmt};
+
+/// An HTTP/2 connection driven by the caller.
+///
+/// See the [module] level documentation for more details.
+///
+/// [module]: index.html
+pub struct Connection {
+    role: Role,
+
+    /// The handles of the open streams, by stream identifier.
+    streams: BTreeMap<u32, Stream>,
+
+    events: VecDeque<Event>,
+
+    is_closed: bool,
+
+    /// The connection and the streams are polled after every change instead
+    /// of waiting to be woken.
+    waker: Waker,
+}
+
+/// Something that happened on a [`Connection`].
+///
+/// [`Connection`]: struct.Connection.html
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Event {
+    /// The peer opened a stream with a request.
+    ///
+    /// Only servers receive this event.
+    NewStream(StreamId, Request<()>),
+
+    /// The peer sent the response headers of a stream.
+    ///
+    /// Only clients receive this event.
+    Headers(StreamId, Response<()>),
+
+    /// The peer sent data on a stream.
+    Data(StreamId, Bytes),
+
+    /// The peer sent trailers on a stream.
+    Trailers(StreamId, HeaderMap),
+
+    /// The peer ended its side of a stream.
+    EndStream(StreamId),
+
+    /// The peer reset a stream, or the library reset it because of a stream
+    /// error.
+    Reset(StreamId, Reason),
+
+    /// The peer sent a SETTINGS frame, with the identifier and value of each
+    /// known setting it carried.
+    Settings(Vec<(u16, u32)>),
+
+    /// The peer sent a GOAWAY frame.
+    GoAway {
+        /// The last stream the peer may have processed.
+        last_stream_id: StreamId,
+        /// The reason for closing the connection.
+        reason: Reason,
+        /// The debug data sent with the frame.
+        debug_data: Bytes,
+    },
+
+    /// The connection closed, with the error that closed it, if any.
+    ///
+    /// This is the last event of a connection.
+    Closed(Option<crate::Error>),
+}
+
+enum Role {
+    Client {
+        connection: proto::Connection<client::Peer, Bytes>,
+        send_request: SendRequest<Bytes>,
+    },
+    Server {
+        connection: proto::Connection<server::Peer, Bytes>,
+
+        /// Shuts the connection down once its configured limits are reached.
+        lifecycle: Lifecycle,
+
+        /// Send `100 Continue` for accepted `Expect: 100-continue` requests.
+        auto_continue: bool,
+
+        /// How many bytes of the client connection preface were received.
+        preface: usize,
+    },
+}
+
+/// The handles of a stream.
+#[derive(Default)]
+struct Stream {
+    /// Sends the response of a received request.
+    respond: Option<SendResponse<Bytes>>,
+
+    /// Resolves to the response of a sent request.
+    response: Option<ResponseFuture>,
+
+    send: Option<SendStream<Bytes>>,
+
+    recv: Option<RecvStream>,
+
+    /// Whether the local side of the stream has ended.
+    is_send_closed: bool,
+
+    /// Whether the peer's side of the stream has ended.
+    is_recv_closed: bool,
+}
+
+struct NoopWaker;
+
+// ===== impl Connection =====
+
+impl Connection {
+    /// Creates a client connection configured by `builder`.
+    ///
+    /// The connection preface and initial SETTINGS frame are ready to be
+    /// transmitted once this returns. The `timer` of `builder` is not used.
+    pub fn client(builder: &client::Builder) -> Connection {
+        let mut builder = builder.clone();
+        builder.timer = None;
+
+        let (send_request, mut connection) = builder.bind(false);
+        connection.set_transmit(&PREFACE);
+        Connection::new(Role::Client {
+            connection,
+            send_request,
+        })
+    }
+
+    /// Creates a server connection configured by `builder`.
+    ///
+    /// The initial SETTINGS frame is ready to be transmitted once this
+    /// returns. The `timer` of `builder` is not used.
+    pub fn server(builder: &server::Builder) -> Connection {
+        let mut builder = builder.clone();
+        builder.timer = None;
+
+        let (codec, qlog) = builder.codec(false);
+        let mut connection = builder.bind(codec, qlog);
+        connection.set_transmit(&[]);
+        Connection::new(Role::Server {
+            connection,
+            lifecycle: Lifecycle::new(&builder),
+            auto_continue: builder.auto_continue,
+            preface: 0,
+        })
+    }
+
+    fn new(role: Role) -> Connection {
+        Connection {
+            role,
+            streams: BTreeMap::new(),
+            events: VecDeque::new(),
+            is_closed: false,
+            waker: Waker::from(Arc::new(NoopWaker)),
+        }
+    }
+
+    /// Passes bytes received from the peer to the connection.
+    ///
+    /// Bytes received after the connection closed are ignored.
+    pub fn recv(&mut self, mut data: &[u8]) {
+        if self.is_closed {
+            return;
+        }
+
+        if let Role::Server {
+            ref mut preface, ..
+        } = self.role
+        {
+            let n = cmp::min(PREFACE.len() - *preface, data.len());
+            if PREFACE[*preface..*preface + n] != data[..n] {
+                proto_err!(conn: "recv: invalid preface");
+                let err = proto::Error::library_go_away(Reason::PROTOCOL_ERROR);
+                self.close(Err(err.into()));
+                return;
+            }
+            *preface += n;
+            data = &data[n..];
+        }
+
+        self.role.recv(data);
+        self.poll_connection();
+    }
+
+    /// Tells the connection that the peer closed its side of the transport.
+    pub fn recv_eof(&mut self) {
+        if !self.is_closed {
+            self.role.recv_eof();
+            self.poll_connection();
+            self.poll_streams();
+        }
+    }
+
+    /// Returns the bytes to send to the peer, if any.
+    ///
+    /// The connection buffers the bytes to send until they are taken.
+    pub fn transmit(&mut self) -> Option<Bytes> {
+        self.poll_connection();
+        self.role.transmit()
+    }
+
+    /// Returns the next event, if any.
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    /// Returns when [`handle_timeout`] should next be called, if a timeout
+    /// is pending.
+    ///
+    /// This changes as the connection is used, so it should be checked again
+    /// after each call to another method.
+    ///
+    /// [`handle_timeout`]: #method.handle_timeout
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        if self.is_closed {
+            return None;
+        }
+
+        match self.role {
+            Role::Client { ref connection, .. } => connection.poll_timeout(),
+            Role::Server {
+                ref connection,
+                ref lifecycle,
+                ..
+            } => match (connection.poll_timeout(), lifecycle.poll_timeout()) {
+                (Some(timeout), Some(limit)) => Some(cmp::min(timeout, limit)),
+                (deadline, None) | (None, deadline) => deadline,
+            },
+        }
+    }
+
+    /// Acts on the timeouts that have expired.
+    ///
+    /// Calling this before a timeout expires does nothing.
+    pub fn handle_timeout(&mut self) {
+        self.poll_connection();
+        self.poll_streams();
+    }
+
+    /// Returns true once the connection has closed.
+    pub fn is_closed(&self) -> bool {
+        self.is_closed
+    }
+
+    /// Sends a request, and returns the identifier of its stream.
+    ///
+    /// Requests beyond the peer's `SETTINGS_MAX_CONCURRENT_STREAMS` are
+    /// queued until a stream closes, as with [`SendRequest::send_request`].
+    ///
+    /// # Panics
+    ///
+    /// This function panics if called on a server connection.
+    ///
+    /// [`SendRequest::send_request`]: ../client/struct.SendRequest.html#method.send_request
+    pub fn send_request(
+        &mut self,
+        request: Request<()>,
+        end_of_stream: bool,
+    ) -> Result<StreamId, crate::Error> {
+        // Each request uses its own handle, so that a request waiting to be
+        // opened does not hold back the next.
+        let mut send_request = match self.role {
+            Role::Client {
+                ref send_request, ..
+            } => send_request.clone(),
+            Role::Server { .. } => panic!("send_request called on a server connection"),
+        };
+
+        let (response, send) = send_request.send_request(request, end_of_stream)?;
+        let id = send.stream_id();
+
+        self.streams.insert(
+            id.as_u32(),
+            Stream {
+                response: Some(response),
+                send: Some(send),
+                is_send_closed: end_of_stream,
+                ..Stream::default()
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Sends the response to the request of a stream.
+    pub fn send_response(
+        &mut self,
+        id: StreamId,
+        response: Response<()>,
+        end_of_stream: bool,
+    ) -> Result<(), crate::Error> {
+        let stream = self.stream(id)?;
+        let respond = stream
+            .respond
+            .as_mut()
+            .ok_or(UserError::UnexpectedFrameType)?;
+
+        let send = respond.send_response(response, end_of_stream)?;
+        stream.respond = None;
+        stream.send = Some(send);
+        stream.is_send_closed = end_of_stream;
+
+        self.poll_stream(id);
+        Ok(())
+    }
+
+    /// Sends data on a stream.
+    ///
+    /// The data is buffered until the peer's flow control windows allow
+    /// sending it.
+    pub fn send_data(
+        &mut self,
+        id: StreamId,
+        data: Bytes,
+        end_of_stream: bool,
+    ) -> Result<(), crate::Error> {
+        let stream = self.stream(id)?;
+        let send = stream.send.as_mut().ok_or(UserError::UnexpectedFrameType)?;
+
+        send.send_data(data, end_of_stream)?;
+        stream.is_send_closed |= end_of_stream;
+
+        self.poll_stream(id);
+        Ok(())
+    }
+
+    /// Sends trailers on a stream, which ends the local side of the stream.
+    pub fn send_trailers(&mut self, id: StreamId, trailers: HeaderMap) -> Result<(), crate::Error> {
+        let stream = self.stream(id)?;
+        let send = stream.send.as_mut().ok_or(UserError::UnexpectedFrameType)?;
+
+        send.send_trailers(trailers)?;
+        stream.is_send_closed = true;
+
+        self.poll_stream(id);
+        Ok(())
+    }
+
+    /// Resets a stream with `reason`.
+    ///
+    /// Does nothing if the stream has already closed.
+    pub fn send_reset(&mut self, id: StreamId, reason: Reason) {
+        if let Some(mut stream) = self.streams.remove(&id.as_u32()) {
+            if let Some(ref mut send) = stream.send {
+                send.send_reset(reason);
+            } else if let Some(ref mut respond) = stream.respond {
+                respond.send_reset(reason);
+            }
+        }
+    }
+
+    /// Releases `sz` bytes of data received on a stream, so that the peer
+    /// can send more.
+    ///
+    /// Does nothing if the stream has already closed, as the capacity of a
+    /// stream is released when it closes.
+    pub fn release_capacity(&mut self, id: StreamId, sz: usize) -> Result<(), crate::Error> {
+        match self
+            .streams
+            .get_mut(&id.as_u32())
+            .and_then(|s| s.recv.as_mut())
+        {
+            Some(recv) => recv.flow_control().release_capacity(sz),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts a graceful shutdown of a server connection.
+    ///
+    /// See [`server::Connection::graceful_shutdown`] for details. This does
+    /// nothing on a client connection.
+    ///
+    /// [`server::Connection::graceful_shutdown`]: ../server/struct.Connection.html#method.graceful_shutdown
+    pub fn graceful_shutdown(&mut self) {
+        if let Role::Server {
+            ref mut connection, ..
+        } = self.role
+        {
+            connection.go_away_gracefully();
+        }
+    }
+
+    fn stream(&mut self, id: StreamId) -> Result<&mut Stream, crate::Error> {
+        self.streams
+            .get_mut(&id.as_u32())
+            .ok_or_else(|| UserError::InactiveStreamId.into())
+    }
+
+    /// Polls the connection until it needs more bytes from the peer, and
+    /// reports the streams it accepts and the frames it reads.
+    fn poll_connection(&mut self) {
+        if self.is_closed {
+            return;
+        }
+
+        let waker = self.waker.clone();
+        let mut cx = Context::from_waker(&waker);
+        let result = loop {
+            let (poll, again) = match self.role {
+                Role::Client {
+                    ref mut connection, ..
+                } => connection.poll_once(&mut cx),
+                Role::Server {
+                    ref mut connection,
+                    ref mut lifecycle,
+                    auto_continue,
+                    ..
+                } => {
+                    let poll = lifecycle.poll_connection(&mut cx, connection);
+                    while let Some(inner) = connection.next_incoming() {
+                        let (request, respond) = server::accepted(inner, auto_continue);
+                        let id = respond.stream_id();
+                        let (parts, body) = request.into_parts();
+                        self.events
+                            .push_back(Event::NewStream(id, Request::from_parts(parts, ())));
+                        self.streams.insert(
+                            id.as_u32(),
+                            Stream {
+                                respond: Some(respond),
+                                recv: Some(body),
+                                ..Stream::default()
+                            },
+                        );
+                    }
+                    (poll, false)
+                }
+            };
+
+            // The connection reads no further frame until the last one is
+            // reported.
+            let received = self.role.take_received();
+            let is_received = received.is_some();
+            match received {
+                Some(Received::Frame(id)) if id.is_zero() => self.poll_streams(),
+                Some(Received::Frame(id)) => self.poll_stream(StreamId::from_internal(id)),
+                Some(Received::Settings(settings)) => {
+                    self.events.push_back(Event::Settings(settings.to_pairs()));
+                    self.poll_streams();
+                }
+                Some(Received::GoAway(go_away)) => {
+                    self.events.push_back(Event::GoAway {
+                        last_stream_id: StreamId::from_internal(go_away.last_stream_id()),
+                        reason: go_away.reason(),
+                        debug_data: go_away.debug_data().clone(),
+                    });
+                    self.poll_streams();
+                }
+                None => {}
+            }
+
+            match poll {
+                Poll::Ready(result) => break result.map_err(Into::into),
+                Poll::Pending if again || is_received => {}
+                Poll::Pending => return,
+            }
+        };
+
+        self.close(result);
+    }
+
+    fn close(&mut self, result: Result<(), crate::Error>) {
+        tracing::trace!("sans-io connection closed");
+        self.is_closed = true;
+
+        // Report what the streams received before the connection closed.
+        self.poll_streams();
+        self.events.push_back(Event::Closed(result.err()));
+    }
+
+    fn poll_stream(&mut self, id: StreamId) {
+        let mut cx = Context::from_waker(&self.waker);
+        if let Some(stream) = self.streams.get_mut(&id.as_u32()) {
+            if stream.poll(id, &mut cx, &mut self.events) {
+                self.streams.remove(&id.as_u32());
+            }
+        }
+    }
+
+    fn poll_streams(&mut self) {
+        let mut cx = Context::from_waker(&self.waker);
+        let events = &mut self.events;
+        self.streams.retain(|&id, stream| {
+            let id = StreamId::from_internal(id.into());
+            !stream.poll(id, &mut cx, events)
+        });
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Connection")
+            .field("is_server", &matches!(self.role, Role::Server { .. }))
+            .field("is_closed", &self.is_closed)
+            .field("streams", &self.streams.len())
+            .finish()
+    }
+}
+
+// ===== impl Role =====
+
+impl Role {
+    fn recv(&mut self, data: &[u8]) {
+        match *self {
+            Role::Client {
+                ref mut connection, ..
+            } => connection.recv(data),
+            Role::Server {
+                ref mut connection, ..
+            } => connection.recv(data),
+        }
+    }
+
+    fn recv_eof(&mut self) {
+        match *self {
+            Role::Client {
+                ref mut connection, ..
+            } => connection.recv_eof(),
+            Role::Server {
+                ref mut connection, ..
+            } => connection.recv_eof(),
+        }
+    }
+
+    fn transmit(&mut self) -> Option<Bytes> {
+        match *self {
+            Role::Client {
+                ref mut connection, ..
+            } => connection.transmit(),
+            Role::Server {
+                ref mut connection, ..
+            } => connection.transmit(),
+        }
+    }
+
+    fn take_received(&mut self) -> Option<Received> {
+        match *self {
+            Role::Client {
+                ref mut connection, ..
+            } => connection.take_received(),
+            Role::Server {
+                ref mut connection, ..
+            } => connection.take_received(),
+        }
+    }
+}
+
+// ===== impl Stream =====
+
+impl Stream {
+    /// Polls the handles of the stream for events, and returns true once the
+    /// stream is done.
+    fn poll(&mut self, id: StreamId, cx: &mut Context<'_>, events: &mut VecDeque<Event>) -> bool {
+        if let Some(ref mut response) = self.response {
+            match Pin::new(response).poll(cx) {
+                Poll::Ready(Ok(response)) => {
+                    let (parts, body) = response.into_parts();
+                    events.push_back(Event::Headers(id, Response::from_parts(parts, ())));
+                    self.response = None;
+                    self.recv = Some(body);
+                }
+                Poll::Ready(Err(e)) => return stream_error(id, e, events),
+                Poll::Pending => {}
+            }
+        }
+
+        if let (Some(recv), false) = (self.recv.as_mut(), self.is_recv_closed) {
+            loop {
+                match recv.poll_data(cx) {
+                    Poll::Ready(Some(Ok(data))) => events.push_back(Event::Data(id, data)),
+                    Poll::Ready(Some(Err(e))) => return stream_error(id, e, events),
+                    Poll::Ready(None) => {
+                        match recv.poll_trailers(cx) {
+                            Poll::Ready(Ok(Some(trailers))) => {
+                                events.push_back(Event::Trailers(id, trailers))
+                            }
+                            Poll::Ready(Err(e)) => return stream_error(id, e, events),
+                            Poll::Ready(Ok(None)) | Poll::Pending => {}
+                        }
+                        events.push_back(Event::EndStream(id));
+                        self.is_recv_closed = true;
+                        break;
+                    }
+                    Poll::Pending => break,
+                }
+            }
+        }
+
+        if self.response.is_some() || !self.is_recv_closed {
+            return false;
+        }
+
+        // Only the local side is open, so watch for the peer resetting it.
+        let reset = match (self.respond.as_mut(), self.send.as_mut()) {
+            (Some(respond), _) => respond.poll_reset(cx),
+            (None, Some(send)) if !self.is_send_closed => send.poll_reset(cx),
+            _ => return true,
+        };
+
+        match reset {
+            Poll::Ready(Ok(reason)) => {
+                events.push_back(Event::Reset(id, reason));
+                true
+            }
+            Poll::Ready(Err(e)) => stream_error(id, e, events),
+            Poll::Pending => false,
+        }
+    }
+}
+
+/// Reports the error of a stream, and returns true as the stream is done.
+///
+/// Connection errors are reported by the `Closed` event instead.
+fn stream_error(id: StreamId, e: crate::Error, events: &mut VecDeque<Event>) -> bool {
+    if let (true, Some(reason)) = (e.is_reset(), e.reason()) {
+        events.push_back(Event::Reset(id, reason));
+    }
+    true
+}
+
+// ===== impl NoopWaker =====
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
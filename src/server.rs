@@ -52,6 +52,11 @@
 //! returns `Ready(None)`, there will no longer be any more inbound streams. At
 //! this point, only [`Connection::poll_close`] should be called.
 //!
+//! Alternatively, [`Connection::split`] separates the two roles. The returned
+//! [`Driver`] future owns the I/O handle and advances the connection state,
+//! while the [`Acceptor`] handle yields the inbound streams. The `Acceptor`
+//! can be cloned and sent to other tasks.
+//!
 //! # Shutting down the server
 //!
 //! Graceful shutdown of the server is [not yet
@@ -109,27 +114,42 @@
 //! [`Connection`]: struct.Connection.html
 //! [`Connection::poll`]: struct.Connection.html#method.poll
 //! [`Connection::poll_close`]: struct.Connection.html#method.poll_close
+//! [`Connection::split`]: struct.Connection.html#method.split
+//! [`Driver`]: struct.Driver.html
+//! [`Acceptor`]: struct.Acceptor.html
 //! [`futures::Stream`]: https://docs.rs/futures/0.1/futures/stream/trait.Stream.html
 //! [`http::Request<RecvStream>`]: ../struct.RecvStream.html
 //! [`RecvStream`]: ../struct.RecvStream.html
 //! [`SendStream`]: ../struct.SendStream.html
 //! [`TcpListener`]: https://docs.rs/tokio-core/0.1/tokio_core/net/struct.TcpListener.html
 
-use crate::codec::{Codec, UserError};
+use crate::capsule::{CapsuleReceiver, CapsuleSender, CAPSULE_PROTOCOL};
+#[cfg(feature = "futures-io")]
+use crate::codec::FuturesIo;
+use crate::codec::{Codec, Framed, Io, UserError};
+use crate::ext::Protocol;
 use crate::frame::{self, Pseudo, PushPromiseHeaderError, Reason, Settings, StreamId};
-use crate::proto::{self, Config, Error, Prioritized};
-use crate::{tracing, FlowControl, PingPong, RecvStream, SendStream};
+#[cfg(feature = "unstable")]
+use crate::intercept::{FrameInterceptor, SharedInterceptor};
+use crate::observer::{FrameLog, FrameObserver, SharedObserver};
+use crate::proto::{self, Config, Error, Prioritized, PREFACE};
+use crate::qlog::{MakeWriter, Qlog};
+use crate::stats::{ConnectionStats, StreamMetrics};
+use crate::timer::{Alarm, SharedTimer, Timer};
+use crate::{tracing, FloodLimit, FlowControl, PingPong, RecvStream, SendStream, Upgraded};
 
 #[cfg(feature = "tracing")]
 use ::tracing::instrument::{Instrument, Instrumented};
 use bytes::{Buf, Bytes};
-use http::{HeaderMap, Method, Request, Response};
+use http::{HeaderMap, Method, Request, Response, StatusCode};
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::Duration;
-use std::{fmt, io};
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use std::time::{Duration, Instant};
+use std::{cmp, fmt, io};
+use tokio::io::{AsyncRead, ReadBuf};
 
 /// In progress HTTP/2 connection handshake future.
 ///
@@ -149,6 +169,8 @@ use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 pub struct Handshake<T, B: Buf = Bytes> {
     /// The config to pass to Connection::new after handshake succeeds.
     builder: Builder,
+    /// The qlog trace of the connection, started with the handshake.
+    qlog: Option<Qlog>,
     /// The current state of the handshake.
     state: Handshaking<T, B>,
     /// Span tracking the handshake
@@ -196,7 +218,63 @@ pub struct Handshake<T, B: Buf = Bytes> {
 /// ```
 #[must_use = "streams do nothing unless polled"]
 pub struct Connection<T, B: Buf> {
-    connection: proto::Connection<T, Peer, B>,
+    io: T,
+
+    connection: proto::Connection<Peer, B>,
+
+    /// Send `100 Continue` for accepted `Expect: 100-continue` requests.
+    auto_continue: bool,
+
+    /// Shuts the connection down once its configured limits are reached.
+    lifecycle: Lifecycle,
+}
+
+/// Accepts inbound HTTP/2 streams on a connection driven by a [`Driver`].
+///
+/// This type does no work itself. Instead, it is a handle to the inner
+/// connection state held by the [`Driver`]. Obtained by calling
+/// [`Connection::split`].
+///
+/// `Acceptor` can be cloned and sent to other tasks, so that streams may be
+/// accepted independently of driving the connection. Each inbound stream is
+/// yielded by only one of the clones.
+///
+/// Once every clone has been dropped, no more streams can be accepted, so the
+/// [`Driver`] starts a graceful shutdown and refuses the streams that were
+/// not accepted with `REFUSED_STREAM`.
+///
+/// [`Driver`]: struct.Driver.html
+/// [`Connection::split`]: struct.Connection.html#method.split
+pub struct Acceptor<B: Buf> {
+    inner: proto::Streams<B, Peer>,
+
+    /// Send `100 Continue` for accepted `Expect: 100-continue` requests.
+    auto_continue: bool,
+
+    /// Number of live clones, shared with the `Driver`.
+    acceptors: Arc<AtomicUsize>,
+}
+
+/// Drives the state of a connection whose streams are accepted by an
+/// [`Acceptor`].
+///
+/// This future owns the I/O handle, and must be polled in order for the
+/// connection to make progress. It completes once the connection has closed.
+/// Obtained by calling [`Connection::split`].
+///
+/// [`Acceptor`]: struct.Acceptor.html
+/// [`Connection::split`]: struct.Connection.html#method.split
+#[must_use = "futures do nothing unless polled"]
+pub struct Driver<T, B: Buf> {
+    io: T,
+
+    connection: proto::Connection<Peer, B>,
+
+    /// Shuts the connection down once its configured limits are reached.
+    lifecycle: Lifecycle,
+
+    /// Number of live `Acceptor` clones.
+    acceptors: Arc<AtomicUsize>,
 }
 
 /// Builds server connections with custom configuration values.
@@ -260,6 +338,76 @@ pub struct Builder {
     ///
     /// When this gets exceeded, we issue GOAWAYs.
     local_max_error_reset_streams: Option<usize>,
+
+    /// Send `100 Continue` for accepted `Expect: 100-continue` requests.
+    pub(crate) auto_continue: bool,
+
+    /// Answer with a `431` when a response header list is too large.
+    oversized_response_431: bool,
+
+    /// Maximum number of CONTINUATION frames in a received header block.
+    max_continuation_frames: Option<usize>,
+
+    /// Maximum number of undecoded bytes in a received header block.
+    max_header_block_size: Option<usize>,
+
+    /// Maximum ratio of decoded to encoded size of a received header block.
+    max_header_decompression_ratio: Option<usize>,
+
+    /// Limits on received control frames.
+    flood_limits: proto::FloodLimits,
+
+    /// Called with every frame read and written.
+    frame_observer: Option<SharedObserver>,
+
+    /// Logs every frame read and written, in addition to `frame_observer`.
+    frame_log: Option<SharedObserver>,
+
+    /// Creates the writer of the qlog trace of each connection.
+    qlog: Option<MakeWriter>,
+
+    /// Called with every frame before it is written.
+    #[cfg(feature = "unstable")]
+    frame_interceptor: Option<SharedInterceptor>,
+
+    /// Creates the sleeps used by time based settings.
+    pub(crate) timer: Option<SharedTimer>,
+
+    /// Number of requests to receive before shutting down gracefully.
+    max_connection_requests: Option<u64>,
+
+    /// How long a connection is kept open before shutting down gracefully.
+    max_connection_age: Option<Duration>,
+
+    /// How long a graceful shutdown may take before it becomes abrupt.
+    graceful_shutdown_timeout: Option<Duration>,
+
+    /// How long a connection may be idle before it is closed.
+    idle_timeout: Option<Duration>,
+
+    /// Decides whether new streams are accepted.
+    admission: Option<SharedAdmission>,
+}
+
+/// Shuts a connection down once the limits configured on the [`Builder`] are
+/// reached.
+#[derive(Debug)]
+pub(crate) struct Lifecycle {
+    /// Number of requests to receive before shutting down gracefully.
+    max_requests: Option<u64>,
+
+    /// When the connection reaches its maximum age.
+    max_age: Option<Instant>,
+
+    max_age_alarm: Alarm,
+
+    /// How long a graceful shutdown may take before it becomes abrupt.
+    shutdown_timeout: Option<Duration>,
+
+    /// When a graceful shutdown in progress should become abrupt.
+    shutdown_deadline: Option<Instant>,
+
+    shutdown_alarm: Alarm,
 }
 
 /// Send a response back to the client
@@ -307,6 +455,71 @@ impl<B: Buf + fmt::Debug> fmt::Debug for SendPushedResponse<B> {
     }
 }
 
+/// The outcome of [`sniff_preface`].
+///
+/// Holds the I/O resource along with every byte that was read from it while
+/// looking for the HTTP/2 connection preface.
+///
+/// [`sniff_preface`]: fn.sniff_preface.html
+#[derive(Debug)]
+pub struct Sniffed<T> {
+    io: T,
+    buf: Bytes,
+    is_http2: bool,
+}
+
+/// What to do with a new stream, as decided by the callback configured with
+/// [`Builder::admission`].
+///
+/// [`Builder::admission`]: struct.Builder.html#method.admission
+#[derive(Debug)]
+pub enum Admission {
+    /// Accept the stream, so that the request is returned by
+    /// [`Connection::accept`].
+    ///
+    /// [`Connection::accept`]: struct.Connection.html#method.accept
+    Accept,
+
+    /// Reset the stream with `REFUSED_STREAM`, which tells the client that
+    /// the request was not processed and can safely be retried.
+    Refuse,
+
+    /// Reset the stream with the given reason.
+    Reset(Reason),
+
+    /// Send the given response, with no body, and close the stream.
+    ///
+    /// This is useful to answer with a `503 Service Unavailable` or a
+    /// `431 Request Header Fields Too Large` without involving the
+    /// application.
+    Respond(Response<()>),
+}
+
+/// The state of the connection when a new stream is received, passed to the
+/// callback configured with [`Builder::admission`].
+///
+/// [`Builder::admission`]: struct.Builder.html#method.admission
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct AdmissionContext {
+    /// The identifier of the new stream.
+    pub stream_id: crate::StreamId,
+
+    /// Number of streams opened by the client that are currently active,
+    /// including the new stream.
+    pub active_streams: usize,
+
+    /// Maximum number of concurrent streams the client may open, or
+    /// `usize::MAX` if there is no limit.
+    pub max_concurrent_streams: usize,
+}
+
+/// An admission callback shared by the connections of a builder.
+#[derive(Clone)]
+pub(crate) struct SharedAdmission(
+    Arc<dyn Fn(&Request<()>, &AdmissionContext) -> Admission + Send + Sync>,
+);
+
 /// Stages of an in-progress handshake.
 enum Handshaking<T, B: Buf> {
     /// State 1. Connection is flushing pending SETTINGS frame.
@@ -339,8 +552,6 @@ struct ReadPreface<T, B> {
 #[derive(Debug)]
 pub(crate) struct Peer;
 
-const PREFACE: [u8; 24] = *b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
-
 /// Creates a new configured HTTP/2 server with default configuration
 /// values backed by `io`.
 ///
@@ -374,39 +585,194 @@ const PREFACE: [u8; 24] = *b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
 /// ```
 pub fn handshake<T>(io: T) -> Handshake<T, Bytes>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Io,
 {
     Builder::new().handshake(io)
 }
 
+/// Reads from `io` until it is known whether the HTTP/2 connection preface
+/// is present.
+///
+/// At most the 24 bytes of the preface are read, and reading stops as soon
+/// as a byte does not match, or `io` reaches EOF. The returned [`Sniffed`]
+/// gives back `io` together with the bytes that were read, so that the
+/// connection can be passed, untouched, to either
+/// [`Builder::handshake_with_prefix`] or another protocol implementation
+/// such as an HTTP/1.1 server.
+///
+/// [`Sniffed`]: struct.Sniffed.html
+/// [`Builder::handshake_with_prefix`]: struct.Builder.html#method.handshake_with_prefix
+///
+/// # Examples
+///
+/// ```
+/// # use tokio::io::{AsyncRead, AsyncWrite};
+/// # use http2::server;
+/// #
+/// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+/// # {
+/// let sniffed = server::sniff_preface(my_io).await.unwrap();
+/// let is_http2 = sniffed.is_http2();
+/// let (io, read) = sniffed.into_parts();
+/// # }
+/// #
+/// # pub fn main() {}
+/// ```
+pub async fn sniff_preface<T>(mut io: T) -> io::Result<Sniffed<T>>
+where
+    T: AsyncRead + Unpin,
+{
+    let mut buf = [0; 24];
+    let mut pos = 0;
+
+    let is_http2 = loop {
+        if pos == PREFACE.len() {
+            break true;
+        }
+
+        let n = crate::poll_fn(|cx| {
+            let mut read = ReadBuf::new(&mut buf[pos..]);
+            ready!(Pin::new(&mut io).poll_read(cx, &mut read))?;
+            Poll::Ready(Ok::<_, io::Error>(read.filled().len()))
+        })
+        .await?;
+
+        if n == 0 {
+            break false;
+        }
+
+        let matches = PREFACE[pos..pos + n] == buf[pos..pos + n];
+        pos += n;
+
+        if !matches {
+            break false;
+        }
+    };
+
+    tracing::trace!(is_http2, read = pos, "sniffed preface");
+
+    Ok(Sniffed {
+        io,
+        buf: Bytes::copy_from_slice(&buf[..pos]),
+        is_http2,
+    })
+}
+
+/// Returns `true` if `request` is a WebSocket handshake, as defined by
+/// [RFC 8441].
+///
+/// Such a request is an extended `CONNECT` request with the `:protocol`
+/// pseudo-header set to `websocket` and a `sec-websocket-version` header of
+/// `13`. Extended `CONNECT` requests are only received by a connection
+/// configured with [`Builder::enable_connect_protocol`].
+///
+/// See [`SendResponse::accept_websocket`] to accept the request.
+///
+/// [RFC 8441]: https://datatracker.ietf.org/doc/html/rfc8441
+/// [`Builder::enable_connect_protocol`]: struct.Builder.html#method.enable_connect_protocol
+/// [`SendResponse::accept_websocket`]: struct.SendResponse.html#method.accept_websocket
+pub fn is_websocket_request<B>(request: &Request<B>) -> bool {
+    let is_websocket = request
+        .extensions()
+        .get::<Protocol>()
+        .map_or(false, |protocol| {
+            protocol.as_str().eq_ignore_ascii_case("websocket")
+        });
+
+    request.method() == Method::CONNECT
+        && is_websocket
+        && request
+            .headers()
+            .get(http::header::SEC_WEBSOCKET_VERSION)
+            .map_or(false, |version| version == "13")
+}
+
+/// Returns `true` if `request` is a UDP proxying request, as defined by
+/// [RFC 9298].
+///
+/// Such a request is an extended `CONNECT` request with the `:protocol`
+/// pseudo-header set to `connect-udp` and an `https` scheme. If present, the
+/// `capsule-protocol` header must be `?1`.
+///
+/// See [`SendResponse::accept_connect_udp`] to accept the request.
+///
+/// [RFC 9298]: https://www.rfc-editor.org/rfc/rfc9298
+/// [`SendResponse::accept_connect_udp`]: struct.SendResponse.html#method.accept_connect_udp
+pub fn is_connect_udp_request<B>(request: &Request<B>) -> bool {
+    let is_connect_udp = request
+        .extensions()
+        .get::<Protocol>()
+        .map_or(false, |protocol| {
+            protocol.as_str().eq_ignore_ascii_case("connect-udp")
+        });
+
+    request.method() == Method::CONNECT
+        && is_connect_udp
+        && request.uri().scheme() == Some(&http::uri::Scheme::HTTPS)
+        && request
+            .headers()
+            .get(CAPSULE_PROTOCOL)
+            .map_or(true, |value| value == "?1")
+}
+
+// ===== impl Sniffed =====
+
+impl<T> Sniffed<T> {
+    /// Returns `true` if the full HTTP/2 connection preface was read.
+    pub fn is_http2(&self) -> bool {
+        self.is_http2
+    }
+
+    /// Returns the bytes that were read from the I/O resource.
+    pub fn read_bytes(&self) -> &Bytes {
+        &self.buf
+    }
+
+    /// Consumes `self`, returning the I/O resource and the bytes that were
+    /// read from it.
+    pub fn into_parts(self) -> (T, Bytes) {
+        (self.io, self.buf)
+    }
+}
+
+// ===== impl SharedAdmission =====
+
+impl SharedAdmission {
+    pub fn admit(&self, request: &Request<()>, context: &AdmissionContext) -> Admission {
+        (self.0)(request, context)
+    }
+}
+
+impl fmt::Debug for SharedAdmission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedAdmission").finish()
+    }
+}
+
 // ===== impl Connection =====
 
 impl<T, B> Connection<T, B>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Io,
     B: Buf,
 {
-    fn handshake2(io: T, builder: Builder) -> Handshake<T, B> {
+    fn handshake2(io: T, prefix: Bytes, builder: Builder) -> Handshake<T, B> {
+        builder.assert_timer();
+
         #[cfg(feature = "tracing")]
         let span = ::tracing::trace_span!("server_handshake");
         #[cfg(feature = "tracing")]
         let entered = span.enter();
 
-        // Create the codec.
-        let mut codec = Codec::new(io);
-
-        if let Some(max) = builder.settings.max_frame_size() {
-            codec.set_max_recv_frame_size(max as usize);
-        }
+        let (mut framed, qlog) = builder.codec(io.is_write_vectored());
 
-        if let Some(max) = builder.settings.max_header_list_size() {
-            codec.set_max_recv_header_list_size(max as usize);
+        // Bytes already read from the socket by the caller are checked
+        // first.
+        if !prefix.is_empty() {
+            framed.recv(&prefix);
         }
 
-        // Send initial settings frame.
-        codec
-            .buffer(builder.settings.clone().into())
-            .expect("invalid SETTINGS frame");
+        let codec = Codec::from_parts(io, framed);
 
         // Create the handshake future.
         #[cfg(feature = "tracing")]
@@ -420,12 +786,26 @@ where
 
         Handshake {
             builder,
+            qlog,
             state,
             #[cfg(feature = "tracing")]
             span,
         }
     }
 
+    /// Creates the connection once the preface was received on `codec`.
+    fn bind(codec: Codec<T, Prioritized<B>>, qlog: Option<Qlog>, builder: &Builder) -> Self {
+        let (io, framed) = codec.into_parts();
+
+        tracing::trace!("connection established!");
+        Connection {
+            io,
+            connection: builder.bind(framed, qlog),
+            auto_continue: builder.auto_continue,
+            lifecycle: Lifecycle::new(builder),
+        }
+    }
+
     /// Accept the next incoming request on this connection.
     pub async fn accept(
         &mut self,
@@ -447,19 +827,70 @@ where
         }
 
         if let Some(inner) = self.connection.next_incoming() {
-            tracing::trace!("received incoming");
-            let (head, _) = inner.take_request().into_parts();
-            let body = RecvStream::new(FlowControl::new(inner.clone_to_opaque()));
-
-            let request = Request::from_parts(head, body);
-            let respond = SendResponse { inner };
-
-            return Poll::Ready(Some(Ok((request, respond))));
+            return Poll::Ready(Some(Ok(accepted(inner, self.auto_continue))));
         }
 
         Poll::Pending
     }
 
+    /// Splits the connection into an [`Acceptor`], which yields inbound
+    /// streams, and a [`Driver`], which drives the connection state.
+    ///
+    /// The [`Driver`] must be polled, usually by spawning it onto an
+    /// executor, for the connection to make progress. Inbound streams are
+    /// queued until they are accepted through the [`Acceptor`], which can be
+    /// cloned and sent to other tasks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server;
+    /// # use http::{Response, StatusCode};
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin + Send + 'static>(my_io: T) {
+    /// let connection = server::handshake(my_io).await.unwrap();
+    /// let (mut acceptor, driver) = connection.split();
+    ///
+    /// tokio::spawn(async move {
+    ///     if let Err(e) = driver.await {
+    ///         println!("connection error: {}", e);
+    ///     }
+    /// });
+    ///
+    /// while let Some((request, mut respond)) = acceptor.accept().await {
+    ///     tokio::spawn(async move {
+    ///         println!("Received request: {:?}", request);
+    ///         let response = Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///             .unwrap();
+    ///         respond.send_response(response, true).unwrap();
+    ///     });
+    /// }
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`Acceptor`]: struct.Acceptor.html
+    /// [`Driver`]: struct.Driver.html
+    pub fn split(self) -> (Acceptor<B>, Driver<T, B>) {
+        let acceptors = Arc::new(AtomicUsize::new(1));
+        let acceptor = Acceptor {
+            inner: self.connection.streams().clone(),
+            auto_continue: self.auto_continue,
+            acceptors: acceptors.clone(),
+        };
+        let driver = Driver {
+            io: self.io,
+            connection: self.connection,
+            lifecycle: self.lifecycle,
+            acceptors,
+        };
+        (acceptor, driver)
+    }
+
     /// Sets the target window size for the whole connection.
     ///
     /// If `size` is greater than the current value, then a `WINDOW_UPDATE`
@@ -528,7 +959,16 @@ where
     /// [`RecvStream`]: ../struct.RecvStream.html
     /// [`SendStream`]: ../struct.SendStream.html
     pub fn poll_closed(&mut self, cx: &mut Context) -> Poll<Result<(), crate::Error>> {
-        self.connection.poll(cx).map_err(Into::into)
+        // Frames the client sent are handled before new ones are written.
+        let _ = self.connection.poll_io(&mut self.io, cx);
+        loop {
+            let poll = self.lifecycle.poll_connection(cx, &mut self.connection);
+            if let Poll::Ready(result) = poll {
+                return Poll::Ready(result.map_err(Into::into));
+            }
+
+            ready!(self.connection.poll_io(&mut self.io, cx));
+        }
     }
 
     /// Sets the connection to a GOAWAY state.
@@ -598,6 +1038,16 @@ where
         self.connection.max_recv_streams()
     }
 
+    /// Returns a snapshot of the connection's statistics.
+    ///
+    /// The snapshot includes the frames and bytes sent and received by frame
+    /// type, the number of streams opened, closed and reset, the connection
+    /// level flow control windows, and the state of the HPACK encoder and
+    /// decoder.
+    pub fn stats(&self) -> ConnectionStats {
+        self.connection.stats()
+    }
+
     // Could disappear at anytime.
     #[doc(hidden)]
     #[cfg(feature = "unstable")]
@@ -609,7 +1059,7 @@ where
 #[cfg(feature = "stream")]
 impl<T, B> futures_core::Stream for Connection<T, B>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Io,
     B: Buf,
 {
     type Item = Result<(Request<RecvStream>, SendResponse<B>), crate::Error>;
@@ -631,106 +1081,391 @@ where
     }
 }
 
-// ===== impl Builder =====
+// ===== impl Acceptor =====
 
-impl Builder {
-    /// Returns a new server builder instance initialized with default
-    /// configuration values.
-    ///
-    /// Configuration methods can be chained on the return value.
+impl<B: Buf> Acceptor<B> {
+    /// Accept the next incoming request on the connection.
     ///
-    /// # Examples
+    /// Returns `None` once the connection has closed. Connection errors are
+    /// returned by the [`Driver`].
     ///
-    /// ```
-    /// # use tokio::io::{AsyncRead, AsyncWrite};
-    /// # use http2::server::*;
-    /// #
-    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
-    /// # -> Handshake<T>
-    /// # {
-    /// // `server_fut` is a future representing the completion of the HTTP/2
-    /// // handshake.
-    /// let server_fut = Builder::new()
-    ///     .initial_window_size(1_000_000)
-    ///     .max_concurrent_streams(1000)
-    ///     .handshake(my_io);
-    /// # server_fut
-    /// # }
-    /// #
-    /// # pub fn main() {}
-    /// ```
-    pub fn new() -> Builder {
-        Builder {
-            reset_stream_duration: Duration::from_secs(proto::DEFAULT_RESET_STREAM_SECS),
-            reset_stream_max: proto::DEFAULT_RESET_STREAM_MAX,
-            pending_accept_reset_stream_max: proto::DEFAULT_REMOTE_RESET_STREAM_MAX,
-            settings: Settings::default(),
-            initial_target_connection_window_size: None,
-            max_send_buffer_size: proto::DEFAULT_MAX_SEND_BUFFER_SIZE,
+    /// [`Driver`]: struct.Driver.html
+    pub async fn accept(&mut self) -> Option<(Request<RecvStream>, SendResponse<B>)> {
+        crate::poll_fn(move |cx| self.poll_accept(cx)).await
+    }
 
-            local_max_error_reset_streams: Some(proto::DEFAULT_LOCAL_RESET_COUNT_MAX),
-        }
+    #[doc(hidden)]
+    pub fn poll_accept(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<(Request<RecvStream>, SendResponse<B>)>> {
+        self.inner
+            .poll_accept(cx)
+            .map(|inner| inner.map(|inner| accepted(inner, self.auto_continue)))
     }
 
-    /// Indicates the initial window size (in octets) for stream-level
-    /// flow control for received data.
-    ///
-    /// The initial window of a stream is used as part of flow control. For more
-    /// details, see [`FlowControl`].
-    ///
-    /// The default value is 65,535.
-    ///
-    /// [`FlowControl`]: ../struct.FlowControl.html
+    /// Starts a [graceful shutdown][1] process.
     ///
-    /// # Examples
+    /// The [`Driver`] sends the `GOAWAY` frame the next time it is polled.
+    /// Requests that were already in flight from the client may still be
+    /// accepted afterwards. Once all active streams have completed, the
+    /// connection is closed.
     ///
-    /// ```
-    /// # use tokio::io::{AsyncRead, AsyncWrite};
-    /// # use http2::server::*;
-    /// #
-    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
-    /// # -> Handshake<T>
-    /// # {
-    /// // `server_fut` is a future representing the completion of the HTTP/2
-    /// // handshake.
-    /// let server_fut = Builder::new()
-    ///     .initial_window_size(1_000_000)
-    ///     .handshake(my_io);
-    /// # server_fut
-    /// # }
-    /// #
-    /// # pub fn main() {}
-    /// ```
-    pub fn initial_window_size(&mut self, size: u32) -> &mut Self {
-        self.settings.set_initial_window_size(Some(size));
-        self
+    /// [1]: http://httpwg.org/specs/rfc7540.html#GOAWAY
+    /// [`Driver`]: struct.Driver.html
+    pub fn graceful_shutdown(&self) {
+        self.inner.request_graceful_shutdown();
     }
 
-    /// Indicates the initial window size (in octets) for connection-level flow control
-    /// for received data.
-    ///
-    /// The initial window of a connection is used as part of flow control. For more details,
-    /// see [`FlowControl`].
-    ///
-    /// The default value is 65,535.
-    ///
-    /// [`FlowControl`]: ../struct.FlowControl.html
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// # use tokio::io::{AsyncRead, AsyncWrite};
-    /// # use http2::server::*;
-    /// #
-    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
-    /// # -> Handshake<T>
-    /// # {
-    /// // `server_fut` is a future representing the completion of the HTTP/2
-    /// // handshake.
-    /// let server_fut = Builder::new()
-    ///     .initial_connection_window_size(1_000_000)
-    ///     .handshake(my_io);
-    /// # server_fut
+    /// Returns a snapshot of the connection's statistics.
+    pub fn stats(&self) -> ConnectionStats {
+        self.inner.stats()
+    }
+}
+
+impl<B: Buf> Clone for Acceptor<B> {
+    fn clone(&self) -> Self {
+        self.acceptors.fetch_add(1, Ordering::Relaxed);
+        Acceptor {
+            inner: self.inner.clone(),
+            auto_continue: self.auto_continue,
+            acceptors: self.acceptors.clone(),
+        }
+    }
+}
+
+impl<B: Buf> Drop for Acceptor<B> {
+    fn drop(&mut self) {
+        if self.acceptors.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Nothing can accept streams anymore, so have the driver stop the
+            // peer from opening new ones.
+            self.inner.request_graceful_shutdown();
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<B: Buf> futures_core::Stream for Acceptor<B> {
+    type Item = (Request<RecvStream>, SendResponse<B>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_accept(cx)
+    }
+}
+
+impl<B> fmt::Debug for Acceptor<B>
+where
+    B: Buf,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Acceptor").finish()
+    }
+}
+
+// ===== impl Driver =====
+
+impl<T, B> Future for Driver<T, B>
+where
+    T: Io,
+    B: Buf,
+{
+    type Output = Result<(), crate::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.connection.streams().take_graceful_shutdown_request() {
+            self.connection.go_away_gracefully();
+        }
+
+        let me = &mut *self;
+        let _ = me.connection.poll_io(&mut me.io, cx);
+        let result = loop {
+            let poll = me.lifecycle.poll_connection(cx, &mut me.connection);
+            if let Poll::Ready(result) = poll {
+                break result;
+            }
+
+            // Streams received after the last acceptor was dropped would
+            // otherwise wait in the accept queue forever. Refusing them queues
+            // frames, so the connection is polled again to send them.
+            if !me.refuse_unaccepted() {
+                ready!(me.connection.poll_io(&mut me.io, cx));
+            }
+        };
+
+        // Let the acceptors know that no more streams are coming, even if the
+        // driver is not dropped right away.
+        let _ = self.connection.streams().as_dyn().recv_eof(false);
+
+        Poll::Ready(result.map_err(Into::into))
+    }
+}
+
+impl<T, B> Driver<T, B>
+where
+    T: Io,
+    B: Buf,
+{
+    /// Resets the streams waiting to be accepted if every `Acceptor` has been
+    /// dropped, returning whether any were.
+    fn refuse_unaccepted(&mut self) -> bool {
+        if self.acceptors.load(Ordering::Acquire) != 0 {
+            return false;
+        }
+
+        let mut refused = false;
+        while let Some(mut stream) = self.connection.next_incoming() {
+            stream.send_reset(Reason::REFUSED_STREAM);
+            refused = true;
+        }
+        refused
+    }
+}
+
+impl<T, B> fmt::Debug for Driver<T, B>
+where
+    T: fmt::Debug,
+    B: fmt::Debug + Buf,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Driver")
+            .field("connection", &self.connection)
+            .finish()
+    }
+}
+
+// ===== impl Lifecycle =====
+
+impl Lifecycle {
+    pub(crate) fn new(builder: &Builder) -> Lifecycle {
+        Lifecycle {
+            max_requests: builder.max_connection_requests,
+            max_age: builder.max_connection_age.map(|age| Instant::now() + age),
+            max_age_alarm: Alarm::new(builder.timer.clone()),
+            shutdown_timeout: builder.graceful_shutdown_timeout,
+            shutdown_deadline: None,
+            shutdown_alarm: Alarm::new(builder.timer.clone()),
+        }
+    }
+
+    /// Returns when a limit next needs the connection to be polled.
+    pub(crate) fn poll_timeout(&self) -> Option<Instant> {
+        match (self.max_age, self.shutdown_deadline) {
+            (Some(max_age), Some(deadline)) => Some(cmp::min(max_age, deadline)),
+            (deadline, None) | (None, deadline) => deadline,
+        }
+    }
+
+    /// Polls the connection, shutting it down once a limit is reached.
+    pub(crate) fn poll_connection<B: Buf>(
+        &mut self,
+        cx: &mut Context,
+        connection: &mut proto::Connection<Peer, B>,
+    ) -> Poll<Result<(), Error>> {
+        loop {
+            let poll = connection.poll(cx);
+
+            // Frames received while polling may have reached a limit, in
+            // which case the connection must be polled again to act on it.
+            if poll.is_ready() || !self.poll_limits(cx, connection) {
+                return poll;
+            }
+        }
+    }
+
+    /// Returns `true` if a shutdown was started or escalated.
+    fn poll_limits<B: Buf>(
+        &mut self,
+        cx: &mut Context,
+        connection: &mut proto::Connection<Peer, B>,
+    ) -> bool {
+        let mut changed = false;
+
+        if connection.is_going_away() {
+            self.max_age = None;
+            self.max_age_alarm.cancel();
+        } else {
+            let served = self
+                .max_requests
+                .map_or(false, |max| connection.num_requests() >= max);
+            let alarm = &mut self.max_age_alarm;
+            let expired = self
+                .max_age
+                .map_or(false, |deadline| alarm.poll_expired(cx, deadline));
+
+            if served || expired {
+                tracing::debug!(
+                    "connection limit reached; served={}, expired={}",
+                    served,
+                    expired
+                );
+                self.max_age = None;
+                self.max_age_alarm.cancel();
+                connection.go_away_gracefully();
+                changed = true;
+            }
+        }
+
+        if connection.is_going_away_gracefully() {
+            if let Some(timeout) = self.shutdown_timeout.take() {
+                self.shutdown_deadline = Some(Instant::now() + timeout);
+            }
+
+            if let Some(deadline) = self.shutdown_deadline {
+                if self.shutdown_alarm.poll_expired(cx, deadline) {
+                    tracing::debug!("graceful shutdown timed out");
+                    self.shutdown_deadline = None;
+                    connection.go_away_from_user(Reason::NO_ERROR);
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+pub(crate) fn accepted<B: Buf>(
+    inner: proto::StreamRef<B>,
+    auto_continue: bool,
+) -> (Request<RecvStream>, SendResponse<B>) {
+    tracing::trace!("received incoming");
+    let (head, _) = inner.take_request().into_parts();
+    let body = RecvStream::new(FlowControl::new(inner.clone_to_opaque()));
+
+    let request = Request::from_parts(head, body);
+    let mut respond = SendResponse { inner };
+
+    if auto_continue
+        && proto::expects_continue(request.headers())
+        && !request.body().is_end_stream()
+    {
+        let mut response = Response::new(());
+        *response.status_mut() = StatusCode::CONTINUE;
+        if let Err(_e) = respond.send_informational(response) {
+            tracing::debug!("failed to send 100 Continue; err={:?}", _e);
+        }
+    }
+
+    (request, respond)
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Returns a new server builder instance initialized with default
+    /// configuration values.
+    ///
+    /// Configuration methods can be chained on the return value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .initial_window_size(1_000_000)
+    ///     .max_concurrent_streams(1000)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn new() -> Builder {
+        Builder {
+            reset_stream_duration: Duration::from_secs(proto::DEFAULT_RESET_STREAM_SECS),
+            reset_stream_max: proto::DEFAULT_RESET_STREAM_MAX,
+            pending_accept_reset_stream_max: proto::DEFAULT_REMOTE_RESET_STREAM_MAX,
+            settings: Settings::default(),
+            initial_target_connection_window_size: None,
+            max_send_buffer_size: proto::DEFAULT_MAX_SEND_BUFFER_SIZE,
+
+            local_max_error_reset_streams: Some(proto::DEFAULT_LOCAL_RESET_COUNT_MAX),
+            auto_continue: false,
+            oversized_response_431: false,
+            max_continuation_frames: None,
+            max_header_block_size: None,
+            max_header_decompression_ratio: None,
+            frame_observer: None,
+            frame_log: None,
+            qlog: None,
+            #[cfg(feature = "unstable")]
+            frame_interceptor: None,
+            flood_limits: proto::FloodLimits::default(),
+            timer: None,
+            max_connection_requests: None,
+            max_connection_age: None,
+            graceful_shutdown_timeout: None,
+            idle_timeout: None,
+            admission: None,
+        }
+    }
+
+    /// Indicates the initial window size (in octets) for stream-level
+    /// flow control for received data.
+    ///
+    /// The initial window of a stream is used as part of flow control. For more
+    /// details, see [`FlowControl`].
+    ///
+    /// The default value is 65,535.
+    ///
+    /// [`FlowControl`]: ../struct.FlowControl.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .initial_window_size(1_000_000)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn initial_window_size(&mut self, size: u32) -> &mut Self {
+        self.settings.set_initial_window_size(Some(size));
+        self
+    }
+
+    /// Indicates the initial window size (in octets) for connection-level flow control
+    /// for received data.
+    ///
+    /// The initial window of a connection is used as part of flow control. For more details,
+    /// see [`FlowControl`].
+    ///
+    /// The default value is 65,535.
+    ///
+    /// [`FlowControl`]: ../struct.FlowControl.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .initial_connection_window_size(1_000_000)
+    ///     .handshake(my_io);
+    /// # server_fut
     /// # }
     /// #
     /// # pub fn main() {}
@@ -812,35 +1547,891 @@ impl Builder {
         self
     }
 
-    /// Sets the maximum number of concurrent streams.
+    /// Sets the maximum number of CONTINUATION frames in a received header
+    /// block.
     ///
-    /// The maximum concurrent streams setting only controls the maximum number
-    /// of streams that can be initiated by the remote peer. In other words,
-    /// when this setting is set to 100, this does not limit the number of
-    /// concurrent streams that can be created by the caller.
+    /// A peer can split a header block across an unbounded number of
+    /// CONTINUATION frames. Once a header block goes over this limit, the
+    /// connection is closed with a GOAWAY frame using `ENHANCE_YOUR_CALM`,
+    /// without waiting for the rest of the block.
     ///
-    /// It is recommended that this value be no smaller than 100, so as to not
-    /// unnecessarily limit parallelism. However, any value is legal, including
-    /// 0. If `max` is set to 0, then the remote will not be permitted to
-    /// initiate streams.
+    /// By default, the limit is derived from the max header list size and the
+    /// max frame size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_continuation_frames(8)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_continuation_frames(&mut self, max: usize) -> &mut Self {
+        self.max_continuation_frames = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of undecoded bytes in a received header block.
+    ///
+    /// This counts the HPACK-encoded bytes of the HEADERS or PUSH_PROMISE frame
+    /// and all of its CONTINUATION frames. Once a header block goes over this
+    /// limit, the connection is closed with a GOAWAY frame using
+    /// `ENHANCE_YOUR_CALM`, without waiting for the rest of the block.
+    ///
+    /// By default, there is no limit other than the one implied by the max
+    /// header list size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_header_block_size(64 * 1024)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_header_block_size(&mut self, max: usize) -> &mut Self {
+        self.max_header_block_size = Some(max);
+        self
+    }
+
+    /// Sets the maximum ratio of the decoded size of a received header list
+    /// to its HPACK-encoded size.
+    ///
+    /// Small indexed references to large dynamic table entries can expand a
+    /// tiny header block into a huge header list. Once a header list being
+    /// decoded goes over this ratio, the remaining headers are dropped as they
+    /// are decoded.
+    /// Requests over the limit are treated the same way as ones over the max
+    /// header list size: the stream is refused with a `431 Request Header
+    /// Fields Too Large` response.
+    ///
+    /// Header lists smaller than 16KB are not checked, since small header
+    /// blocks can be very well compressed. By default, there is no limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_header_decompression_ratio(64)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_header_decompression_ratio(&mut self, ratio: usize) -> &mut Self {
+        self.max_header_decompression_ratio = Some(ratio);
+        self
+    }
+
+    /// Sets an observer that is called with every frame read from and written
+    /// to the connection.
+    ///
+    /// The observer sees each frame's type, flags, stream identifier and
+    /// length, the decoded header list of HEADERS and PUSH_PROMISE frames,
+    /// and optionally the raw bytes of the frame. See the [`observer`] module
+    /// for details.
+    ///
+    /// [`observer`]: ../observer/index.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// # use http2::observer::{FrameObserver, ObservedFrame};
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// struct LogFrames;
+    ///
+    /// impl FrameObserver for LogFrames {
+    ///     fn on_frame(&self, frame: &ObservedFrame<'_>) {
+    ///         println!("{:?} {:?}", frame.direction(), frame.frame_type());
+    ///     }
+    /// }
+    ///
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .frame_observer(LogFrames)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn frame_observer<O>(&mut self, observer: O) -> &mut Self
+    where
+        O: FrameObserver + 'static,
+    {
+        self.frame_observer = Some(SharedObserver::new(observer));
+        self
+    }
+
+    /// Writes a human-readable line for every frame read from and written to
+    /// the connection to `writer`, in the format of `nghttp -v`.
+    ///
+    /// The log is written in addition to calling the observer set with
+    /// [`frame_observer`], if any, in whichever order the two are set. See
+    /// [`FrameLog`] for details.
+    ///
+    /// [`frame_observer`]: #method.frame_observer
+    /// [`FrameLog`]: ../observer/struct.FrameLog.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .frame_log(std::io::stderr())
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn frame_log<W>(&mut self, writer: W) -> &mut Self
+    where
+        W: io::Write + Send + 'static,
+    {
+        self.frame_log = Some(SharedObserver::new(FrameLog::new(writer)));
+        self
+    }
+
+    /// Creates the codec of a connection, with the initial SETTINGS frame
+    /// buffered.
+    pub(crate) fn codec<B: Buf>(
+        &self,
+        is_write_vectored: bool,
+    ) -> (Framed<Prioritized<B>>, Option<Qlog>) {
+        let mut codec = Framed::new(is_write_vectored);
+
+        if let Some(max) = self.settings.max_frame_size() {
+            codec.set_max_recv_frame_size(max as usize);
+        }
+
+        if let Some(max) = self.settings.max_header_list_size() {
+            codec.set_max_recv_header_list_size(max as usize);
+        }
+
+        if let Some(max) = self.max_continuation_frames {
+            codec.set_max_recv_continuation_frames(max);
+        }
+
+        if let Some(max) = self.max_header_block_size {
+            codec.set_max_recv_header_block_size(max);
+        }
+
+        if let Some(ratio) = self.max_header_decompression_ratio {
+            codec.set_max_recv_header_decoded_ratio(ratio);
+        }
+
+        let mut observer = self.frame_observer.clone();
+
+        if let Some(ref log) = self.frame_log {
+            observer = Some(match observer {
+                Some(observer) => observer.join(log.clone()),
+                None => log.clone(),
+            });
+        }
+
+        let qlog = self.qlog.as_ref().map(|make| make.start(true));
+        if let Some(ref qlog) = qlog {
+            let frames = qlog.frame_observer();
+            observer = Some(match observer {
+                Some(observer) => observer.join(frames),
+                None => frames,
+            });
+        }
+
+        if let Some(observer) = observer {
+            codec.set_frame_observer(observer);
+        }
+
+        #[cfg(feature = "unstable")]
+        if let Some(interceptor) = self.frame_interceptor.clone() {
+            codec.set_frame_interceptor(interceptor);
+        }
+
+        // Send initial settings frame.
+        codec
+            .buffer(self.settings.clone().into())
+            .expect("invalid SETTINGS frame");
+
+        (codec, qlog)
+    }
+
+    /// Creates the connection state of a connection whose preface was read
+    /// by `codec`.
+    pub(crate) fn bind<B: Buf>(
+        &self,
+        codec: Framed<Prioritized<B>>,
+        qlog: Option<Qlog>,
+    ) -> proto::Connection<Peer, B> {
+        let mut connection = proto::Connection::new(
+            codec,
+            Config {
+                next_stream_id: 2.into(),
+                max_stream_id: StreamId::MAX,
+                // Server does not need to locally initiate any streams
+                initial_max_send_streams: 0,
+                max_send_buffer_size: self.max_send_buffer_size,
+                reset_stream_duration: self.reset_stream_duration,
+                reset_stream_max: self.reset_stream_max,
+                remote_reset_stream_max: self.pending_accept_reset_stream_max,
+                local_error_reset_streams_max: self.local_max_error_reset_streams,
+                settings: self.settings.clone(),
+                headers_priority: None,
+                headers_pseudo_order: None,
+                priority: None,
+                expect_continue: false,
+                retain_unprocessed_requests: false,
+                oversized_response_431: self.oversized_response_431,
+                flood_limits: self.flood_limits.clone(),
+                qlog,
+                idle_timeout: self.idle_timeout,
+                timer: self.timer.clone(),
+                admission: self.admission.clone(),
+                max_queued_requests: None,
+                request_queue_timeout: None,
+            },
+        );
+        if let Some(sz) = self.initial_target_connection_window_size {
+            connection.set_target_window_size(sz);
+        }
+        connection
+    }
+
+    /// Panics if a setting that requires a timer is used without one.
+    fn assert_timer(&self) {
+        if self.timer.is_some() {
+            return;
+        }
+
+        assert!(
+            self.max_connection_age.is_none(),
+            "max_connection_age requires a timer"
+        );
+        assert!(
+            self.graceful_shutdown_timeout.is_none(),
+            "graceful_shutdown_timeout requires a timer"
+        );
+        assert!(self.idle_timeout.is_none(), "idle_timeout requires a timer");
+    }
+
+    /// Writes a [qlog] trace of each connection, as JSON-SEQ, to a writer
+    /// returned by `make_writer`.
+    ///
+    /// The trace records every frame read and written, changes to the
+    /// settings, GOAWAY frames, stream state changes and flow control window
+    /// updates, with times relative to the start of the handshake. It can be
+    /// loaded in tools such as [qvis]. Errors returned by the writer are
+    /// ignored.
+    ///
+    /// `make_writer` is called once per connection created from this
+    /// builder, when its handshake starts, so that the records of different
+    /// connections are not interleaved. Only the frame header of DATA frames
+    /// is recorded, and their payload is not copied.
+    ///
+    /// The writer is called synchronously from the task driving the
+    /// connection, sometimes while the state of the streams is locked. It is
+    /// only flushed once the connection is dropped, so writers that block,
+    /// such as files, should be wrapped in a [`BufWriter`].
+    ///
+    /// [qlog]: https://datatracker.ietf.org/doc/draft-ietf-quic-qlog-main-schema/
+    /// [qvis]: https://qvis.quictools.info/
+    /// [`BufWriter`]: https://doc.rust-lang.org/std/io/struct.BufWriter.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .qlog(std::io::stderr)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn qlog<F, W>(&mut self, make_writer: F) -> &mut Self
+    where
+        F: Fn() -> W + Send + Sync + 'static,
+        W: io::Write + Send + 'static,
+    {
+        self.qlog = Some(MakeWriter::new(make_writer));
+        self
+    }
+
+    /// Sets an interceptor that is called with every frame before it is
+    /// written to the connection.
+    ///
+    /// The interceptor can rewrite, drop, insert or delay frames, within
+    /// limits that keep the stream states consistent with what the peer sees.
+    /// See the [`intercept`] module for details.
+    ///
+    /// This is only available with the `unstable` feature.
+    ///
+    /// [`intercept`]: ../intercept/index.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// # use http2::frame::Frame;
+    /// # use http2::intercept::{FrameInterceptor, Intercept};
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// struct SplitData;
+    ///
+    /// impl FrameInterceptor for SplitData {
+    ///     fn intercept(&self, frame: Frame) -> Intercept {
+    ///         match frame {
+    ///             // Write DATA frames one byte at a time.
+    ///             Frame::Data(data) if data.payload().len() > 1 => {
+    ///                 let id = data.stream_id();
+    ///                 let eos = data.is_end_stream();
+    ///                 let payload = data.into_payload();
+    ///                 let frames = (0..payload.len())
+    ///                     .map(|i| {
+    ///                         let mut data = http2::frame::Data::new(id, payload.slice(i..i + 1));
+    ///                         data.set_end_stream(eos && i == payload.len() - 1);
+    ///                         data.into()
+    ///                     })
+    ///                     .collect();
+    ///                 Intercept::Replace(frames)
+    ///             }
+    ///             frame => Intercept::Send(frame),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .frame_interceptor(SplitData)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    #[cfg(feature = "unstable")]
+    pub fn frame_interceptor<I>(&mut self, interceptor: I) -> &mut Self
+    where
+        I: FrameInterceptor + 'static,
+    {
+        self.frame_interceptor = Some(SharedInterceptor::new(interceptor));
+        self
+    }
+
+    /// Sets the maximum number of concurrent streams.
+    ///
+    /// The maximum concurrent streams setting only controls the maximum number
+    /// of streams that can be initiated by the remote peer. In other words,
+    /// when this setting is set to 100, this does not limit the number of
+    /// concurrent streams that can be created by the caller.
+    ///
+    /// It is recommended that this value be no smaller than 100, so as to not
+    /// unnecessarily limit parallelism. However, any value is legal, including
+    /// 0. If `max` is set to 0, then the remote will not be permitted to
+    /// initiate streams.
+    ///
+    /// Note that streams in the reserved state, i.e., push promises that have
+    /// been reserved but the stream has not started, do not count against this
+    /// setting.
+    ///
+    /// Also note that if the remote *does* exceed the value set here, it is not
+    /// a protocol level error. Instead, the `http2` library will immediately reset
+    /// the stream.
+    ///
+    /// See [Section 5.1.2] in the HTTP/2 spec for more details.
+    ///
+    /// [Section 5.1.2]: https://http2.github.io/http2-spec/#rfc.section.5.1.2
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_concurrent_streams(1000)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_concurrent_streams(&mut self, max: u32) -> &mut Self {
+        self.settings.set_max_concurrent_streams(Some(max));
+        self
+    }
+
+    /// Sets the maximum number of concurrent locally reset streams.
+    ///
+    /// When a stream is explicitly reset by either calling
+    /// [`SendResponse::send_reset`] or by dropping a [`SendResponse`] instance
+    /// before completing the stream, the HTTP/2 specification requires that
+    /// any further frames received for that stream must be ignored for "some
+    /// time".
+    ///
+    /// In order to satisfy the specification, internal state must be maintained
+    /// to implement the behavior. This state grows linearly with the number of
+    /// streams that are locally reset.
+    ///
+    /// The `max_concurrent_reset_streams` setting configures sets an upper
+    /// bound on the amount of state that is maintained. When this max value is
+    /// reached, the oldest reset stream is purged from memory.
+    ///
+    /// Once the stream has been fully purged from memory, any additional frames
+    /// received for that stream will result in a connection level protocol
+    /// error, forcing the connection to terminate.
+    ///
+    /// The default value is 10.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_concurrent_reset_streams(1000)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_concurrent_reset_streams(&mut self, max: usize) -> &mut Self {
+        self.reset_stream_max = max;
+        self
+    }
+
+    /// Sets the maximum number of local resets due to protocol errors made by the remote end.
+    ///
+    /// Invalid frames and many other protocol errors will lead to resets being generated for those streams.
+    /// Too many of these often indicate a malicious client, and there are attacks which can abuse this to DOS servers.
+    /// This limit protects against these DOS attacks by limiting the amount of resets we can be forced to generate.
+    ///
+    /// When the number of local resets exceeds this threshold, the server will issue GOAWAYs with an error code of
+    /// `ENHANCE_YOUR_CALM` to the client.
+    ///
+    /// If you really want to disable this, supply [`Option::None`] here.
+    /// Disabling this is not recommended and may expose you to DOS attacks.
+    ///
+    /// The default value is currently 1024, but could change.
+    pub fn max_local_error_reset_streams(&mut self, max: Option<usize>) -> &mut Self {
+        self.local_max_error_reset_streams = max;
+        self
+    }
+
+    /// Limits how many frames of a kind the remote peer may send.
+    ///
+    /// Frames that cost little to send but must be processed or answered, such
+    /// as PING or SETTINGS, can be used to exhaust a peer's resources. This
+    /// allows at most `max` frames of the given kind within any sliding
+    /// `window` of time. Once the limit is exceeded, the connection is closed
+    /// with a GOAWAY frame using `ENHANCE_YOUR_CALM`, and the returned error
+    /// reports the limit with [`Error::flood_limit`].
+    ///
+    /// Memory used to track a limit is proportional to `max`.
+    ///
+    /// By default, none of these limits are enforced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// # use http2::FloodLimit;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .flood_limit(FloodLimit::Ping, 100, Duration::from_secs(10))
+    ///     .flood_limit(FloodLimit::Settings, 100, Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`Error::flood_limit`]: ../struct.Error.html#method.flood_limit
+    pub fn flood_limit(&mut self, limit: FloodLimit, max: usize, window: Duration) -> &mut Self {
+        self.flood_limits.set(limit, max, window);
+        self
+    }
+
+    /// Sets the maximum number of pending-accept remotely-reset streams.
+    ///
+    /// Streams that have been received by the peer, but not accepted by the
+    /// user, can also receive a RST_STREAM. This is a legitimate pattern: one
+    /// could send a request and then shortly after, realize it is not needed,
+    /// sending a CANCEL.
+    ///
+    /// However, since those streams are now "closed", they don't count towards
+    /// the max concurrent streams. So, they will sit in the accept queue,
+    /// using memory.
+    ///
+    /// When the number of remotely-reset streams sitting in the pending-accept
+    /// queue reaches this maximum value, a connection error with the code of
+    /// `ENHANCE_YOUR_CALM` will be sent to the peer, and returned by the
+    /// `Future`.
+    ///
+    /// The default value is currently 20, but could change.
+    ///
+    /// # Examples
+    ///
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_pending_accept_reset_streams(100)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn max_pending_accept_reset_streams(&mut self, max: usize) -> &mut Self {
+        self.pending_accept_reset_stream_max = max;
+        self
+    }
+
+    /// Sets the maximum send buffer size per stream.
+    ///
+    /// Once a stream has buffered up to (or over) the maximum, the stream's
+    /// flow control will not "poll" additional capacity. Once bytes for the
+    /// stream have been written to the connection, the send buffer capacity
+    /// will be freed up again.
+    ///
+    /// The default is currently ~400KB, but may change.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `max` is larger than `u32::MAX`.
+    pub fn max_send_buffer_size(&mut self, max: usize) -> &mut Self {
+        assert!(max <= u32::MAX as usize);
+        self.max_send_buffer_size = max;
+        self
+    }
+
+    /// Sets the maximum number of concurrent locally reset streams.
+    ///
+    /// When a stream is explicitly reset by either calling
+    /// [`SendResponse::send_reset`] or by dropping a [`SendResponse`] instance
+    /// before completing the stream, the HTTP/2 specification requires that
+    /// any further frames received for that stream must be ignored for "some
+    /// time".
+    ///
+    /// In order to satisfy the specification, internal state must be maintained
+    /// to implement the behavior. This state grows linearly with the number of
+    /// streams that are locally reset.
+    ///
+    /// The `reset_stream_duration` setting configures the max amount of time
+    /// this state will be maintained in memory. Once the duration elapses, the
+    /// stream state is purged from memory.
+    ///
+    /// Once the stream has been fully purged from memory, any additional frames
+    /// received for that stream will result in a connection level protocol
+    /// error, forcing the connection to terminate.
+    ///
+    /// The default value is 30 seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .reset_stream_duration(Duration::from_secs(10))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn reset_stream_duration(&mut self, dur: Duration) -> &mut Self {
+        self.reset_stream_duration = dur;
+        self
+    }
+
+    /// Enables or disables answering oversized responses with a `431`.
+    ///
+    /// Responses whose header list is larger than the client's
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE` are never sent, and
+    /// [`SendResponse::send_response`] returns an error instead. When this is
+    /// enabled, a bare `431 Request Header Fields Too Large` response is sent
+    /// in its place, ending the stream. Otherwise, the application may still
+    /// send a smaller response.
+    ///
+    /// Default value: `false`.
+    ///
+    /// [`SendResponse::send_response`]: struct.SendResponse.html#method.send_response
+    pub fn oversized_response_431(&mut self, enabled: bool) -> &mut Self {
+        self.oversized_response_431 = enabled;
+        self
+    }
+
+    /// Enables the [extended CONNECT protocol].
+    ///
+    /// [extended CONNECT protocol]: https://datatracker.ietf.org/doc/html/rfc8441#section-4
+    pub fn enable_connect_protocol(&mut self) -> &mut Self {
+        self.settings.set_enable_connect_protocol(Some(1));
+        self
+    }
+
+    /// Sets the timer used by time based settings.
+    ///
+    /// A timer is required by [`max_connection_age`],
+    /// [`graceful_shutdown_timeout`] and [`idle_timeout`]. See the [`timer`]
+    /// module for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// # use http2::timer::{Sleep, Timer};
+    /// # use std::pin::Pin;
+    /// # use std::time::Duration;
+    /// #
+    /// #[derive(Debug)]
+    /// struct TokioTimer;
+    ///
+    /// impl Timer for TokioTimer {
+    ///     fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+    ///         Box::pin(tokio::time::sleep(duration))
+    ///     }
+    /// }
+    ///
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .timer(TokioTimer)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [`max_connection_age`]: #method.max_connection_age
+    /// [`graceful_shutdown_timeout`]: #method.graceful_shutdown_timeout
+    /// [`idle_timeout`]: #method.idle_timeout
+    /// [`timer`]: ../timer/index.html
+    pub fn timer<M>(&mut self, timer: M) -> &mut Self
+    where
+        M: Timer + 'static,
+    {
+        self.timer = Some(SharedTimer::new(timer));
+        self
+    }
+
+    /// Sets the number of requests a connection receives before it is shut
+    /// down gracefully.
+    ///
+    /// Once the client has opened `max` streams, a [graceful shutdown] is
+    /// started, as if [`Connection::graceful_shutdown`] was called. Requests
+    /// that were already in flight may still be accepted.
+    ///
+    /// By default, there is no limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .max_connection_requests(1000)
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [graceful shutdown]: http://httpwg.org/specs/rfc7540.html#GOAWAY
+    /// [`Connection::graceful_shutdown`]: struct.Connection.html#method.graceful_shutdown
+    pub fn max_connection_requests(&mut self, max: u64) -> &mut Self {
+        self.max_connection_requests = Some(max);
+        self
+    }
+
+    /// Sets how long a connection is kept open before it is shut down
+    /// gracefully.
+    ///
+    /// The age is measured from the end of the handshake. Once it is reached,
+    /// a [graceful shutdown] is started, as if
+    /// [`Connection::graceful_shutdown`] was called.
+    ///
+    /// By default, there is no limit.
+    ///
+    /// # Panics
+    ///
+    /// [`handshake`] panics if no [`timer`] is configured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// # use http2::timer::{Sleep, Timer};
+    /// # use std::pin::Pin;
+    /// # use std::time::Duration;
+    /// #
+    /// # struct TokioTimer;
+    /// #
+    /// # impl Timer for TokioTimer {
+    /// #     fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+    /// #         Box::pin(tokio::time::sleep(duration))
+    /// #     }
+    /// # }
+    /// #
+    /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # -> Handshake<T>
+    /// # {
+    /// // `server_fut` is a future representing the completion of the HTTP/2
+    /// // handshake.
+    /// let server_fut = Builder::new()
+    ///     .timer(TokioTimer)
+    ///     .max_connection_age(Duration::from_secs(3600))
+    ///     .handshake(my_io);
+    /// # server_fut
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [graceful shutdown]: http://httpwg.org/specs/rfc7540.html#GOAWAY
+    /// [`Connection::graceful_shutdown`]: struct.Connection.html#method.graceful_shutdown
+    /// [`handshake`]: #method.handshake
+    /// [`timer`]: #method.timer
+    pub fn max_connection_age(&mut self, age: Duration) -> &mut Self {
+        self.max_connection_age = Some(age);
+        self
+    }
+
+    /// Sets how long a graceful shutdown may take before it becomes abrupt.
     ///
-    /// Note that streams in the reserved state, i.e., push promises that have
-    /// been reserved but the stream has not started, do not count against this
-    /// setting.
+    /// This applies to every graceful shutdown, whether started by the limits
+    /// of the builder or by [`Connection::graceful_shutdown`]. Once `timeout`
+    /// has elapsed, streams that are still active are reset and the
+    /// connection is closed, as if [`Connection::abrupt_shutdown`] was called
+    /// with `NO_ERROR`.
     ///
-    /// Also note that if the remote *does* exceed the value set here, it is not
-    /// a protocol level error. Instead, the `http2` library will immediately reset
-    /// the stream.
+    /// By default, a graceful shutdown waits for the active streams for as
+    /// long as it takes.
     ///
-    /// See [Section 5.1.2] in the HTTP/2 spec for more details.
+    /// # Panics
     ///
-    /// [Section 5.1.2]: https://http2.github.io/http2-spec/#rfc.section.5.1.2
+    /// [`handshake`] panics if no [`timer`] is configured.
     ///
     /// # Examples
     ///
     /// ```
     /// # use tokio::io::{AsyncRead, AsyncWrite};
     /// # use http2::server::*;
+    /// # use http2::timer::{Sleep, Timer};
+    /// # use std::pin::Pin;
+    /// # use std::time::Duration;
+    /// #
+    /// # struct TokioTimer;
+    /// #
+    /// # impl Timer for TokioTimer {
+    /// #     fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+    /// #         Box::pin(tokio::time::sleep(duration))
+    /// #     }
+    /// # }
     /// #
     /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
     /// # -> Handshake<T>
@@ -848,45 +2439,54 @@ impl Builder {
     /// // `server_fut` is a future representing the completion of the HTTP/2
     /// // handshake.
     /// let server_fut = Builder::new()
-    ///     .max_concurrent_streams(1000)
+    ///     .timer(TokioTimer)
+    ///     .graceful_shutdown_timeout(Duration::from_secs(30))
     ///     .handshake(my_io);
     /// # server_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_concurrent_streams(&mut self, max: u32) -> &mut Self {
-        self.settings.set_max_concurrent_streams(Some(max));
+    ///
+    /// [`Connection::graceful_shutdown`]: struct.Connection.html#method.graceful_shutdown
+    /// [`Connection::abrupt_shutdown`]: struct.Connection.html#method.abrupt_shutdown
+    /// [`handshake`]: #method.handshake
+    /// [`timer`]: #method.timer
+    pub fn graceful_shutdown_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.graceful_shutdown_timeout = Some(timeout);
         self
     }
 
-    /// Sets the maximum number of concurrent locally reset streams.
-    ///
-    /// When a stream is explicitly reset by either calling
-    /// [`SendResponse::send_reset`] or by dropping a [`SendResponse`] instance
-    /// before completing the stream, the HTTP/2 specification requires that
-    /// any further frames received for that stream must be ignored for "some
-    /// time".
+    /// Sets how long a connection may be idle before it is closed.
     ///
-    /// In order to satisfy the specification, internal state must be maintained
-    /// to implement the behavior. This state grows linearly with the number of
-    /// streams that are locally reset.
+    /// The connection is idle while no streams are open, no PING is waiting
+    /// to be acknowledged, and no frames are received. Once it has been idle
+    /// for `timeout`, a `GOAWAY` frame with `NO_ERROR` is sent and the
+    /// connection is closed. The connection then completes with an error for
+    /// which [`Error::is_idle_timeout`] returns `true`.
     ///
-    /// The `max_concurrent_reset_streams` setting configures sets an upper
-    /// bound on the amount of state that is maintained. When this max value is
-    /// reached, the oldest reset stream is purged from memory.
+    /// By default, idle connections are kept open.
     ///
-    /// Once the stream has been fully purged from memory, any additional frames
-    /// received for that stream will result in a connection level protocol
-    /// error, forcing the connection to terminate.
+    /// # Panics
     ///
-    /// The default value is 10.
+    /// [`handshake`] panics if no [`timer`] is configured.
     ///
     /// # Examples
     ///
     /// ```
     /// # use tokio::io::{AsyncRead, AsyncWrite};
     /// # use http2::server::*;
+    /// # use http2::timer::{Sleep, Timer};
+    /// # use std::pin::Pin;
+    /// # use std::time::Duration;
+    /// #
+    /// # struct TokioTimer;
+    /// #
+    /// # impl Timer for TokioTimer {
+    /// #     fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+    /// #         Box::pin(tokio::time::sleep(duration))
+    /// #     }
+    /// # }
     /// #
     /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
     /// # -> Handshake<T>
@@ -894,60 +2494,49 @@ impl Builder {
     /// // `server_fut` is a future representing the completion of the HTTP/2
     /// // handshake.
     /// let server_fut = Builder::new()
-    ///     .max_concurrent_reset_streams(1000)
+    ///     .timer(TokioTimer)
+    ///     .idle_timeout(Duration::from_secs(60))
     ///     .handshake(my_io);
     /// # server_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_concurrent_reset_streams(&mut self, max: usize) -> &mut Self {
-        self.reset_stream_max = max;
-        self
-    }
-
-    /// Sets the maximum number of local resets due to protocol errors made by the remote end.
-    ///
-    /// Invalid frames and many other protocol errors will lead to resets being generated for those streams.
-    /// Too many of these often indicate a malicious client, and there are attacks which can abuse this to DOS servers.
-    /// This limit protects against these DOS attacks by limiting the amount of resets we can be forced to generate.
-    ///
-    /// When the number of local resets exceeds this threshold, the server will issue GOAWAYs with an error code of
-    /// `ENHANCE_YOUR_CALM` to the client.
-    ///
-    /// If you really want to disable this, supply [`Option::None`] here.
-    /// Disabling this is not recommended and may expose you to DOS attacks.
     ///
-    /// The default value is currently 1024, but could change.
-    pub fn max_local_error_reset_streams(&mut self, max: Option<usize>) -> &mut Self {
-        self.local_max_error_reset_streams = max;
+    /// [`Error::is_idle_timeout`]: ../struct.Error.html#method.is_idle_timeout
+    /// [`handshake`]: #method.handshake
+    /// [`timer`]: #method.timer
+    pub fn idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.idle_timeout = Some(timeout);
         self
     }
 
-    /// Sets the maximum number of pending-accept remotely-reset streams.
+    /// Sets a callback deciding whether new streams are accepted.
     ///
-    /// Streams that have been received by the peer, but not accepted by the
-    /// user, can also receive a RST_STREAM. This is a legitimate pattern: one
-    /// could send a request and then shortly after, realize it is not needed,
-    /// sending a CANCEL.
+    /// The callback is invoked with the request headers of every stream
+    /// opened by the client, before the request is queued to be returned by
+    /// [`Connection::accept`]. Depending on the returned [`Admission`], the
+    /// stream is accepted, refused with `REFUSED_STREAM`, reset with another
+    /// reason, or answered with a response without a body. Streams that are
+    /// not accepted never reach the application.
     ///
-    /// However, since those streams are now "closed", they don't count towards
-    /// the max concurrent streams. So, they will sit in the accept queue,
-    /// using memory.
+    /// The request passed to the callback has no body, but has the same
+    /// method, URI, version, headers, and extensions as the request returned
+    /// by [`Connection::accept`].
     ///
-    /// When the number of remotely-reset streams sitting in the pending-accept
-    /// queue reaches this maximum value, a connection error with the code of
-    /// `ENHANCE_YOUR_CALM` will be sent to the peer, and returned by the
-    /// `Future`.
+    /// The callback runs while the connection's stream state is locked, so it
+    /// must return quickly and must not use any handle to a stream of the
+    /// connection, such as a `SendResponse`, `SendStream` or `RecvStream`, or
+    /// take connection statistics: doing so deadlocks.
     ///
-    /// The default value is currently 20, but could change.
+    /// By default, every stream is accepted.
     ///
     /// # Examples
     ///
-    ///
     /// ```
     /// # use tokio::io::{AsyncRead, AsyncWrite};
     /// # use http2::server::*;
+    /// # use http::{Response, StatusCode};
     /// #
     /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
     /// # -> Handshake<T>
@@ -955,64 +2544,52 @@ impl Builder {
     /// // `server_fut` is a future representing the completion of the HTTP/2
     /// // handshake.
     /// let server_fut = Builder::new()
-    ///     .max_pending_accept_reset_streams(100)
+    ///     .admission(|request, context| {
+    ///         if request.uri().path().starts_with("/admin") {
+    ///             Admission::Respond(
+    ///                 Response::builder()
+    ///                     .status(StatusCode::FORBIDDEN)
+    ///                     .body(())
+    ///                     .unwrap(),
+    ///             )
+    ///         } else if context.active_streams > 100 {
+    ///             Admission::Refuse
+    ///         } else {
+    ///             Admission::Accept
+    ///         }
+    ///     })
     ///     .handshake(my_io);
     /// # server_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn max_pending_accept_reset_streams(&mut self, max: usize) -> &mut Self {
-        self.pending_accept_reset_stream_max = max;
-        self
-    }
-
-    /// Sets the maximum send buffer size per stream.
-    ///
-    /// Once a stream has buffered up to (or over) the maximum, the stream's
-    /// flow control will not "poll" additional capacity. Once bytes for the
-    /// stream have been written to the connection, the send buffer capacity
-    /// will be freed up again.
-    ///
-    /// The default is currently ~400KB, but may change.
     ///
-    /// # Panics
-    ///
-    /// This function panics if `max` is larger than `u32::MAX`.
-    pub fn max_send_buffer_size(&mut self, max: usize) -> &mut Self {
-        assert!(max <= u32::MAX as usize);
-        self.max_send_buffer_size = max;
+    /// [`Connection::accept`]: struct.Connection.html#method.accept
+    /// [`Admission`]: enum.Admission.html
+    pub fn admission<F>(&mut self, admission: F) -> &mut Self
+    where
+        F: Fn(&Request<()>, &AdmissionContext) -> Admission + Send + Sync + 'static,
+    {
+        self.admission = Some(SharedAdmission(Arc::new(admission)));
         self
     }
 
-    /// Sets the maximum number of concurrent locally reset streams.
-    ///
-    /// When a stream is explicitly reset by either calling
-    /// [`SendResponse::send_reset`] or by dropping a [`SendResponse`] instance
-    /// before completing the stream, the HTTP/2 specification requires that
-    /// any further frames received for that stream must be ignored for "some
-    /// time".
-    ///
-    /// In order to satisfy the specification, internal state must be maintained
-    /// to implement the behavior. This state grows linearly with the number of
-    /// streams that are locally reset.
-    ///
-    /// The `reset_stream_duration` setting configures the max amount of time
-    /// this state will be maintained in memory. Once the duration elapses, the
-    /// stream state is purged from memory.
+    /// Enables or disables automatically answering `Expect: 100-continue`.
     ///
-    /// Once the stream has been fully purged from memory, any additional frames
-    /// received for that stream will result in a connection level protocol
-    /// error, forcing the connection to terminate.
+    /// When enabled, a `100 Continue` informational response is sent as soon
+    /// as a request carrying an `Expect: 100-continue` header and a body is
+    /// accepted. Applications that want to reject such requests based on
+    /// their headers, before the client sends the body, should leave this
+    /// disabled and use [`SendResponse::send_informational`] instead.
     ///
-    /// The default value is 30 seconds.
+    /// Default value: `false`.
     ///
     /// # Examples
     ///
     /// ```
     /// # use tokio::io::{AsyncRead, AsyncWrite};
     /// # use http2::server::*;
-    /// # use std::time::Duration;
     /// #
     /// # fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
     /// # -> Handshake<T>
@@ -1020,23 +2597,17 @@ impl Builder {
     /// // `server_fut` is a future representing the completion of the HTTP/2
     /// // handshake.
     /// let server_fut = Builder::new()
-    ///     .reset_stream_duration(Duration::from_secs(10))
+    ///     .auto_continue(true)
     ///     .handshake(my_io);
     /// # server_fut
     /// # }
     /// #
     /// # pub fn main() {}
     /// ```
-    pub fn reset_stream_duration(&mut self, dur: Duration) -> &mut Self {
-        self.reset_stream_duration = dur;
-        self
-    }
-
-    /// Enables the [extended CONNECT protocol].
     ///
-    /// [extended CONNECT protocol]: https://datatracker.ietf.org/doc/html/rfc8441#section-4
-    pub fn enable_connect_protocol(&mut self) -> &mut Self {
-        self.settings.set_enable_connect_protocol(Some(1));
+    /// [`SendResponse::send_informational`]: struct.SendResponse.html#method.send_informational
+    pub fn auto_continue(&mut self, enabled: bool) -> &mut Self {
+        self.auto_continue = enabled;
         self
     }
 
@@ -1056,6 +2627,13 @@ impl Builder {
     /// [`Connection`]: struct.Connection.html
     /// [Outbound data type]: ../index.html#outbound-data-type.
     ///
+    /// # Panics
+    ///
+    /// This function panics if a setting that requires a [`timer`] is used
+    /// without one.
+    ///
+    /// [`timer`]: #method.timer
+    ///
     /// # Examples
     ///
     /// Basic usage:
@@ -1098,10 +2676,96 @@ impl Builder {
     /// ```
     pub fn handshake<T, B>(&self, io: T) -> Handshake<T, B>
     where
-        T: AsyncRead + AsyncWrite + Unpin,
+        T: Io,
+        B: Buf,
+    {
+        Connection::handshake2(io, Bytes::new(), self.clone())
+    }
+
+    /// Creates a new configured HTTP/2 server backed by `io`, some of which
+    /// has already been read.
+    ///
+    /// This is useful when the first bytes of a connection have been consumed
+    /// to decide which protocol to speak, for example when serving HTTP/1.1
+    /// and HTTP/2 on the same port. `prefix` must contain the bytes that were
+    /// read from `io`, in order, starting with the first byte of the
+    /// connection. They are processed as if they had been read from `io`,
+    /// and may include more than the connection preface.
+    ///
+    /// See [`handshake`](#method.handshake) and [`sniff_preface`] for more
+    /// details.
+    ///
+    /// [`sniff_preface`]: fn.sniff_preface.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # {
+    /// let sniffed = sniff_preface(my_io).await.unwrap();
+    ///
+    /// if sniffed.is_http2() {
+    ///     let (io, prefix) = sniffed.into_parts();
+    ///     let connection = Builder::new()
+    ///         .handshake_with_prefix::<_, bytes::Bytes>(io, prefix)
+    ///         .await
+    ///         .unwrap();
+    ///     // ...
+    /// } else {
+    ///     // Hand `sniffed.into_parts()` to an HTTP/1.1 server.
+    /// }
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    pub fn handshake_with_prefix<T, B>(&self, io: T, prefix: Bytes) -> Handshake<T, B>
+    where
+        T: Io,
+        B: Buf,
+    {
+        Connection::handshake2(io, prefix, self.clone())
+    }
+
+    /// Creates a new configured HTTP/2 server backed by `io`, which
+    /// implements the [`futures-io`] traits instead of the tokio ones.
+    ///
+    /// This is the same as [`handshake`], except for the traits `io`
+    /// implements. Frames are read straight into the read buffer of the
+    /// connection, and written with vectored writes. This method is only
+    /// available with the `futures-io` feature.
+    ///
+    /// [`futures-io`]: https://docs.rs/futures-io
+    /// [`handshake`]: #method.handshake
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use futures_io::{AsyncRead, AsyncWrite};
+    /// # use http2::server::*;
+    /// # use bytes::Bytes;
+    /// #
+    /// # async fn doc<T: AsyncRead + AsyncWrite + Unpin>(my_io: T)
+    /// # {
+    /// let connection = Builder::new()
+    ///     .handshake_futures_io::<_, Bytes>(my_io)
+    ///     .await
+    ///     .unwrap();
+    /// // The HTTP/2 handshake has completed, now use `connection` to
+    /// // accept inbound HTTP/2 streams.
+    /// # }
+    /// #
+    /// # pub fn main() {}
+    /// ```
+    #[cfg(feature = "futures-io")]
+    pub fn handshake_futures_io<T, B>(&self, io: T) -> Handshake<FuturesIo<T>, B>
+    where
+        T: futures_io::AsyncRead + futures_io::AsyncWrite + Unpin,
         B: Buf,
     {
-        Connection::handshake2(io, self.clone())
+        self.handshake(FuturesIo(io))
     }
 }
 
@@ -1127,9 +2791,14 @@ impl<B: Buf> SendResponse<B> {
     /// request.  This function may only be called once per instance and only if
     /// [`send_reset`] has not been previously called.
     ///
+    /// Returns an error if the response's header list is larger than the
+    /// client's `SETTINGS_MAX_HEADER_LIST_SIZE`. Nothing is sent in that case,
+    /// unless [`Builder::oversized_response_431`] is enabled.
+    ///
     /// [`SendResponse`]: #
     /// [`SendStream`]: ../struct.SendStream.html
     /// [`send_reset`]: #method.send_reset
+    /// [`Builder::oversized_response_431`]: struct.Builder.html#method.oversized_response_431
     pub fn send_response(
         &mut self,
         response: Response<()>,
@@ -1141,6 +2810,31 @@ impl<B: Buf> SendResponse<B> {
             .map_err(Into::into)
     }
 
+    /// Send an informational (1xx) response to a client request.
+    ///
+    /// Informational responses, such as `100 Continue` or `103 Early Hints`,
+    /// are sent ahead of the final response, which must still be sent with
+    /// [`send_response`]. This function may be called any number of times
+    /// before [`send_response`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the status of `response` is not informational, or
+    /// is `101 Switching Protocols`, which HTTP/2 does not allow. Also
+    /// returns an error if the final response or a reset has already been
+    /// sent.
+    ///
+    /// [`send_response`]: #method.send_response
+    pub fn send_informational(&mut self, response: Response<()>) -> Result<(), crate::Error> {
+        let status = response.status();
+        if !status.is_informational() || status == StatusCode::SWITCHING_PROTOCOLS {
+            tracing::debug!("invalid informational status; status={}", status);
+            return Err(UserError::InvalidInformationalStatus.into());
+        }
+
+        self.inner.send_informational(response).map_err(Into::into)
+    }
+
     /// Push a request and response to the client
     ///
     /// On success, a [`SendResponse`] instance is returned.
@@ -1201,6 +2895,151 @@ impl<B: Buf> SendResponse<B> {
     pub fn stream_id(&self) -> crate::StreamId {
         crate::StreamId::from_internal(self.inner.stream_id())
     }
+
+    /// Returns a snapshot of the timings and frame counts of this stream.
+    ///
+    /// See [`StreamMetrics`] for details.
+    ///
+    /// [`StreamMetrics`]: ../stats/struct.StreamMetrics.html
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the stream store has been poisoned.
+    pub fn metrics(&self) -> StreamMetrics {
+        self.inner.metrics()
+    }
+}
+
+impl SendResponse<Bytes> {
+    /// Accepts a WebSocket over HTTP/2, as defined by [RFC 8441].
+    ///
+    /// `request` must be the request this `SendResponse` was received with,
+    /// and must be a valid WebSocket handshake as checked by
+    /// [`is_websocket_request`]. `response` is sent to the client and must
+    /// have a `2xx` status. It may carry headers such as
+    /// `sec-websocket-protocol`, but not `sec-websocket-accept`, which is not
+    /// used over HTTP/2.
+    ///
+    /// On success, an [`Upgraded`] byte stream is returned over which
+    /// WebSocket frames are exchanged with the client.
+    ///
+    /// Receiving extended `CONNECT` requests requires the connection to be
+    /// configured with [`Builder::enable_connect_protocol`].
+    ///
+    /// # Errors
+    ///
+    /// An error is returned, and no response is sent, if `request` or
+    /// `response` is not a valid WebSocket handshake. The caller is then
+    /// still responsible for responding to the request, for example with a
+    /// `400 Bad Request`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use http2::server::*;
+    /// # use http2::RecvStream;
+    /// # use bytes::Bytes;
+    /// # use http::{Request, Response, StatusCode};
+    /// # fn doc(request: Request<RecvStream>, mut respond: SendResponse<Bytes>) {
+    /// if is_websocket_request(&request) {
+    ///     let response = Response::new(());
+    ///     let websocket = respond.accept_websocket(request, response).unwrap();
+    ///     // Exchange WebSocket frames over `websocket`...
+    /// } else {
+    ///     let response = Response::builder()
+    ///         .status(StatusCode::BAD_REQUEST)
+    ///         .body(())
+    ///         .unwrap();
+    ///     respond.send_response(response, true).unwrap();
+    /// }
+    /// # }
+    /// # pub fn main() {}
+    /// ```
+    ///
+    /// [RFC 8441]: https://datatracker.ietf.org/doc/html/rfc8441
+    /// [`is_websocket_request`]: fn.is_websocket_request.html
+    /// [`Upgraded`]: ../struct.Upgraded.html
+    /// [`Builder::enable_connect_protocol`]: struct.Builder.html#method.enable_connect_protocol
+    pub fn accept_websocket(
+        &mut self,
+        request: Request<RecvStream>,
+        response: Response<()>,
+    ) -> Result<Upgraded, crate::Error> {
+        if !is_websocket_request(&request) {
+            tracing::debug!("accept_websocket; not a websocket request");
+            return Err(UserError::InvalidExtendedConnect.into());
+        }
+
+        if !response.status().is_success()
+            || response
+                .headers()
+                .contains_key(http::header::SEC_WEBSOCKET_ACCEPT)
+        {
+            tracing::debug!("accept_websocket; invalid response: {:?}", response);
+            return Err(UserError::InvalidExtendedConnect.into());
+        }
+
+        let send = self.send_response(response, false)?;
+
+        Ok(Upgraded::new(send, request.into_body()))
+    }
+
+    /// Accepts a UDP proxying request, as defined by [RFC 9298].
+    ///
+    /// `request` must be the request this `SendResponse` was received with,
+    /// and must be a valid UDP proxying request as checked by
+    /// [`is_connect_udp_request`]. It is up to the caller to parse the target
+    /// host and port from the request path, according to its URI template.
+    /// `response` is sent to the client and must have a `2xx` status. A
+    /// `capsule-protocol: ?1` header is added if not already present.
+    ///
+    /// On success, a [`CapsuleSender`] and a [`CapsuleReceiver`] are returned,
+    /// used to exchange UDP payloads with the client in `DATAGRAM` capsules;
+    /// see [`Capsule::udp`] and [`Capsule::udp_payload`].
+    ///
+    /// Receiving extended `CONNECT` requests requires the connection to be
+    /// configured with [`Builder::enable_connect_protocol`].
+    ///
+    /// # Errors
+    ///
+    /// An error is returned, and no response is sent, if `request` is not a
+    /// valid UDP proxying request, or `response` does not have a `2xx`
+    /// status.
+    ///
+    /// [RFC 9298]: https://www.rfc-editor.org/rfc/rfc9298
+    /// [`is_connect_udp_request`]: fn.is_connect_udp_request.html
+    /// [`CapsuleSender`]: ../capsule/struct.CapsuleSender.html
+    /// [`CapsuleReceiver`]: ../capsule/struct.CapsuleReceiver.html
+    /// [`Capsule::udp`]: ../capsule/enum.Capsule.html#method.udp
+    /// [`Capsule::udp_payload`]: ../capsule/enum.Capsule.html#method.udp_payload
+    /// [`Builder::enable_connect_protocol`]: struct.Builder.html#method.enable_connect_protocol
+    pub fn accept_connect_udp(
+        &mut self,
+        request: Request<RecvStream>,
+        mut response: Response<()>,
+    ) -> Result<(CapsuleSender, CapsuleReceiver), crate::Error> {
+        if !is_connect_udp_request(&request) {
+            tracing::debug!("accept_connect_udp; not a connect-udp request");
+            return Err(UserError::InvalidExtendedConnect.into());
+        }
+
+        if !response.status().is_success() {
+            tracing::debug!("accept_connect_udp; invalid response: {:?}", response);
+            return Err(UserError::InvalidExtendedConnect.into());
+        }
+
+        response
+            .headers_mut()
+            .entry(CAPSULE_PROTOCOL)
+            .or_insert(http::HeaderValue::from_static("?1"));
+
+        let send = self.send_response(response, false)?;
+
+        Ok((
+            CapsuleSender::new(send),
+            CapsuleReceiver::new(request.into_body()),
+        ))
+    }
 }
 
 // ===== impl SendPushedResponse =====
@@ -1285,7 +3124,7 @@ impl<T, B: Buf> Flush<T, B> {
 
 impl<T, B> Future for Flush<T, B>
 where
-    T: AsyncWrite + Unpin,
+    T: Io,
     B: Buf,
 {
     type Output = Result<Codec<T, B>, crate::Error>;
@@ -1299,53 +3138,58 @@ where
     }
 }
 
-impl<T, B: Buf> ReadPreface<T, B> {
+impl<T, B> ReadPreface<T, B>
+where
+    T: Io,
+    B: Buf,
+{
     fn new(codec: Codec<T, B>) -> Self {
         ReadPreface {
             codec: Some(codec),
             pos: 0,
         }
     }
-
-    fn inner_mut(&mut self) -> &mut T {
-        self.codec.as_mut().unwrap().get_mut()
-    }
 }
 
 impl<T, B> Future for ReadPreface<T, B>
 where
-    T: AsyncRead + Unpin,
+    T: Io,
     B: Buf,
 {
     type Output = Result<Codec<T, B>, crate::Error>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let mut buf = [0; 24];
-        let mut rem = PREFACE.len() - self.pos;
-
-        while rem > 0 {
-            let mut buf = ReadBuf::new(&mut buf[..rem]);
-            ready!(Pin::new(self.inner_mut()).poll_read(cx, &mut buf))
-                .map_err(crate::Error::from_io)?;
-            let n = buf.filled().len();
-            if n == 0 {
-                return Poll::Ready(Err(crate::Error::from_io(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    "connection closed before reading preface",
-                ))));
-            }
+        loop {
+            let this = &mut *self;
+            let pos = this.pos;
+            let codec = this.codec.as_mut().unwrap();
+
+            // The preface is read into the codec, so anything after it is
+            // decoded as frames.
+            let buf = codec.read_buf();
+            let n = cmp::min(buf.len(), PREFACE.len() - pos);
 
-            if &PREFACE[self.pos..self.pos + n] != buf.filled() {
+            if PREFACE[pos..pos + n] != buf[..n] {
                 proto_err!(conn: "read_preface: invalid preface");
                 // TODO: Should this just write the GO_AWAY frame directly?
                 return Poll::Ready(Err(Error::library_go_away(Reason::PROTOCOL_ERROR).into()));
             }
 
-            self.pos += n;
-            rem -= n; // TODO test
-        }
+            buf.advance(n);
+            this.pos += n;
 
-        Poll::Ready(Ok(self.codec.take().unwrap()))
+            if this.pos == PREFACE.len() {
+                return Poll::Ready(Ok(this.codec.take().unwrap()));
+            }
+
+            let n = ready!(codec.poll_read(cx)).map_err(crate::Error::from_io)?;
+            if n == 0 {
+                return Poll::Ready(Err(crate::Error::from_io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed before reading preface",
+                ))));
+            }
+        }
     }
 }
 
@@ -1353,7 +3197,7 @@ where
 
 impl<T, B: Buf> Future for Handshake<T, B>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Io,
     B: Buf,
 {
     type Output = Result<Connection<T, B>, crate::Error>;
@@ -1391,33 +3235,8 @@ where
 
                     self.state = Handshaking::Done;
 
-                    let connection = proto::Connection::new(
-                        codec,
-                        Config {
-                            next_stream_id: 2.into(),
-                            // Server does not need to locally initiate any streams
-                            initial_max_send_streams: 0,
-                            max_send_buffer_size: self.builder.max_send_buffer_size,
-                            reset_stream_duration: self.builder.reset_stream_duration,
-                            reset_stream_max: self.builder.reset_stream_max,
-                            remote_reset_stream_max: self.builder.pending_accept_reset_stream_max,
-                            local_error_reset_streams_max: self
-                                .builder
-                                .local_max_error_reset_streams,
-                            settings: self.builder.settings.clone(),
-                            headers_priority: None,
-                            headers_pseudo_order: None,
-                            priority: None,
-                        },
-                    );
-
-                    tracing::trace!("connection established!");
-                    let mut c = Connection { connection };
-                    if let Some(sz) = self.builder.initial_target_connection_window_size {
-                        c.set_target_window_size(sz);
-                    }
-
-                    return Poll::Ready(Ok(c));
+                    let qlog = self.qlog.take();
+                    return Poll::Ready(Ok(Connection::bind(codec, qlog, &self.builder)));
                 }
                 Handshaking::Done => {
                     panic!("Handshaking::poll() called again after handshaking was complete")
@@ -1429,7 +3248,7 @@ where
 
 impl<T, B> fmt::Debug for Handshake<T, B>
 where
-    T: AsyncRead + AsyncWrite + fmt::Debug,
+    T: Io + fmt::Debug,
     B: fmt::Debug + Buf,
 {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
@@ -1,6 +1,7 @@
 use crate::codec::UserError;
 use crate::frame::Reason;
 use crate::proto::{self, WindowSize};
+use crate::stats::StreamMetrics;
 
 use bytes::{Buf, Bytes};
 use http::HeaderMap;
@@ -341,6 +342,9 @@ impl<B: Buf> SendStream<B> {
     ///
     /// Sending trailers implicitly closes the send stream. Once the send stream
     /// is closed, no more data can be sent.
+    ///
+    /// Returns an error, without sending anything, if the trailers are larger
+    /// than the peer's `SETTINGS_MAX_HEADER_LIST_SIZE`.
     pub fn send_trailers(&mut self, trailers: HeaderMap) -> Result<(), crate::Error> {
         self.inner.send_trailers(trailers).map_err(Into::into)
     }
@@ -380,6 +384,19 @@ impl<B: Buf> SendStream<B> {
     pub fn stream_id(&self) -> StreamId {
         StreamId::from_internal(self.inner.stream_id())
     }
+
+    /// Returns a snapshot of the timings and frame counts of this stream.
+    ///
+    /// See [`StreamMetrics`] for details.
+    ///
+    /// [`StreamMetrics`]: stats/struct.StreamMetrics.html
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the stream store has been poisoned.
+    pub fn metrics(&self) -> StreamMetrics {
+        self.inner.metrics()
+    }
 }
 
 // ===== impl StreamId =====
@@ -458,6 +475,19 @@ impl RecvStream {
     pub fn stream_id(&self) -> StreamId {
         self.inner.stream_id()
     }
+
+    /// Returns a snapshot of the timings and frame counts of this stream.
+    ///
+    /// See [`StreamMetrics`] for details.
+    ///
+    /// [`StreamMetrics`]: stats/struct.StreamMetrics.html
+    ///
+    /// # Panics
+    ///
+    /// If the lock on the stream store has been poisoned.
+    pub fn metrics(&self) -> StreamMetrics {
+        self.inner.inner.metrics()
+    }
 }
 
 #[cfg(feature = "stream")]
@@ -0,0 +1,301 @@
+//! Connection statistics.
+//!
+//! A [`ConnectionStats`] snapshot can be taken at any time by calling
+//! `stats()` on a client `Connection`, a `SendRequest` handle, or a server
+//! `Connection`. The counters are maintained as frames are read and written,
+//! so taking a snapshot does not interfere with the connection.
+//!
+//! A [`StreamMetrics`] snapshot of a single stream is taken by calling
+//! `metrics()` on the handles of the stream: `ResponseFuture`, `SendStream`
+//! and `RecvStream` on a client, and `SendResponse`, `SendStream` and
+//! `RecvStream` on a server.
+//!
+//! [`ConnectionStats`]: struct.ConnectionStats.html
+//! [`StreamMetrics`]: struct.StreamMetrics.html
+
+use crate::frame::Reason;
+
+use std::time::Duration;
+
+/// A snapshot of a connection's counters.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct ConnectionStats {
+    /// Frames written to the connection.
+    pub sent: FrameStats,
+
+    /// Frames read from the connection.
+    pub received: FrameStats,
+
+    /// Stream lifecycle counters.
+    pub streams: StreamStats,
+
+    /// Connection-level flow control.
+    pub flow_control: FlowControlStats,
+
+    /// HPACK encoding of sent header blocks.
+    pub hpack_encoder: HpackStats,
+
+    /// HPACK decoding of received header blocks.
+    pub hpack_decoder: HpackStats,
+}
+
+/// Frames sent or received, by frame type.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct FrameStats {
+    /// DATA frames.
+    pub data: FrameCount,
+
+    /// HEADERS frames.
+    pub headers: FrameCount,
+
+    /// PRIORITY frames.
+    pub priority: FrameCount,
+
+    /// RST_STREAM frames.
+    pub reset: FrameCount,
+
+    /// SETTINGS frames.
+    pub settings: FrameCount,
+
+    /// PUSH_PROMISE frames.
+    pub push_promise: FrameCount,
+
+    /// PING frames.
+    pub ping: FrameCount,
+
+    /// GOAWAY frames.
+    pub go_away: FrameCount,
+
+    /// WINDOW_UPDATE frames.
+    pub window_update: FrameCount,
+
+    /// CONTINUATION frames.
+    pub continuation: FrameCount,
+
+    /// Frames of an unknown type.
+    pub unknown: FrameCount,
+}
+
+/// The number of frames of a type, and their size on the wire.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FrameCount {
+    /// Number of frames.
+    pub frames: u64,
+
+    /// Number of bytes, including the frame headers.
+    pub bytes: u64,
+}
+
+/// Stream lifecycle counters.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct StreamStats {
+    /// Streams opened by this endpoint.
+    pub opened_local: u64,
+
+    /// Streams opened by the remote peer.
+    pub opened_remote: u64,
+
+    /// Streams that were opened and have since closed.
+    pub closed: u64,
+
+    /// Streams that are currently open.
+    pub active: usize,
+
+    /// Streams reset by this endpoint, by reason.
+    pub reset_local: ResetCounts,
+
+    /// Streams reset by the remote peer, by reason.
+    pub reset_remote: ResetCounts,
+}
+
+/// Stream resets, by reason.
+///
+/// Each error code defined by RFC 9113 is counted separately. Resets with
+/// any other reason are counted together, see [`other`].
+///
+/// [`other`]: #method.other
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResetCounts {
+    // Indexed by error code, with the last entry counting all other reasons.
+    counts: [u64; ResetCounts::BUCKETS],
+}
+
+/// Connection-level flow control state.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct FlowControlStats {
+    /// The connection window for sending DATA frames.
+    pub send_window: u32,
+
+    /// The connection window for receiving DATA frames.
+    pub recv_window: u32,
+
+    /// Total time that streams waited for the connection send window.
+    pub send_blocked: Duration,
+}
+
+/// HPACK state for one direction of the connection.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct HpackStats {
+    /// Size of the header lists, as defined by `SETTINGS_MAX_HEADER_LIST_SIZE`.
+    pub header_list_bytes: u64,
+
+    /// Size of the HPACK-encoded header blocks.
+    pub encoded_bytes: u64,
+
+    /// Bytes currently used by the dynamic table.
+    pub table_size: usize,
+
+    /// Maximum size of the dynamic table.
+    pub max_table_size: usize,
+}
+
+/// Timings and counters of a single stream.
+///
+/// Times are measured from when the stream was opened: when `send_request`
+/// was called on a client, or when the request HEADERS frame was received on
+/// a server. A time is `None` until the event has happened.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct StreamMetrics {
+    /// Time until the first HEADERS frame of the stream was flushed to the
+    /// I/O.
+    ///
+    /// This includes any time spent waiting for the I/O to accept the frame,
+    /// such as when the socket applies back-pressure.
+    pub headers_sent: Option<Duration>,
+
+    /// Time until the first HEADERS frame of the stream was received,
+    /// including informational (1xx) responses.
+    pub headers_received: Option<Duration>,
+
+    /// Time until the first HEADERS frame that is not an informational (1xx)
+    /// response was received: the final response on a client, or the request
+    /// on a server.
+    pub final_headers_received: Option<Duration>,
+
+    /// Time until the first DATA frame with a payload was received.
+    pub first_data_received: Option<Duration>,
+
+    /// Frames written to the connection for this stream.
+    pub frames_sent: u64,
+
+    /// Frames received for this stream.
+    pub frames_received: u64,
+
+    /// Bytes of DATA frame payloads written for this stream.
+    pub data_bytes_sent: u64,
+
+    /// Bytes of DATA frame payloads received for this stream, not including
+    /// padding.
+    pub data_bytes_received: u64,
+
+    /// Total time the stream waited for the peer to grow the stream's send
+    /// window.
+    pub send_blocked: Duration,
+}
+
+// ===== impl FrameStats =====
+
+impl FrameStats {
+    /// Returns the total number of frames.
+    pub fn frames(&self) -> u64 {
+        self.counts().map(|count| count.frames).sum()
+    }
+
+    /// Returns the total number of bytes.
+    pub fn bytes(&self) -> u64 {
+        self.counts().map(|count| count.bytes).sum()
+    }
+
+    fn counts(&self) -> impl Iterator<Item = &FrameCount> {
+        [
+            &self.data,
+            &self.headers,
+            &self.priority,
+            &self.reset,
+            &self.settings,
+            &self.push_promise,
+            &self.ping,
+            &self.go_away,
+            &self.window_update,
+            &self.continuation,
+            &self.unknown,
+        ]
+        .into_iter()
+    }
+}
+
+// ===== impl ResetCounts =====
+
+impl ResetCounts {
+    /// The number of error codes defined by RFC 9113, plus one for all other
+    /// reasons.
+    pub(crate) const BUCKETS: usize = 15;
+
+    /// Returns the number of streams reset with `reason`.
+    ///
+    /// Returns 0 if `reason` is not defined by RFC 9113; those resets are
+    /// only counted by [`other`].
+    ///
+    /// [`other`]: #method.other
+    pub fn get(&self, reason: Reason) -> u64 {
+        let bucket = Self::bucket(reason);
+        if bucket == Self::BUCKETS - 1 {
+            return 0;
+        }
+
+        self.counts[bucket]
+    }
+
+    /// Returns the number of streams reset with a reason not defined by
+    /// RFC 9113.
+    pub fn other(&self) -> u64 {
+        self.counts[Self::BUCKETS - 1]
+    }
+
+    /// Returns the total number of streams reset.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns an iterator over the reasons defined by RFC 9113 and how many
+    /// streams were reset with each, skipping those never used.
+    pub fn iter(&self) -> impl Iterator<Item = (Reason, u64)> + '_ {
+        self.counts[..Self::BUCKETS - 1]
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count != 0)
+            .map(|(code, &count)| (Reason::from(code as u32), count))
+    }
+
+    /// Returns the index of the counter for `reason`.
+    pub(crate) fn bucket(reason: Reason) -> usize {
+        let code = u32::from(reason) as usize;
+        code.min(Self::BUCKETS - 1)
+    }
+
+    pub(crate) fn from_counts(counts: [u64; Self::BUCKETS]) -> ResetCounts {
+        ResetCounts { counts }
+    }
+}
+
+// ===== impl HpackStats =====
+
+impl HpackStats {
+    /// Returns the ratio of header list bytes to encoded bytes.
+    ///
+    /// Returns `None` if no header blocks were encoded or decoded yet.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        if self.encoded_bytes == 0 {
+            return None;
+        }
+
+        Some(self.header_list_bytes as f64 / self.encoded_bytes as f64)
+    }
+}
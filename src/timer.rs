@@ -0,0 +1,143 @@
+//! Timers used by time based connection settings.
+//!
+//! This crate does not depend on the timer of any particular runtime.
+//! Settings that need to wait, such as the idle timeout of a connection,
+//! instead use a [`Timer`] configured with the `timer` method on the
+//! `Builder`.
+//!
+//! # Examples
+//!
+//! ```
+//! use http2::timer::{Sleep, Timer};
+//! use std::pin::Pin;
+//! use std::time::Duration;
+//!
+//! #[derive(Debug)]
+//! struct TokioTimer;
+//!
+//! impl Timer for TokioTimer {
+//!     fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+//!         Box::pin(tokio::time::sleep(duration))
+//!     }
+//! }
+//!
+//! let mut builder = http2::server::Builder::new();
+//! builder
+//!     .timer(TokioTimer)
+//!     .max_connection_age(Duration::from_secs(3600));
+//! ```
+//!
+//! [`Timer`]: trait.Timer.html
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::time::{Duration, Instant};
+
+/// Creates futures that complete after a duration.
+pub trait Timer: Send + Sync {
+    /// Returns a future that completes once `duration` has elapsed.
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>>;
+}
+
+/// A future returned by a [`Timer`].
+///
+/// This is implemented for every `Future<Output = ()>` that is `Send` and
+/// `Sync`.
+///
+/// [`Timer`]: trait.Timer.html
+pub trait Sleep: Future<Output = ()> + Send + Sync {}
+
+impl<T: Future<Output = ()> + Send + Sync> Sleep for T {}
+
+impl<T: Timer + ?Sized> Timer for Arc<T> {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+        (**self).sleep(duration)
+    }
+}
+
+/// A `Timer` shared by the connections of a builder.
+#[derive(Clone)]
+pub(crate) struct SharedTimer(Arc<dyn Timer>);
+
+/// Wakes the task polling a connection once a deadline has passed.
+///
+/// Without a timer, a deadline is only noticed the next time the connection
+/// is polled.
+pub(crate) struct Alarm {
+    timer: Option<SharedTimer>,
+
+    /// A sleep that completes at the paired deadline.
+    armed: Option<(Instant, Pin<Box<dyn Sleep>>)>,
+}
+
+// ===== impl SharedTimer =====
+
+impl SharedTimer {
+    pub fn new<T: Timer + 'static>(timer: T) -> SharedTimer {
+        SharedTimer(Arc::new(timer))
+    }
+
+    pub fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+        self.0.sleep(duration)
+    }
+}
+
+impl fmt::Debug for SharedTimer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedTimer").finish()
+    }
+}
+
+// ===== impl Alarm =====
+
+impl Alarm {
+    pub fn new(timer: Option<SharedTimer>) -> Alarm {
+        Alarm { timer, armed: None }
+    }
+
+    /// Returns `true` once `deadline` has passed.
+    ///
+    /// Until then, the task is woken at `deadline` if there is a timer.
+    pub fn poll_expired(&mut self, cx: &mut Context, deadline: Instant) -> bool {
+        let now = Instant::now();
+        if now >= deadline {
+            self.armed = None;
+            return true;
+        }
+
+        let timer = match self.timer {
+            Some(ref timer) => timer,
+            None => return false,
+        };
+
+        let sleep = match self.armed {
+            Some((at, ref mut sleep)) if at == deadline => sleep,
+            _ => &mut self.armed.insert((deadline, timer.sleep(deadline - now))).1,
+        };
+
+        if sleep.as_mut().poll(cx).is_pending() {
+            return false;
+        }
+
+        // The timer may complete slightly before the clock reaches the
+        // deadline.
+        self.armed = None;
+        true
+    }
+
+    pub fn cancel(&mut self) {
+        self.armed = None;
+    }
+}
+
+impl fmt::Debug for Alarm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Alarm")
+            .field("timer", &self.timer.is_some())
+            .field("armed", &self.armed.as_ref().map(|&(at, _)| at))
+            .finish()
+    }
+}
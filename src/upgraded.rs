@@ -0,0 +1,256 @@
+use crate::{Reason, RecvStream, SendStream, StreamId};
+
+use bytes::{Buf, Bytes};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A byte stream tunnelled over a single HTTP/2 stream.
+///
+/// `Upgraded` joins the [`SendStream`] and [`RecvStream`] halves of an HTTP/2
+/// stream into a type implementing [`AsyncRead`] and [`AsyncWrite`]. This is
+/// the shape needed once a `CONNECT` or extended `CONNECT` request has been
+/// accepted, when the stream carries an opaque byte stream instead of an HTTP
+/// message body. With the `futures-io` feature, it also implements the
+/// `futures-io` traits.
+///
+/// The same type is used on both sides of the tunnel:
+///
+/// * On the client, the [`SendStream`] is returned by
+///   [`SendRequest::send_request`] and the [`RecvStream`] is the body of the
+///   response.
+/// * On the server, the [`RecvStream`] is the body of the request and the
+///   [`SendStream`] is returned by [`SendResponse::send_response`].
+///
+/// # Reading
+///
+/// Received `DATA` frames are yielded as they arrive. Capacity is released
+/// back to the peer as soon as a frame is handed out, so the receive window
+/// is managed without any involvement from the caller. The end of the stream
+/// is reported as EOF. A `RST_STREAM` with `NO_ERROR` or `CANCEL` is also
+/// treated as EOF; any other reset is returned as an `io::Error`.
+///
+/// # Writing
+///
+/// Each write reserves send capacity for the provided buffer and waits until
+/// some of it has been assigned, sending at most the assigned amount. As
+/// such, a write may be partial. Written data is buffered by the connection,
+/// so flushing is a no-op. Shutting down the writer sends an empty `DATA`
+/// frame with the `END_STREAM` flag set.
+///
+/// # Errors
+///
+/// Errors from the connection are converted to `io::Error`. I/O errors are
+/// returned unchanged. Writing after the peer has reset the stream with
+/// `NO_ERROR`, `CANCEL` or `STREAM_CLOSED` results in an error of kind
+/// [`io::ErrorKind::BrokenPipe`], as does writing after shutdown. Other errors have kind
+/// [`io::ErrorKind::Other`] and wrap the [`Error`](crate::Error).
+///
+/// # Examples
+///
+/// ```
+/// # use http2::client::SendRequest;
+/// # use http2::Upgraded;
+/// # use http::{Method, Request};
+/// # use bytes::Bytes;
+/// # use tokio::io::AsyncWriteExt;
+/// #
+/// # async fn doc(mut client: SendRequest<Bytes>) -> Result<(), Box<dyn std::error::Error>> {
+/// let request = Request::builder()
+///     .method(Method::CONNECT)
+///     .uri("example.com:443")
+///     .body(())?;
+///
+/// let (response, send) = client.send_request(request, false)?;
+/// let response = response.await?;
+///
+/// if response.status().is_success() {
+///     let mut tunnel = Upgraded::new(send, response.into_body());
+///     tunnel.write_all(b"hello").await?;
+///     tunnel.shutdown().await?;
+/// }
+/// # Ok(())
+/// # }
+/// #
+/// # pub fn main() {}
+/// ```
+///
+/// [`SendRequest::send_request`]: client/struct.SendRequest.html#method.send_request
+/// [`SendResponse::send_response`]: server/struct.SendResponse.html#method.send_response
+#[derive(Debug)]
+pub struct Upgraded {
+    send: SendStream<Bytes>,
+    recv: RecvStream,
+    /// Received data that has not been read yet.
+    buf: Bytes,
+    /// Set once `END_STREAM` has been sent.
+    is_shutdown: bool,
+}
+
+// ===== impl Upgraded =====
+
+impl Upgraded {
+    /// Joins the two halves of an HTTP/2 stream into a byte stream.
+    ///
+    /// Both halves must belong to the same stream.
+    pub fn new(send: SendStream<Bytes>, recv: RecvStream) -> Self {
+        debug_assert_eq!(send.stream_id(), recv.stream_id());
+
+        Upgraded {
+            send,
+            recv,
+            buf: Bytes::new(),
+            is_shutdown: false,
+        }
+    }
+
+    /// Returns the stream ID of the tunnelled stream.
+    pub fn stream_id(&self) -> StreamId {
+        self.send.stream_id()
+    }
+
+    /// Resets the tunnelled stream.
+    ///
+    /// This sends a `RST_STREAM` frame with the given reason. Any further
+    /// reads or writes will fail.
+    pub fn send_reset(&mut self, reason: Reason) {
+        self.send.send_reset(reason)
+    }
+
+    /// Waits for the peer to reset the stream and maps the outcome to the
+    /// error returned from a write.
+    fn poll_write_error(&mut self, cx: &mut Context<'_>) -> Poll<io::Error> {
+        match ready!(self.send.poll_reset(cx)) {
+            Ok(Reason::NO_ERROR) | Ok(Reason::CANCEL) | Ok(Reason::STREAM_CLOSED) => {
+                Poll::Ready(io::ErrorKind::BrokenPipe.into())
+            }
+            Ok(reason) => Poll::Ready(into_io_error(reason.into())),
+            Err(e) => Poll::Ready(into_io_error(e)),
+        }
+    }
+}
+
+impl AsyncRead for Upgraded {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.buf.is_empty() {
+            match ready!(self.recv.poll_data(cx)) {
+                Some(Ok(data)) => {
+                    let _ = self.recv.flow_control().release_capacity(data.len());
+                    self.buf = data;
+                }
+                Some(Err(e)) => {
+                    return Poll::Ready(match e.reason() {
+                        Some(Reason::NO_ERROR) | Some(Reason::CANCEL) => Ok(()),
+                        _ => Err(into_io_error(e)),
+                    });
+                }
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let n = self.buf.len().min(buf.remaining());
+        buf.put_slice(&self.buf[..n]);
+        self.buf.advance(n);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Upgraded {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.is_shutdown {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        self.send.reserve_capacity(buf.len());
+
+        loop {
+            let n = self.send.capacity().min(buf.len());
+
+            if n > 0 {
+                let data = Bytes::copy_from_slice(&buf[..n]);
+                if self.send.send_data(data, false).is_ok() {
+                    return Poll::Ready(Ok(n));
+                }
+                break;
+            }
+
+            match ready!(self.send.poll_capacity(cx)) {
+                Some(Ok(_)) => continue,
+                Some(Err(_)) | None => break,
+            }
+        }
+
+        self.poll_write_error(cx).map(Err)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.is_shutdown {
+            return Poll::Ready(Ok(()));
+        }
+
+        if self.send.send_data(Bytes::new(), true).is_ok() {
+            self.is_shutdown = true;
+            return Poll::Ready(Ok(()));
+        }
+
+        self.poll_write_error(cx).map(Err)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncRead for Upgraded {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(AsyncRead::poll_read(self, cx, &mut buf))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncWrite for Upgraded {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(self, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(self, cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_shutdown(self, cx)
+    }
+}
+
+fn into_io_error(e: crate::Error) -> io::Error {
+    if e.is_io() {
+        e.into_io().unwrap()
+    } else {
+        io::Error::new(io::ErrorKind::Other, e)
+    }
+}
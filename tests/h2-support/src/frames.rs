@@ -226,6 +226,11 @@ impl Mock<frame::Data> {
         self
     }
 
+    pub fn pad_len(mut self, pad_len: u8) -> Self {
+        self.0.set_pad_len(pad_len);
+        self
+    }
+
     pub fn eos(mut self) -> Self {
         self.0.set_end_stream(true);
         self
@@ -277,6 +277,11 @@ impl Handle {
         .await;
     }
 
+    /// Makes writes to the mock pend until `unbounded_bytes` is called.
+    pub fn block_bytes(&mut self) {
+        self.codec.get_mut().inner.lock().unwrap().tx_rem = 0;
+    }
+
     pub async fn unbounded_bytes(&mut self) {
         let mut i = self.codec.get_mut().inner.lock().unwrap();
         i.tx_rem = usize::MAX;
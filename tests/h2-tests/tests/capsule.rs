@@ -0,0 +1,259 @@
+#![deny(warnings)]
+
+use futures::future::join;
+use h2::capsule::{Capsule, CapsuleReceiver, CapsuleSender};
+use h2_support::prelude::*;
+use tokio::io::DuplexStream;
+
+/// Performs the HTTP/2 handshake over an in-memory pipe, with the extended
+/// CONNECT protocol enabled on the server. The client connection is driven in
+/// the background.
+async fn connect() -> (
+    client::SendRequest<Bytes>,
+    server::Connection<DuplexStream, Bytes>,
+) {
+    let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+
+    let server = async move {
+        server::Builder::new()
+            .enable_connect_protocol()
+            .handshake(server_io)
+            .await
+            .unwrap()
+    };
+
+    let client = async move {
+        let (client, conn) = client::handshake(client_io).await.unwrap();
+        tokio::spawn(async move { conn.await.unwrap() });
+        client
+    };
+
+    let (srv, client) = join(server, client).await;
+
+    // Wait for the client connection to process the server's SETTINGS.
+    while !client.is_extended_connect_protocol_enabled() {
+        tokio::task::yield_now().await;
+    }
+
+    (client, srv)
+}
+
+fn connect_udp_request() -> Request<()> {
+    Request::builder()
+        .uri("https://proxy.example/.well-known/masque/udp/192.0.2.6/443/")
+        .body(())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn connect_udp_echo() {
+    h2_support::trace_init!();
+
+    let (mut client, mut srv) = connect().await;
+
+    let server = async move {
+        let (req, mut respond) = srv.accept().await.unwrap().unwrap();
+        assert!(server::is_connect_udp_request(&req));
+        assert_eq!(req.uri().path(), "/.well-known/masque/udp/192.0.2.6/443/");
+        assert_eq!(req.headers()["capsule-protocol"], "?1");
+
+        let (mut send, mut recv): (CapsuleSender, CapsuleReceiver) =
+            respond.accept_connect_udp(req, Response::new(())).unwrap();
+
+        tokio::spawn(async move { while srv.accept().await.is_some() {} });
+
+        // Unknown capsules are skipped when receiving datagrams.
+        while let Some(payload) = recv.datagram().await {
+            let capsule = Capsule::Datagram(payload.unwrap());
+            let udp = capsule.udp_payload().unwrap();
+            send.send_capsule(&Capsule::udp(&udp)).unwrap();
+        }
+        send.finish().unwrap();
+    };
+
+    let client = async move {
+        let (rsp, mut send, mut recv) = client
+            .send_connect_udp(connect_udp_request())
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(rsp.status(), StatusCode::OK);
+        assert_eq!(rsp.headers()["capsule-protocol"], "?1");
+
+        send.send_capsule(&Capsule::udp(b"one")).unwrap();
+        send.send_capsule(&Capsule::Unknown {
+            capsule_type: 0x2a,
+            payload: Bytes::from_static(b"ignored"),
+        })
+        .unwrap();
+        send.send_capsule(&Capsule::udp(b"two")).unwrap();
+        send.finish().unwrap();
+
+        let mut received = Vec::new();
+        while let Some(capsule) = recv.capsule().await {
+            received.push(capsule.unwrap().udp_payload().unwrap());
+        }
+        assert_eq!(received, vec!["one", "two"]);
+    };
+
+    join(server, client).await;
+}
+
+#[tokio::test]
+async fn capsules_split_across_data_frames() {
+    h2_support::trace_init!();
+
+    let (mut client, mut srv) = connect().await;
+
+    let server = async move {
+        let (req, mut respond) = srv.accept().await.unwrap().unwrap();
+        let (_send, mut recv) = respond.accept_connect_udp(req, Response::new(())).unwrap();
+
+        tokio::spawn(async move { while srv.accept().await.is_some() {} });
+
+        let capsule = recv.capsule().await.unwrap().unwrap();
+        assert_eq!(capsule, Capsule::Datagram(Bytes::from(vec![7; 300])));
+
+        let capsule = recv.capsule().await.unwrap().unwrap();
+        assert_eq!(capsule.capsule_type(), 0x4000);
+        assert!(capsule.payload().is_empty());
+
+        assert!(recv.capsule().await.is_none());
+    };
+
+    let client = async move {
+        let (_, send, _recv) = client
+            .send_connect_udp(connect_udp_request())
+            .unwrap()
+            .await
+            .unwrap();
+        let mut send = send.into_inner();
+
+        // DATAGRAM capsule with a two-byte length, followed by a capsule with
+        // a four-byte type, each split into several DATA frames.
+        send.send_data(Bytes::from_static(&[0x00, 0x41]), false)
+            .unwrap();
+        send.send_data(Bytes::from(vec![0x2c]), false).unwrap();
+        send.send_data(Bytes::from(vec![7; 100]), false).unwrap();
+        send.send_data(Bytes::from(vec![7; 200]), false).unwrap();
+        send.send_data(Bytes::from_static(&[0x80, 0x00]), false)
+            .unwrap();
+        send.send_data(Bytes::from_static(&[0x40, 0x00, 0x00]), true)
+            .unwrap();
+
+        // Keep the stream open until the server is done reading.
+        (send, _recv)
+    };
+
+    join(server, client).await;
+}
+
+#[tokio::test]
+async fn partial_capsule_at_end_of_stream() {
+    h2_support::trace_init!();
+
+    let (mut client, mut srv) = connect().await;
+
+    let server = async move {
+        let (req, mut respond) = srv.accept().await.unwrap().unwrap();
+        let (_send, mut recv) = respond.accept_connect_udp(req, Response::new(())).unwrap();
+
+        tokio::spawn(async move { while srv.accept().await.is_some() {} });
+
+        let err = recv.capsule().await.unwrap().unwrap_err();
+        assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+    };
+
+    let client = async move {
+        let (_, send, _recv) = client
+            .send_connect_udp(connect_udp_request())
+            .unwrap()
+            .await
+            .unwrap();
+        let mut send = send.into_inner();
+
+        send.send_data(Bytes::from_static(&[0x00, 0x05, b'a']), true)
+            .unwrap();
+
+        // Keep the stream open until the server is done reading.
+        (send, _recv)
+    };
+
+    join(server, client).await;
+}
+
+#[tokio::test]
+async fn capsule_larger_than_max_size() {
+    h2_support::trace_init!();
+
+    let (mut client, mut srv) = connect().await;
+
+    let server = async move {
+        let (req, mut respond) = srv.accept().await.unwrap().unwrap();
+        let (_send, mut recv) = respond.accept_connect_udp(req, Response::new(())).unwrap();
+        recv.set_max_capsule_size(16);
+
+        tokio::spawn(async move { while srv.accept().await.is_some() {} });
+
+        let capsule = recv.capsule().await.unwrap().unwrap();
+        assert_eq!(capsule.udp_payload().unwrap(), "small");
+
+        let err = recv.capsule().await.unwrap().unwrap_err();
+        assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+    };
+
+    let client = async move {
+        let (_, mut send, _recv) = client
+            .send_connect_udp(connect_udp_request())
+            .unwrap()
+            .await
+            .unwrap();
+
+        send.send_capsule(&Capsule::udp(b"small")).unwrap();
+        send.send_capsule(&Capsule::udp(&[0; 32])).unwrap();
+
+        // Keep the stream open until the server is done reading.
+        (send, _recv)
+    };
+
+    join(server, client).await;
+}
+
+#[tokio::test]
+async fn server_rejects_non_https_connect_udp() {
+    h2_support::trace_init!();
+
+    let (mut client, mut srv) = connect().await;
+
+    let server = async move {
+        let (req, mut respond) = srv.accept().await.unwrap().unwrap();
+        assert!(!server::is_connect_udp_request(&req));
+
+        let err = respond
+            .accept_connect_udp(req, Response::new(()))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "user error: invalid extended CONNECT handshake"
+        );
+
+        let rsp = Response::builder().status(400).body(()).unwrap();
+        respond.send_response(rsp, true).unwrap();
+
+        while srv.accept().await.is_some() {}
+    };
+
+    let client = async move {
+        let req = Request::builder()
+            .uri("http://proxy.example/.well-known/masque/udp/192.0.2.6/443/")
+            .body(())
+            .unwrap();
+        let err = client.send_connect_udp(req).unwrap().await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "user error: extended CONNECT rejected by peer"
+        );
+    };
+
+    join(server, client).await;
+}
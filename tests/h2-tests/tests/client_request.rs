@@ -3,8 +3,17 @@ use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use h2_support::prelude::*;
 use std::pin::Pin;
-use std::task::Context;
+use std::task::{Context, Poll};
 use std::{io, panic};
+use timer::{Sleep, Timer};
+
+struct TokioTimer;
+
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
 
 #[tokio::test]
 async fn handshake() {
@@ -148,6 +157,68 @@ async fn request_stream_id_overflows() {
     join(srv, h2).await;
 }
 
+#[tokio::test]
+async fn max_stream_id_exhausts_connection() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let h2 = async move {
+        let (mut client, h2) = client::Builder::new()
+            .max_stream_id(3)
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+        let request = Request::builder()
+            .uri("https://example.com/")
+            .body(())
+            .unwrap();
+
+        let (response1, _) = client.send_request(request.clone(), true).unwrap();
+        let (response3, _) = client.send_request(request.clone(), true).unwrap();
+
+        // no more stream IDs can be allocated
+        let poll_err = poll_fn(|cx| client.poll_ready(cx)).await.unwrap_err();
+        assert!(poll_err.is_connection_exhausted());
+        let err = client.send_request(request, true).unwrap_err();
+        assert!(err.is_connection_exhausted());
+        assert_eq!(
+            err.to_string(),
+            "user error: connection exhausted its stream IDs"
+        );
+
+        // the streams in flight still complete, then the connection closes,
+        // even though `client` and the responses are alive
+        let (response1, response3, conn) = join3(response1, response3, h2).await;
+        conn.unwrap();
+        assert_eq!(response1.unwrap().status(), StatusCode::OK);
+        assert_eq!(response3.unwrap().status(), StatusCode::OK);
+        drop(client);
+    };
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        srv.recv_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+        srv.send_frame(frames::headers(3).response(200).eos()).await;
+        srv.recv_frame(frames::go_away(0)).await;
+        srv.recv_eof().await;
+    };
+
+    join(srv, h2).await;
+}
+
 #[tokio::test]
 async fn client_builder_max_concurrent_streams() {
     h2_support::trace_init!();
@@ -1390,6 +1461,255 @@ async fn informational_while_local_streaming() {
     join(srv, h2).await;
 }
 
+#[tokio::test]
+async fn recv_informational_responses() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::headers(1).response(100)).await;
+        srv.send_frame(
+            frames::headers(1)
+                .response(103)
+                .field("link", "</style.css>; rel=preload"),
+        )
+        .await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, h2) = client::handshake(io).await.unwrap();
+        tokio::spawn(async {
+            h2.await.expect("connection failed");
+        });
+        let request = Request::builder()
+            .uri("https://example.com/")
+            .body(())
+            .unwrap();
+        let (mut response, _) = client.send_request(request, true).unwrap();
+
+        let rsp = response.informational().await.unwrap().unwrap();
+        assert_eq!(rsp.status(), StatusCode::CONTINUE);
+
+        let rsp = response.informational().await.unwrap().unwrap();
+        assert_eq!(rsp.status(), StatusCode::EARLY_HINTS);
+        assert_eq!(rsp.headers()["link"], "</style.css>; rel=preload");
+
+        assert!(response.informational().await.is_none());
+        assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn expect_continue_holds_back_body() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+                .field("expect", "100-continue"),
+        )
+        .await;
+        // The body is not sent ahead of the PING ACK.
+        srv.ping_pong([1; 8]).await;
+        srv.send_frame(frames::headers(1).response(100)).await;
+        srv.recv_frame(frames::data(1, "hello").eos()).await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, h2) = client::Builder::new()
+            .expect_continue(true)
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+        tokio::spawn(async {
+            h2.await.expect("connection failed");
+        });
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("https://example.com/")
+            .header("expect", "100-continue")
+            .body(())
+            .unwrap();
+        let (response, mut stream) = client.send_request(request, false).unwrap();
+        stream.send_data("hello".into(), true).unwrap();
+
+        assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn expect_continue_body_released_by_final_response() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("POST", "https://example.com/")
+                .field("expect", "100-continue"),
+        )
+        .await;
+        srv.ping_pong([1; 8]).await;
+        srv.send_frame(frames::headers(1).response(417).eos()).await;
+        srv.recv_frame(frames::data(1, "hello").eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, h2) = client::Builder::new()
+            .expect_continue(true)
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+        tokio::spawn(async {
+            h2.await.expect("connection failed");
+        });
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("https://example.com/")
+            .header("expect", "100-continue")
+            .body(())
+            .unwrap();
+        let (response, mut stream) = client.send_request(request, false).unwrap();
+        stream.send_data("hello".into(), true).unwrap();
+
+        assert_eq!(
+            response.await.unwrap().status(),
+            StatusCode::EXPECTATION_FAILED
+        );
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn send_over_peer_max_header_list_size() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let srv = async move {
+        let settings = srv
+            .assert_client_handshake_with_settings(frames::settings().max_header_list_size(200))
+            .await;
+        assert_default_settings!(settings);
+        srv.recv_frame(frames::headers(1).request("POST", "https://example.com/"))
+            .await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+        srv.recv_frame(frames::headers(1).field("trailer", "ok").eos())
+            .await;
+    };
+
+    let h2 = async move {
+        let (mut client, h2) = client::handshake(io).await.unwrap();
+        tokio::spawn(async {
+            h2.await.expect("connection failed");
+        });
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("https://example.com/")
+            .body(())
+            .unwrap();
+        let (response, mut stream) = client.send_request(request, false).unwrap();
+        // The peer's SETTINGS have been applied once the response is received.
+        assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+
+        let big = "a".repeat(200);
+
+        let request = Request::builder()
+            .uri("https://example.com/")
+            .header("x-big", &big)
+            .body(())
+            .unwrap();
+        let err = client.send_request(request, true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "user error: header list larger than peer's max header list size"
+        );
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-big", big.parse().unwrap());
+        let err = stream.send_trailers(trailers).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "user error: header list larger than peer's max header list size"
+        );
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("trailer", "ok".parse().unwrap());
+        stream.send_trailers(trailers).unwrap();
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn recv_response_over_max_continuation_frames() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        // The headers are split into a HEADERS frame and 3 CONTINUATION frames.
+        srv.send_frame(
+            frames::headers(1)
+                .response(200)
+                .field("a".repeat(10_000), "b".repeat(10_000))
+                .field("c".repeat(10_000), "d".repeat(10_000))
+                .field("e".repeat(10_000), "f".repeat(10_000))
+                .field("g".repeat(10_000), "h".repeat(10_000)),
+        )
+        .await;
+        srv.recv_frame(frames::go_away(0).calm().data("too_many_continuations"))
+            .await;
+    };
+
+    let h2 = async move {
+        let (mut client, h2) = client::Builder::new()
+            .max_continuation_frames(1)
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+        let request = Request::builder()
+            .uri("https://example.com/")
+            .body(())
+            .unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let (res, conn) = join(response, h2).await;
+        assert_eq!(res.unwrap_err().reason(), Some(Reason::ENHANCE_YOUR_CALM));
+        let err = conn.unwrap_err();
+        assert!(err.is_go_away());
+        assert!(err.is_library());
+    };
+
+    join(srv, h2).await;
+}
+
 #[tokio::test]
 async fn extended_connect_protocol_disabled_by_default() {
     h2_support::trace_init!();
@@ -1855,6 +2175,293 @@ async fn server_drop_connection_unexpectedly_return_unexpected_eof_err() {
     join(srv, h2).await;
 }
 
+#[tokio::test]
+async fn unprocessed_requests_are_returned_for_retry() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/refused")
+                .field("x-retry", "yes")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::reset(1).refused()).await;
+        srv.recv_frame(
+            frames::headers(3)
+                .request("GET", "https://example.com/canceled")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::reset(3).cancel()).await;
+        srv.recv_frame(
+            frames::headers(5)
+                .request("POST", "https://example.com/processed")
+                .eos(),
+        )
+        .await;
+        srv.recv_frame(
+            frames::headers(7)
+                .request("POST", "https://example.com/unprocessed")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::go_away(5)).await;
+        srv.send_frame(frames::headers(5).response(200).eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::Builder::new()
+            .retain_unprocessed_requests(true)
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+
+        let request = Request::get("https://example.com/refused")
+            .header("x-retry", "yes")
+            .body(())
+            .unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let mut err = h2.drive(response).await.unwrap_err();
+        assert_eq!(err.reason(), Some(Reason::REFUSED_STREAM));
+        assert!(err.is_safe_to_retry());
+        let request = err.take_request().expect("unprocessed request");
+        assert_eq!(request.method(), Method::GET);
+        assert_eq!(request.uri(), "https://example.com/refused");
+        assert_eq!(request.headers()["x-retry"], "yes");
+        assert!(err.take_request().is_none());
+
+        let request = Request::get("https://example.com/canceled")
+            .body(())
+            .unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let mut err = h2.drive(response).await.unwrap_err();
+        assert_eq!(err.reason(), Some(Reason::CANCEL));
+        assert!(!err.is_safe_to_retry());
+        assert!(err.take_request().is_none());
+
+        let request = Request::post("https://example.com/processed")
+            .body(())
+            .unwrap();
+        let (processed, _) = client.send_request(request, true).unwrap();
+        let request = Request::post("https://example.com/unprocessed")
+            .body(())
+            .unwrap();
+        let (unprocessed, _) = client.send_request(request, true).unwrap();
+
+        let mut err = h2.drive(unprocessed).await.unwrap_err();
+        assert!(err.is_go_away());
+        assert!(err.is_safe_to_retry());
+        let request = err.take_request().expect("unprocessed request");
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(request.uri(), "https://example.com/unprocessed");
+
+        let response = h2.drive(processed).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        h2.await.unwrap();
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn unprocessed_requests_are_not_retained_by_default() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::reset(1).refused()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::handshake(io).await.unwrap();
+
+        let request = Request::get("https://example.com/").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let mut err = h2.drive(response).await.unwrap_err();
+        assert!(err.is_safe_to_retry());
+        assert!(err.take_request().is_none());
+
+        drop(client);
+        h2.await.unwrap();
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn request_queue_holds_requests_beyond_max_concurrent_streams() {
+    use tokio::sync::oneshot;
+
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+    let (settings_tx, settings_rx) = oneshot::channel();
+
+    let mut settings = frame::Settings::default();
+    settings.set_max_concurrent_streams(Some(1));
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake_with_settings(settings).await;
+        assert_default_settings!(settings);
+        settings_tx.send(()).unwrap();
+        for id in [1, 3, 5] {
+            srv.recv_frame(
+                frames::headers(id)
+                    .request("GET", "https://example.com/")
+                    .eos(),
+            )
+            .await;
+            srv.send_frame(frames::headers(id).response(200).eos())
+                .await;
+        }
+    };
+
+    fn request() -> Request<()> {
+        Request::get("https://example.com/").body(()).unwrap()
+    }
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::Builder::new()
+            .max_queued_requests(2)
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+        h2.drive(settings_rx).await.unwrap();
+
+        // One request is sent and two wait in the queue, all from the same
+        // handle.
+        let mut responses = Vec::new();
+        for _ in 0..3 {
+            poll_fn(|cx| client.poll_ready(cx)).await.unwrap();
+            let (response, _) = client.send_request(request(), true).unwrap();
+            responses.push(response);
+        }
+
+        poll_fn(|cx| {
+            assert!(client.poll_ready(cx).is_pending());
+            Poll::Ready(())
+        })
+        .await;
+        let err = client.send_request(request(), true).unwrap_err();
+        assert_eq!(err.to_string(), "user error: rejected");
+
+        let mut responses = responses.into_iter();
+        let response = h2.drive(responses.next().unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The first queued request was sent, which makes room in the queue.
+        h2.drive(poll_fn(|cx| client.poll_ready(cx))).await.unwrap();
+
+        for response in responses {
+            let response = h2.drive(response).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        drop(client);
+        h2.await.unwrap();
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn request_queue_timeout_fails_queued_requests() {
+    use tokio::sync::oneshot;
+
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+    let (settings_tx, settings_rx) = oneshot::channel();
+    let (timeout_tx, timeout_rx) = oneshot::channel();
+
+    let mut settings = frame::Settings::default();
+    settings.set_max_concurrent_streams(Some(1));
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake_with_settings(settings).await;
+        assert_default_settings!(settings);
+        settings_tx.send(()).unwrap();
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        timeout_rx.await.unwrap();
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+        // Nothing was sent for stream 3, which timed out in the queue.
+        srv.recv_frame(
+            frames::headers(5)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::headers(5).response(200).eos()).await;
+    };
+
+    fn request() -> Request<()> {
+        Request::get("https://example.com/").body(()).unwrap()
+    }
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::Builder::new()
+            .timer(TokioTimer)
+            .max_queued_requests(10)
+            .request_queue_timeout(Duration::from_millis(10))
+            .retain_unprocessed_requests(true)
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+        h2.drive(settings_rx).await.unwrap();
+
+        let (sent, _) = client.send_request(request(), true).unwrap();
+        let (queued, _) = client.send_request(request(), true).unwrap();
+
+        let mut err = h2.drive(queued).await.unwrap_err();
+        assert_eq!(err.reason(), Some(Reason::REFUSED_STREAM));
+        assert!(err.is_library());
+        assert!(err.is_safe_to_retry());
+        assert!(err.take_request().is_some());
+        timeout_tx.send(()).unwrap();
+
+        let response = h2.drive(sent).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let (response, _) = client.send_request(request(), true).unwrap();
+        let response = h2.drive(response).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        drop(client);
+        h2.await.unwrap();
+    };
+
+    join(srv, h2).await;
+}
+
+#[test]
+#[should_panic(expected = "request_queue_timeout requires a timer")]
+fn request_queue_timeout_without_timer_panics_before_handshake() {
+    let (io, _srv) = mock::new();
+
+    drop(
+        client::Builder::new()
+            .request_queue_timeout(Duration::from_secs(5))
+            .handshake::<_, Bytes>(io),
+    );
+}
+
 const SETTINGS: &[u8] = &[0, 0, 0, 4, 0, 0, 0, 0, 0];
 const SETTINGS_ACK: &[u8] = &[0, 0, 0, 4, 1, 0, 0, 0, 0];
 
@@ -0,0 +1,245 @@
+#![deny(warnings)]
+
+use futures::future::join;
+use futures::StreamExt;
+use h2_support::prelude::*;
+use std::time::Duration;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// PRIORITY frame on stream 1, depending on stream 0 with weight 16.
+const PRIORITY: &[u8] = &[0, 0, 5, 2, 0, 0, 0, 0, 1, 0, 0, 0, 0, 15];
+
+async fn accept_flood_error(srv: &mut server::Connection<mock::Mock, Bytes>, limit: FloodLimit) {
+    let err = srv.next().await.unwrap().expect_err("server");
+    assert!(err.is_go_away());
+    assert!(err.is_library());
+    assert_eq!(err.reason(), Some(Reason::ENHANCE_YOUR_CALM));
+    assert_eq!(err.flood_limit(), Some(limit));
+}
+
+#[tokio::test]
+async fn ping_flood_sends_goaway() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+
+        for _ in 0..2 {
+            client.send_frame(frames::ping([1; 8])).await;
+            client.recv_frame(frames::ping([1; 8]).pong()).await;
+        }
+        client.send_frame(frames::ping([1; 8])).await;
+        client
+            .recv_frame(frames::go_away(0).calm().data("ping_flood"))
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .flood_limit(FloodLimit::Ping, 2, WINDOW)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        accept_flood_error(&mut srv, FloodLimit::Ping).await;
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn ping_flood_limit_ignores_frames_outside_window() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+
+        for _ in 0..3 {
+            client.send_frame(frames::ping([1; 8])).await;
+            client.recv_frame(frames::ping([1; 8]).pong()).await;
+            idle_ms(60).await;
+        }
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .flood_limit(FloodLimit::Ping, 1, Duration::from_millis(50))
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        assert!(srv.next().await.is_none());
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn settings_flood_sends_goaway() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+
+        // The handshake SETTINGS frame counts against the limit.
+        client.send_frame(frames::settings()).await;
+        client.recv_frame(frames::settings_ack()).await;
+        client.send_frame(frames::settings()).await;
+        client
+            .recv_frame(frames::go_away(0).calm().data("settings_flood"))
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .flood_limit(FloodLimit::Settings, 2, WINDOW)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        accept_flood_error(&mut srv, FloodLimit::Settings).await;
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn empty_data_flood_sends_goaway() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+
+        client
+            .send_frame(frames::headers(1).request("POST", "https://example.com/"))
+            .await;
+        client.recv_frame(frames::headers(1).response(200)).await;
+        client.send_frame(frames::data(1, "hello")).await;
+        for _ in 0..3 {
+            client.send_frame(frames::data(1, "")).await;
+        }
+        client
+            .recv_frame(frames::go_away(1).calm().data("empty_data_flood"))
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .flood_limit(FloodLimit::EmptyData, 2, WINDOW)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let (req, mut stream) = srv.next().await.unwrap().unwrap();
+        assert_eq!(req.method(), &http::Method::POST);
+        let rsp = http::Response::builder().status(200).body(()).unwrap();
+        let _send = stream.send_response(rsp, false).unwrap();
+
+        accept_flood_error(&mut srv, FloodLimit::EmptyData).await;
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn window_update_flood_sends_goaway() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+
+        for _ in 0..3 {
+            client.send_frame(frames::window_update(0, 1)).await;
+        }
+        client
+            .recv_frame(frames::go_away(0).calm().data("window_update_flood"))
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .flood_limit(FloodLimit::WindowUpdate, 2, WINDOW)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        accept_flood_error(&mut srv, FloodLimit::WindowUpdate).await;
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn priority_flood_sends_goaway() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+
+        for _ in 0..3 {
+            client.send_bytes(PRIORITY).await;
+        }
+        client
+            .recv_frame(frames::go_away(0).calm().data("priority_flood"))
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .flood_limit(FloodLimit::Priority, 2, WINDOW)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        accept_flood_error(&mut srv, FloodLimit::Priority).await;
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn client_ping_flood_sends_goaway() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+
+        srv.send_frame(frames::ping([2; 8])).await;
+        srv.recv_frame(frames::ping([2; 8]).pong()).await;
+        srv.send_frame(frames::ping([2; 8])).await;
+        srv.recv_frame(frames::go_away(0).calm().data("ping_flood"))
+            .await;
+    };
+
+    let h2 = async move {
+        let (_client, conn) = client::Builder::new()
+            .flood_limit(FloodLimit::Ping, 1, WINDOW)
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+
+        let err = conn.await.expect_err("connection");
+        assert_eq!(err.flood_limit(), Some(FloodLimit::Ping));
+        assert_eq!(
+            err.to_string(),
+            "connection error detected: detected excessive load generating behavior (b\"ping_flood\")"
+        );
+    };
+
+    join(srv, h2).await;
+}
@@ -0,0 +1,320 @@
+#![deny(warnings)]
+
+use futures::future::join;
+use futures::StreamExt;
+use h2::frame::{self, Frame};
+use h2::intercept::{FrameInterceptor, Intercept, InterceptError};
+use h2_support::prelude::*;
+use std::sync::{Arc, Mutex};
+
+/// Calls a closure with every frame, and records rejections.
+struct Interceptor<F> {
+    f: F,
+    rejected: Mutex<Vec<String>>,
+}
+
+impl<F> Interceptor<F>
+where
+    F: Fn(Frame) -> Intercept + Send + Sync,
+{
+    fn new(f: F) -> Arc<Self> {
+        Arc::new(Interceptor {
+            f,
+            rejected: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn rejected(&self) -> Vec<String> {
+        self.rejected.lock().unwrap().clone()
+    }
+}
+
+impl<F> FrameInterceptor for Interceptor<F>
+where
+    F: Fn(Frame) -> Intercept + Send + Sync,
+{
+    fn intercept(&self, frame: Frame) -> Intercept {
+        (self.f)(frame)
+    }
+
+    fn on_rejected(&self, _: &Frame, error: &InterceptError) {
+        self.rejected.lock().unwrap().push(error.to_string());
+    }
+}
+
+#[tokio::test]
+async fn client_splits_and_pads_data_frames() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let interceptor = Interceptor::new(|frame| match frame {
+        Frame::Data(data) => {
+            let payload = data.payload().clone();
+            let mut head = frame::Data::new(data.stream_id(), payload.slice(..2));
+            head.set_pad_len(4);
+            let mut tail = frame::Data::new(data.stream_id(), payload.slice(2..));
+            tail.set_end_stream(data.is_end_stream());
+            Intercept::Replace(vec![head.into(), tail.into()])
+        }
+        frame => Intercept::Send(frame),
+    });
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(frames::headers(1).request("POST", "https://example.com/"))
+            .await;
+        srv.recv_frame(frames::data(1, "he").pad_len(4)).await;
+        srv.recv_frame(frames::data(1, "llo").eos()).await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::Builder::new()
+            .frame_interceptor(interceptor.clone())
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+
+        let request = Request::post("https://example.com/").body(()).unwrap();
+        let (response, mut stream) = client.send_request(request, false).unwrap();
+        stream.send_data("hello".into(), true).unwrap();
+
+        let response = h2.drive(response).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        h2.await.unwrap();
+
+        assert!(interceptor.rejected().is_empty());
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn padding_is_charged_to_send_windows() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let interceptor = Interceptor::new(|frame| match frame {
+        Frame::Data(mut data) => {
+            data.set_pad_len(4);
+            Intercept::Send(data.into())
+        }
+        frame => Intercept::Send(frame),
+    });
+
+    let mut settings = frame::Settings::default();
+    settings.set_initial_window_size(Some(16));
+
+    let srv = async move {
+        srv.assert_client_handshake_with_settings(settings).await;
+        srv.recv_frame(frames::headers(1).request("POST", "https://example.com/"))
+            .await;
+        srv.recv_frame(frames::data(1, "hello").pad_len(4)).await;
+        // The first padding used up the stream window left after the
+        // buffered payloads.
+        srv.recv_frame(frames::data(1, "world").eos()).await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::Builder::new()
+            .frame_interceptor(interceptor.clone())
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+
+        let request = Request::post("https://example.com/").body(()).unwrap();
+        let (response, mut stream) = client.send_request(request, false).unwrap();
+        stream.send_data("hello".into(), false).unwrap();
+        stream.send_data("world".into(), true).unwrap();
+
+        let response = h2.drive(response).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        h2.await.unwrap();
+
+        assert_eq!(
+            interceptor.rejected(),
+            ["DATA padding larger than the stream and connection send windows"]
+        );
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn inserted_frames_follow_continuation_frames() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let interceptor = Interceptor::new(|frame| match frame {
+        Frame::Headers(headers) => Intercept::Replace(vec![
+            frame::Ping::new([1; 8]).into(),
+            headers.into(),
+            frame::Ping::new([2; 8]).into(),
+        ]),
+        frame => Intercept::Send(frame),
+    });
+
+    let big = "a".repeat(10_000);
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .eos(),
+            )
+            .await;
+        client.recv_frame(frames::ping([1; 8])).await;
+        // The CONTINUATION frames of the response are written before the
+        // second PING.
+        client
+            .recv_frame(
+                frames::headers(1)
+                    .response(200)
+                    .field("y0", &big[..])
+                    .field("y1", &big[..])
+                    .field("y2", &big[..])
+                    .eos(),
+            )
+            .await;
+        client.recv_frame(frames::ping([2; 8])).await;
+        client.send_frame(frames::ping([1; 8]).pong()).await;
+        client.send_frame(frames::ping([2; 8]).pong()).await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .frame_interceptor(interceptor.clone())
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let (_, mut stream) = srv.next().await.unwrap().unwrap();
+        let big = "a".repeat(10_000);
+        let rsp = http::Response::builder()
+            .status(200)
+            .header("y0", &big[..])
+            .header("y1", &big[..])
+            .header("y2", &big[..])
+            .body(())
+            .unwrap();
+        stream.send_response(rsp, true).unwrap();
+        assert!(srv.next().await.is_none());
+
+        assert!(interceptor.rejected().is_empty());
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn delayed_frame_is_written_after_next_frame() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let interceptor = Interceptor::new(|frame| match frame {
+        Frame::Ping(ping) if !ping.is_ack() => Intercept::Delay(ping.into()),
+        frame => Intercept::Send(frame),
+    });
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        srv.recv_frame(frames::ping(frame::Ping::USER)).await;
+        srv.send_frame(frames::ping(frame::Ping::USER).pong()).await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::Builder::new()
+            .frame_interceptor(interceptor.clone())
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+
+        // Finish the SETTINGS exchange, so that the PING is buffered right
+        // before the HEADERS frame.
+        h2.drive(idle_ms(10)).await;
+        let mut ping_pong = h2.ping_pong().unwrap();
+        ping_pong.send_ping(h2::Ping::opaque()).unwrap();
+        let request = Request::get("https://example.com/").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+
+        h2.drive(futures::future::poll_fn(|cx| ping_pong.poll_pong(cx)))
+            .await
+            .unwrap();
+        let response = h2.drive(response).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        h2.await.unwrap();
+
+        assert!(interceptor.rejected().is_empty());
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn inconsistent_frames_are_rejected() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let interceptor = Interceptor::new(|frame| match frame {
+        // Dropping the HEADERS frame would leave the stream open only on
+        // this side.
+        Frame::Headers(_) => Intercept::Drop,
+        // Ending the stream early would leave it open on this side.
+        Frame::Data(mut data) => {
+            data.set_end_stream(true);
+            Intercept::Send(data.into())
+        }
+        frame => Intercept::Send(frame),
+    });
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(frames::headers(1).request("POST", "https://example.com/"))
+            .await;
+        srv.recv_frame(frames::data(1, "hello")).await;
+        srv.recv_frame(frames::data(1, "world").eos()).await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::Builder::new()
+            .frame_interceptor(interceptor.clone())
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+
+        let request = Request::post("https://example.com/").body(()).unwrap();
+        let (response, mut stream) = client.send_request(request, false).unwrap();
+        stream.send_data("hello".into(), false).unwrap();
+        h2.drive(idle_ms(10)).await;
+        stream.send_data("world".into(), true).unwrap();
+
+        let response = h2.drive(response).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        h2.await.unwrap();
+
+        assert_eq!(
+            interceptor.rejected(),
+            [
+                "frame dropped or changed in a way that affects the connection state",
+                "END_STREAM flag changed",
+            ]
+        );
+    };
+
+    join(srv, h2).await;
+}
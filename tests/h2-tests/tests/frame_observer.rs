@@ -0,0 +1,478 @@
+#![deny(warnings)]
+
+use futures::future::join;
+use futures::StreamExt;
+use h2::observer::{Direction, FrameObserver, FrameType, ObservedFrame};
+use h2_support::prelude::*;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Recorded {
+    direction: Direction,
+    frame_type: FrameType,
+    flags: u8,
+    stream_id: u32,
+    len: usize,
+    headers: Option<Vec<(Bytes, Bytes)>>,
+    raw: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Default)]
+struct Recorder {
+    frames: Mutex<Vec<Recorded>>,
+    raw: bool,
+    skip_raw_data: bool,
+}
+
+impl Recorder {
+    fn with_raw_bytes() -> Recorder {
+        Recorder {
+            raw: true,
+            ..Default::default()
+        }
+    }
+
+    fn frames(&self, direction: Direction) -> Vec<Recorded> {
+        let frames = self.frames.lock().unwrap();
+        frames
+            .iter()
+            .filter(|frame| frame.direction == direction)
+            .cloned()
+            .collect()
+    }
+
+    fn frame_types(&self, direction: Direction) -> Vec<FrameType> {
+        self.frames(direction)
+            .iter()
+            .map(|frame| frame.frame_type)
+            .collect()
+    }
+}
+
+impl FrameObserver for Recorder {
+    fn on_frame(&self, frame: &ObservedFrame<'_>) {
+        self.frames.lock().unwrap().push(Recorded {
+            direction: frame.direction(),
+            frame_type: frame.frame_type(),
+            flags: frame.flags(),
+            stream_id: frame.stream_id().into(),
+            len: frame.len(),
+            headers: frame.headers().map(<[_]>::to_vec),
+            raw: frame.raw().map(<[_]>::to_vec),
+        });
+    }
+
+    fn wants_raw_bytes(&self) -> bool {
+        self.raw
+    }
+
+    fn wants_raw_data(&self) -> bool {
+        self.raw && !self.skip_raw_data
+    }
+}
+
+fn header(name: &'static str, value: &'static str) -> (Bytes, Bytes) {
+    (Bytes::from_static(name.as_bytes()), Bytes::from(value))
+}
+
+#[tokio::test]
+async fn client_observes_frames_in_wire_order() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+    let recorder = Arc::new(Recorder::default());
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::headers(1).response(200)).await;
+        srv.send_frame(frames::data(1, "hello").eos()).await;
+    };
+
+    let observer = recorder.clone();
+    let h2 = async move {
+        let (mut client, mut h2) = client::Builder::new()
+            .frame_observer(observer)
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+
+        let request = Request::get("https://example.com/").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let response = h2.drive(response).await.unwrap();
+        let mut body = response.into_body();
+        assert_eq!(h2.drive(body.data()).await.unwrap().unwrap(), "hello");
+        h2.await.unwrap();
+    };
+
+    join(srv, h2).await;
+
+    assert_eq!(
+        recorder.frame_types(Direction::Sent),
+        [FrameType::Settings, FrameType::Settings, FrameType::Headers]
+    );
+    assert_eq!(
+        recorder.frame_types(Direction::Received),
+        [
+            FrameType::Settings,
+            FrameType::Settings,
+            FrameType::Headers,
+            FrameType::Data
+        ]
+    );
+
+    let sent = recorder.frames(Direction::Sent);
+    // The client's SETTINGS, then the ACK of the server's SETTINGS.
+    assert_eq!(sent[0].flags, 0);
+    assert_eq!(sent[1].flags, 0x1);
+    assert_eq!(sent[1].len, 0);
+
+    // END_STREAM | END_HEADERS
+    assert_eq!(sent[2].flags, 0x5);
+    assert_eq!(sent[2].stream_id, 1);
+    let headers = sent[2].headers.as_ref().unwrap();
+    assert!(headers.contains(&header(":method", "GET")));
+    assert!(headers.contains(&header(":scheme", "https")));
+    assert!(headers.contains(&header(":authority", "example.com")));
+    assert!(headers.contains(&header(":path", "/")));
+
+    let received = recorder.frames(Direction::Received);
+    assert_eq!(received[2].flags, 0x4);
+    assert_eq!(received[2].headers, Some(vec![header(":status", "200")]));
+    assert_eq!(received[3].flags, 0x1);
+    assert_eq!(received[3].stream_id, 1);
+    assert_eq!(received[3].len, 5);
+    assert_eq!(received[3].headers, None);
+    assert_eq!(received[3].raw, None);
+}
+
+#[tokio::test]
+async fn observer_receives_raw_bytes_when_requested() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+    let recorder = Arc::new(Recorder::with_raw_bytes());
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(frames::headers(1).request("POST", "https://example.com/"))
+            .await;
+        client.send_frame(frames::data(1, "hello").eos()).await;
+        client
+            .recv_frame(frames::headers(1).response(200).eos())
+            .await;
+    };
+
+    let observer = recorder.clone();
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .frame_observer(observer)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let (req, mut stream) = srv.next().await.unwrap().unwrap();
+        let mut body = req.into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+        let rsp = http::Response::builder().status(200).body(()).unwrap();
+        stream.send_response(rsp, true).unwrap();
+        assert!(srv.next().await.is_none());
+    };
+
+    join(client, srv).await;
+
+    let received = recorder.frames(Direction::Received);
+    let data = received
+        .iter()
+        .find(|frame| frame.frame_type == FrameType::Data)
+        .unwrap();
+    assert_eq!(
+        data.raw.as_deref(),
+        Some(&[0, 0, 5, 0, 1, 0, 0, 0, 1, b'h', b'e', b'l', b'l', b'o'][..])
+    );
+
+    let sent = recorder.frames(Direction::Sent);
+    let headers = sent
+        .iter()
+        .find(|frame| frame.frame_type == FrameType::Headers)
+        .unwrap();
+    // `:status: 200` is a single byte static table reference.
+    assert_eq!(
+        headers.raw.as_deref(),
+        Some(&[0, 0, 1, 1, 0x5, 0, 0, 0, 1, 0x88][..])
+    );
+}
+
+#[tokio::test]
+async fn observer_can_skip_raw_bytes_of_data_frames() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+    let recorder = Arc::new(Recorder {
+        skip_raw_data: true,
+        ..Recorder::with_raw_bytes()
+    });
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(frames::headers(1).request("POST", "https://example.com/"))
+            .await;
+        client.send_frame(frames::data(1, "hello").eos()).await;
+        client.recv_frame(frames::headers(1).response(200)).await;
+        client.recv_frame(frames::data(1, "world").eos()).await;
+    };
+
+    let observer = recorder.clone();
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .frame_observer(observer)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let (req, mut stream) = srv.next().await.unwrap().unwrap();
+        let mut body = req.into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+        let rsp = http::Response::builder().status(200).body(()).unwrap();
+        let mut stream = stream.send_response(rsp, false).unwrap();
+        stream.send_data("world".into(), true).unwrap();
+        assert!(srv.next().await.is_none());
+    };
+
+    join(client, srv).await;
+
+    for direction in [Direction::Received, Direction::Sent] {
+        for frame in recorder.frames(direction) {
+            match frame.frame_type {
+                FrameType::Data => assert_eq!(frame.raw, None, "{:?}", frame),
+                _ => assert!(frame.raw.is_some(), "{:?}", frame),
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn header_list_is_observed_with_last_continuation_frame() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+    let recorder = Arc::new(Recorder::default());
+
+    let big = "a".repeat(10_000);
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .field("x0", &big[..])
+                    .field("x1", &big[..])
+                    .field("x2", &big[..])
+                    .eos(),
+            )
+            .await;
+        // The response is split into HEADERS and CONTINUATION frames too.
+        client
+            .recv_frame(
+                frames::headers(1)
+                    .response(200)
+                    .field("y0", &big[..])
+                    .field("y1", &big[..])
+                    .field("y2", &big[..])
+                    .eos(),
+            )
+            .await;
+    };
+
+    let observer = recorder.clone();
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .frame_observer(observer)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let (_, mut stream) = srv.next().await.unwrap().unwrap();
+        let big = "a".repeat(10_000);
+        let rsp = http::Response::builder()
+            .status(200)
+            .header("y0", &big[..])
+            .header("y1", &big[..])
+            .header("y2", &big[..])
+            .body(())
+            .unwrap();
+        stream.send_response(rsp, true).unwrap();
+        assert!(srv.next().await.is_none());
+    };
+
+    join(client, srv).await;
+
+    for direction in [Direction::Received, Direction::Sent] {
+        let frames = recorder.frames(direction);
+        let block: Vec<_> = frames
+            .iter()
+            .skip_while(|frame| frame.frame_type != FrameType::Headers)
+            .take_while(|frame| {
+                matches!(
+                    frame.frame_type,
+                    FrameType::Headers | FrameType::Continuation
+                )
+            })
+            .collect();
+
+        assert!(block.len() > 1, "{:?}", frames);
+        let (last, rest) = block.split_last().unwrap();
+        assert!(rest.iter().all(|frame| frame.headers.is_none()));
+        assert_eq!(last.frame_type, FrameType::Continuation);
+        // END_HEADERS
+        assert_eq!(last.flags, 0x4);
+
+        let headers = last.headers.as_ref().unwrap();
+        let names: Vec<_> = headers
+            .iter()
+            .map(|(name, _)| name.clone())
+            .filter(|name| !name.starts_with(b":"))
+            .collect();
+        let prefix = if direction == Direction::Received {
+            "x"
+        } else {
+            "y"
+        };
+        assert_eq!(
+            names,
+            (0..3)
+                .map(|i| Bytes::from(format!("{}{}", prefix, i)))
+                .collect::<Vec<_>>()
+        );
+        assert!(headers
+            .iter()
+            .all(|(name, value)| name.starts_with(b":") || value.len() == 10_000));
+    }
+}
+
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn frame_log_writes_nghttp_style_lines() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+    let log = SharedBuf::default();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_eq!(settings.max_concurrent_streams(), Some(100));
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::headers(1).response(200)).await;
+        srv.send_frame(frames::data(1, "hello").eos()).await;
+        srv.send_frame(frames::window_update(0, 1_000)).await;
+        srv.send_frame(frames::go_away(1).calm().data("bye")).await;
+    };
+
+    let writer = log.clone();
+    let h2 = async move {
+        let (mut client, h2) = client::Builder::new()
+            .max_concurrent_streams(100)
+            .frame_log(writer)
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+
+        let request = Request::get("https://example.com/").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let (response, _) = join(response, h2).await;
+        let mut body = response.unwrap().into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+    };
+
+    join(srv, h2).await;
+
+    let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+    // Every frame starts with a timestamp, which is stripped below.
+    assert!(log.starts_with("[  0.0"), "{}", log);
+    let log: String = log
+        .lines()
+        .map(|line| match line.strip_prefix('[') {
+            Some(line) => &line[line.find("] ").unwrap() + 2..],
+            None => line,
+        })
+        .map(|line| format!("{}\n", line))
+        .collect();
+
+    let expected = [
+        "send SETTINGS frame <length=6, flags=0x00, stream_id=0>\n          (niv=1)\n          [SETTINGS_MAX_CONCURRENT_STREAMS(0x03):100]\n",
+        "recv SETTINGS frame <length=0, flags=0x00, stream_id=0>\n          (niv=0)\n",
+        "send SETTINGS frame <length=0, flags=0x01, stream_id=0>\n          ; ACK\n          (niv=0)\n",
+        "send HEADERS frame <length=",
+        ", flags=0x05, stream_id=1>\n          ; END_STREAM | END_HEADERS\n          (padlen=0)\n          :method: GET\n",
+        "recv HEADERS frame <length=1, flags=0x04, stream_id=1>\n          ; END_HEADERS\n          (padlen=0)\n          :status: 200\n",
+        "recv DATA frame <length=5, flags=0x01, stream_id=1>\n          ; END_STREAM\n",
+        "recv WINDOW_UPDATE frame <length=4, flags=0x00, stream_id=0>\n          (window_size_increment=1000)\n",
+        "recv GOAWAY frame <length=11, flags=0x00, stream_id=0>\n          (last_stream_id=1, error_code=ENHANCE_YOUR_CALM(0x0b), opaque_data(3)=[bye])\n",
+    ];
+    let mut rest = &log[..];
+    for part in expected {
+        let i = rest
+            .find(part)
+            .unwrap_or_else(|| panic!("{:?} not found in:\n{}", part, log));
+        rest = &rest[i + part.len()..];
+    }
+}
+
+#[tokio::test]
+async fn frame_log_and_frame_observer_coexist() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+    let log = SharedBuf::default();
+    let recorder = Arc::new(Recorder::default());
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+    };
+
+    let writer = log.clone();
+    let observer = recorder.clone();
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .frame_log(writer)
+            .frame_observer(observer)
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+        assert!(srv.next().await.is_none());
+    };
+
+    join(client, srv).await;
+
+    let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+    assert!(log.contains("send SETTINGS frame"), "{}", log);
+    assert_eq!(
+        recorder.frame_types(Direction::Sent)[0],
+        FrameType::Settings
+    );
+}
@@ -0,0 +1,202 @@
+#![deny(warnings)]
+
+use futures::future::join;
+use futures::StreamExt;
+use h2_support::prelude::*;
+use std::pin::Pin;
+use timer::{Sleep, Timer};
+
+struct TokioTimer;
+
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+#[tokio::test]
+async fn max_connection_requests_goes_away_gracefully() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .eos(),
+            )
+            .await;
+        client.recv_frame(frames::go_away(2147483647)).await;
+        client.recv_frame(frames::ping(frame::Ping::SHUTDOWN)).await;
+        client
+            .recv_frame(frames::headers(1).response(200).eos())
+            .await;
+        client
+            .send_frame(frames::ping(frame::Ping::SHUTDOWN).pong())
+            .await;
+        client.recv_frame(frames::go_away(1)).await;
+        client.recv_eof().await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .max_connection_requests(1)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let (_, mut stream) = srv.next().await.unwrap().unwrap();
+        let rsp = http::Response::builder().status(200).body(()).unwrap();
+        stream.send_response(rsp, true).unwrap();
+
+        assert!(srv.next().await.is_none(), "unexpected request");
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn graceful_shutdown_timeout_after_max_connection_age() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(frames::headers(1).request("POST", "https://example.com/"))
+            .await;
+
+        // The connection reaches its maximum age.
+        client.recv_frame(frames::go_away(2147483647)).await;
+        client.recv_frame(frames::ping(frame::Ping::SHUTDOWN)).await;
+        client
+            .send_frame(frames::ping(frame::Ping::SHUTDOWN).pong())
+            .await;
+        client.recv_frame(frames::go_away(1)).await;
+
+        // The request never completes, so the shutdown times out.
+        client.recv_eof().await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .timer(TokioTimer)
+            .max_connection_age(Duration::from_millis(10))
+            .graceful_shutdown_timeout(Duration::from_millis(50))
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let (req, _stream) = srv.next().await.unwrap().unwrap();
+        assert_eq!(req.method(), &http::Method::POST);
+
+        assert!(srv.next().await.is_none(), "unexpected request");
+    };
+
+    join(client, srv).await;
+}
+
+#[test]
+#[should_panic(expected = "max_connection_age requires a timer")]
+fn max_connection_age_without_timer_panics_before_handshake() {
+    let (io, _client) = mock::new();
+
+    // Panics when the handshake is created, not when it is first polled.
+    drop(
+        server::Builder::new()
+            .max_connection_age(Duration::from_secs(60))
+            .handshake::<_, Bytes>(io),
+    );
+}
+
+#[tokio::test]
+async fn server_idle_timeout_sends_go_away() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .recv_frame(frames::go_away(0).data("idle_timeout"))
+            .await;
+        client.recv_eof().await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .timer(TokioTimer)
+            .idle_timeout(Duration::from_millis(20))
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let err = srv.next().await.unwrap().unwrap_err();
+        assert!(err.is_idle_timeout(), "{:?}", err);
+        assert_eq!(err.to_string(), "connection closed after idle timeout");
+    };
+
+    join(client, srv).await;
+}
+
+#[test]
+#[should_panic(expected = "idle_timeout requires a timer")]
+fn client_idle_timeout_without_timer_panics_before_handshake() {
+    let (io, _srv) = mock::new();
+
+    drop(
+        client::Builder::new()
+            .idle_timeout(Duration::from_secs(60))
+            .handshake::<_, Bytes>(io),
+    );
+}
+
+#[tokio::test]
+async fn client_idle_timeout_waits_for_streams() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+
+        // The open stream keeps the connection from being idle.
+        idle_ms(50).await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+
+        // Clients close idle connections without a GOAWAY.
+        srv.recv_eof().await;
+    };
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::Builder::new()
+            .timer(TokioTimer)
+            .idle_timeout(Duration::from_millis(20))
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+
+        let request = Request::get("https://example.com/").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let response = h2.drive(response).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let err = h2.await.unwrap_err();
+        assert!(err.is_idle_timeout(), "{:?}", err);
+
+        let err = poll_fn(|cx| client.poll_ready(cx)).await.unwrap_err();
+        assert!(err.is_idle_timeout(), "{:?}", err);
+    };
+
+    join(srv, h2).await;
+}
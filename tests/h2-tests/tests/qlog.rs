@@ -0,0 +1,173 @@
+#![deny(warnings)]
+
+use futures::future::join;
+use futures::StreamExt;
+use h2_support::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A writer that keeps what is written, and counts how often it is flushed.
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>, Arc<AtomicUsize>);
+
+impl std::io::Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Splits a JSON-SEQ trace into records, with the `time` of events removed.
+fn records(trace: &[u8]) -> Vec<String> {
+    let trace = String::from_utf8(trace.to_vec()).unwrap();
+    assert!(trace.starts_with('\x1e'), "{:?}", trace);
+
+    trace[1..]
+        .split('\x1e')
+        .map(|record| {
+            let record = record
+                .strip_suffix('\n')
+                .unwrap_or_else(|| panic!("record not terminated: {:?}", record));
+            match record.strip_prefix("{\"time\":") {
+                Some(rest) => {
+                    let end = rest.find(',').unwrap();
+                    let time: f64 = rest[..end].parse().unwrap();
+                    assert!(time >= 0.0);
+                    format!("{{{}", &rest[end + 1..])
+                }
+                None => record.to_string(),
+            }
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn client_writes_qlog_events() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+    let trace = SharedBuf::default();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_eq!(settings.max_concurrent_streams(), Some(100));
+        srv.recv_frame(frames::headers(1).request("POST", "https://example.com/"))
+            .await;
+        srv.recv_frame(frames::data(1, "hello").eos()).await;
+        srv.send_frame(frames::window_update(0, 5)).await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+        srv.send_frame(frames::go_away(1).calm().data("bye")).await;
+    };
+
+    let writer = trace.clone();
+    let h2 = async move {
+        let (mut client, h2) = client::Builder::new()
+            .max_concurrent_streams(100)
+            .qlog(move || writer.clone())
+            .handshake::<_, Bytes>(io)
+            .await
+            .unwrap();
+
+        let request = Request::post("https://example.com/").body(()).unwrap();
+        let (response, mut stream) = client.send_request(request, false).unwrap();
+        stream.send_data("hello".into(), true).unwrap();
+        let (response, _) = join(response, h2).await;
+        assert_eq!(response.unwrap().status(), StatusCode::OK);
+    };
+
+    join(srv, h2).await;
+
+    let records = records(&trace.0.lock().unwrap());
+    let expected = [
+        r#"{"qlog_version":"0.3","qlog_format":"JSON-SEQ","trace":{"vantage_point":{"type":"client"},"common_fields":{"time_format":"relative","reference_time":0}}}"#,
+        r#"{"name":"http2:frame_created","data":{"stream_id":0,"length":6,"flags":0,"frame":{"frame_type":"settings","settings":[{"name":"SETTINGS_MAX_CONCURRENT_STREAMS","id":3,"value":100}]}}}"#,
+        r#"{"name":"http2:settings_updated","data":{"owner":"local","settings":[{"name":"SETTINGS_MAX_CONCURRENT_STREAMS","id":3,"value":100}]}}"#,
+        r#"{"name":"http2:stream_state_updated","data":{"stream_id":1,"old":"idle","new":"open"}}"#,
+        r#"{"name":"http2:stream_state_updated","data":{"stream_id":1,"old":"open","new":"half_closed_local"}}"#,
+        r#"{"name":"http2:frame_parsed","data":{"stream_id":0,"length":0,"flags":0,"frame":{"frame_type":"settings","settings":[]}}}"#,
+        r#"{"name":"http2:settings_updated","data":{"owner":"remote","settings":[]}}"#,
+        r#"{"name":"http2:frame_created","data":{"stream_id":1,"length":"#,
+        r#"{"name":"http2:frame_created","data":{"stream_id":1,"length":5,"flags":1,"frame":{"frame_type":"data"}}}"#,
+        r#"{"name":"http2:frame_parsed","data":{"stream_id":0,"length":4,"flags":0,"frame":{"frame_type":"window_update","increment":5}}}"#,
+        r#"{"name":"http2:flow_control_updated","data":{"stream_id":0,"owner":"remote","increment":5,"window":65535}}"#,
+        r#"{"name":"http2:frame_parsed","data":{"stream_id":1,"length":1,"flags":5,"frame":{"frame_type":"headers","headers":[{"name":":status","value":"200"}]}}}"#,
+        r#"{"name":"http2:stream_state_updated","data":{"stream_id":1,"old":"half_closed_local","new":"closed"}}"#,
+        r#"{"name":"http2:frame_parsed","data":{"stream_id":0,"length":11,"flags":0,"frame":{"frame_type":"goaway","last_stream_id":1,"error_code":11}}}"#,
+        r#"{"name":"http2:goaway","data":{"owner":"remote","last_stream_id":1,"error_code":11,"debug_data":"bye"}}"#,
+    ];
+
+    let mut rest = &records[..];
+    for part in expected {
+        let i = rest
+            .iter()
+            .position(|record| record.starts_with(part))
+            .unwrap_or_else(|| panic!("{} not found in:\n{}", part, records.join("\n")));
+        rest = &rest[i + 1..];
+    }
+
+    let headers = records
+        .iter()
+        .find(|record| record.contains(r#""frame_type":"headers""#))
+        .unwrap();
+    assert!(
+        headers.contains(r#"{"name":":method","value":"POST"}"#),
+        "{}",
+        headers
+    );
+}
+
+#[tokio::test]
+async fn server_writes_one_qlog_trace_per_connection() {
+    h2_support::trace_init!();
+    let traces = Arc::new(Mutex::new(Vec::new()));
+
+    let mut builder = server::Builder::new();
+    let make_writer = traces.clone();
+    builder.qlog(move || {
+        let trace = SharedBuf::default();
+        make_writer.lock().unwrap().push(trace.clone());
+        trace
+    });
+
+    for _ in 0..2 {
+        let (io, mut client) = mock::new();
+
+        let client = async move {
+            let settings = client.assert_server_handshake().await;
+            assert_default_settings!(settings);
+            client.send_frame(frames::go_away(0)).await;
+        };
+
+        let srv = async {
+            let mut srv = builder.handshake::<_, Bytes>(io).await.expect("handshake");
+            assert!(srv.next().await.is_none());
+        };
+
+        join(client, srv).await;
+    }
+
+    let traces = traces.lock().unwrap();
+    assert_eq!(traces.len(), 2);
+    for trace in traces.iter() {
+        let records = records(&trace.0.lock().unwrap());
+        let headers = records
+            .iter()
+            .filter(|record| record.starts_with(r#"{"qlog_version""#))
+            .count();
+        assert_eq!(headers, 1, "{}", records.join("\n"));
+        // Records are not flushed one by one, only once the connection is
+        // dropped.
+        assert_eq!(trace.1.load(Ordering::SeqCst), 1);
+        assert!(
+            records
+                .iter()
+                .any(|record| record.starts_with(r#"{"name":"http2:goaway""#)),
+            "{}",
+            records.join("\n")
+        );
+    }
+}
@@ -0,0 +1,250 @@
+#![deny(warnings)]
+
+use h2_support::prelude::*;
+use sans_io::{Connection, Event};
+use std::time::Instant;
+
+/// Moves bytes between the connections until neither has any to send, and
+/// returns the events of the client and of the server.
+fn pump(client: &mut Connection, server: &mut Connection) -> (Vec<Event>, Vec<Event>) {
+    loop {
+        let mut idle = true;
+        while let Some(bytes) = client.transmit() {
+            server.recv(&bytes);
+            idle = false;
+        }
+        while let Some(bytes) = server.transmit() {
+            client.recv(&bytes);
+            idle = false;
+        }
+        if idle {
+            break;
+        }
+    }
+
+    let client_events = std::iter::from_fn(|| client.poll_event()).collect();
+    let server_events = std::iter::from_fn(|| server.poll_event()).collect();
+    (client_events, server_events)
+}
+
+fn handshake(client: &client::Builder, server: &server::Builder) -> (Connection, Connection) {
+    let mut client = Connection::client(client);
+    let mut server = Connection::server(server);
+
+    let (client_events, server_events) = pump(&mut client, &mut server);
+    assert!(
+        matches!(client_events[..], [Event::Settings(_)]),
+        "{:?}",
+        client_events
+    );
+    assert!(
+        matches!(server_events[..], [Event::Settings(_)]),
+        "{:?}",
+        server_events
+    );
+
+    (client, server)
+}
+
+#[test]
+fn request_and_response_with_body_and_trailers() {
+    h2_support::trace_init!();
+    let (mut client, mut server) = handshake(&client::Builder::new(), &server::Builder::new());
+
+    let request = Request::post("https://example.com/").body(()).unwrap();
+    let id = client.send_request(request, false).unwrap();
+    assert_eq!(id.as_u32(), 1);
+    client.send_data(id, "hello".into(), true).unwrap();
+
+    let (client_events, server_events) = pump(&mut client, &mut server);
+    assert!(client_events.is_empty(), "{:?}", client_events);
+    match &server_events[..] {
+        [Event::NewStream(new_id, request), Event::Data(data_id, data), Event::EndStream(end_id)] =>
+        {
+            assert_eq!(*new_id, id);
+            assert_eq!(request.method(), Method::POST);
+            assert_eq!(request.uri(), "https://example.com/");
+            assert_eq!((*data_id, &data[..]), (id, &b"hello"[..]));
+            assert_eq!(*end_id, id);
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+    server.release_capacity(id, 5).unwrap();
+
+    let response = Response::builder().status(201).body(()).unwrap();
+    server.send_response(id, response, false).unwrap();
+    server.send_data(id, "world".into(), false).unwrap();
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", "0".parse().unwrap());
+    server.send_trailers(id, trailers).unwrap();
+
+    let (client_events, server_events) = pump(&mut client, &mut server);
+    assert!(server_events.is_empty(), "{:?}", server_events);
+    match &client_events[..] {
+        [Event::Headers(_, response), Event::Data(_, data), Event::Trailers(_, trailers), Event::EndStream(_)] =>
+        {
+            assert_eq!(response.status(), StatusCode::CREATED);
+            assert_eq!(&data[..], b"world");
+            assert_eq!(trailers["grpc-status"], "0");
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+
+    // The stream is closed on both sides.
+    let err = server.send_data(id, "more".into(), true).unwrap_err();
+    assert_eq!(err.to_string(), "user error: inactive stream");
+    assert!(!client.is_closed());
+    assert!(!server.is_closed());
+}
+
+#[test]
+fn flow_control_waits_for_released_capacity() {
+    h2_support::trace_init!();
+    let mut builder = server::Builder::new();
+    builder.initial_window_size(5);
+    let (mut client, mut server) = handshake(&client::Builder::new(), &builder);
+
+    let request = Request::post("https://example.com/").body(()).unwrap();
+    let id = client.send_request(request, false).unwrap();
+    client.send_data(id, "hello world".into(), true).unwrap();
+
+    let (_, server_events) = pump(&mut client, &mut server);
+    match &server_events[..] {
+        [Event::NewStream(..), Event::Data(_, data)] => assert_eq!(&data[..], b"hello"),
+        events => panic!("unexpected events: {:?}", events),
+    }
+
+    server.release_capacity(id, 5).unwrap();
+    let (_, server_events) = pump(&mut client, &mut server);
+    match &server_events[..] {
+        [Event::Data(_, data)] => assert_eq!(&data[..], b" worl"),
+        events => panic!("unexpected events: {:?}", events),
+    }
+
+    // The last byte is sent once the window grows again.
+    server.release_capacity(id, 5).unwrap();
+    let (_, server_events) = pump(&mut client, &mut server);
+    match &server_events[..] {
+        [Event::Data(_, data), Event::EndStream(_)] => assert_eq!(&data[..], b"d"),
+        events => panic!("unexpected events: {:?}", events),
+    }
+}
+
+#[test]
+fn reset_and_graceful_shutdown() {
+    h2_support::trace_init!();
+    let (mut client, mut server) = handshake(&client::Builder::new(), &server::Builder::new());
+
+    let request = Request::get("https://example.com/").body(()).unwrap();
+    let id1 = client.send_request(request.clone(), true).unwrap();
+    let id3 = client.send_request(request, true).unwrap();
+
+    let (_, server_events) = pump(&mut client, &mut server);
+    assert_eq!(server_events.len(), 4, "{:?}", server_events);
+
+    server.send_reset(id1, Reason::REFUSED_STREAM);
+    server.graceful_shutdown();
+
+    let (client_events, _) = pump(&mut client, &mut server);
+    match &client_events[..] {
+        [Event::GoAway {
+            last_stream_id,
+            reason,
+            ..
+        }, Event::Reset(reset_id, Reason::REFUSED_STREAM), Event::GoAway {
+            last_stream_id: last,
+            ..
+        }] => {
+            assert_eq!(last_stream_id.as_u32(), (1 << 31) - 1);
+            assert_eq!(*reason, Reason::NO_ERROR);
+            assert_eq!(*reset_id, id1);
+            assert_eq!(*last, id3);
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+
+    let response = Response::new(());
+    server.send_response(id3, response, true).unwrap();
+
+    let (client_events, server_events) = pump(&mut client, &mut server);
+    match &client_events[..] {
+        [Event::Headers(headers_id, _), Event::EndStream(_), Event::Closed(None)] => {
+            assert_eq!(*headers_id, id3)
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+    assert!(
+        matches!(server_events[..], [Event::Closed(None)]),
+        "{:?}",
+        server_events
+    );
+    assert!(client.is_closed());
+    assert!(server.is_closed());
+}
+
+#[test]
+fn events_follow_the_order_of_frames() {
+    h2_support::trace_init!();
+    let (mut client, mut server) = handshake(&client::Builder::new(), &server::Builder::new());
+
+    let request = Request::get("https://example.com/").body(()).unwrap();
+    let id = client.send_request(request, true).unwrap();
+    let (_, server_events) = pump(&mut client, &mut server);
+    assert_eq!(server_events.len(), 2, "{:?}", server_events);
+
+    // The response is written before the GOAWAY, and both reach the client
+    // in one read.
+    server.send_response(id, Response::new(()), true).unwrap();
+    let mut bytes = server.transmit().unwrap().to_vec();
+    server.graceful_shutdown();
+    bytes.extend_from_slice(&server.transmit().unwrap());
+
+    client.recv(&bytes);
+    let client_events: Vec<_> = std::iter::from_fn(|| client.poll_event()).collect();
+    match &client_events[..] {
+        [Event::Headers(headers_id, _), Event::EndStream(end_id), Event::GoAway { reason, .. }, Event::Closed(None)] =>
+        {
+            assert_eq!(*headers_id, id);
+            assert_eq!(*end_id, id);
+            assert_eq!(*reason, Reason::NO_ERROR);
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+}
+
+#[test]
+fn idle_timeout_is_reported_by_poll_timeout() {
+    h2_support::trace_init!();
+    let timeout = Duration::from_millis(50);
+    let mut builder = server::Builder::new();
+    builder.idle_timeout(timeout);
+
+    let start = Instant::now();
+    let (mut client, mut server) = handshake(&client::Builder::new(), &builder);
+    assert_eq!(client.poll_timeout(), None);
+
+    let deadline = server.poll_timeout().expect("idle deadline");
+    assert!(deadline >= start + timeout);
+    assert!(deadline <= Instant::now() + timeout);
+
+    // Nothing happens before the deadline.
+    server.handle_timeout();
+    assert!(server.transmit().is_none());
+    assert!(server.poll_event().is_none());
+
+    std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+    server.handle_timeout();
+    assert!(server.is_closed());
+    assert_eq!(server.poll_timeout(), None);
+
+    let (client_events, _) = pump(&mut client, &mut server);
+    match &client_events[..] {
+        [Event::GoAway {
+            reason, debug_data, ..
+        }, Event::Closed(_)] => {
+            assert_eq!(*reason, Reason::NO_ERROR);
+            assert_eq!(&debug_data[..], b"idle_timeout");
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+}
@@ -26,6 +26,122 @@ async fn read_preface_in_multiple_frames() {
     assert!(h2.next().await.is_none());
 }
 
+#[tokio::test]
+async fn handshake_with_prefix_containing_frames() {
+    h2_support::trace_init!();
+
+    let mock = mock_io::Builder::new()
+        .write(SETTINGS)
+        .write(SETTINGS_ACK)
+        .read(SETTINGS_ACK)
+        .build();
+
+    let mut prefix = BytesMut::from(&b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"[..]);
+    prefix.extend_from_slice(SETTINGS);
+
+    let mut h2 = server::Builder::new()
+        .handshake_with_prefix::<_, Bytes>(mock, prefix.freeze())
+        .await
+        .unwrap();
+
+    assert!(h2.next().await.is_none());
+}
+
+#[tokio::test]
+async fn handshake_with_partial_preface_prefix() {
+    h2_support::trace_init!();
+
+    let mock = mock_io::Builder::new()
+        .write(SETTINGS)
+        .read(b"\r\nSM\r\n\r\n")
+        .read(SETTINGS)
+        .write(SETTINGS_ACK)
+        .read(SETTINGS_ACK)
+        .build();
+
+    let mut h2 = server::Builder::new()
+        .handshake_with_prefix::<_, Bytes>(mock, Bytes::from_static(b"PRI * HTTP/2.0\r\n"))
+        .await
+        .unwrap();
+
+    assert!(h2.next().await.is_none());
+}
+
+#[tokio::test]
+async fn handshake_with_invalid_prefix() {
+    h2_support::trace_init!();
+
+    let mock = mock_io::Builder::new().write(SETTINGS).build();
+
+    let err = server::Builder::new()
+        .handshake_with_prefix::<_, Bytes>(mock, Bytes::from_static(b"GET / HTTP/1.1\r\n"))
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.reason(), Some(Reason::PROTOCOL_ERROR));
+}
+
+#[tokio::test]
+async fn sniff_preface_then_handshake() {
+    h2_support::trace_init!();
+
+    let mock = mock_io::Builder::new()
+        .read(b"PRI * HTTP/2.0")
+        .read(b"\r\n\r\nSM\r\n\r\n")
+        .write(SETTINGS)
+        .read(SETTINGS)
+        .write(SETTINGS_ACK)
+        .read(SETTINGS_ACK)
+        .build();
+
+    let sniffed = server::sniff_preface(mock).await.unwrap();
+    assert!(sniffed.is_http2());
+    assert_eq!(sniffed.read_bytes().len(), 24);
+
+    let (io, prefix) = sniffed.into_parts();
+    let mut h2 = server::Builder::new()
+        .handshake_with_prefix::<_, Bytes>(io, prefix)
+        .await
+        .unwrap();
+
+    assert!(h2.next().await.is_none());
+}
+
+#[tokio::test]
+async fn sniff_preface_http1() {
+    use tokio::io::AsyncReadExt;
+
+    h2_support::trace_init!();
+
+    let mock = mock_io::Builder::new()
+        .read(b"GET / HTTP/1.1\r\n")
+        .read(b"Host: example.com\r\n\r\n")
+        .build();
+
+    let sniffed = server::sniff_preface(mock).await.unwrap();
+    assert!(!sniffed.is_http2());
+
+    let (mut io, read) = sniffed.into_parts();
+    assert_eq!(read, &b"GET / HTTP/1.1\r\n"[..]);
+
+    let mut rest = Vec::new();
+    io.read_to_end(&mut rest).await.unwrap();
+    assert_eq!(rest, b"Host: example.com\r\n\r\n");
+}
+
+#[tokio::test]
+async fn sniff_preface_eof() {
+    h2_support::trace_init!();
+
+    let mock = mock_io::Builder::new().read(b"PRI * HTTP/2.0").build();
+
+    let sniffed = server::sniff_preface(mock).await.unwrap();
+    assert!(!sniffed.is_http2());
+
+    let (_io, read) = sniffed.into_parts();
+    assert_eq!(read, &b"PRI * HTTP/2.0"[..]);
+}
+
 #[tokio::test]
 async fn server_builder_set_max_concurrent_streams() {
     h2_support::trace_init!();
@@ -105,6 +221,207 @@ async fn serve_request() {
     join(client, srv).await;
 }
 
+#[tokio::test]
+async fn send_informational_responses() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .eos(),
+            )
+            .await;
+        client
+            .recv_frame(
+                frames::headers(1)
+                    .response(103)
+                    .field("link", "</style.css>; rel=preload"),
+            )
+            .await;
+        client
+            .recv_frame(frames::headers(1).response(200).eos())
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::handshake(io).await.expect("handshake");
+        let (_, mut stream) = srv.next().await.unwrap().unwrap();
+
+        let hints = http::Response::builder()
+            .status(103)
+            .header("link", "</style.css>; rel=preload")
+            .body(())
+            .unwrap();
+        stream.send_informational(hints).unwrap();
+
+        for status in [200, 101] {
+            let rsp = http::Response::builder().status(status).body(()).unwrap();
+            let err = stream.send_informational(rsp).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "user error: invalid informational status code"
+            );
+        }
+
+        let rsp = http::Response::builder().status(200).body(()).unwrap();
+        stream.send_response(rsp, true).unwrap();
+
+        let rsp = http::Response::builder().status(100).body(()).unwrap();
+        let err = stream.send_informational(rsp).unwrap_err();
+        assert_eq!(err.to_string(), "user error: unexpected frame type");
+
+        assert!(srv.next().await.is_none());
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn auto_continue() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("POST", "https://example.com/")
+                    .field("expect", "100-continue"),
+            )
+            .await;
+        client.recv_frame(frames::headers(1).response(100)).await;
+        client.send_frame(frames::data(1, "hello").eos()).await;
+        client
+            .recv_frame(frames::headers(1).response(200).eos())
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .auto_continue(true)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+        let (req, mut stream) = srv.next().await.unwrap().unwrap();
+        let mut body = req.into_body();
+
+        let body = async move {
+            assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+
+            let rsp = http::Response::builder().status(200).body(()).unwrap();
+            stream.send_response(rsp, true).unwrap();
+        };
+
+        let mut srv = Box::pin(async move {
+            assert!(srv.next().await.is_none(), "unexpected request");
+        });
+        srv.drive(body).await;
+        srv.await;
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn send_response_over_peer_max_header_list_size() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client
+            .assert_server_handshake_with_settings(frames::settings().max_header_list_size(100))
+            .await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .eos(),
+            )
+            .await;
+        client
+            .recv_frame(frames::headers(1).response(200).eos())
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::handshake(io).await.expect("handshake");
+        let (_, mut stream) = srv.next().await.unwrap().unwrap();
+
+        let rsp = http::Response::builder()
+            .status(200)
+            .header("x-big", "a".repeat(100))
+            .body(())
+            .unwrap();
+        let err = stream.send_response(rsp, true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "user error: header list larger than peer's max header list size"
+        );
+
+        let rsp = http::Response::builder().status(200).body(()).unwrap();
+        stream.send_response(rsp, true).unwrap();
+
+        assert!(srv.next().await.is_none());
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn oversized_response_431() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client
+            .assert_server_handshake_with_settings(frames::settings().max_header_list_size(100))
+            .await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .eos(),
+            )
+            .await;
+        client
+            .recv_frame(frames::headers(1).response(431).eos())
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .oversized_response_431(true)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+        let (_, mut stream) = srv.next().await.unwrap().unwrap();
+
+        let rsp = http::Response::builder()
+            .status(200)
+            .header("x-big", "a".repeat(100))
+            .body(())
+            .unwrap();
+        let err = stream.send_response(rsp, true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "user error: header list larger than peer's max header list size"
+        );
+
+        assert!(srv.next().await.is_none());
+    };
+
+    join(client, srv).await;
+}
+
 #[tokio::test]
 async fn serve_connect() {
     h2_support::trace_init!();
@@ -709,6 +1026,180 @@ async fn graceful_shutdown() {
     join(client, srv).await;
 }
 
+#[tokio::test]
+async fn split_graceful_shutdown_through_acceptor() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .eos(),
+            )
+            .await;
+        client.recv_frame(frames::go_away(2147483647)).await;
+        client.recv_frame(frames::ping(frame::Ping::SHUTDOWN)).await;
+        client
+            .recv_frame(frames::headers(1).response(200).eos())
+            .await;
+        client
+            .send_frame(frames::ping(frame::Ping::SHUTDOWN).pong())
+            .await;
+        client.recv_frame(frames::go_away(1)).await;
+        client.recv_eof().await;
+    };
+
+    let srv = async move {
+        let srv = server::handshake(io).await.expect("handshake");
+        let (mut acceptor, driver) = srv.split();
+        let driver = tokio::spawn(driver);
+
+        // Accept from another task, through a clone of the acceptor.
+        let mut other = acceptor.clone();
+        let handler = tokio::spawn(async move {
+            let (req, mut stream) = other.accept().await.unwrap();
+            assert_eq!(req.method(), &http::Method::GET);
+
+            other.graceful_shutdown();
+
+            let rsp = http::Response::builder().status(200).body(()).unwrap();
+            stream.send_response(rsp, true).unwrap();
+        });
+        handler.await.unwrap();
+
+        assert!(acceptor.accept().await.is_none(), "unexpected request");
+        driver.await.unwrap().expect("driver");
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn split_refuses_streams_once_acceptors_are_dropped() {
+    use tokio::sync::oneshot;
+
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+    let (tx, rx) = oneshot::channel();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        tx.send(()).unwrap();
+        client.recv_frame(frames::go_away(2147483647)).await;
+        client.recv_frame(frames::ping(frame::Ping::SHUTDOWN)).await;
+        // Sent before the client saw the `GOAWAY`, so it is still in flight.
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .eos(),
+            )
+            .await;
+        client.recv_frame(frames::reset(1).refused()).await;
+        client
+            .send_frame(frames::ping(frame::Ping::SHUTDOWN).pong())
+            .await;
+        client.recv_frame(frames::go_away(1)).await;
+        client.recv_eof().await;
+    };
+
+    let srv = async move {
+        let srv = server::handshake(io).await.expect("handshake");
+        let (acceptor, driver) = srv.split();
+        let driver = tokio::spawn(driver);
+
+        let other = acceptor.clone();
+        rx.await.unwrap();
+        drop(acceptor);
+        drop(other);
+
+        driver.await.unwrap().expect("driver");
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn admission_refuses_resets_and_responds() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/refuse")
+                    .eos(),
+            )
+            .await;
+        client.recv_frame(frames::reset(1).refused()).await;
+        client
+            .send_frame(
+                frames::headers(3)
+                    .request("GET", "https://example.com/reset")
+                    .eos(),
+            )
+            .await;
+        client
+            .recv_frame(frames::reset(3).reason(Reason::ENHANCE_YOUR_CALM))
+            .await;
+        client
+            .send_frame(frames::headers(5).request("POST", "https://example.com/busy"))
+            .await;
+        client
+            .recv_frame(frames::headers(5).response(503).eos())
+            .await;
+        client
+            .recv_frame(frames::reset(5).reason(Reason::NO_ERROR))
+            .await;
+        client
+            .send_frame(
+                frames::headers(7)
+                    .request("GET", "https://example.com/")
+                    .eos(),
+            )
+            .await;
+        client
+            .recv_frame(frames::headers(7).response(200).eos())
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .admission(|request, context| {
+                assert_eq!(context.active_streams, 1);
+                assert_eq!(context.max_concurrent_streams, usize::MAX);
+                match request.uri().path() {
+                    "/refuse" => server::Admission::Refuse,
+                    "/reset" => server::Admission::Reset(Reason::ENHANCE_YOUR_CALM),
+                    "/busy" => server::Admission::Respond(
+                        http::Response::builder().status(503).body(()).unwrap(),
+                    ),
+                    _ => server::Admission::Accept,
+                }
+            })
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let (req, mut stream) = srv.next().await.unwrap().unwrap();
+        assert_eq!(req.uri().path(), "/");
+        let rsp = http::Response::builder().status(200).body(()).unwrap();
+        stream.send_response(rsp, true).unwrap();
+
+        assert!(srv.next().await.is_none(), "unexpected request");
+    };
+
+    join(client, srv).await;
+}
+
 #[tokio::test]
 async fn goaway_even_if_client_sent_goaway() {
     h2_support::trace_init!();
@@ -932,6 +1423,223 @@ async fn too_many_continuation_frames_sends_goaway() {
     join(client, srv).await;
 }
 
+#[tokio::test]
+async fn max_continuation_frames_sends_goaway() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+
+        // The headers are split into a HEADERS frame and 3 CONTINUATION frames.
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .field("a".repeat(10_000), "b".repeat(10_000))
+                    .field("c".repeat(10_000), "d".repeat(10_000))
+                    .field("e".repeat(10_000), "f".repeat(10_000))
+                    .field("g".repeat(10_000), "h".repeat(10_000)),
+            )
+            .await;
+        client
+            .recv_frame(frames::go_away(0).calm().data("too_many_continuations"))
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .max_continuation_frames(1)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let err = srv.next().await.unwrap().expect_err("server");
+        assert!(err.is_go_away());
+        assert!(err.is_library());
+        assert_eq!(err.reason(), Some(Reason::ENHANCE_YOUR_CALM));
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn max_header_block_size_sends_goaway() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .field("a".repeat(1_000), "b".repeat(1_000)),
+            )
+            .await;
+        client
+            .recv_frame(frames::go_away(0).calm().data("header_block_too_large"))
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .max_header_block_size(1024)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let err = srv.next().await.unwrap().expect_err("server");
+        assert!(err.is_go_away());
+        assert!(err.is_library());
+        assert_eq!(err.reason(), Some(Reason::ENHANCE_YOUR_CALM));
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn max_header_block_size_counts_continuation_frames() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+
+        // The HEADERS frame alone fits, the CONTINUATION frame does not.
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .field("a".repeat(10_000), "b".repeat(10_000))
+                    .field("c".repeat(10_000), "d".repeat(10_000)),
+            )
+            .await;
+        client
+            .recv_frame(frames::go_away(0).calm().data("header_block_too_large"))
+            .await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .max_header_block_size(17_000)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        let err = srv.next().await.unwrap().expect_err("server");
+        assert!(err.is_go_away());
+        assert_eq!(err.reason(), Some(Reason::ENHANCE_YOUR_CALM));
+    };
+
+    join(client, srv).await;
+}
+
+/// Encodes a GET request HEADERS frame with END_HEADERS and END_STREAM set,
+/// followed by the given raw HPACK fields.
+fn raw_get_request(stream_id: u8, fields: &[u8]) -> Vec<u8> {
+    // :method GET, :scheme https, :path /, :authority example.com
+    let mut block = vec![0x82, 0x87, 0x84, 0x01, 11];
+    block.extend_from_slice(b"example.com");
+    block.extend_from_slice(fields);
+
+    let len = block.len();
+    let mut frame = vec![(len >> 16) as u8, (len >> 8) as u8, len as u8, 1, 0x5];
+    frame.extend_from_slice(&[0, 0, 0, stream_id]);
+    frame.extend_from_slice(&block);
+    frame
+}
+
+/// A request that indexes a 4000 byte header value and references it 20
+/// times, followed by a small indexed header.
+fn hpack_bomb() -> Vec<u8> {
+    // Literal with incremental indexing, new name "x", 4000 byte value
+    let mut fields = vec![0x40, 1, b'x', 0x7f, 0xa1, 0x1e];
+    fields.extend_from_slice(&[b'a'; 4000]);
+    // 20 references to dynamic table entry 62
+    fields.extend_from_slice(&[0xbe; 20]);
+    // Literal with incremental indexing, new name "y", value "b"
+    fields.extend_from_slice(&[0x40, 1, b'y', 1, b'b']);
+    raw_get_request(1, &fields)
+}
+
+async fn recv_hpack_bomb_then_request(client: &mut mock::Handle) {
+    client.send_bytes(&hpack_bomb()).await;
+    client
+        .recv_frame(frames::headers(1).response(431).eos())
+        .await;
+
+    // Entry 62 is now "y: b" and entry 63 is "x: aaa...", which the decoder
+    // must have kept track of.
+    client.send_bytes(&raw_get_request(3, &[0xbe, 0xbf])).await;
+    client
+        .recv_frame(frames::headers(3).response(200).eos())
+        .await;
+}
+
+async fn accept_request_after_hpack_bomb(srv: &mut server::Connection<mock::Mock, Bytes>) {
+    let (req, mut stream) = srv.next().await.unwrap().unwrap();
+    assert_eq!(req.headers()["y"], "b");
+    assert_eq!(req.headers()["x"], "a".repeat(4000));
+
+    let rsp = http::Response::builder().status(200).body(()).unwrap();
+    stream.send_response(rsp, true).unwrap();
+
+    assert!(srv.next().await.is_none());
+}
+
+#[tokio::test]
+async fn hpack_bomb_over_max_header_list_size() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_frame_eq(settings, frames::settings().max_header_list_size(16 * 1024));
+        recv_hpack_bomb_then_request(&mut client).await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .max_header_list_size(16 * 1024)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        accept_request_after_hpack_bomb(&mut srv).await;
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn hpack_bomb_over_max_header_decompression_ratio() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        recv_hpack_bomb_then_request(&mut client).await;
+    };
+
+    let srv = async move {
+        let mut srv = server::Builder::new()
+            .max_header_decompression_ratio(10)
+            .handshake::<_, Bytes>(io)
+            .await
+            .expect("handshake");
+
+        accept_request_after_hpack_bomb(&mut srv).await;
+    };
+
+    join(client, srv).await;
+}
+
 #[tokio::test]
 async fn pending_accept_recv_illegal_content_length_data() {
     h2_support::trace_init!();
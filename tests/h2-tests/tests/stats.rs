@@ -0,0 +1,368 @@
+#![deny(warnings)]
+
+use futures::channel::oneshot;
+use futures::future::join;
+use futures::StreamExt;
+use h2_support::prelude::*;
+
+#[tokio::test]
+async fn client_stats_count_frames_and_streams() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::headers(1).response(200)).await;
+        srv.send_frame(frames::data(1, "hello").eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::handshake(io).await.unwrap();
+
+        let request = Request::get("https://example.com/").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let response = h2.drive(response).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut body = response.into_body();
+        let data = h2.drive(body.data()).await.unwrap().unwrap();
+        assert_eq!(data, "hello");
+        h2.await.unwrap();
+
+        let stats = client.stats();
+
+        assert_eq!(stats.sent.headers.frames, 1);
+        assert_eq!(stats.sent.settings.frames, 2);
+        assert_eq!(stats.sent.data.frames, 0);
+        assert_eq!(stats.received.headers.frames, 1);
+        assert_eq!(stats.received.settings.frames, 2);
+        assert_eq!(stats.received.data.frames, 1);
+        assert_eq!(stats.received.data.bytes, 9 + 5);
+        assert_eq!(stats.received.frames(), stats.received.headers.frames + 3);
+
+        assert_eq!(stats.streams.opened_local, 1);
+        assert_eq!(stats.streams.opened_remote, 0);
+        assert_eq!(stats.streams.closed, 1);
+        assert_eq!(stats.streams.active, 0);
+        assert_eq!(stats.streams.reset_local.total(), 0);
+        assert_eq!(stats.streams.reset_remote.total(), 0);
+
+        assert_eq!(stats.flow_control.send_window, 65_535);
+        assert_eq!(stats.flow_control.recv_window, 65_530);
+        assert_eq!(stats.flow_control.send_blocked, Duration::ZERO);
+
+        assert!(stats.hpack_encoder.encoded_bytes > 0);
+        assert!(stats.hpack_encoder.compression_ratio().unwrap() > 1.0);
+        assert_eq!(stats.hpack_encoder.max_table_size, 4_096);
+
+        // `:status: 200` is a single byte static table reference.
+        assert_eq!(stats.hpack_decoder.header_list_bytes, 7 + 3 + 32);
+        assert_eq!(stats.hpack_decoder.encoded_bytes, 1);
+        assert_eq!(stats.hpack_decoder.table_size, 0);
+        assert_eq!(stats.hpack_decoder.max_table_size, 4_096);
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn server_stats_count_resets_by_side_and_reason() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        client
+            .send_frame(
+                frames::headers(1)
+                    .request("GET", "https://example.com/")
+                    .eos(),
+            )
+            .await;
+        client.recv_frame(frames::reset(1).internal_error()).await;
+        client
+            .send_frame(frames::headers(3).request("POST", "https://example.com/"))
+            .await;
+        client.recv_frame(frames::headers(3).response(200)).await;
+        client.send_frame(frames::reset(3).cancel()).await;
+    };
+
+    let srv = async move {
+        let mut srv = server::handshake(io).await.expect("handshake");
+
+        let (_, mut stream) = srv.next().await.unwrap().unwrap();
+        stream.send_reset(Reason::INTERNAL_ERROR);
+
+        let (_, mut stream) = srv.next().await.unwrap().unwrap();
+        let rsp = http::Response::builder().status(200).body(()).unwrap();
+        let _send = stream.send_response(rsp, false).unwrap();
+        assert!(srv.next().await.is_none());
+
+        let stats = srv.stats();
+
+        assert_eq!(stats.sent.reset.frames, 1);
+        assert_eq!(stats.sent.reset.bytes, 9 + 4);
+        assert_eq!(stats.received.reset.frames, 1);
+        assert_eq!(stats.received.headers.frames, 2);
+        assert_eq!(stats.sent.headers.frames, 1);
+
+        assert_eq!(stats.streams.opened_local, 0);
+        assert_eq!(stats.streams.opened_remote, 2);
+        assert_eq!(stats.streams.closed, 2);
+        assert_eq!(stats.streams.active, 0);
+
+        assert_eq!(stats.streams.reset_local.get(Reason::INTERNAL_ERROR), 1);
+        assert_eq!(stats.streams.reset_local.total(), 1);
+        assert_eq!(stats.streams.reset_remote.get(Reason::CANCEL), 1);
+        assert_eq!(stats.streams.reset_remote.get(Reason::INTERNAL_ERROR), 0);
+        assert_eq!(
+            stats.streams.reset_remote.iter().collect::<Vec<_>>(),
+            [(Reason::CANCEL, 1)]
+        );
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn server_stats_count_unknown_reset_reasons_together() {
+    h2_support::trace_init!();
+    let (io, mut client) = mock::new();
+
+    let client = async move {
+        let settings = client.assert_server_handshake().await;
+        assert_default_settings!(settings);
+        for (id, code) in [(1, 0x100), (3, 0x101), (5, 0xffff_ffff)] {
+            client
+                .send_frame(frames::headers(id).request("POST", "https://example.com/"))
+                .await;
+            client
+                .send_frame(frames::reset(id).reason(Reason::from(code)))
+                .await;
+        }
+    };
+
+    let srv = async move {
+        let mut srv = server::handshake(io).await.expect("handshake");
+
+        let mut requests = vec![];
+        while let Some(request) = srv.next().await {
+            requests.push(request.unwrap());
+        }
+
+        let stats = srv.stats();
+        assert_eq!(stats.streams.reset_remote.other(), 3);
+        assert_eq!(stats.streams.reset_remote.get(Reason::from(0x100)), 0);
+        assert_eq!(stats.streams.reset_remote.total(), 3);
+        assert_eq!(stats.streams.reset_remote.iter().count(), 0);
+    };
+
+    join(client, srv).await;
+}
+
+#[tokio::test]
+async fn stats_measure_time_blocked_on_connection_window() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+    let (tx, rx) = oneshot::channel();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+
+        // Raise the stream window so only the connection window blocks.
+        srv.send_frame(frames::settings().initial_window_size(1 << 20))
+            .await;
+        srv.recv_frame(frames::settings_ack()).await;
+        tx.send(()).unwrap();
+
+        srv.recv_frame(frames::headers(1).request("POST", "https://example.com/"))
+            .await;
+        for _ in 0..3 {
+            srv.recv_frame(frames::data(1, vec![0; 16_384])).await;
+        }
+        srv.recv_frame(frames::data(1, vec![0; 16_383])).await;
+
+        idle_ms(50).await;
+        srv.send_frame(frames::window_update(0, 4_465)).await;
+        srv.recv_frame(frames::data(1, vec![0; 4_465]).eos()).await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::handshake(io).await.unwrap();
+        h2.drive(rx).await.unwrap();
+
+        let request = Request::post("https://example.com/").body(()).unwrap();
+        let (response, mut stream) = client.send_request(request, false).unwrap();
+        stream.send_data(vec![0; 70_000].into(), true).unwrap();
+
+        let response = h2.drive(response).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let stats = client.stats();
+        assert_eq!(stats.sent.data.frames, 5);
+        assert_eq!(stats.sent.data.bytes, 70_000 + 5 * 9);
+        assert_eq!(stats.flow_control.send_window, 0);
+        assert!(stats.flow_control.send_blocked >= Duration::from_millis(50));
+
+        h2.await.unwrap();
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn stream_metrics_track_frames_bytes_and_blocked_time() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+    let (tx, rx) = oneshot::channel();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+
+        srv.send_frame(frames::settings().initial_window_size(2))
+            .await;
+        srv.recv_frame(frames::settings_ack()).await;
+        tx.send(()).unwrap();
+
+        srv.recv_frame(frames::headers(1).request("POST", "https://example.com/"))
+            .await;
+        srv.recv_frame(frames::data(1, "he")).await;
+
+        idle_ms(50).await;
+        srv.send_frame(frames::window_update(1, 3)).await;
+        srv.recv_frame(frames::data(1, "llo").eos()).await;
+        srv.send_frame(frames::headers(1).response(200)).await;
+        srv.send_frame(frames::data(1, "world").eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::handshake(io).await.unwrap();
+        h2.drive(rx).await.unwrap();
+
+        let request = Request::post("https://example.com/").body(()).unwrap();
+        let (response, mut stream) = client.send_request(request, false).unwrap();
+        stream.send_data("hello".into(), true).unwrap();
+
+        let response = h2.drive(response).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut body = response.into_body();
+        let data = h2.drive(body.data()).await.unwrap().unwrap();
+        assert_eq!(data, "world");
+
+        let metrics = body.metrics();
+        let headers_sent = metrics.headers_sent.unwrap();
+        let headers_received = metrics.headers_received.unwrap();
+        let first_data_received = metrics.first_data_received.unwrap();
+        assert!(headers_sent <= headers_received);
+        assert!(headers_received <= first_data_received);
+
+        assert_eq!(metrics.frames_sent, 3);
+        assert_eq!(metrics.data_bytes_sent, 5);
+        assert_eq!(metrics.frames_received, 3);
+        assert_eq!(metrics.data_bytes_received, 5);
+        assert!(metrics.send_blocked >= Duration::from_millis(50));
+
+        assert_eq!(stream.metrics().frames_sent, 3);
+
+        h2.await.unwrap();
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn stream_metrics_headers_sent_waits_for_the_io() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+    let (tx, rx) = oneshot::channel();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+
+        // The socket applies back-pressure to the HEADERS frame for a while.
+        srv.block_bytes();
+        tx.send(()).unwrap();
+        idle_ms(50).await;
+        srv.unbounded_bytes().await;
+
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, mut h2) = client::handshake(io).await.unwrap();
+        h2.drive(rx).await.unwrap();
+
+        let request = Request::get("https://example.com/").body(()).unwrap();
+        let (response, stream) = client.send_request(request, true).unwrap();
+        let response = h2.drive(response).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let headers_sent = stream.metrics().headers_sent.unwrap();
+        assert!(
+            headers_sent >= Duration::from_millis(50),
+            "{:?}",
+            headers_sent
+        );
+
+        h2.await.unwrap();
+    };
+
+    join(srv, h2).await;
+}
+
+#[tokio::test]
+async fn stream_metrics_time_final_response_after_informational() {
+    h2_support::trace_init!();
+    let (io, mut srv) = mock::new();
+
+    let srv = async move {
+        let settings = srv.assert_client_handshake().await;
+        assert_default_settings!(settings);
+        srv.recv_frame(
+            frames::headers(1)
+                .request("GET", "https://example.com/")
+                .eos(),
+        )
+        .await;
+        srv.send_frame(frames::headers(1).response(103)).await;
+        idle_ms(50).await;
+        srv.send_frame(frames::headers(1).response(200).eos()).await;
+    };
+
+    let h2 = async move {
+        let (mut client, h2) = client::handshake(io).await.unwrap();
+
+        let request = Request::get("https://example.com/").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let (response, _) = join(response, h2).await;
+        let response = response.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let metrics = response.into_body().metrics();
+        let headers_received = metrics.headers_received.unwrap();
+        let final_headers_received = metrics.final_headers_received.unwrap();
+        assert!(final_headers_received >= headers_received + Duration::from_millis(50));
+        assert_eq!(metrics.frames_received, 2);
+    };
+
+    join(srv, h2).await;
+}
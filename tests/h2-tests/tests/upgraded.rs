@@ -0,0 +1,164 @@
+#![deny(warnings)]
+
+use futures::future::join;
+use h2_support::prelude::*;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Connects a client and a server over an in-memory pipe, sends a `CONNECT`
+/// request and returns both ends of the tunnel.
+async fn tunnel() -> (Upgraded, Upgraded) {
+    let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+
+    let server = async move {
+        let mut srv = server::handshake(server_io).await.unwrap();
+        let (req, mut respond) = srv.accept().await.unwrap().unwrap();
+        assert_eq!(req.method(), Method::CONNECT);
+
+        let rsp = Response::builder().status(200).body(()).unwrap();
+        let send = respond.send_response(rsp, false).unwrap();
+        let upgraded = Upgraded::new(send, req.into_body());
+
+        tokio::spawn(async move { while srv.accept().await.is_some() {} });
+        upgraded
+    };
+
+    let client = async move {
+        let (mut client, conn) = client::handshake(client_io).await.unwrap();
+        tokio::spawn(async move { conn.await.unwrap() });
+
+        let req = Request::builder()
+            .method(Method::CONNECT)
+            .uri("example.com:443")
+            .body(())
+            .unwrap();
+        let (response, send) = client.send_request(req, false).unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Upgraded::new(send, response.into_body())
+    };
+
+    let (server, client) = join(server, client).await;
+    (client, server)
+}
+
+#[tokio::test]
+async fn echo_over_tunnel() {
+    h2_support::trace_init!();
+
+    let (mut client, mut server) = tunnel().await;
+
+    let srv = async move {
+        let mut buf = [0; 64];
+        loop {
+            let n = server.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            server.write_all(&buf[..n]).await.unwrap();
+        }
+        server.shutdown().await.unwrap();
+    };
+
+    let cli = async move {
+        client.write_all(b"hello world").await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let mut echoed = Vec::new();
+        client.read_to_end(&mut echoed).await.unwrap();
+        assert_eq!(echoed, b"hello world");
+    };
+
+    join(srv, cli).await;
+}
+
+#[tokio::test]
+async fn transfer_larger_than_window() {
+    h2_support::trace_init!();
+
+    // Larger than the default connection and stream windows, so the transfer
+    // only completes if capacity is requested and released as data flows.
+    const LEN: usize = 300 * 1024;
+
+    let (mut client, mut server) = tunnel().await;
+
+    let srv = async move {
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received.len(), LEN);
+        assert!(received.iter().enumerate().all(|(i, b)| *b == i as u8));
+
+        server.write_all(&received).await.unwrap();
+        server.shutdown().await.unwrap();
+    };
+
+    let cli = async move {
+        let data = (0..LEN).map(|i| i as u8).collect::<Vec<_>>();
+
+        let write = async {
+            let (mut rd, mut wr) = tokio::io::split(&mut client);
+            let write = async {
+                wr.write_all(&data).await.unwrap();
+                wr.shutdown().await.unwrap();
+            };
+            let read = async {
+                let mut echoed = Vec::new();
+                rd.read_to_end(&mut echoed).await.unwrap();
+                echoed
+            };
+            join(write, read).await.1
+        };
+
+        assert_eq!(write.await, data);
+    };
+
+    join(srv, cli).await;
+}
+
+#[tokio::test]
+async fn reset_maps_to_io_error() {
+    h2_support::trace_init!();
+
+    let (mut client, mut server) = tunnel().await;
+
+    server.send_reset(Reason::INTERNAL_ERROR);
+
+    let mut buf = [0; 8];
+    let err = client.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Other);
+    let err = err.into_inner().unwrap().downcast::<h2::Error>().unwrap();
+    assert_eq!(err.reason(), Some(Reason::INTERNAL_ERROR));
+
+    let err = client.write(b"hello").await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Other);
+}
+
+#[tokio::test]
+async fn cancel_is_eof() {
+    h2_support::trace_init!();
+
+    let (mut client, mut server) = tunnel().await;
+
+    server.send_reset(Reason::CANCEL);
+
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).await.unwrap();
+    assert!(received.is_empty());
+
+    let err = client.write(b"hello").await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+}
+
+#[tokio::test]
+async fn write_after_shutdown() {
+    h2_support::trace_init!();
+
+    let (mut client, _server) = tunnel().await;
+
+    client.shutdown().await.unwrap();
+    // Shutting down again is a no-op.
+    client.shutdown().await.unwrap();
+
+    let err = client.write(b"hello").await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+}
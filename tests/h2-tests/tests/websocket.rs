@@ -0,0 +1,230 @@
+#![deny(warnings)]
+
+use futures::future::join;
+use h2_support::prelude::*;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+/// Performs the HTTP/2 handshake over an in-memory pipe, with the extended
+/// CONNECT protocol enabled on the server. The client connection is driven in
+/// the background.
+async fn connect() -> (
+    client::SendRequest<Bytes>,
+    server::Connection<DuplexStream, Bytes>,
+) {
+    let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+
+    let server = async move {
+        server::Builder::new()
+            .enable_connect_protocol()
+            .handshake(server_io)
+            .await
+            .unwrap()
+    };
+
+    let client = async move {
+        let (client, conn) = client::handshake(client_io).await.unwrap();
+        tokio::spawn(async move { conn.await.unwrap() });
+        client
+    };
+
+    let (srv, client) = join(server, client).await;
+
+    // Wait for the client connection to process the server's SETTINGS.
+    while !client.is_extended_connect_protocol_enabled() {
+        tokio::task::yield_now().await;
+    }
+
+    (client, srv)
+}
+
+#[tokio::test]
+async fn websocket_echo() {
+    h2_support::trace_init!();
+
+    let (mut client, mut srv) = connect().await;
+
+    let server = async move {
+        let (req, mut respond) = srv.accept().await.unwrap().unwrap();
+        assert!(server::is_websocket_request(&req));
+        assert_eq!(req.uri().path(), "/chat");
+        assert_eq!(req.headers()["sec-websocket-protocol"], "chat");
+
+        let rsp = Response::builder()
+            .header("sec-websocket-protocol", "chat")
+            .body(())
+            .unwrap();
+        let mut ws = respond.accept_websocket(req, rsp).unwrap();
+
+        tokio::spawn(async move { while srv.accept().await.is_some() {} });
+
+        let mut buf = Vec::new();
+        ws.read_to_end(&mut buf).await.unwrap();
+        ws.write_all(&buf).await.unwrap();
+        ws.shutdown().await.unwrap();
+    };
+
+    let client = async move {
+        let req = Request::builder()
+            .uri("https://example.com/chat")
+            .header("sec-websocket-protocol", "chat")
+            .body(())
+            .unwrap();
+        let (rsp, mut ws) = client.send_websocket(req).unwrap().await.unwrap();
+        assert_eq!(rsp.status(), StatusCode::OK);
+        assert_eq!(rsp.headers()["sec-websocket-protocol"], "chat");
+
+        ws.write_all(b"ping").await.unwrap();
+        ws.shutdown().await.unwrap();
+
+        let mut buf = Vec::new();
+        ws.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"ping");
+    };
+
+    join(server, client).await;
+}
+
+#[tokio::test]
+async fn websocket_rejected_by_server() {
+    h2_support::trace_init!();
+
+    let (mut client, mut srv) = connect().await;
+
+    let server = async move {
+        let (req, mut respond) = srv.accept().await.unwrap().unwrap();
+        assert!(server::is_websocket_request(&req));
+
+        let rsp = Response::builder().status(403).body(()).unwrap();
+        respond.send_response(rsp, true).unwrap();
+
+        while srv.accept().await.is_some() {}
+    };
+
+    let client = async move {
+        let req = Request::builder()
+            .uri("https://example.com/chat")
+            .body(())
+            .unwrap();
+        let err = client.send_websocket(req).unwrap().await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "user error: extended CONNECT rejected by peer"
+        );
+    };
+
+    join(server, client).await;
+}
+
+#[tokio::test]
+async fn server_rejects_plain_connect() {
+    h2_support::trace_init!();
+
+    let (mut client, mut srv) = connect().await;
+
+    let server = async move {
+        let (req, mut respond) = srv.accept().await.unwrap().unwrap();
+        assert!(!server::is_websocket_request(&req));
+
+        let err = respond
+            .accept_websocket(req, Response::new(()))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "user error: invalid extended CONNECT handshake"
+        );
+
+        let rsp = Response::builder().status(400).body(()).unwrap();
+        respond.send_response(rsp, true).unwrap();
+
+        while srv.accept().await.is_some() {}
+    };
+
+    let client = async move {
+        let req = Request::builder()
+            .method(Method::CONNECT)
+            .uri("example.com:443")
+            .body(())
+            .unwrap();
+        let (rsp, _) = client.send_request(req, true).unwrap();
+        assert_eq!(rsp.await.unwrap().status(), StatusCode::BAD_REQUEST);
+    };
+
+    join(server, client).await;
+}
+
+#[tokio::test]
+async fn server_rejects_invalid_response() {
+    h2_support::trace_init!();
+
+    let (mut client, mut srv) = connect().await;
+
+    let server = async move {
+        let (req, mut respond) = srv.accept().await.unwrap().unwrap();
+
+        let rsp = Response::builder()
+            .header("sec-websocket-accept", "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+            .body(())
+            .unwrap();
+        let err = respond.accept_websocket(req, rsp).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "user error: invalid extended CONNECT handshake"
+        );
+
+        respond.send_reset(Reason::REFUSED_STREAM);
+
+        while srv.accept().await.is_some() {}
+    };
+
+    let client = async move {
+        let req = Request::builder()
+            .uri("https://example.com/chat")
+            .body(())
+            .unwrap();
+        let err = client.send_websocket(req).unwrap().await.unwrap_err();
+        assert_eq!(err.reason(), Some(Reason::REFUSED_STREAM));
+    };
+
+    join(server, client).await;
+}
+
+#[tokio::test]
+async fn client_requires_extended_connect_protocol() {
+    h2_support::trace_init!();
+
+    let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+
+    let server = async move {
+        let mut srv = server::handshake(server_io).await.unwrap();
+        while srv.accept().await.is_some() {}
+    };
+    tokio::spawn(server);
+
+    let (mut client, conn) = client::handshake(client_io).await.unwrap();
+    tokio::spawn(async move { conn.await.unwrap() });
+
+    let req = Request::builder()
+        .uri("https://example.com/chat")
+        .body(())
+        .unwrap();
+    let err = client.send_websocket(req).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "user error: extended CONNECT protocol not enabled by peer"
+    );
+}
+
+#[tokio::test]
+async fn client_rejects_http1_handshake_headers() {
+    h2_support::trace_init!();
+
+    let (mut client, _srv) = connect().await;
+
+    let req = Request::builder()
+        .uri("https://example.com/chat")
+        .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .body(())
+        .unwrap();
+    let err = client.send_websocket(req).unwrap_err();
+    assert_eq!(err.to_string(), "user error: malformed headers");
+}
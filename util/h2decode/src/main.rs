@@ -0,0 +1,303 @@
+//! Decodes a captured HTTP/2 byte stream, sent by either a client or a server.
+//!
+//! The input file holds the raw bytes, or the bytes as hex digits, optionally
+//! separated by whitespace. The frames are decoded with the same codec and
+//! HPACK decoder as a connection, so the dynamic table is kept across header
+//! blocks. Every frame and header list is printed in the format of
+//! `nghttp -v`, and protocol violations are printed as lines starting with
+//! `!!`.
+//!
+//! The decoder does not see the SETTINGS sent by the peer, so the limits the
+//! peer advertised can be passed as options.
+
+use futures::StreamExt;
+use http2::frame::{Frame, StreamId};
+use http2::observer::FrameLog;
+use http2::proto::Error;
+use http2::Codec;
+
+use bytes::Bytes;
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor};
+use std::process;
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const USAGE: &str =
+    "usage: h2decode [--header-table-size N] [--max-frame-size N] client|server FILE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Client,
+    Server,
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    /// SETTINGS_HEADER_TABLE_SIZE advertised by the peer
+    header_table_size: Option<usize>,
+
+    /// SETTINGS_MAX_FRAME_SIZE advertised by the peer
+    max_frame_size: Option<usize>,
+}
+
+/// Checks the frames sent by one endpoint for protocol violations that the
+/// codec does not detect by itself.
+struct Checker {
+    side: Side,
+
+    /// Number of frames checked so far
+    frames: usize,
+
+    /// The last stream opened or reserved by the endpoint
+    last_stream_id: StreamId,
+
+    /// Streams opened or reserved by the endpoint
+    opened: HashSet<StreamId>,
+
+    /// Streams the endpoint has sent END_STREAM on
+    ended: HashSet<StreamId>,
+
+    violations: usize,
+}
+
+fn main() {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--header-table-size" => options.header_table_size = Some(number(args.next())),
+            "--max-frame-size" => options.max_frame_size = Some(number(args.next())),
+            _ => positional.push(arg),
+        }
+    }
+
+    let (side, path) = match &positional[..] {
+        [side, path] if side == "client" => (Side::Client, path),
+        [side, path] if side == "server" => (Side::Server, path),
+        _ => usage(),
+    };
+
+    let raw = fs::read(path).unwrap_or_else(|e| {
+        eprintln!("h2decode: {}: {}", path, e);
+        process::exit(2);
+    });
+    let data = decode_hex(&raw).unwrap_or(raw);
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let violations = runtime.block_on(decode(side, data, &options));
+
+    if violations > 0 {
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn number(arg: Option<String>) -> usize {
+    arg.and_then(|arg| arg.parse().ok())
+        .unwrap_or_else(|| usage())
+}
+
+/// Returns the bytes encoded by `src`, if it consists only of hex digits and
+/// whitespace.
+fn decode_hex(src: &[u8]) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = src
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+
+    let pairs = digits.chunks_exact(2);
+    if digits.is_empty() || !pairs.remainder().is_empty() {
+        return None;
+    }
+
+    pairs
+        .map(|pair| {
+            let hi = (pair[0] as char).to_digit(16)?;
+            let lo = (pair[1] as char).to_digit(16)?;
+            Some((hi << 4 | lo) as u8)
+        })
+        .collect()
+}
+
+/// Decodes and prints the frames in `data`, and returns the number of
+/// protocol violations found.
+async fn decode(side: Side, mut data: Vec<u8>, options: &Options) -> usize {
+    let mut checker = Checker::new(side);
+
+    if data.starts_with(PREFACE) {
+        println!("recv connection preface");
+        if side == Side::Server {
+            checker.violation("server sent the client connection preface");
+        }
+        data.drain(..PREFACE.len());
+    } else if side == Side::Client {
+        checker.violation("missing client connection preface");
+    }
+
+    // The codec never writes, since no frames are buffered.
+    let mut codec: Codec<_, Bytes> = Codec::new(Cursor::new(data));
+    codec.set_observer(FrameLog::new(io::stdout()));
+
+    if let Some(size) = options.header_table_size {
+        codec.set_recv_header_table_size(size);
+    }
+
+    if let Some(size) = options.max_frame_size {
+        codec.set_max_recv_frame_size(size);
+    }
+
+    while let Some(frame) = codec.next().await {
+        match frame {
+            Ok(frame) => checker.check(&frame),
+            Err(Error::Reset(id, reason, _)) => {
+                // Stream errors leave the connection usable.
+                checker.violation(format!("stream {}: {:?}", u32::from(id), reason));
+            }
+            Err(Error::GoAway(debug_data, reason, _)) => {
+                checker.violation(format!(
+                    "connection error: {:?} {}",
+                    reason,
+                    String::from_utf8_lossy(&debug_data)
+                ));
+                break;
+            }
+            Err(Error::Io(kind, msg)) => {
+                checker.violation(format!("{:?}: {}", kind, msg.unwrap_or_default()));
+                break;
+            }
+        }
+    }
+
+    println!(
+        "{} frames, {} protocol violations",
+        checker.frames, checker.violations
+    );
+    checker.violations
+}
+
+// ===== impl Checker =====
+
+impl Checker {
+    fn new(side: Side) -> Checker {
+        Checker {
+            side,
+            frames: 0,
+            last_stream_id: StreamId::ZERO,
+            opened: HashSet::new(),
+            ended: HashSet::new(),
+            violations: 0,
+        }
+    }
+
+    fn violation<T: fmt::Display>(&mut self, msg: T) {
+        println!("!! {}", msg);
+        self.violations += 1;
+    }
+
+    /// Returns whether streams with this identifier are initiated by the
+    /// endpoint.
+    fn is_local(&self, id: StreamId) -> bool {
+        match self.side {
+            Side::Client => id.is_client_initiated(),
+            Side::Server => id.is_server_initiated(),
+        }
+    }
+
+    fn check(&mut self, frame: &Frame) {
+        if self.frames == 0 {
+            match *frame {
+                Frame::Settings(ref settings) if !settings.is_ack() => {}
+                _ => self.violation("first frame is not SETTINGS"),
+            }
+        }
+        self.frames += 1;
+
+        match *frame {
+            Frame::Headers(ref headers) => {
+                let id = headers.stream_id();
+                self.check_open(id, "HEADERS");
+
+                if self.side == Side::Client && id.is_server_initiated() {
+                    self.violation(format!(
+                        "stream {}: HEADERS on a pushed stream",
+                        u32::from(id)
+                    ));
+                } else if self.is_local(id) && !self.opened.contains(&id) {
+                    if self.side == Side::Server {
+                        self.violation(format!(
+                            "stream {}: HEADERS on a stream that was not promised",
+                            u32::from(id)
+                        ));
+                    } else {
+                        self.open(id);
+                    }
+                }
+
+                if headers.is_end_stream() {
+                    self.ended.insert(id);
+                }
+            }
+            Frame::PushPromise(ref push_promise) => {
+                let id = push_promise.promised_id();
+                if self.side == Side::Client {
+                    self.violation("client sent PUSH_PROMISE");
+                } else if !id.is_server_initiated() {
+                    self.violation(format!("promised odd-numbered stream {}", u32::from(id)));
+                } else {
+                    self.open(id);
+                }
+            }
+            Frame::Data(ref data) => {
+                let id = data.stream_id();
+                self.check_open(id, "DATA");
+
+                if self.is_local(id) && !self.opened.contains(&id) {
+                    self.violation(format!("stream {}: DATA on an idle stream", u32::from(id)));
+                }
+
+                if data.is_end_stream() {
+                    self.ended.insert(id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks that the endpoint has not ended the stream.
+    fn check_open(&mut self, id: StreamId, kind: &str) {
+        if self.ended.contains(&id) {
+            self.violation(format!(
+                "stream {}: {} after END_STREAM",
+                u32::from(id),
+                kind
+            ));
+        }
+    }
+
+    /// Records a stream opened or reserved by the endpoint.
+    fn open(&mut self, id: StreamId) {
+        if id <= self.last_stream_id {
+            self.violation(format!(
+                "stream {}: opened after stream {}",
+                u32::from(id),
+                u32::from(self.last_stream_id)
+            ));
+        }
+
+        self.last_stream_id = self.last_stream_id.max(id);
+        self.opened.insert(id);
+    }
+}